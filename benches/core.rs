@@ -0,0 +1,90 @@
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use koholint_emulator::{decoding::decode_instruction, gameboy::Gameboy};
+
+const DOTS_IN_FRAME: u64 = 70224;
+// the boot rom is done after ~23.5M cycles, this leaves some margin
+const BOOT_CYCLES: u64 = 25_000_000;
+
+// the rom to run, KOHOLINT_BENCH_ROM if set
+// otherwise a blank cartridge : the boot rom then runs, fails the logo check
+// and loops forever with the screen on, which is enough to exercise the core
+fn load_rom() -> Vec<u8> {
+    return match std::env::var("KOHOLINT_BENCH_ROM") {
+        Ok(path) => std::fs::read(path).expect("could not read KOHOLINT_BENCH_ROM"),
+        Err(_) => vec![0; 0x8000],
+    };
+}
+
+fn booted_console() -> Gameboy {
+    let mut console = Gameboy::new(load_rom()).unwrap();
+    let mut cycles = 0;
+    while cycles < BOOT_CYCLES {
+        cycles += console.step().unwrap();
+    }
+    return console;
+}
+
+fn decode(c: &mut Criterion) {
+    let console = Gameboy::new(load_rom()).unwrap();
+
+    // the code part of the boot rom, which stays mapped until the boot is over
+    c.bench_function("decode boot rom", |b| {
+        b.iter(|| {
+            let mut address = 0;
+            while address < 0xA8 {
+                let instr = decode_instruction(&console, black_box(address)).unwrap();
+                address += instr.size;
+            }
+        })
+    });
+
+    c.bench_function("decode cartridge", |b| {
+        b.iter(|| {
+            for address in 0x150..0x4000 {
+                // not every byte is the start of a valid instruction
+                let _ = black_box(decode_instruction(&console, black_box(address)));
+            }
+        })
+    });
+}
+
+fn execute(c: &mut Criterion) {
+    // the boot rom is mostly loads, increments, bit operations and relative jumps
+    c.bench_function("step 10k boot instructions", |b| {
+        b.iter_batched(
+            || Gameboy::new(load_rom()).unwrap(),
+            |mut console| {
+                for _ in 0..10_000 {
+                    console.step().unwrap();
+                }
+                return console;
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn video(c: &mut Criterion) {
+    let mut console = booted_console();
+
+    // includes drawing the 144 visible lines
+    c.bench_function("emulate one frame", |b| {
+        b.iter(|| {
+            let mut dots = 0;
+            while dots < DOTS_IN_FRAME {
+                dots += console.step().unwrap();
+            }
+        })
+    });
+
+    c.bench_function("update tile atlas", |b| {
+        b.iter(|| console.update_tile_atlas())
+    });
+
+    c.bench_function("update tile map", |b| b.iter(|| console.update_tile_map()));
+}
+
+criterion_group!(benches, decode, execute, video);
+criterion_main!(benches);
@@ -0,0 +1,293 @@
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+// https://gbdev.io/pandocs/Audio_Registers.html
+// the audio processing unit : its registers and the channels' state, no sound is generated yet
+
+// NR52 : sound on/off
+const NR52: u16 = 0xFF26;
+
+// per channel : the length timer (NRx1), dac enable (NRx2, NR30 for the wave channel)
+// and control (NRx4) registers
+const LENGTH_REGISTERS: [u16; 4] = [0xFF11, 0xFF16, 0xFF1B, 0xFF20];
+const DAC_REGISTERS: [u16; 4] = [0xFF12, 0xFF17, 0xFF1A, 0xFF21];
+const CONTROL_REGISTERS: [u16; 4] = [0xFF14, 0xFF19, 0xFF1E, 0xFF23];
+// low byte of the period of the pulse & wave channels, the high 3 bits are in NRx4
+const PERIOD_REGISTERS: [u16; 3] = [0xFF13, 0xFF18, 0xFF1D];
+// NR43 : clock shift & divider of the noise channel
+const NR43: u16 = 0xFF22;
+
+// https://gbdev.io/pandocs/Audio_details.html#length-timer
+// silences its channel once it runs out, if enabled
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct LengthCounter {
+    pub remaining: u16,
+    pub enabled: bool,
+}
+
+impl LengthCounter {
+    // the wave channel counts from 256, the others from 64
+    fn max(channel: usize) -> u16 {
+        return if channel == 2 { 256 } else { 64 };
+    }
+
+    // returns true when the counter just ran out
+    fn clock(&mut self) -> bool {
+        if !self.enabled || self.remaining == 0 {
+            return false;
+        }
+
+        self.remaining -= 1;
+        return self.remaining == 0;
+    }
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+struct Channel {
+    // shown in NR52, turned on by a trigger and off by the length counter or the dac
+    on: bool,
+    length: LengthCounter,
+}
+
+// what a channel is set to play, read from its registers
+pub struct ChannelStatus {
+    pub on: bool,
+    pub muted: bool,
+    // in Hz : the tone of the pulse & wave channels, how often the noise channel's lfsr shifts
+    pub frequency: f64,
+    // 0-15, the initial volume of the envelope (envelopes aren't emulated yet)
+    // the output level of the wave channel is mapped to the same range
+    pub volume: u8,
+    pub length: LengthCounter,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Apu {
+    // FF10-FF25, NR52 is kept apart
+    registers: [u8; 0x16],
+    // FF30-FF3F
+    wave_ram: [u8; 0x10],
+    // NR52 bit 7 : when off, the registers read as 0 and ignore writes
+    powered: bool,
+    // pulse 1, pulse 2, wave & noise
+    channels: [Channel; 4],
+    // the frame sequencer clocks the length counters, envelopes & sweep
+    // it goes through 8 steps, at 512 Hz
+    sequencer_step: u8,
+    // channels muted by the user, on top of what the game enables
+    // the game can't see it, and it isn't part of save states
+    #[serde(skip)]
+    muted: [bool; 4],
+}
+
+impl Apu {
+    pub fn new() -> Apu {
+        return Apu {
+            registers: [0; 0x16],
+            wave_ram: [0; 0x10],
+            powered: false,
+            channels: [Channel::default(); 4],
+            sequencer_step: 0,
+            muted: [false; 4],
+        };
+    }
+
+    pub fn is_powered(&self) -> bool {
+        return self.powered;
+    }
+
+    // channels are numbered from 0 (pulse 1) to 3 (noise)
+    pub fn is_channel_on(&self, channel: usize) -> bool {
+        return self.channels[channel].on;
+    }
+
+    pub fn set_channel_enabled(&mut self, channel: usize, enabled: bool) {
+        self.muted[channel] = !enabled;
+    }
+
+    pub fn is_channel_enabled(&self, channel: usize) -> bool {
+        return !self.muted[channel];
+    }
+
+    // what the mixer should go by : on for the game and not muted by the user
+    pub fn is_channel_audible(&self, channel: usize) -> bool {
+        return self.powered && self.channels[channel].on && !self.muted[channel];
+    }
+
+    pub fn length_counter(&self, channel: usize) -> LengthCounter {
+        return self.channels[channel].length;
+    }
+
+    // https://gbdev.io/pandocs/Audio_Registers.html
+    pub fn channel_status(&self, channel: usize) -> ChannelStatus {
+        let frequency = if channel < 3 {
+            let period = self.register(PERIOD_REGISTERS[channel]) as u32
+                | (self.register(CONTROL_REGISTERS[channel]) as u32 & 0b111) << 8;
+            // the wave channel steps through 32 samples, the pulse ones through 8 at twice
+            // the rate : both are 2048 - period ticks of their own clock per step
+            let clock = if channel == 2 { 65536.0 } else { 131072.0 };
+            clock / (2048 - period) as f64
+        } else {
+            let nr43 = self.register(NR43);
+            let divider = match nr43 & 0b111 {
+                0 => 0.5,
+                divider => divider as f64,
+            };
+            262144.0 / (divider * (1u32 << (nr43 >> 4)) as f64)
+        };
+
+        let dac = self.register(DAC_REGISTERS[channel]);
+        let volume = if channel == 2 {
+            // NR32 : mute, 100%, 50% or 25%
+            [0, 15, 7, 3][((self.register(0xFF1C) >> 5) & 0b11) as usize]
+        } else {
+            dac >> 4
+        };
+
+        return ChannelStatus {
+            on: self.powered && self.channels[channel].on,
+            muted: self.muted[channel],
+            frequency,
+            volume,
+            length: self.channels[channel].length,
+        };
+    }
+
+    fn register(&self, address: u16) -> u8 {
+        return self.registers[(address - 0xFF10) as usize];
+    }
+
+    // one step of the frame sequencer, clocked by the divider at 512 Hz
+    pub fn step(&mut self) {
+        if !self.powered {
+            return;
+        }
+
+        // the length counters are clocked every other step, at 256 Hz
+        if self.sequencer_step % 2 == 0 {
+            for (i, channel) in self.channels.iter_mut().enumerate() {
+                if channel.length.clock() {
+                    debug!("APU CHANNEL {} LENGTH EXPIRED", i + 1);
+                    channel.on = false;
+                }
+            }
+        }
+
+        self.sequencer_step = (self.sequencer_step + 1) % 8;
+    }
+
+    // `address` is in FF10-FF3F
+    // the unused bits are set by the caller
+    pub fn read(&self, address: u16) -> u8 {
+        return match address {
+            NR52 => {
+                let channels_on = self
+                    .channels
+                    .iter()
+                    .enumerate()
+                    .fold(0, |bits, (i, c)| bits | (c.on as u8) << i);
+                (self.powered as u8) << 7 | channels_on
+            }
+            0xFF10..NR52 if !self.powered => 0x00,
+            0xFF10..NR52 => self.registers[(address - 0xFF10) as usize],
+            0xFF30..0xFF40 => self.wave_ram[(address - 0xFF30) as usize],
+            // FF27-FF2F are unused
+            _ => 0x00,
+        };
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            NR52 => {
+                let powered = value & 0x80 != 0;
+                if powered && !self.powered {
+                    debug!("APU POWERED ON");
+                    // every register starts over from its power-on value
+                    self.registers = [0; 0x16];
+                    self.sequencer_step = 0;
+                } else if !powered && self.powered {
+                    debug!("APU POWERED OFF");
+                    self.channels = [Channel::default(); 4];
+                }
+                self.powered = powered;
+            }
+            0xFF10..NR52 if !self.powered => {
+                debug!(
+                    "WRITE TO AUDIO REGISTER ({:#06X}) WHILE THE APU IS OFF",
+                    address
+                );
+            }
+            0xFF10..NR52 => {
+                self.registers[(address - 0xFF10) as usize] = value;
+                self.update_channels(address, value);
+            }
+            // wave ram stays accessible whatever the power state
+            0xFF30..0xFF40 => self.wave_ram[(address - 0xFF30) as usize] = value,
+            _ => {}
+        }
+    }
+
+    // side effects of the channel registers
+    fn update_channels(&mut self, address: u16, value: u8) {
+        if let Some(i) = LENGTH_REGISTERS.iter().position(|&r| r == address) {
+            // the wave channel uses the whole byte, the others the lower 6 bits
+            let length = if i == 2 { value } else { value & 0x3F };
+            self.channels[i].length.remaining = LengthCounter::max(i) - length as u16;
+        } else if let Some(i) = DAC_REGISTERS.iter().position(|&r| r == address) {
+            if !self.is_dac_on(i) {
+                self.channels[i].on = false;
+            }
+        } else if let Some(i) = CONTROL_REGISTERS.iter().position(|&r| r == address) {
+            let dac_on = self.is_dac_on(i);
+            let channel = &mut self.channels[i];
+            channel.length.enabled = value & 0x40 != 0;
+
+            // trigger
+            if value & 0x80 != 0 {
+                if channel.length.remaining == 0 {
+                    channel.length.remaining = LengthCounter::max(i);
+                }
+                channel.on = dac_on;
+            }
+        }
+    }
+
+    fn is_dac_on(&self, channel: usize) -> bool {
+        let register = self.registers[(DAC_REGISTERS[channel] - 0xFF10) as usize];
+        return match channel {
+            // NR30 bit 7
+            2 => register & 0x80 != 0,
+            // initial volume or envelope direction
+            _ => register & 0xF8 != 0,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn muted_channels_stay_on_for_the_game() {
+        let mut apu = Apu::new();
+        apu.write(NR52, 0x80);
+        // pulse 2 : dac on, then triggered
+        apu.write(0xFF17, 0xF0);
+        apu.write(0xFF19, 0x80);
+        assert!(apu.is_channel_audible(1));
+
+        apu.set_channel_enabled(1, false);
+        assert!(!apu.is_channel_enabled(1));
+        assert!(!apu.is_channel_audible(1));
+        assert!(apu.channel_status(1).muted);
+        // the game still sees the channel playing
+        assert!(apu.is_channel_on(1));
+        assert_eq!(apu.read(NR52), 0b1000_0010);
+        // and the other channels aren't affected
+        assert!(apu.is_channel_enabled(0));
+
+        apu.set_channel_enabled(1, true);
+        assert!(apu.is_channel_audible(1));
+        assert!(!apu.channel_status(1).muted);
+    }
+}
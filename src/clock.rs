@@ -0,0 +1,81 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// where the emulation gets the time of day from, for the parts of a cartridge that keep
+// running while the console is off (e.g. the MBC3 real time clock)
+// the console never reads the wall clock directly, so that a run can be made deterministic
+// by handing it a clock that only moves when told to
+pub trait Clock: Send {
+    // time elapsed since some fixed origin, only differences between calls matter
+    fn now(&self) -> Duration;
+}
+
+// the host's clock, counted from the unix epoch so that it survives restarts
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        // a host clock set before 1970 is treated as the epoch
+        return SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+    }
+}
+
+// a clock that is frozen until advanced, for tests, movies & netplay
+// clones share the same time : keep one to advance the one given to the console
+#[derive(Clone, Default)]
+pub struct ManualClock {
+    time: Arc<Mutex<Duration>>,
+}
+
+impl ManualClock {
+    pub fn new(start: Duration) -> ManualClock {
+        return ManualClock {
+            time: Arc::new(Mutex::new(start)),
+        };
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.time.lock().unwrap() += duration;
+    }
+
+    pub fn set(&self, time: Duration) {
+        *self.time.lock().unwrap() = time;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        return *self.time.lock().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_only_moves_when_told() {
+        let clock = ManualClock::new(Duration::from_secs(10));
+        assert_eq!(clock.now(), Duration::from_secs(10));
+        assert_eq!(clock.now(), Duration::from_secs(10));
+
+        clock.advance(Duration::from_millis(1500));
+        assert_eq!(clock.now(), Duration::from_millis(11500));
+
+        clock.set(Duration::ZERO);
+        assert_eq!(clock.now(), Duration::ZERO);
+    }
+
+    #[test]
+    fn manual_clock_clones_share_the_time() {
+        let clock = ManualClock::default();
+        let given: Box<dyn Clock> = Box::new(clock.clone());
+
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(given.now(), Duration::from_secs(60));
+    }
+}
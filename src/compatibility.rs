@@ -0,0 +1,114 @@
+use crate::{memory, quirks};
+
+// what the header of a rom says it needs, compared to what the emulator can do
+// this is only a guess from the header : a game can still run into a missing feature
+// the header doesn't mention (e.g. an unusual use of the ppu)
+
+// one thing the rom needs
+#[derive(Debug, PartialEq)]
+pub enum Finding {
+    // the rom won't run, or won't get far
+    Unsupported(String),
+    // the rom runs, but some of it is missing or wrong
+    Partial(String),
+    // worth knowing, but not a gap of the emulator
+    Note(String),
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Finding::Unsupported(text) => write!(f, "Unsupported : {text}"),
+            Finding::Partial(text) => write!(f, "Partial     : {text}"),
+            Finding::Note(text) => write!(f, "Note        : {text}"),
+        }
+    }
+}
+
+// https://gbdev.io/pandocs/The_Cartridge_Header.html
+pub fn check(rom: &[u8]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    if rom.len() < 0x0150 {
+        findings.push(Finding::Note(format!(
+            "the rom is only {} bytes and has no header, it is loaded as ROM ONLY",
+            rom.len()
+        )));
+        return findings;
+    }
+
+    // cartridge hardware
+    let cartridge_type = rom[0x0147];
+    let name = memory::cartridge_type_name(cartridge_type);
+    match cartridge_type {
+        0x00 | 0x01 => {}
+        _ => findings.push(Finding::Unsupported(format!(
+            "the cartridge uses {name} ({cartridge_type:#04X}), which is not supported"
+        ))),
+    }
+    if matches!(cartridge_type, 0x0F | 0x10) {
+        findings.push(Finding::Unsupported(
+            "the cartridge has a real time clock, which is not implemented".to_string(),
+        ));
+    }
+    if name.contains("BATTERY") {
+        findings.push(Finding::Partial(
+            "the cartridge has a battery, but the saves are not kept between sessions".to_string(),
+        ));
+    }
+
+    // https://gbdev.io/pandocs/The_Cartridge_Header.html#0149--ram-size
+    match rom[0x0149] {
+        0x00 | 0x01 | 0x02 => {}
+        ram_size => findings.push(Finding::Partial(format!(
+            "the cartridge has more than 8KiB of ram ({ram_size:#04X}), only the first 8KiB are emulated"
+        ))),
+    }
+
+    // https://gbdev.io/pandocs/The_Cartridge_Header.html#0148--rom-size
+    let size_byte = rom[0x0148];
+    if size_byte <= 0x08 && rom.len() != 0x8000 << size_byte {
+        findings.push(Finding::Note(format!(
+            "the rom is {}KiB but its header declares {}KiB",
+            rom.len() / 0x400,
+            (0x8000 << size_byte) / 0x400
+        )));
+    }
+
+    // https://gbdev.io/pandocs/The_Cartridge_Header.html#0143--cgb-flag
+    match rom[0x0143] {
+        0xC0 => findings.push(Finding::Unsupported(
+            "the game only runs on a gameboy color, whose colors & vram banks are not implemented"
+                .to_string(),
+        )),
+        0x80 => findings.push(Finding::Partial(
+            "the game has gameboy color features : it runs in DMG mode, without them".to_string(),
+        )),
+        _ => {}
+    }
+
+    // https://gbdev.io/pandocs/The_Cartridge_Header.html#0146--sgb-flag
+    if rom[0x0146] == 0x03 {
+        findings.push(Finding::Note(
+            "the game has super gameboy features (borders, colors), which are ignored".to_string(),
+        ));
+    }
+
+    // https://gbdev.io/pandocs/The_Cartridge_Header.html#014d--header-checksum
+    let checksum = rom[0x0134..=0x014C]
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1));
+    if checksum != rom[0x014D] {
+        findings.push(Finding::Unsupported(format!(
+            "the header checksum is wrong ({:#04X}, expected {checksum:#04X}) : the boot rom locks up, like on hardware",
+            rom[0x014D]
+        )));
+    }
+
+    if let Some(game) = quirks::lookup(rom) {
+        if let Some(note) = game.note {
+            findings.push(Finding::Note(format!("{} : {note}", game.name)));
+        }
+    }
+
+    return findings;
+}
@@ -0,0 +1,589 @@
+use std::path::PathBuf;
+
+use glfw::Key;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::input::Button;
+
+// number of roms kept in the recent list
+const RECENT_ROMS_LEN: usize = 10;
+
+// bumped whenever a field changes meaning, so older files can be upgraded when loaded
+pub const CONFIG_VERSION: u32 = 1;
+
+// user settings shared by the frontends, read from ~/.config/koholint/config.toml
+// missing fields fall back to their default value, unknown ones are ignored
+// so files written by other versions of the emulator still load
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmulatorConfig {
+    // version of the emulator that wrote the file
+    pub version: u32,
+    // window size, in multiples of the gameboy's resolution
+    pub scale: u32,
+    // how the screen is drawn in the window, screenshots are never filtered
+    pub screen_filter: ScreenFilter,
+    pub crt: CrtSettings,
+    // emulation speed while fast forwarding, in multiples of the normal speed
+    // 0 removes the limit
+    pub fast_forward_speed: u32,
+    // where the last rom was opened from
+    pub last_rom_directory: Option<PathBuf>,
+    // snapshots the console when the window is closed, to resume from with --resume
+    pub save_state_on_exit: bool,
+    // the last roms opened, most recent first
+    pub recent_roms: Vec<RecentRom>,
+    pub buttons: ButtonBindings,
+    pub commands: CommandBindings,
+}
+
+impl Default for EmulatorConfig {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            scale: 4,
+            screen_filter: ScreenFilter::Crisp,
+            crt: CrtSettings::default(),
+            fast_forward_speed: 0,
+            last_rom_directory: None,
+            save_state_on_exit: false,
+            recent_roms: Vec::new(),
+            buttons: ButtonBindings::default(),
+            commands: CommandBindings::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScreenFilter {
+    // square pixels, as they are
+    Crisp,
+    // scanlines, a curved screen & some glow around the bright pixels
+    Crt,
+}
+
+// strength of each effect of the crt filter, from 0 (off) to 1
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CrtSettings {
+    pub scanlines: f32,
+    pub curvature: f32,
+    pub glow: f32,
+}
+
+impl Default for CrtSettings {
+    fn default() -> Self {
+        Self {
+            scanlines: 0.4,
+            curvature: 0.1,
+            glow: 0.2,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecentRom {
+    pub path: PathBuf,
+    // from the cartridge header, empty if there is none
+    pub title: String,
+}
+
+// keys are stored by name (e.g. "Z", "Enter", "Up") so the file stays editable by hand
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ButtonBindings {
+    pub up: String,
+    pub right: String,
+    pub left: String,
+    pub down: String,
+    pub a: String,
+    pub b: String,
+    pub start: String,
+    pub select: String,
+}
+
+impl Default for ButtonBindings {
+    fn default() -> Self {
+        Self {
+            up: "Up".to_string(),
+            right: "Right".to_string(),
+            left: "Left".to_string(),
+            down: "Down".to_string(),
+            a: "X".to_string(),
+            b: "Z".to_string(),
+            start: "Enter".to_string(),
+            select: "Backspace".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommandBindings {
+    pub pause: String,
+    pub save_state: String,
+    pub load_state: String,
+    pub screenshot: String,
+    pub fast_forward: String,
+    pub fullscreen: String,
+    pub reset: String,
+    // debugger controls, only meaningful while paused
+    #[serde(rename = "continue")]
+    pub continue_: String,
+    pub step_over: String,
+    pub step_into: String,
+}
+
+impl Default for CommandBindings {
+    fn default() -> Self {
+        Self {
+            pause: "P".to_string(),
+            save_state: "F6".to_string(),
+            load_state: "F8".to_string(),
+            screenshot: "F12".to_string(),
+            fast_forward: "Tab".to_string(),
+            fullscreen: "F4".to_string(),
+            reset: "F2".to_string(),
+            continue_: "F5".to_string(),
+            step_over: "F10".to_string(),
+            step_into: "F11".to_string(),
+        }
+    }
+}
+
+// emulator actions that can be bound to a key
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Pause,
+    SaveState,
+    LoadState,
+    Screenshot,
+    FastForward,
+    Fullscreen,
+    Reset,
+    Continue,
+    StepOver,
+    StepInto,
+}
+
+impl EmulatorConfig {
+    // reads the config file, or writes the default one if there is none yet
+    // any error is reported and the defaults are used instead
+    pub fn load_or_create() -> EmulatorConfig {
+        let Some(path) = config_path() else {
+            warn!("COULD NOT LOCATE THE CONFIG DIRECTORY, USING DEFAULT CONFIG");
+            return EmulatorConfig::default();
+        };
+
+        let mut config = match std::fs::read_to_string(&path) {
+            Ok(text) => match toml::from_str::<EmulatorConfig>(&text) {
+                Ok(config) => config,
+                Err(e) => {
+                    println!("Error : invalid config file {} ({e})", path.display());
+                    return EmulatorConfig::default();
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let config = EmulatorConfig::default();
+                match config.save(&path) {
+                    Ok(()) => info!("WROTE DEFAULT CONFIG TO {}", path.display()),
+                    Err(e) => warn!("COULD NOT WRITE DEFAULT CONFIG ({e})"),
+                }
+                return config;
+            }
+            Err(e) => {
+                println!(
+                    "Error : could not read config file {} ({e})",
+                    path.display()
+                );
+                return EmulatorConfig::default();
+            }
+        };
+
+        if config.version > CONFIG_VERSION {
+            warn!(
+                "CONFIG FILE IS FROM A NEWER VERSION ({}), SOME SETTINGS MAY BE IGNORED",
+                config.version
+            );
+        }
+        // written back in the current format
+        config.version = CONFIG_VERSION;
+        if !(1..=8).contains(&config.scale) {
+            warn!("INVALID SCALE {} IN CONFIG, USING 4", config.scale);
+            config.scale = 4;
+        }
+        let crt = config.crt;
+        if [crt.scanlines, crt.curvature, crt.glow]
+            .iter()
+            .any(|value| !(0.0..=1.0).contains(value))
+        {
+            warn!("INVALID CRT SETTINGS IN CONFIG (SHOULD BE BETWEEN 0 AND 1), USING THE DEFAULT ONES");
+            config.crt = CrtSettings::default();
+        }
+
+        config.prune_recent_roms();
+        config.check_bindings();
+        return config;
+    }
+
+    // moves the rom to the top of the recent list
+    pub fn add_recent_rom(&mut self, path: PathBuf, title: String) {
+        self.recent_roms.retain(|rom| rom.path != path);
+        self.recent_roms.insert(0, RecentRom { path, title });
+        self.recent_roms.truncate(RECENT_ROMS_LEN);
+    }
+
+    // forgets the roms that were moved or deleted since
+    pub fn prune_recent_roms(&mut self) {
+        self.recent_roms.retain(|rom| rom.path.exists());
+    }
+
+    // writes the settings back to the config file, reporting any error
+    pub fn store(&self) {
+        let Some(path) = config_path() else {
+            warn!("COULD NOT LOCATE THE CONFIG DIRECTORY, SETTINGS NOT SAVED");
+            return;
+        };
+
+        if let Err(e) = self.save(&path) {
+            println!(
+                "Error : could not save config file {} ({e})",
+                path.display()
+            );
+        }
+    }
+
+    fn save(&self, path: &PathBuf) -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // serializing plain values can't fail
+        let text = toml::to_string_pretty(self).unwrap();
+        return std::fs::write(path, text);
+    }
+
+    // every binding with its name in the config file
+    fn bindings(&self) -> [(&'static str, &String); 18] {
+        let buttons = &self.buttons;
+        let commands = &self.commands;
+        return [
+            ("up", &buttons.up),
+            ("right", &buttons.right),
+            ("left", &buttons.left),
+            ("down", &buttons.down),
+            ("a", &buttons.a),
+            ("b", &buttons.b),
+            ("start", &buttons.start),
+            ("select", &buttons.select),
+            ("pause", &commands.pause),
+            ("save_state", &commands.save_state),
+            ("load_state", &commands.load_state),
+            ("screenshot", &commands.screenshot),
+            ("fast_forward", &commands.fast_forward),
+            ("fullscreen", &commands.fullscreen),
+            ("reset", &commands.reset),
+            ("continue", &commands.continue_),
+            ("step_over", &commands.step_over),
+            ("step_into", &commands.step_into),
+        ];
+    }
+
+    // warns about key names that don't match any key, those bindings are ignored
+    // a key bound twice is rejected : all the bindings go back to the default ones
+    fn check_bindings(&mut self) {
+        let bindings = self.bindings();
+        for (_, name) in bindings {
+            if key_from_name(name).is_none() {
+                warn!("UNKNOWN KEY \"{name}\" IN CONFIG, BINDING IGNORED");
+            }
+        }
+
+        let duplicate = bindings.iter().enumerate().find_map(|(i, (first, name))| {
+            let key = key_from_name(name)?;
+            return bindings[i + 1..]
+                .iter()
+                .find(|(_, other)| key_from_name(other) == Some(key))
+                .map(|(second, _)| (*first, *second, name.to_string()));
+        });
+        if let Some((first, second, name)) = duplicate {
+            warn!(
+                "KEY \"{name}\" BOUND TO BOTH {} AND {} IN CONFIG, USING THE DEFAULT BINDINGS",
+                first.to_uppercase(),
+                second.to_uppercase()
+            );
+            self.buttons = ButtonBindings::default();
+            self.commands = CommandBindings::default();
+        }
+    }
+
+    pub fn button_for_key(&self, key: Key) -> Option<Button> {
+        let buttons = &self.buttons;
+        return [
+            (&buttons.up, Button::Up),
+            (&buttons.right, Button::Right),
+            (&buttons.left, Button::Left),
+            (&buttons.down, Button::Down),
+            (&buttons.a, Button::A),
+            (&buttons.b, Button::B),
+            (&buttons.start, Button::Start),
+            (&buttons.select, Button::Select),
+        ]
+        .into_iter()
+        .find(|(name, _)| key_from_name(name) == Some(key))
+        .map(|(_, button)| button);
+    }
+
+    pub fn command_for_key(&self, key: Key) -> Option<Command> {
+        let commands = &self.commands;
+        return [
+            (&commands.pause, Command::Pause),
+            (&commands.save_state, Command::SaveState),
+            (&commands.load_state, Command::LoadState),
+            (&commands.screenshot, Command::Screenshot),
+            (&commands.fast_forward, Command::FastForward),
+            (&commands.fullscreen, Command::Fullscreen),
+            (&commands.reset, Command::Reset),
+            (&commands.continue_, Command::Continue),
+            (&commands.step_over, Command::StepOver),
+            (&commands.step_into, Command::StepInto),
+        ]
+        .into_iter()
+        .find(|(name, _)| key_from_name(name) == Some(key))
+        .map(|(_, command)| command);
+    }
+}
+
+// $XDG_CONFIG_HOME/koholint/config.toml, or ~/.config/koholint/config.toml
+fn config_path() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+
+    return Some(config_dir.join("koholint").join("config.toml"));
+}
+
+// the names are the ones of the glfw key enum
+const KEY_NAMES: &[(&str, Key)] = &[
+    ("A", Key::A),
+    ("B", Key::B),
+    ("C", Key::C),
+    ("D", Key::D),
+    ("E", Key::E),
+    ("F", Key::F),
+    ("G", Key::G),
+    ("H", Key::H),
+    ("I", Key::I),
+    ("J", Key::J),
+    ("K", Key::K),
+    ("L", Key::L),
+    ("M", Key::M),
+    ("N", Key::N),
+    ("O", Key::O),
+    ("P", Key::P),
+    ("Q", Key::Q),
+    ("R", Key::R),
+    ("S", Key::S),
+    ("T", Key::T),
+    ("U", Key::U),
+    ("V", Key::V),
+    ("W", Key::W),
+    ("X", Key::X),
+    ("Y", Key::Y),
+    ("Z", Key::Z),
+    ("Num0", Key::Num0),
+    ("Num1", Key::Num1),
+    ("Num2", Key::Num2),
+    ("Num3", Key::Num3),
+    ("Num4", Key::Num4),
+    ("Num5", Key::Num5),
+    ("Num6", Key::Num6),
+    ("Num7", Key::Num7),
+    ("Num8", Key::Num8),
+    ("Num9", Key::Num9),
+    ("F1", Key::F1),
+    ("F2", Key::F2),
+    ("F3", Key::F3),
+    ("F4", Key::F4),
+    ("F5", Key::F5),
+    ("F6", Key::F6),
+    ("F7", Key::F7),
+    ("F8", Key::F8),
+    ("F9", Key::F9),
+    ("F10", Key::F10),
+    ("F11", Key::F11),
+    ("F12", Key::F12),
+    ("Up", Key::Up),
+    ("Right", Key::Right),
+    ("Left", Key::Left),
+    ("Down", Key::Down),
+    ("Space", Key::Space),
+    ("Enter", Key::Enter),
+    ("Backspace", Key::Backspace),
+    ("Tab", Key::Tab),
+    ("Escape", Key::Escape),
+    ("Insert", Key::Insert),
+    ("Delete", Key::Delete),
+    ("Home", Key::Home),
+    ("End", Key::End),
+    ("PageUp", Key::PageUp),
+    ("PageDown", Key::PageDown),
+    ("LeftShift", Key::LeftShift),
+    ("RightShift", Key::RightShift),
+    ("LeftControl", Key::LeftControl),
+    ("RightControl", Key::RightControl),
+    ("LeftAlt", Key::LeftAlt),
+    ("RightAlt", Key::RightAlt),
+    ("Comma", Key::Comma),
+    ("Period", Key::Period),
+    ("Slash", Key::Slash),
+    ("Semicolon", Key::Semicolon),
+    ("Minus", Key::Minus),
+    ("Equal", Key::Equal),
+];
+
+pub fn key_from_name(name: &str) -> Option<Key> {
+    return KEY_NAMES
+        .iter()
+        .find(|(key_name, _)| key_name.eq_ignore_ascii_case(name))
+        .map(|(_, key)| *key);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_bindings_are_distinct() {
+        let mut config = EmulatorConfig::default();
+        config.commands.fullscreen = "P".to_string();
+        config.check_bindings();
+        assert_eq!(config.commands.fullscreen, "F4");
+
+        let bindings = config.bindings();
+        for (i, (first, name)) in bindings.iter().enumerate() {
+            let key = key_from_name(name);
+            assert!(key.is_some(), "{first}");
+            for (second, other) in &bindings[i + 1..] {
+                assert_ne!(key, key_from_name(other), "{first} and {second}");
+            }
+        }
+        assert_eq!(config.command_for_key(Key::F4), Some(Command::Fullscreen));
+        assert_eq!(config.command_for_key(Key::F11), Some(Command::StepInto));
+    }
+
+    #[test]
+    fn duplicates_are_case_insensitive() {
+        let mut config = EmulatorConfig::default();
+        config.buttons.a = "enter".to_string();
+        config.check_bindings();
+        assert_eq!(config.buttons.a, "X");
+        assert!(matches!(
+            config.button_for_key(Key::Enter),
+            Some(Button::Start)
+        ));
+
+        // unknown keys are not duplicates of each other
+        config.commands.pause = "Nothing".to_string();
+        config.commands.reset = "Nothing".to_string();
+        config.check_bindings();
+        assert_eq!(config.commands.pause, "Nothing");
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut config = EmulatorConfig::default();
+        config.scale = 2;
+        config.screen_filter = ScreenFilter::Crt;
+        config.crt.glow = 0.5;
+        config.fast_forward_speed = 3;
+        config.last_rom_directory = Some(PathBuf::from("/roms"));
+        config.recent_roms.push(RecentRom {
+            path: PathBuf::from("/roms/game.gb"),
+            title: "GAME".to_string(),
+        });
+        config.buttons.a = "A".to_string();
+        config.commands.continue_ = "F9".to_string();
+
+        let text = toml::to_string(&config).unwrap();
+        let read: EmulatorConfig = toml::from_str(&text).unwrap();
+        assert_eq!(toml::to_string(&read).unwrap(), text);
+        assert_eq!(read.scale, 2);
+        assert_eq!(read.screen_filter, ScreenFilter::Crt);
+        assert_eq!(read.crt.glow, 0.5);
+        assert_eq!(read.recent_roms[0].title, "GAME");
+        assert_eq!(read.buttons.a, "A");
+        // renamed in the file, as continue is a keyword
+        assert!(text.contains("continue = \"F9\""));
+        assert_eq!(read.commands.continue_, "F9");
+    }
+
+    #[test]
+    fn unknown_keys_are_ignored() {
+        // a file from a newer version, with settings this one doesn't know about
+        let text = r#"
+            version = 7
+            scale = 3
+            rewind_seconds = 10
+
+            [crt]
+            glow = 0.3
+            mask = "aperture"
+
+            [buttons]
+            a = "A"
+            turbo_a = "S"
+
+            [netplay]
+            host = "localhost"
+        "#;
+        let config: EmulatorConfig = toml::from_str(text).unwrap();
+        assert_eq!(config.version, 7);
+        assert_eq!(config.scale, 3);
+        assert_eq!(config.crt.glow, 0.3);
+        assert_eq!(config.buttons.a, "A");
+        // and the missing ones get their default value
+        assert_eq!(config.crt.scanlines, CrtSettings::default().scanlines);
+        assert_eq!(config.buttons.b, "Z");
+        assert_eq!(config.commands.pause, "P");
+        assert_eq!(config.screen_filter, ScreenFilter::Crisp);
+    }
+
+    #[test]
+    fn recent_roms() {
+        let mut config = EmulatorConfig::default();
+        let rom = |i: usize| PathBuf::from(format!("/roms/{i}.gb"));
+        for i in 0..RECENT_ROMS_LEN + 2 {
+            config.add_recent_rom(rom(i), format!("GAME {i}"));
+        }
+        // most recent first, and the oldest ones are dropped
+        assert_eq!(config.recent_roms.len(), RECENT_ROMS_LEN);
+        assert_eq!(config.recent_roms[0].path, rom(RECENT_ROMS_LEN + 1));
+        assert_eq!(config.recent_roms[RECENT_ROMS_LEN - 1].path, rom(2));
+
+        // opening one again moves it back to the top, without a duplicate
+        config.add_recent_rom(rom(5), "GAME 5".to_string());
+        assert_eq!(config.recent_roms.len(), RECENT_ROMS_LEN);
+        assert_eq!(config.recent_roms[0].path, rom(5));
+        assert_eq!(
+            config
+                .recent_roms
+                .iter()
+                .filter(|r| r.path == rom(5))
+                .count(),
+            1
+        );
+
+        // only the roms still on disk are kept
+        let existing =
+            std::env::temp_dir().join(format!("koholint-recent-{}.gb", std::process::id()));
+        std::fs::write(&existing, [0]).unwrap();
+        config.add_recent_rom(existing.clone(), String::new());
+        config.prune_recent_roms();
+        std::fs::remove_file(&existing).unwrap();
+        assert_eq!(config.recent_roms.len(), 1);
+        assert_eq!(config.recent_roms[0].path, existing);
+    }
+}
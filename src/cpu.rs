@@ -1,5 +1,8 @@
+use serde::{Deserialize, Serialize};
+
 use crate::decoding::Operand;
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct CPU {
     // 8 & 16 bits registers
     a: u8,
@@ -13,6 +16,8 @@ pub struct CPU {
     sp: u16,   // stack pointer
     pc: u16,   // program counter
     ime: bool, // interrupt flag
+    // set by EI, which only enables interrupts after the next instruction
+    ime_scheduled: bool,
 }
 
 enum Flags {
@@ -37,6 +42,28 @@ impl CPU {
             sp: 0,
             pc: 0,
             ime: false,
+            ime_scheduled: false,
+        };
+    }
+
+    // https://gbdev.io/pandocs/Power_Up_Sequence.html#cpu-registers
+    // the registers as the cgb boot rom leaves them for a cgb cartridge
+    // only the dmg boot rom is included, so cgb games skip it and start from here
+    // games read A = 0x11 to know they are running on a cgb
+    pub fn blank_cgb() -> CPU {
+        return CPU {
+            a: 0x11,
+            f: 0x80,
+            b: 0x00,
+            c: 0x00,
+            d: 0xFF,
+            e: 0x56,
+            h: 0x00,
+            l: 0x0D,
+            sp: 0xFFFE,
+            pc: 0x0100,
+            ime: false,
+            ime_scheduled: false,
         };
     }
 
@@ -197,12 +224,10 @@ impl CPU {
         self.pc = self.pc.wrapping_add(increment);
     }
 
+    // the address space wraps around, in both directions
+    // (taking the absolute value of the offset would overflow for -128)
     pub fn offset_program_counter(&mut self, offset: i8) {
-        if offset > 0 {
-            self.pc = self.pc.wrapping_add(offset.abs() as u16);
-        } else {
-            self.pc = self.pc.wrapping_sub(offset.abs() as u16);
-        }
+        self.pc = self.pc.wrapping_add_signed(offset as i16);
     }
 
     pub fn read_stack_pointer(&self) -> u16 {
@@ -214,11 +239,7 @@ impl CPU {
     }
 
     pub fn offset_stack_pointer(&mut self, offset: i8) {
-        if offset > 0 {
-            self.sp = self.sp.wrapping_add(offset.abs() as u16);
-        } else {
-            self.sp = self.sp.wrapping_sub(offset.abs() as u16);
-        }
+        self.sp = self.sp.wrapping_add_signed(offset as i16);
     }
 
     // flags register :
@@ -296,5 +317,22 @@ impl CPU {
 
     pub fn disable_interrupts(&mut self) {
         self.ime = false;
+        // a DI right after an EI cancels it
+        self.ime_scheduled = false;
+    }
+
+    pub fn schedule_enable_interrupts(&mut self) {
+        self.ime_scheduled = true;
+    }
+
+    pub fn is_ime_scheduled(&self) -> bool {
+        return self.ime_scheduled;
+    }
+
+    pub fn apply_scheduled_ime(&mut self) {
+        if self.ime_scheduled {
+            self.ime = true;
+            self.ime_scheduled = false;
+        }
     }
 }
@@ -1,14 +1,245 @@
-use std::io::Write;
+use std::{collections::VecDeque, io::Write};
+
+use log::warn;
 
 use crate::{
-    decoding::{decode_instruction, decode_next_instruction},
+    decoding::{decode_instruction, decode_next_instruction, Operand},
     error::EmulationError,
-    gameboy::Gameboy,
+    expression::{self, Expression, Format},
+    gameboy::{palette_shades, Gameboy},
+    image,
+    input::GBInputState,
+    memory::AccessCounters,
+    perf::Phase,
+    ram_search::{Comparison, RamSearch, RamSnapshot},
 };
 
+// the profiler groups program counters in buckets of this many bytes
+// to keep its memory bounded
+const PROFILE_BUCKET_SIZE: usize = 16;
+// number of executed instructions kept by default for the history command
+const DEFAULT_HISTORY_CAPACITY: usize = 100;
+
 pub struct Debugger {
-    breakpoints: Vec<u16>,
+    breakpoints: Vec<Breakpoint>,
     paused: bool,
+    // cycles elapsed per program counter bucket
+    // empty until the profiler is started for the first time
+    profile: Vec<u64>,
+    profiling: bool,
+    // pause when the cpu accesses memory it can't reach during an OAM DMA
+    break_on_dma_conflict: bool,
+    // last executed instructions (pc, disassembly), oldest first
+    executed: VecDeque<(u16, String)>,
+    history_capacity: usize,
+    // mnemonics of the instructions printed every time they are executed
+    logged_mnemonics: Vec<String>,
+    // one-shot breakpoint, e.g. the return address when stepping over a call
+    run_to: Option<u16>,
+    // printed every time the emulation stops
+    watches: Vec<Watch>,
+    // pc the watches were last printed at, so they are only printed again once it moved
+    watches_pc: Option<u16>,
+    // named copies of the ram, to find where values are kept
+    ram_search: RamSearch,
+    // buttons to hold & for how many frames, played one after the other
+    scripted_inputs: VecDeque<(GBInputState, u32)>,
+    // frame the first scripted input started at
+    scripted_input_start: Option<u64>,
+}
+
+// e.g. "display [hl+2]/d"
+struct Watch {
+    expression: Expression,
+    format: Format,
+}
+
+struct Breakpoint {
+    address: u16,
+    // the same address in 4000-7FFF exists once per rom bank,
+    // so a breakpoint there can be restricted to a single bank
+    bank: Option<u8>,
+    // executed in order when the breakpoint is hit
+    actions: Vec<BreakpointAction>,
+}
+
+// breakpoints are identified by their location only
+impl PartialEq for Breakpoint {
+    fn eq(&self, other: &Self) -> bool {
+        return self.address == other.address && self.bank == other.bank;
+    }
+}
+
+// what a breakpoint can do by itself, e.g. for game genie style patches :
+// "break 1234 do set a=00; continue"
+enum BreakpointAction {
+    SetRegister(Operand, u16),
+    SetProgramCounter(u16),
+    SetMemory(u16, u8),
+    // don't pause once the actions are done
+    Continue,
+}
+
+impl BreakpointAction {
+    // parses "set <register>=<value>", "set <address>=<value>" or "continue"
+    fn parse(string: &str) -> Result<BreakpointAction, String> {
+        let words: Vec<&str> = string.split_whitespace().collect();
+        return match words.as_slice() {
+            ["continue"] | ["c"] => Ok(BreakpointAction::Continue),
+            ["set", assignment] => {
+                let Some((target, value_string)) = assignment.split_once('=') else {
+                    return Err(format!("missing value in \"{string}\""));
+                };
+                let value = u16::from_str_radix(value_string, 16).map_err(|e| e.to_string())?;
+                match expression::parse_register(target) {
+                    Some(register) => {
+                        let is_r8 = target.len() == 1;
+                        if is_r8 && value > 0xFF {
+                            return Err(format!("{value:#X} does not fit in {target}"));
+                        }
+                        Ok(BreakpointAction::SetRegister(register, value))
+                    }
+                    None if target == "pc" => Ok(BreakpointAction::SetProgramCounter(value)),
+                    None => {
+                        let address = u16::from_str_radix(target, 16)
+                            .map_err(|_| format!("unknown register or address \"{target}\""))?;
+                        if value > 0xFF {
+                            return Err(format!("{value:#X} does not fit in a byte"));
+                        }
+                        Ok(BreakpointAction::SetMemory(address, value as u8))
+                    }
+                }
+            }
+            _ => Err(format!("unknown action \"{string}\"")),
+        };
+    }
+
+    fn run(&self, console: &mut Gameboy) -> Result<(), EmulationError> {
+        match self {
+            BreakpointAction::SetRegister(register, value) => match register {
+                Operand::R16_AF
+                | Operand::R16_BC
+                | Operand::R16_DE
+                | Operand::R16_HL
+                | Operand::R16_SP => console.cpu_mut().write_r16(register, *value),
+                _ => console.cpu_mut().write_r8(register, *value as u8),
+            },
+            BreakpointAction::SetProgramCounter(value) => {
+                console.cpu_mut().write_program_counter(*value)
+            }
+            BreakpointAction::SetMemory(address, value) => {
+                console.memory_mut().write_byte(*address, *value)?;
+            }
+            BreakpointAction::Continue => {}
+        }
+
+        return Ok(());
+    }
+}
+
+impl std::fmt::Display for BreakpointAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BreakpointAction::SetRegister(register, value) => {
+                write!(
+                    f,
+                    "set {}={:X}",
+                    format!("{register}").to_lowercase(),
+                    value
+                )
+            }
+            BreakpointAction::SetProgramCounter(value) => write!(f, "set pc={:X}", value),
+            BreakpointAction::SetMemory(address, value) => {
+                write!(f, "set {:04X}={:X}", address, value)
+            }
+            BreakpointAction::Continue => write!(f, "continue"),
+        }
+    }
+}
+
+impl Breakpoint {
+    // parses either "4ABC" or "3:4ABC"
+    fn parse(string: &str) -> Result<Breakpoint, std::num::ParseIntError> {
+        return match string.split_once(':') {
+            Some((bank_string, address_string)) => Ok(Breakpoint {
+                address: u16::from_str_radix(address_string, 16)?,
+                bank: Some(u8::from_str_radix(bank_string, 16)?),
+                actions: Vec::new(),
+            }),
+            None => Ok(Breakpoint {
+                address: u16::from_str_radix(string, 16)?,
+                bank: None,
+                actions: Vec::new(),
+            }),
+        };
+    }
+
+    // parses "[bank:]address [do <action>; ...]", as typed after "break"
+    // and as saved in breakpoint files
+    fn parse_line(line: &str) -> Result<Breakpoint, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (location, script) = match words.as_slice() {
+            [location] => (*location, String::new()),
+            [location, "do", actions @ ..] => (*location, actions.join(" ")),
+            _ => return Err("Usage : break [bank:]address [do <action>; ...]".to_string()),
+        };
+
+        let mut breakpoint = Breakpoint::parse(location).map_err(|e| e.to_string())?;
+        for action_string in script.split(';') {
+            if action_string.trim().is_empty() {
+                continue;
+            }
+            breakpoint
+                .actions
+                .push(BreakpointAction::parse(action_string)?);
+        }
+
+        return Ok(breakpoint);
+    }
+
+    // the line parse_line reads back
+    fn to_line(&self) -> String {
+        let mut line = match self.bank {
+            Some(bank) => format!("{:02X}:{:04X}", bank, self.address),
+            None => format!("{:04X}", self.address),
+        };
+        if !self.actions.is_empty() {
+            let actions: Vec<String> = self.actions.iter().map(|a| a.to_string()).collect();
+            line.push_str(&format!(" do {}", actions.join("; ")));
+        }
+
+        return line;
+    }
+
+    fn matches(&self, console: &Gameboy) -> bool {
+        let pc = console.cpu().read_program_counter();
+        if pc != self.address {
+            return false;
+        }
+
+        // the bank only matters for the switchable rom area
+        return match self.bank {
+            Some(bank) if (0x4000..0x8000).contains(&pc) => {
+                bank == console.memory().selected_rom_bank()
+            }
+            _ => true,
+        };
+    }
+}
+
+impl std::fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.bank {
+            Some(bank) => write!(f, "{:02X}:{:04X}", bank, self.address)?,
+            None => write!(f, "{:#06X}", self.address)?,
+        }
+        if !self.actions.is_empty() {
+            let actions: Vec<String> = self.actions.iter().map(|a| a.to_string()).collect();
+            write!(f, " do {}", actions.join("; "))?;
+        }
+
+        return Ok(());
+    }
 }
 
 impl Debugger {
@@ -16,22 +247,67 @@ impl Debugger {
         return Self {
             breakpoints: Vec::new(),
             paused,
+            profile: Vec::new(),
+            profiling: false,
+            break_on_dma_conflict: false,
+            executed: VecDeque::with_capacity(DEFAULT_HISTORY_CAPACITY),
+            history_capacity: DEFAULT_HISTORY_CAPACITY,
+            logged_mnemonics: Vec::new(),
+            run_to: None,
+            watches: Vec::new(),
+            watches_pc: None,
+            ram_search: RamSearch::new(),
+            scripted_inputs: VecDeque::new(),
+            scripted_input_start: None,
         };
     }
 
     pub fn step(&mut self, console: &mut Gameboy) -> Result<u64, EmulationError> {
         if !self.paused {
-            let cycles = console.step()?;
+            let pc_before = console.cpu().read_program_counter();
+            self.record_history(console);
+            self.log_instruction(console);
+            self.apply_scripted_input(console);
+            let dots = console.step()?;
+
+            if self.profiling {
+                self.profile[pc_before as usize / PROFILE_BUCKET_SIZE] +=
+                    console.last_step_cycles();
+            }
 
             // pc to check for breakpoints
             let pc = console.cpu().read_program_counter();
 
-            if self.breakpoints.iter().any(|breakpoint| pc == *breakpoint) {
-                println!("Reached breakpoint ({:#06X})", pc);
+            if let Some(breakpoint) = self.breakpoints.iter().find(|b| b.matches(console)) {
+                for action in &breakpoint.actions {
+                    action.run(console)?;
+                }
+                let continues = breakpoint
+                    .actions
+                    .iter()
+                    .any(|a| matches!(a, BreakpointAction::Continue));
+                if !continues {
+                    println!("Reached breakpoint ({} | PC {:#06X})", breakpoint, pc);
+                    self.paused = true;
+                }
+            } else if self.run_to == Some(pc) {
                 self.paused = true;
             }
+            if self.paused {
+                self.run_to = None;
+            }
 
-            return Ok(cycles);
+            if let Some(address) = console.dma_conflict() {
+                if self.break_on_dma_conflict {
+                    println!(
+                        "Access to {:#06X} during OAM DMA (PC {:#06X})",
+                        address, pc_before
+                    );
+                    self.paused = true;
+                }
+            }
+
+            return Ok(dots);
         } else {
             return self.prompt_command(console);
         }
@@ -41,14 +317,180 @@ impl Debugger {
         self.paused = true;
     }
 
+    // holds `buttons` for `frames` frames, once the inputs queued before are done
+    // e.g. start for 10 frames then a for 5, to get a game to a known state in a test
+    pub fn inject_input(&mut self, buttons: GBInputState, frames: u32) {
+        self.scripted_inputs.push_back((buttons, frames));
+    }
+
+    fn apply_scripted_input(&mut self, console: &mut Gameboy) {
+        if self.scripted_inputs.is_empty() {
+            return;
+        }
+
+        let frame = console.frame_count();
+        let mut elapsed = frame - *self.scripted_input_start.get_or_insert(frame);
+        // the next input starts on the frame the previous one ended
+        while let Some(&(_, frames)) = self.scripted_inputs.front() {
+            if elapsed < frames as u64 {
+                break;
+            }
+            self.scripted_inputs.pop_front();
+            self.scripted_input_start = Some(frame);
+            elapsed = 0;
+        }
+
+        match self.scripted_inputs.front() {
+            Some(&(buttons, _)) => console.set_input_state(buttons),
+            None => {
+                // the script is over, everything is released
+                console.set_input_state(GBInputState::default());
+                self.scripted_input_start = None;
+            }
+        }
+    }
+
+    fn record_history(&mut self, console: &Gameboy) {
+        if self.history_capacity == 0 || console.is_halted() {
+            return;
+        }
+
+        let pc = console.cpu().read_program_counter();
+        let instruction = match decode_next_instruction(console) {
+            Ok(instr) => instr.to_string(),
+            Err(_) => String::from("???"),
+        };
+
+        if self.executed.len() >= self.history_capacity {
+            self.executed.pop_front();
+        }
+        self.executed.push_back((pc, instruction));
+    }
+
+    fn log_instruction(&self, console: &Gameboy) {
+        if let Some(line) = self.logged_line(console) {
+            println!("{line}");
+        }
+    }
+
+    // the line logop prints for the next instruction, if its mnemonic is logged
+    fn logged_line(&self, console: &Gameboy) -> Option<String> {
+        if self.logged_mnemonics.is_empty() || console.is_halted() {
+            return None;
+        }
+
+        let instr = decode_next_instruction(console).ok()?;
+        if !self.logged_mnemonics.iter().any(|m| m == instr.mnemonic()) {
+            return None;
+        }
+
+        return Some(format!(
+            "{:#06X} | {}",
+            console.cpu().read_program_counter(),
+            instr
+        ));
+    }
+
+    fn print_watches(&self, console: &Gameboy) {
+        for (i, watch) in self.watches.iter().enumerate() {
+            let value = watch.expression.evaluate(console);
+            println!(
+                "{}: {} = {}",
+                i + 1,
+                watch.expression,
+                watch.format.format(value, watch.expression.is_byte())
+            );
+        }
+    }
+
+    fn print_history(&self, nb: usize) {
+        for (pc, instruction) in self.last_instructions(nb) {
+            println!("  {pc:#06X} | {instruction}");
+        }
+    }
+
+    // the `nb` last executed instructions (pc, disassembly), oldest first
+    pub fn last_instructions(&self, nb: usize) -> impl Iterator<Item = &(u16, String)> {
+        let skipped = self.executed.len().saturating_sub(nb);
+        return self.executed.iter().skip(skipped);
+    }
+
     pub fn is_paused(&self) -> bool {
         return self.paused;
     }
 
-    fn prompt_command(&mut self, console: &mut Gameboy) -> Result<u64, EmulationError> {
-        // prompt
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    // resumes until pc reaches `address`, or something else pauses first
+    pub fn run_to(&mut self, address: u16) {
+        self.run_to = Some(address);
+        self.paused = false;
+    }
+
+    pub fn is_at_breakpoint(&self, console: &Gameboy) -> bool {
+        return self.breakpoints.iter().any(|b| b.matches(console));
+    }
+
+    // returns false if the breakpoint was already placed
+    pub fn add_breakpoint(&mut self, address: u16) -> bool {
+        let breakpoint = Breakpoint {
+            address,
+            bank: None,
+            actions: Vec::new(),
+        };
+        if self.breakpoints.contains(&breakpoint) {
+            return false;
+        }
+
+        self.breakpoints.push(breakpoint);
+        return true;
+    }
+
+    // one breakpoint per line, "[bank:]address [do <action>; ...]" in hexadecimal
+    pub fn save_breakpoints(&self, path: &str) -> std::io::Result<()> {
+        let lines: Vec<String> = self.breakpoints.iter().map(Breakpoint::to_line).collect();
+        return std::fs::write(path, lines.join("\n") + "\n");
+    }
+
+    // adds the breakpoints of the file to the current ones, returns how many were new
+    // invalid lines are reported and skipped, empty ones & comments (";") are ignored
+    pub fn load_breakpoints(&mut self, path: &str) -> std::io::Result<usize> {
+        let text = std::fs::read_to_string(path)?;
+        let mut added = 0;
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+
+            match Breakpoint::parse_line(line) {
+                Ok(breakpoint) if !self.breakpoints.contains(&breakpoint) => {
+                    self.breakpoints.push(breakpoint);
+                    added += 1;
+                }
+                Ok(_) => {}
+                Err(e) => println!("Error : {path}, line {} : {e}", i + 1),
+            }
+        }
+
+        return Ok(added);
+    }
+
+    pub fn print_prompt(&mut self, console: &Gameboy) {
+        let pc = console.cpu().read_program_counter();
+        if self.watches_pc != Some(pc) {
+            self.print_watches(console);
+            self.watches_pc = Some(pc);
+        }
+
         print!("(dbg)> ");
         std::io::stdout().flush().unwrap();
+    }
+
+    fn prompt_command(&mut self, console: &mut Gameboy) -> Result<u64, EmulationError> {
+        self.print_prompt(console);
 
         // get user input
         let mut input = String::new();
@@ -59,6 +501,16 @@ impl Debugger {
             return Ok(0);
         }
 
+        return self.run_command(console, &input);
+    }
+
+    // runs a single debugger command, e.g. "break 0150"
+    // returns the dots elapsed if it executed instructions
+    pub fn run_command(
+        &mut self,
+        console: &mut Gameboy,
+        input: &str,
+    ) -> Result<u64, EmulationError> {
         let subcommands: Vec<&str> = input.trim().split_whitespace().collect();
         match subcommands.get(0) {
             None => {
@@ -73,11 +525,41 @@ impl Debugger {
                         println!("  exit     : quit the debugger");
                         println!("  list     : print assembly at current program counter");
                         println!("  print    : print the value of a register");
+                        println!("             print lines [all] : scroll, lcdc & palette registers of each line");
+                        println!("             print ime : the interrupt master enable, and whether the cpu is halted");
+                        println!("             print pixel <x> <y> : the tile & object drawn at a pixel of the screen");
+                        println!("             print palettes : BGP, OBP0 & OBP1 and the shade of each color");
+                        println!(
+                            "             print audio : what each audio channel is set to play"
+                        );
                         println!("  flags    : print the value of the flags register");
                         println!("  next     : execute current instruction");
                         println!("  continue : resume execution until next beakpoint");
-                        println!("  break    : place a breakpoint at a specific program counter");
-                        println!("  remove   : remove a breakpoint at a specific program counter");
+                        println!("  break    : place a breakpoint at a specific program counter ([bank:]address [do <action>; ...])");
+                        println!("             actions : set <register|address>=<value>, continue");
+                        println!("  remove   : remove a breakpoint at a specific program counter ([bank:]address)");
+                        println!(
+                            "  save-breaks : write the breakpoints to a file (save-breaks <file>)"
+                        );
+                        println!(
+                            "  load-breaks : add the breakpoints of a file (load-breaks <file>)"
+                        );
+                        println!("  info     : list breakpoints (info breakpoints)");
+                        println!("  history  : print the last executed instructions (history [n] | history size <n>)");
+                        println!("  save     : write a memory range to a binary file (save <start> <end> <file>)");
+                        println!("  profile  : count cycles spent per address (profile start|stop|report [n])");
+                        println!("  heatmap  : count reads, writes & executions per address (heatmap start|stop|clear|dump <path>)");
+                        println!("  stats    : time spent per frame in each part of the emulator (stats [on|off])");
+                        println!("  dmabreak : pause on accesses outside HRAM during OAM DMA (dmabreak on|off)");
+                        println!("  logop    : toggle printing every execution of an instruction (logop [mnemonic|clear])");
+                        println!("  display  : print an expression every time the emulation stops (display [<expr>[/d|/b]])");
+                        println!("             e.g. a, hl+2, [C0A0], [hl]");
+                        println!("  undisplay: stop printing an expression (undisplay <n>)");
+                        println!("  snap     : find ram values by comparing snapshots (snap save <name> [ext] | diff <name> [==|!=|<|>] | reset | list | limit <n>)");
+                        println!("  skip     : move past the current instruction without executing it (skip [bytes])");
+                        println!("  jump     : set the program counter, leaving the stack alone (jump <address>)");
+                        println!("  mute     : toggle muting an audio channel (mute [1-4])");
+                        println!("  solo     : only play one audio channel (solo <1-4>|off)");
                         println!("  dump     : dump a VRAM tile into a ppm image file");
                     }
                     "exit" => {
@@ -137,6 +619,17 @@ impl Debugger {
                             "sp" => {
                                 println!("sp : {:#06X}", console.cpu().read_stack_pointer())
                             }
+                            "ime" => {
+                                println!(
+                                    "ime : {}{}",
+                                    if console.interrupts_enabled() {
+                                        "on"
+                                    } else {
+                                        "off"
+                                    },
+                                    if console.is_halted() { " (halted)" } else { "" }
+                                )
+                            }
                             "f" => {
                                 println!(
                                     "f : {:#10b} (Z:{} N:{} H:{} C:{})",
@@ -148,16 +641,120 @@ impl Debugger {
                                 )
                             }
                             "oam" => {
-                                for i in 0..40 {
+                                for obj in console.oam_entries() {
                                     print!(
                                         "{} {} {} {} | ",
-                                        console.memory().read_byte(0xFE00 + i * 4 + 0),
-                                        console.memory().read_byte(0xFE00 + i * 4 + 1),
-                                        console.memory().read_byte(0xFE00 + i * 4 + 2),
-                                        console.memory().read_byte(0xFE00 + i * 4 + 3),
+                                        obj.y,
+                                        obj.x,
+                                        obj.tile,
+                                        obj.flags.bits()
                                     );
                                 }
                                 println!("");
+                                // OPRI, which decides who is drawn on top
+                                println!(
+                                    "Priority : {}",
+                                    if console.memory().is_object_priority_by_x() {
+                                        "x coordinate, then oam position"
+                                    } else {
+                                        "oam position"
+                                    }
+                                );
+                            }
+                            "pixel" => {
+                                let position = match (subcommands.get(2), subcommands.get(3)) {
+                                    (Some(x), Some(y)) => {
+                                        x.parse::<usize>().ok().zip(y.parse::<usize>().ok())
+                                    }
+                                    _ => None,
+                                };
+                                match position.and_then(|(x, y)| console.inspect_pixel(x, y)) {
+                                    Some(source) => {
+                                        println!(
+                                            "{} : map {:#06X}, tile {:#04X} at {:#06X}, color {}",
+                                            if source.window {
+                                                "Window"
+                                            } else {
+                                                "Background"
+                                            },
+                                            source.map_address,
+                                            source.tile_index,
+                                            source.tile_address,
+                                            source.color
+                                        );
+                                        if let Some(index) = source.object {
+                                            match console.get_oam_entry(index) {
+                                                Some(obj) => println!(
+                                                    "Object {index} : tile {:#04X} at {:#06X}",
+                                                    obj.tile,
+                                                    0x8000 + obj.tile as u16 * 16
+                                                ),
+                                                None => {
+                                                    println!("Error : no object {index} in the OAM")
+                                                }
+                                            }
+                                        }
+                                    }
+                                    None => println!("Error : Usage : print pixel <0-159> <0-143>"),
+                                }
+                            }
+                            "palettes" => {
+                                // the object palettes have no color 0 : it is transparent
+                                println!("       | value | color 0 1 2 3");
+                                for (name, address) in
+                                    [("BGP", 0xFF47), ("OBP0", 0xFF48), ("OBP1", 0xFF49)]
+                                {
+                                    let register = console.memory().read_byte(address);
+                                    let shades = palette_shades(register);
+                                    println!(
+                                        "  {name:<4} |  {register:#04X} |       {} {} {} {}",
+                                        shades[0], shades[1], shades[2], shades[3]
+                                    );
+                                }
+                            }
+                            "audio" => {
+                                println!("  channel | on  | muted | frequency  | volume | length");
+                                for (channel, name) in
+                                    ["pulse 1", "pulse 2", "wave", "noise"].iter().enumerate()
+                                {
+                                    let status = console.memory().apu().channel_status(channel);
+                                    println!(
+                                        "  {name:<7} | {:<3} | {:<5} | {:>7.1} Hz | {:>6} | {}",
+                                        if status.on { "on" } else { "off" },
+                                        if status.muted { "yes" } else { "no" },
+                                        status.frequency,
+                                        status.volume,
+                                        if status.length.enabled {
+                                            status.length.remaining.to_string()
+                                        } else {
+                                            "off".to_string()
+                                        }
+                                    );
+                                }
+                            }
+                            "lines" => {
+                                // only the lines where something changed, unless asked for all
+                                let all = subcommands.get(2) == Some(&"all");
+                                println!("  line |  scx |  scy | lcdc |  bgp |   wx");
+                                let mut previous = None;
+                                for (line, registers) in console.line_registers().iter().enumerate()
+                                {
+                                    if all || previous != Some(registers) {
+                                        println!(
+                                            "  {line:>4} | {:#04X} | {:#04X} | {:#04X} | {:#04X} | {:#04X}",
+                                            registers.scx,
+                                            registers.scy,
+                                            registers.lcdc,
+                                            registers.bgp,
+                                            registers.wx
+                                        );
+                                    }
+                                    previous = Some(registers);
+                                }
+                                let ly = console.memory().ppu_line();
+                                if (ly as usize) < console.line_registers().len() {
+                                    println!("Lines from {ly} on are from the previous frame");
+                                }
                             }
                             _ => match u16::from_str_radix(&name, 16) {
                                 Ok(address) => {
@@ -180,18 +777,55 @@ impl Debugger {
                     "step" | "s" => {
                         let pc = console.cpu().read_program_counter();
                         println!("  {pc:#06X} | {}", decode_next_instruction(&console)?);
+                        self.record_history(console);
                         return console.step();
                     }
                     "continue" | "c" => {
                         self.paused = false;
                     }
-                    "break" | "b" => match subcommands.get(1) {
+                    "break" | "b" => {
+                        if subcommands.len() < 2 {
+                            println!("Error : Missing breakpoint adress");
+                            return Ok(0);
+                        }
+                        let breakpoint = match Breakpoint::parse_line(&subcommands[1..].join(" ")) {
+                            Ok(parsed) => parsed,
+                            Err(e) => {
+                                println!("Error : {e}");
+                                return Ok(0);
+                            }
+                        };
+
+                        if self.breakpoints.contains(&breakpoint) {
+                            println!("Error : Breakpoint is already placed");
+                            return Ok(0);
+                        }
+
+                        self.breakpoints.push(breakpoint);
+                    }
+                    "save-breaks" => match subcommands.get(1) {
+                        Some(path) => match self.save_breakpoints(path) {
+                            Ok(()) => {
+                                println!("Saved {} breakpoints to {path}", self.breakpoints.len())
+                            }
+                            Err(e) => println!("Error : could not save breakpoints ({e})"),
+                        },
+                        None => println!("Error : Usage : save-breaks <file>"),
+                    },
+                    "load-breaks" => match subcommands.get(1) {
+                        Some(path) => match self.load_breakpoints(path) {
+                            Ok(added) => println!("Loaded {added} breakpoints from {path}"),
+                            Err(e) => println!("Error : could not load breakpoints ({e})"),
+                        },
+                        None => println!("Error : Usage : load-breaks <file>"),
+                    },
+                    "remove" | "r" => match subcommands.get(1) {
                         None => {
                             println!("Error : Missing breakpoint adress");
                             return Ok(0);
                         }
                         Some(address_string) => {
-                            let address = match u16::from_str_radix(address_string, 16) {
+                            let breakpoint = match Breakpoint::parse(address_string) {
                                 Ok(parsed) => parsed,
                                 Err(e) => {
                                     println!("Error : {e}");
@@ -199,33 +833,430 @@ impl Debugger {
                                 }
                             };
 
-                            if self.breakpoints.contains(&address) {
-                                println!("Error : Breakpoint is already placed");
+                            if let Some(pos) =
+                                self.breakpoints.iter().position(|x| *x == breakpoint)
+                            {
+                                self.breakpoints.remove(pos);
+                            } else {
+                                println!("Error : Breakpoint not found");
+                            }
+                        }
+                    },
+                    "info" | "i" => match subcommands.get(1) {
+                        Some(&"breakpoints") | Some(&"b") => {
+                            if self.breakpoints.is_empty() {
+                                println!("No breakpoints");
+                            }
+                            for (i, breakpoint) in self.breakpoints.iter().enumerate() {
+                                println!("  {i:>3} | {breakpoint}");
+                            }
+                        }
+                        _ => {
+                            println!("Error : Usage : info breakpoints");
+                        }
+                    },
+                    "history" => match (subcommands.get(1), subcommands.get(2)) {
+                        (Some(&"size"), Some(size_string)) => match size_string.parse() {
+                            Ok(size) => {
+                                self.history_capacity = size;
+                                while self.executed.len() > size {
+                                    self.executed.pop_front();
+                                }
+                                println!("Keeping the last {size} executed instructions");
+                            }
+                            Err(e) => println!("Error : {e}"),
+                        },
+                        (Some(&"size"), None) => {
+                            println!("Error : Usage : history size <n>");
+                        }
+                        (None, _) => self.print_history(10),
+                        (Some(nb_string), None) => match nb_string.parse() {
+                            Ok(nb) => self.print_history(nb),
+                            Err(e) => println!("Error : {e}"),
+                        },
+                        _ => {
+                            println!("Error : Usage : history [n] | history size <n>");
+                        }
+                    },
+                    "save" => {
+                        let (Some(start_string), Some(end_string), Some(path)) =
+                            (subcommands.get(1), subcommands.get(2), subcommands.get(3))
+                        else {
+                            println!("Error : Usage : save <start> <end> <file>");
+                            return Ok(0);
+                        };
+
+                        let (start, end) = match (
+                            u16::from_str_radix(start_string, 16),
+                            u16::from_str_radix(end_string, 16),
+                        ) {
+                            (Ok(start), Ok(end)) => (start, end),
+                            (Err(e), _) | (_, Err(e)) => {
+                                println!("Error : {e}");
+                                return Ok(0);
+                            }
+                        };
+
+                        // the end is inclusive, and a range ending before its start
+                        // wraps around the end of the address space
+                        let length = end.wrapping_sub(start) as usize + 1;
+                        let bytes: Vec<u8> = (0..length)
+                            .map(|i| console.memory().read_byte(start.wrapping_add(i as u16)))
+                            .collect();
+
+                        match std::fs::write(path, &bytes) {
+                            Ok(()) => println!("Wrote {length} bytes to {path}"),
+                            Err(e) => println!("Error : {e}"),
+                        }
+                    }
+                    "profile" => match subcommands.get(1) {
+                        Some(&"start") => {
+                            self.profile = vec![0; 0x10000 / PROFILE_BUCKET_SIZE];
+                            self.profiling = true;
+                            println!("Profiler started");
+                        }
+                        Some(&"stop") => {
+                            self.profiling = false;
+                            println!("Profiler stopped");
+                        }
+                        Some(&"report") => {
+                            let to_list = match subcommands.get(2) {
+                                None => 10,
+                                Some(nb_string) => match nb_string.parse() {
+                                    Ok(nb) => nb,
+                                    Err(e) => {
+                                        println!("Error : {e}");
+                                        return Ok(0);
+                                    }
+                                },
+                            };
+
+                            let total: u64 = self.profile.iter().sum();
+                            if total == 0 {
+                                println!("Error : No profiling data");
+                                return Ok(0);
+                            }
+
+                            let mut hottest: Vec<(usize, u64)> = self
+                                .profile
+                                .iter()
+                                .copied()
+                                .enumerate()
+                                .filter(|(_, cycles)| *cycles > 0)
+                                .collect();
+                            hottest.sort_by(|a, b| b.1.cmp(&a.1));
+
+                            for (bucket, cycles) in hottest.into_iter().take(to_list) {
+                                let start = bucket * PROFILE_BUCKET_SIZE;
+                                println!(
+                                    "  {:#06X}-{:#06X} | {:>12} cycles | {:>6.2}%",
+                                    start,
+                                    start + PROFILE_BUCKET_SIZE - 1,
+                                    cycles,
+                                    cycles as f64 * 100.0 / total as f64
+                                );
+                            }
+                        }
+                        _ => {
+                            println!("Error : Usage : profile start|stop|report [n]");
+                        }
+                    },
+                    "stats" => match subcommands.get(1) {
+                        Some(&"on") => {
+                            console.perf_stats_mut().set_enabled(true);
+                            println!("Timing statistics enabled");
+                        }
+                        Some(&"off") => {
+                            console.perf_stats_mut().set_enabled(false);
+                            println!("Timing statistics disabled");
+                        }
+                        None => {
+                            let stats = console.perf_stats();
+                            if !stats.is_enabled() {
+                                println!("Error : Timing statistics are disabled (stats on)");
                                 return Ok(0);
                             }
 
-                            self.breakpoints.push(address);
+                            let frame_time = stats.average_frame_time();
+                            println!("  frame    | {:>9.3} ms", frame_time.as_secs_f64() * 1000.0);
+                            for phase in Phase::ALL {
+                                let time = stats.average(phase);
+                                println!(
+                                    "  {:<8} | {:>9.3} ms | {:>6.2}%",
+                                    format!("{phase:?}").to_lowercase(),
+                                    time.as_secs_f64() * 1000.0,
+                                    time.as_secs_f64() * 100.0
+                                        / frame_time.as_secs_f64().max(f64::EPSILON)
+                                );
+                            }
+                        }
+                        _ => {
+                            println!("Error : Usage : stats [on|off]");
                         }
                     },
-                    "remove" | "r" => match subcommands.get(1) {
+                    "dmabreak" => match subcommands.get(1) {
+                        Some(&"on") => {
+                            self.break_on_dma_conflict = true;
+                            println!("Pausing on OAM DMA conflicts");
+                        }
+                        Some(&"off") => {
+                            self.break_on_dma_conflict = false;
+                            println!("No longer pausing on OAM DMA conflicts");
+                        }
+                        _ => {
+                            println!("Error : Usage : dmabreak on|off");
+                        }
+                    },
+                    "logop" => match subcommands.get(1) {
                         None => {
-                            println!("Error : Missing breakpoint adress");
+                            if self.logged_mnemonics.is_empty() {
+                                println!("No logged instructions");
+                            } else {
+                                println!(
+                                    "Logged instructions : {}",
+                                    self.logged_mnemonics.join(", ")
+                                );
+                            }
+                        }
+                        Some(&"clear") => {
+                            self.logged_mnemonics.clear();
+                            println!("No longer logging instructions");
+                        }
+                        Some(mnemonic) => {
+                            let mnemonic = mnemonic.to_lowercase();
+                            if let Some(position) =
+                                self.logged_mnemonics.iter().position(|m| *m == mnemonic)
+                            {
+                                self.logged_mnemonics.remove(position);
+                                println!("No longer logging {mnemonic}");
+                            } else {
+                                println!("Logging every {mnemonic}");
+                                self.logged_mnemonics.push(mnemonic);
+                            }
+                        }
+                    },
+                    "heatmap" => match subcommands.get(1) {
+                        Some(&"start") => {
+                            console.memory_mut().start_access_tracking();
+                            println!("Access tracking started");
+                        }
+                        Some(&"stop") => {
+                            console.memory_mut().stop_access_tracking();
+                            println!("Access tracking stopped");
+                        }
+                        Some(&"clear") => {
+                            // only the accesses from now on will be counted
+                            if console.memory().access_counters().is_none() {
+                                println!("Error : Access tracking was never started");
+                                return Ok(0);
+                            }
+                            console.memory_mut().clear_access_counters();
+                            println!("Access counters cleared");
+                        }
+                        Some(&"dump") => {
+                            let Some(path) = subcommands.get(2) else {
+                                println!("Error : Usage : heatmap dump <path>");
+                                return Ok(0);
+                            };
+                            let Some(counters) = console.memory().access_counters() else {
+                                println!("Error : Access tracking was never started");
+                                return Ok(0);
+                            };
+
+                            match dump_heatmap(counters, path) {
+                                Ok(()) => println!(
+                                    "Wrote {path}_reads.png, {path}_writes.png, {path}_executes.png and {path}.csv"
+                                ),
+                                Err(e) => println!("Error : {e}"),
+                            }
+                        }
+                        _ => {
+                            println!("Error : Usage : heatmap start|stop|clear|dump <path>");
+                        }
+                    },
+                    "display" => {
+                        if subcommands.len() == 1 {
+                            self.print_watches(console);
                             return Ok(0);
                         }
-                        Some(address_string) => {
-                            let address = match u16::from_str_radix(address_string, 16) {
-                                Ok(parsed) => parsed,
+
+                        let text = subcommands[1..].join(" ");
+                        let (expression_text, format) = match text.rsplit_once('/') {
+                            Some((expression_text, format_text)) => {
+                                match Format::parse(format_text) {
+                                    Some(format) => (expression_text.to_string(), format),
+                                    None => {
+                                        println!(
+                                            "Error : unknown format /{format_text} (x, d or b)"
+                                        );
+                                        return Ok(0);
+                                    }
+                                }
+                            }
+                            None => (text, Format::Hex),
+                        };
+                        match Expression::parse(&expression_text) {
+                            Ok(expression) => {
+                                self.watches.push(Watch { expression, format });
+                                self.print_watches(console);
+                            }
+                            Err(e) => println!("Error : {e}"),
+                        }
+                    }
+                    "undisplay" => match subcommands.get(1).map(|n| n.parse::<usize>()) {
+                        Some(Ok(n)) if (1..=self.watches.len()).contains(&n) => {
+                            let watch = self.watches.remove(n - 1);
+                            println!("No longer displaying {}", watch.expression);
+                        }
+                        _ => {
+                            println!("Error : Usage : undisplay <n>");
+                        }
+                    },
+                    "snap" => match (subcommands.get(1), subcommands.get(2)) {
+                        (Some(&"save"), Some(name)) => {
+                            let with_ext_ram = subcommands.get(3) == Some(&"ext");
+                            let snapshot = RamSnapshot::capture(console, with_ext_ram);
+                            println!("Saved {} bytes as \"{name}\"", snapshot.len());
+                            self.ram_search.save(name, snapshot);
+                        }
+                        (Some(&"diff"), Some(name)) => {
+                            let comparison = match subcommands.get(3) {
+                                None => Comparison::NotEqual,
+                                Some(text) => {
+                                    match Comparison::parse(text) {
+                                        Some(comparison) => comparison,
+                                        None => {
+                                            println!("Error : unknown comparison {text} (==, !=, < or >)");
+                                            return Ok(0);
+                                        }
+                                    }
+                                }
+                            };
+                            let Some(matching) = self.ram_search.diff(console, name, comparison)
+                            else {
+                                println!("Error : no snapshot named \"{name}\"");
+                                return Ok(0);
+                            };
+
+                            println!("{} candidates", matching.len());
+                            for (address, old, new) in
+                                matching.iter().take(self.ram_search.result_limit)
+                            {
+                                println!("  {address:#06X} : {old:#04X} -> {new:#04X}");
+                            }
+                            if matching.len() > self.ram_search.result_limit {
+                                println!("  ...");
+                            }
+                        }
+                        (Some(&"reset"), None) => {
+                            self.ram_search.reset();
+                            println!("Search reset, the next diff starts from every address");
+                        }
+                        (Some(&"list"), None) => {
+                            let names: Vec<&String> = self.ram_search.names().collect();
+                            if names.is_empty() {
+                                println!("No snapshots");
+                            } else {
+                                for name in names {
+                                    println!("  {name}");
+                                }
+                            }
+                        }
+                        (Some(&"limit"), Some(limit)) => match limit.parse() {
+                            Ok(limit) => {
+                                self.ram_search.result_limit = limit;
+                                println!("Printing at most {limit} results");
+                            }
+                            Err(e) => println!("Error : {e}"),
+                        },
+                        _ => {
+                            println!("Error : Usage : snap save <name> [ext] | diff <name> [==|!=|<|>] | reset | list | limit <n>");
+                        }
+                    },
+                    // both leave the console in a state the game never expected,
+                    // e.g. to get past an instruction the emulator doesn't handle
+                    "skip" | "jump" if !self.paused => {
+                        println!("Error : pause the emulation first");
+                    }
+                    "skip" => {
+                        let pc = console.cpu().read_program_counter();
+                        let size = match (subcommands.get(1), decode_next_instruction(console)) {
+                            (Some(bytes), _) => match bytes.parse::<u16>() {
+                                Ok(bytes) => bytes,
                                 Err(e) => {
                                     println!("Error : {e}");
                                     return Ok(0);
                                 }
-                            };
-
-                            if let Some(pos) = self.breakpoints.iter().position(|&x| x == address) {
-                                self.breakpoints.remove(pos);
+                            },
+                            (None, Ok(instr)) => instr.size,
+                            (None, Err(e)) => {
+                                println!("Error : {e}");
+                                println!("Error : Usage : skip <bytes>");
+                                return Ok(0);
+                            }
+                        };
+                        console.cpu_mut().increment_program_counter(size);
+                        let new_pc = console.cpu().read_program_counter();
+                        warn!("SKIPPED {size} BYTES AT {pc:#06X}, THE STATE HAS DIVERGED");
+                        println!("Skipped {size} bytes : {pc:#06X} -> {new_pc:#06X}, the emulation no longer matches the hardware");
+                    }
+                    "jump" => {
+                        match subcommands.get(1).map(|a| u16::from_str_radix(a, 16)) {
+                            Some(Ok(address)) => {
+                                let pc = console.cpu().read_program_counter();
+                                console.cpu_mut().write_program_counter(address);
+                                warn!("JUMPED FROM {pc:#06X} TO {address:#06X}, THE STATE HAS DIVERGED");
+                                println!("Jumped : {pc:#06X} -> {address:#06X}, the emulation no longer matches the hardware");
+                            }
+                            Some(Err(e)) => println!("Error : {e}"),
+                            None => println!("Error : Usage : jump <address>"),
+                        }
+                    }
+                    "mute" => match subcommands.get(1).map(|c| parse_audio_channel(c)) {
+                        None => {
+                            let muted: Vec<String> = (0..4)
+                                .filter(|&c| !console.is_channel_enabled(c))
+                                .map(|c| (c + 1).to_string())
+                                .collect();
+                            if muted.is_empty() {
+                                println!("No muted channels");
                             } else {
-                                println!("Error : Breakpoint not found");
+                                println!("Muted channels : {}", muted.join(", "));
+                            }
+                        }
+                        Some(Some(channel)) => {
+                            let enabled = !console.is_channel_enabled(channel);
+                            console.set_channel_enabled(channel, enabled);
+                            println!(
+                                "Channel {} {}",
+                                channel + 1,
+                                if enabled { "unmuted" } else { "muted" }
+                            );
+                        }
+                        Some(None) => {
+                            println!("Error : Usage : mute [1-4]");
+                        }
+                    },
+                    "solo" => match subcommands.get(1) {
+                        Some(&"off") => {
+                            for channel in 0..4 {
+                                console.set_channel_enabled(channel, true);
+                            }
+                            println!("All channels unmuted");
+                        }
+                        Some(arg) => match parse_audio_channel(arg) {
+                            Some(solo) => {
+                                for channel in 0..4 {
+                                    console.set_channel_enabled(channel, channel == solo);
+                                }
+                                println!("Only playing channel {}", solo + 1);
                             }
+                            None => {
+                                println!("Error : Usage : solo <1-4>|off");
+                            }
+                        },
+                        None => {
+                            println!("Error : Usage : solo <1-4>|off");
                         }
                     },
                     /*                     "dump" => {
@@ -255,6 +1286,65 @@ impl Debugger {
     }
 }
 
+// number of addresses listed in the csv export of the heatmap
+const HEATMAP_CSV_ENTRIES: usize = 1000;
+
+// writes one 256x256 grayscale image per access kind (address = y * 256 + x)
+// and a csv of the most accessed addresses
+fn dump_heatmap(counters: &AccessCounters, path: &str) -> std::io::Result<()> {
+    let reads: Vec<u32> = (0..=0xFFFF).map(|a| counters.reads(a)).collect();
+    let writes: Vec<u32> = (0..=0xFFFF).map(|a| counters.writes(a)).collect();
+    let executes: Vec<u32> = (0..=0xFFFF).map(|a| counters.executes(a)).collect();
+
+    for (counts, kind) in [
+        (&reads, "reads"),
+        (&writes, "writes"),
+        (&executes, "executes"),
+    ] {
+        // log scale, otherwise a few hot loops make everything else black
+        let max = (*counts.iter().max().unwrap() as f64).ln_1p();
+        let pixels: Vec<u8> = counts
+            .iter()
+            .map(|&count| {
+                if max == 0.0 {
+                    return 0;
+                }
+                return ((count as f64).ln_1p() / max * 255.0).round() as u8;
+            })
+            .collect();
+
+        image::write_png(
+            format!("{path}_{kind}.png"),
+            256,
+            256,
+            png::ColorType::Grayscale,
+            &pixels,
+        )?;
+    }
+
+    let total = |a: usize| reads[a] as u64 + writes[a] as u64 + executes[a] as u64;
+    let mut hottest: Vec<usize> = (0..0x10000).filter(|&a| total(a) > 0).collect();
+    hottest.sort_by_key(|&a| std::cmp::Reverse(total(a)));
+
+    let mut csv = String::from("address,reads,writes,executes\n");
+    for address in hottest.into_iter().take(HEATMAP_CSV_ENTRIES) {
+        csv.push_str(&format!(
+            "{:#06X},{},{},{}\n",
+            address, reads[address], writes[address], executes[address]
+        ));
+    }
+
+    return std::fs::write(format!("{path}.csv"), csv);
+}
+
+// channels are numbered from 1 for the user : pulse 1, pulse 2, wave & noise
+fn parse_audio_channel(arg: &str) -> Option<usize> {
+    return match arg.parse::<usize>() {
+        Ok(channel @ 1..=4) => Some(channel - 1),
+        _ => None,
+    };
+}
+
 /* pub fn debug_console(mut console: Gameboy) {
     println!("Welcome to my GBC debugger !");
 
@@ -482,3 +1572,97 @@ impl Debugger {
 
     return disassembly;
 } */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a console past the boot rom, at the entry point of `rom`
+    fn console_at_entry(rom: Vec<u8>) -> Gameboy {
+        let mut console = Gameboy::new(rom).unwrap();
+        console.memory_mut().skip_boot_rom();
+        console.cpu_mut().write_program_counter(0x0100);
+        return console;
+    }
+
+    #[test]
+    fn save_memory_range() {
+        let mut console = console_at_entry(vec![0; 0x8000]);
+        for (i, address) in (0xC000..0xC100).enumerate() {
+            console.memory_mut().write_byte(address, i as u8).unwrap();
+        }
+        let path = std::env::temp_dir().join(format!("koholint-save-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut debugger = Debugger::new(true);
+        debugger
+            .run_command(&mut console, &format!("save C010 C01F {path}"))
+            .unwrap();
+        let saved = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        // the end is included
+        assert_eq!(saved, (0x10..=0x1F).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn logop_toggles_mnemonics() {
+        let mut rom = vec![0; 0x8000];
+        // LD A, 0x42 ; INC A ; LD B, A
+        rom[0x0100..0x0104].copy_from_slice(&[0x3E, 0x42, 0x3C, 0x47]);
+        let mut console = console_at_entry(rom);
+        let mut debugger = Debugger::new(false);
+        assert_eq!(debugger.logged_line(&console), None);
+
+        // mnemonics are case insensitive
+        debugger.run_command(&mut console, "logop LD").unwrap();
+        debugger.run_command(&mut console, "logop inc").unwrap();
+        assert_eq!(debugger.logged_mnemonics, ["ld", "inc"]);
+        assert_eq!(
+            debugger.logged_line(&console).as_deref(),
+            Some("0x0100 | ld a, 0x42")
+        );
+        debugger.step(&mut console).unwrap();
+        assert_eq!(
+            debugger.logged_line(&console).as_deref(),
+            Some("0x0102 | inc a")
+        );
+
+        // a second time stops logging it
+        debugger.run_command(&mut console, "logop inc").unwrap();
+        assert_eq!(debugger.logged_mnemonics, ["ld"]);
+        assert_eq!(debugger.logged_line(&console), None);
+        debugger.step(&mut console).unwrap();
+        assert!(debugger.logged_line(&console).is_some());
+
+        debugger.run_command(&mut console, "logop clear").unwrap();
+        assert!(debugger.logged_mnemonics.is_empty());
+        assert_eq!(debugger.logged_line(&console), None);
+    }
+
+    #[test]
+    fn breakpoint_actions() {
+        let mut console = console_at_entry(vec![0; 0x8000]);
+        let mut debugger = Debugger::new(false);
+        debugger
+            .run_command(&mut console, "break 0102 do set a=42; continue")
+            .unwrap();
+        debugger
+            .run_command(&mut console, "break 0104 do set C000=99")
+            .unwrap();
+
+        // NOPs up to 0102, where the actions run and execution goes on
+        debugger.step(&mut console).unwrap();
+        debugger.step(&mut console).unwrap();
+        assert_eq!(console.cpu().read_program_counter(), 0x0102);
+        assert_eq!(console.cpu().read_a_register(), 0x42);
+        assert!(!debugger.is_paused());
+
+        // without continue, the breakpoint still pauses once its actions are done
+        debugger.step(&mut console).unwrap();
+        assert!(!debugger.is_paused());
+        debugger.step(&mut console).unwrap();
+        assert_eq!(console.cpu().read_program_counter(), 0x0104);
+        assert_eq!(console.memory().read_byte(0xC000), 0x99);
+        assert!(debugger.is_paused());
+    }
+}
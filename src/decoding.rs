@@ -53,6 +53,8 @@ pub enum Operation {
     DEC { x: Operand },
     INC { x: Operand },
     ADD { x: Operand, y: Operand },
+    // add sp, imm8 : sp + a signed offset, with the flags of an 8-bit add on the low byte of sp
+    LD_SP_PLUS_IMM8 { offset: i8 },
     SUB { y: Operand },
     ADC { y: Operand },
     SBC { y: Operand },
@@ -73,12 +75,16 @@ pub enum Operation {
     RRCA,
     DAA,
     SRL { x: Operand },
+    SRA { x: Operand },
     SLA { x: Operand },
     CP { y: Operand },
     DI,
     EI,
     CPL,
+    SCF,
+    CCF,
     HALT,
+    STOP,
 }
 
 #[derive(Debug)]
@@ -101,8 +107,8 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
     use Operation::*;
 
     let instr = console.memory().read_byte(address); // instruction byte
-    let imm8 = console.memory().read_byte(address + 1); // immediate byte, if needed
-    let imm16 = console.memory().read_word(address + 1); // immediate word, if needed
+    let imm8 = console.memory().read_byte(address.wrapping_add(1)); // immediate byte, if needed
+    let imm16 = console.memory().read_word(address.wrapping_add(1)); // immediate word, if needed
 
     match instr {
         // nop
@@ -186,6 +192,18 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                 branch_cycles: None,
             });
         }
+        // ld (imm16), sp
+        0x08 => {
+            return Ok(Instruction {
+                op: LD {
+                    dst: PTR(Box::new(IMM16(imm16))),
+                    src: R16_SP,
+                },
+                size: 3,
+                cycles: 20,
+                branch_cycles: None,
+            });
+        }
         // add hl, bc
         0x09 => {
             return Ok(Instruction {
@@ -258,6 +276,16 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                 branch_cycles: None,
             });
         }
+        // stop
+        0x10 => {
+            // the second byte is ignored, but stop is still 2 bytes long
+            return Ok(Instruction {
+                op: STOP,
+                size: 2,
+                cycles: 4,
+                branch_cycles: None,
+            });
+        }
         // ld de, imm16
         0x11 => {
             return Ok(Instruction {
@@ -326,7 +354,7 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
             return Ok(Instruction {
                 op: RLA,
                 size: 1,
-                cycles: 8,
+                cycles: 4,
                 branch_cycles: None,
             });
         }
@@ -336,7 +364,7 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                 op: JR {
                     offset_oprd: IMM8_SIGNED(i8::from_le_bytes([console
                         .memory()
-                        .read_byte(address + 1)])),
+                        .read_byte(address.wrapping_add(1))])),
                 },
                 size: 2,
                 cycles: 12,
@@ -390,7 +418,7 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
             return Ok(Instruction {
                 op: DEC { x: R8_E },
                 size: 1,
-                cycles: 8,
+                cycles: 4,
                 branch_cycles: None,
             });
         }
@@ -616,7 +644,7 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                     src: R8_A,
                 },
                 size: 1,
-                cycles: 12,
+                cycles: 8,
                 branch_cycles: None,
             });
         }
@@ -663,6 +691,15 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                 branch_cycles: None,
             });
         }
+        // scf
+        0x37 => {
+            return Ok(Instruction {
+                op: SCF,
+                size: 1,
+                cycles: 4,
+                branch_cycles: None,
+            });
+        }
         // jr c, imm8
         0x38 => {
             return Ok(Instruction {
@@ -738,6 +775,15 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                 branch_cycles: None,
             });
         }
+        // ccf
+        0x3F => {
+            return Ok(Instruction {
+                op: CCF,
+                size: 1,
+                cycles: 4,
+                branch_cycles: None,
+            });
+        }
         // ld b, b
         0x40 => {
             return Ok(Instruction {
@@ -1935,7 +1981,7 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                     y: PTR(Box::new(R16_HL)),
                 },
                 size: 1,
-                cycles: 4,
+                cycles: 8,
                 branch_cycles: None,
             });
         }
@@ -2213,6 +2259,163 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
         0xCB => {
             //prefixed bit manipulation instructions
             match imm8 {
+                // rlc b
+                0x00 => {
+                    return Ok(Instruction {
+                        op: RLC { x: R8_B },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // rlc c
+                0x01 => {
+                    return Ok(Instruction {
+                        op: RLC { x: R8_C },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // rlc d
+                0x02 => {
+                    return Ok(Instruction {
+                        op: RLC { x: R8_D },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // rlc e
+                0x03 => {
+                    return Ok(Instruction {
+                        op: RLC { x: R8_E },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // rlc h
+                0x04 => {
+                    return Ok(Instruction {
+                        op: RLC { x: R8_H },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // rlc l
+                0x05 => {
+                    return Ok(Instruction {
+                        op: RLC { x: R8_L },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // rlc (hl)
+                0x06 => {
+                    return Ok(Instruction {
+                        op: RLC {
+                            x: PTR(Box::new(R16_HL)),
+                        },
+                        size: 2,
+                        cycles: 16,
+                        branch_cycles: None,
+                    });
+                }
+                // rlc a
+                0x07 => {
+                    return Ok(Instruction {
+                        op: RLC { x: R8_A },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // rrc b
+                0x08 => {
+                    return Ok(Instruction {
+                        op: RRC { x: R8_B },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // rrc c
+                0x09 => {
+                    return Ok(Instruction {
+                        op: RRC { x: R8_C },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // rrc d
+                0x0A => {
+                    return Ok(Instruction {
+                        op: RRC { x: R8_D },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // rrc e
+                0x0B => {
+                    return Ok(Instruction {
+                        op: RRC { x: R8_E },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // rrc h
+                0x0C => {
+                    return Ok(Instruction {
+                        op: RRC { x: R8_H },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // rrc l
+                0x0D => {
+                    return Ok(Instruction {
+                        op: RRC { x: R8_L },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // rrc (hl)
+                0x0E => {
+                    return Ok(Instruction {
+                        op: RRC {
+                            x: PTR(Box::new(R16_HL)),
+                        },
+                        size: 2,
+                        cycles: 16,
+                        branch_cycles: None,
+                    });
+                }
+                // rrc a
+                0x0F => {
+                    return Ok(Instruction {
+                        op: RRC { x: R8_A },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // rl b
+                0x10 => {
+                    return Ok(Instruction {
+                        op: RL { x: R8_B },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
                 // rl c
                 0x11 => {
                     return Ok(Instruction {
@@ -2222,6 +2425,62 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                         branch_cycles: None,
                     });
                 }
+                // rl d
+                0x12 => {
+                    return Ok(Instruction {
+                        op: RL { x: R8_D },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // rl e
+                0x13 => {
+                    return Ok(Instruction {
+                        op: RL { x: R8_E },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // rl h
+                0x14 => {
+                    return Ok(Instruction {
+                        op: RL { x: R8_H },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // rl l
+                0x15 => {
+                    return Ok(Instruction {
+                        op: RL { x: R8_L },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // rl (hl)
+                0x16 => {
+                    return Ok(Instruction {
+                        op: RL {
+                            x: PTR(Box::new(R16_HL)),
+                        },
+                        size: 2,
+                        cycles: 16,
+                        branch_cycles: None,
+                    });
+                }
+                // rl a
+                0x17 => {
+                    return Ok(Instruction {
+                        op: RL { x: R8_A },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
                 // rr b
                 0x18 => {
                     return Ok(Instruction {
@@ -2371,6 +2630,80 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                     });
                 }
 
+                // sra b
+                0x28 => {
+                    return Ok(Instruction {
+                        op: SRA { x: R8_B },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // sra c
+                0x29 => {
+                    return Ok(Instruction {
+                        op: SRA { x: R8_C },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // sra d
+                0x2A => {
+                    return Ok(Instruction {
+                        op: SRA { x: R8_D },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // sra e
+                0x2B => {
+                    return Ok(Instruction {
+                        op: SRA { x: R8_E },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // sra h
+                0x2C => {
+                    return Ok(Instruction {
+                        op: SRA { x: R8_H },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // sra l
+                0x2D => {
+                    return Ok(Instruction {
+                        op: SRA { x: R8_L },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
+                // sra (hl)
+                0x2E => {
+                    return Ok(Instruction {
+                        op: SRA {
+                            x: PTR(Box::new(R16_HL)),
+                        },
+                        size: 2,
+                        cycles: 16,
+                        branch_cycles: None,
+                    });
+                }
+                // sra a
+                0x2F => {
+                    return Ok(Instruction {
+                        op: SRA { x: R8_A },
+                        size: 2,
+                        cycles: 8,
+                        branch_cycles: None,
+                    });
+                }
                 // swap b
                 0x30 => {
                     return Ok(Instruction {
@@ -2581,7 +2914,7 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                             src: PTR(Box::new(R16_HL)),
                         },
                         size: 2,
-                        cycles: 16,
+                        cycles: 12,
                         branch_cycles: None,
                     });
                 }
@@ -2656,7 +2989,7 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                             src: PTR(Box::new(R16_HL)),
                         },
                         size: 2,
-                        cycles: 16,
+                        cycles: 12,
                         branch_cycles: None,
                     });
                 }
@@ -2731,7 +3064,7 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                             src: PTR(Box::new(R16_HL)),
                         },
                         size: 2,
-                        cycles: 16,
+                        cycles: 12,
                         branch_cycles: None,
                     });
                 }
@@ -2806,7 +3139,7 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                             src: PTR(Box::new(R16_HL)),
                         },
                         size: 2,
-                        cycles: 16,
+                        cycles: 12,
                         branch_cycles: None,
                     });
                 }
@@ -2881,7 +3214,7 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                             src: PTR(Box::new(R16_HL)),
                         },
                         size: 2,
-                        cycles: 16,
+                        cycles: 12,
                         branch_cycles: None,
                     });
                 }
@@ -2956,7 +3289,7 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                             src: PTR(Box::new(R16_HL)),
                         },
                         size: 2,
-                        cycles: 16,
+                        cycles: 12,
                         branch_cycles: None,
                     });
                 }
@@ -3031,7 +3364,7 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                             src: PTR(Box::new(R16_HL)),
                         },
                         size: 2,
-                        cycles: 16,
+                        cycles: 12,
                         branch_cycles: None,
                     });
                 }
@@ -3106,7 +3439,7 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                             src: PTR(Box::new(R16_HL)),
                         },
                         size: 2,
-                        cycles: 16,
+                        cycles: 12,
                         branch_cycles: None,
                     });
                 }
@@ -4319,12 +4652,6 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                         branch_cycles: None,
                     });
                 }
-                _ => {
-                    return Err(EmulationError {
-                        ty: EmulationErrorType::UnhandledInstructionDecode(0xCB00 + imm8 as u16),
-                        pc: Some(address),
-                    });
-                }
             }
         }
         // call z, imm16
@@ -4456,6 +4783,18 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                 branch_cycles: None,
             });
         }
+        // jp c, imm16
+        0xDA => {
+            return Ok(Instruction {
+                op: JP_CC {
+                    cc: CC_C,
+                    addr: IMM16(imm16),
+                },
+                size: 3,
+                cycles: 12,
+                branch_cycles: Some(16),
+            });
+        }
         // call c, imm16
         0xDC => {
             return Ok(Instruction {
@@ -4468,6 +4807,15 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                 branch_cycles: Some(24),
             });
         }
+        // sbc a, imm8
+        0xDE => {
+            return Ok(Instruction {
+                op: SBC { y: IMM8(imm8) },
+                size: 2,
+                cycles: 8,
+                branch_cycles: None,
+            });
+        }
         // rst 18
         0xDF => {
             return Ok(Instruction {
@@ -4541,6 +4889,17 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                 branch_cycles: None,
             });
         }
+        // add sp, imm8
+        0xE8 => {
+            return Ok(Instruction {
+                op: LD_SP_PLUS_IMM8 {
+                    offset: i8::from_le_bytes([imm8]),
+                },
+                size: 2,
+                cycles: 16,
+                branch_cycles: None,
+            });
+        }
         // jp hl
         0xE9 => {
             return Ok(Instruction {
@@ -4718,11 +5077,13 @@ pub fn decode_instruction(console: &Gameboy, address: u16) -> Result<Instruction
                 branch_cycles: None,
             });
         }
-        _ => {
-            return Err(EmulationError {
-                ty: EmulationErrorType::UnhandledInstructionDecode(instr as u16),
-                pc: Some(address),
-            });
+        // https://gbdev.io/pandocs/CPU_Instruction_Set.html
+        // these opcodes don't exist on the sm83, which locks up when it runs into one
+        0xD3 | 0xDB | 0xDD | 0xE3 | 0xE4 | 0xEB | 0xEC | 0xED | 0xF4 | 0xFC | 0xFD => {
+            return Err(EmulationError::new(
+                EmulationErrorType::IllegalOpcode(instr),
+                Some(address),
+            ));
         }
     }
 }
@@ -4761,6 +5122,57 @@ impl fmt::Display for Operand {
     }
 }
 
+impl Instruction {
+    // the instruction name alone, conditional or not (e.g. "call" for both call & call nz)
+    pub fn mnemonic(&self) -> &'static str {
+        return match &self.op {
+            Operation::NOP => "nop",
+            Operation::LD { .. } => "ld",
+            Operation::JP { .. } | Operation::JP_CC { .. } => "jp",
+            Operation::JR { .. } | Operation::JR_CC { .. } => "jr",
+            Operation::CALL { .. } | Operation::CALL_CC { .. } => "call",
+            Operation::RST { .. } => "rst",
+            Operation::RET | Operation::RET_CC { .. } => "ret",
+            Operation::RETI => "reti",
+            Operation::PUSH { .. } => "push",
+            Operation::POP { .. } => "pop",
+            Operation::DEC { .. } => "dec",
+            Operation::INC { .. } => "inc",
+            Operation::ADD { .. } | Operation::LD_SP_PLUS_IMM8 { .. } => "add",
+            Operation::ADC { .. } => "adc",
+            Operation::SUB { .. } => "sub",
+            Operation::SBC { .. } => "sbc",
+            Operation::OR { .. } => "or",
+            Operation::XOR { .. } => "xor",
+            Operation::AND { .. } => "and",
+            Operation::BIT { .. } => "bit",
+            Operation::RES { .. } => "res",
+            Operation::SET { .. } => "set",
+            Operation::SWAP { .. } => "swap",
+            Operation::RL { .. } => "rl",
+            Operation::RR { .. } => "rr",
+            Operation::RLC { .. } => "rlc",
+            Operation::RRC { .. } => "rrc",
+            Operation::RLA => "rla",
+            Operation::RRA => "rra",
+            Operation::RLCA => "rlca",
+            Operation::RRCA => "rrca",
+            Operation::DAA => "daa",
+            Operation::SRL { .. } => "srl",
+            Operation::SRA { .. } => "sra",
+            Operation::SLA { .. } => "sla",
+            Operation::CP { .. } => "cp",
+            Operation::DI => "di",
+            Operation::EI => "ei",
+            Operation::CPL => "cpl",
+            Operation::SCF => "scf",
+            Operation::CCF => "ccf",
+            Operation::HALT => "halt",
+            Operation::STOP => "stop",
+        };
+    }
+}
+
 pub fn instruction_to_string(instr: &Instruction) -> String {
     match &instr.op {
         Operation::NOP => String::from("nop"),
@@ -4785,6 +5197,7 @@ pub fn instruction_to_string(instr: &Instruction) -> String {
         Operation::DEC { x } => format!("dec {x}"),
         Operation::INC { x } => format!("inc {x}"),
         Operation::ADD { x, y } => format!("add {x}, {y}"),
+        Operation::LD_SP_PLUS_IMM8 { offset } => format!("add sp, {offset}"),
         Operation::ADC { y } => format!("adc a, {y}"),
         Operation::SUB { y } => format!("sub a, {y}"),
         Operation::SBC { y } => format!("sbc a, {y}"),
@@ -4805,12 +5218,16 @@ pub fn instruction_to_string(instr: &Instruction) -> String {
         Operation::RRCA => String::from("rrca"),
         Operation::DAA => String::from("daa"),
         Operation::SRL { x } => format!("srl {x}"),
+        Operation::SRA { x } => format!("sra {x}"),
         Operation::SLA { x } => format!("sla {x}"),
         Operation::CP { y } => format!("cp {y}"),
         Operation::DI => String::from("di"),
         Operation::EI => String::from("ei"),
         Operation::CPL => String::from("cpl"),
+        Operation::SCF => String::from("scf"),
+        Operation::CCF => String::from("ccf"),
         Operation::HALT => String::from("halt"),
+        Operation::STOP => String::from("stop"),
     }
 }
 
@@ -4819,3 +5236,131 @@ impl fmt::Display for Instruction {
         write!(f, "{}", instruction_to_string(self))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // https://gbdev.io/pandocs/CPU_Instruction_Set.html, in t-cycles
+    // conditional instructions are listed with the cycles they take when they don't branch
+    // 0 marks the opcodes the cpu doesn't have
+    #[rustfmt::skip]
+    const CYCLES: [u64; 256] = [
+    //  x0  x1  x2  x3  x4  x5  x6  x7  x8  x9  xA  xB  xC  xD  xE  xF
+         4, 12,  8,  8,  4,  4,  8,  4, 20,  8,  8,  8,  4,  4,  8,  4, // 0x
+         4, 12,  8,  8,  4,  4,  8,  4, 12,  8,  8,  8,  4,  4,  8,  4, // 1x
+         8, 12,  8,  8,  4,  4,  8,  4,  8,  8,  8,  8,  4,  4,  8,  4, // 2x
+         8, 12,  8,  8, 12, 12, 12,  4,  8,  8,  8,  8,  4,  4,  8,  4, // 3x
+         4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4, // 4x
+         4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4, // 5x
+         4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4, // 6x
+         8,  8,  8,  8,  8,  8,  4,  8,  4,  4,  4,  4,  4,  4,  8,  4, // 7x
+         4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4, // 8x
+         4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4, // 9x
+         4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4, // Ax
+         4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4, // Bx
+         8, 12, 12, 16, 12, 16,  8, 16,  8, 16, 12,  4, 12, 24,  8, 16, // Cx
+         8, 12, 12,  0, 12, 16,  8, 16,  8, 16, 12,  0, 12,  0,  8, 16, // Dx
+        12, 12,  8,  0,  0, 16,  8, 16, 16,  4, 16,  0,  0,  0,  8, 16, // Ex
+        12, 12,  8,  4,  0, 16,  8, 16, 12,  8, 16,  4,  0,  0,  8, 16, // Fx
+    ];
+
+    #[rustfmt::skip]
+    const SIZES: [u16; 256] = [
+    //  x0  x1  x2  x3  x4  x5  x6  x7  x8  x9  xA  xB  xC  xD  xE  xF
+         1,  3,  1,  1,  1,  1,  2,  1,  3,  1,  1,  1,  1,  1,  2,  1, // 0x
+         2,  3,  1,  1,  1,  1,  2,  1,  2,  1,  1,  1,  1,  1,  2,  1, // 1x
+         2,  3,  1,  1,  1,  1,  2,  1,  2,  1,  1,  1,  1,  1,  2,  1, // 2x
+         2,  3,  1,  1,  1,  1,  2,  1,  2,  1,  1,  1,  1,  1,  2,  1, // 3x
+         1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1, // 4x
+         1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1, // 5x
+         1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1, // 6x
+         1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1, // 7x
+         1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1, // 8x
+         1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1, // 9x
+         1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1, // Ax
+         1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1,  1, // Bx
+         1,  1,  3,  3,  3,  1,  2,  1,  1,  1,  3,  2,  3,  3,  2,  1, // Cx
+         1,  1,  3,  0,  3,  1,  2,  1,  1,  1,  3,  0,  3,  0,  2,  1, // Dx
+         2,  1,  1,  0,  0,  1,  2,  1,  2,  1,  3,  0,  0,  0,  2,  1, // Ex
+         2,  1,  1,  1,  0,  1,  2,  1,  2,  1,  3,  1,  0,  0,  2,  1, // Fx
+    ];
+
+    // the cycles taken by the conditional instructions when they do branch
+    fn branch_cycles(opcode: u8) -> Option<u64> {
+        return match opcode {
+            0x20 | 0x28 | 0x30 | 0x38 => Some(12), // jr cc
+            0xC0 | 0xC8 | 0xD0 | 0xD8 => Some(20), // ret cc
+            0xC2 | 0xCA | 0xD2 | 0xDA => Some(16), // jp cc
+            0xC4 | 0xCC | 0xD4 | 0xDC => Some(24), // call cc
+            _ => None,
+        };
+    }
+
+    // the cb-prefixed instructions only differ by their operand
+    fn cb_cycles(opcode: u8) -> u64 {
+        return match (opcode, opcode & 0x07) {
+            (0x40..=0x7F, 0x06) => 12, // bit b, (hl)
+            (_, 0x06) => 16,
+            _ => 8,
+        };
+    }
+
+    fn console_with_opcodes() -> Gameboy {
+        let mut rom = vec![0; 0x8000];
+        for opcode in 0..=255u8 {
+            rom[0x1000 + opcode as usize * 4] = opcode;
+            rom[0x2000 + opcode as usize * 4] = 0xCB;
+            rom[0x2001 + opcode as usize * 4] = opcode;
+        }
+        return Gameboy::new(rom).unwrap();
+    }
+
+    #[test]
+    fn opcode_timings() {
+        let console = console_with_opcodes();
+
+        for opcode in 0..=255u8 {
+            let address = 0x1000 + opcode as u16 * 4;
+            match (opcode, decode_instruction(&console, address)) {
+                // the prefix is covered below
+                (0xCB, _) => {}
+                (_, Err(e)) => {
+                    assert!(
+                        CYCLES[opcode as usize] == 0
+                            && matches!(e.ty, EmulationErrorType::IllegalOpcode(o) if o == opcode),
+                        "{opcode:#04X} : {e:?}"
+                    );
+                }
+                (_, Ok(instruction)) => {
+                    assert_eq!(
+                        (
+                            instruction.size,
+                            instruction.cycles,
+                            instruction.branch_cycles
+                        ),
+                        (
+                            SIZES[opcode as usize],
+                            CYCLES[opcode as usize],
+                            branch_cycles(opcode)
+                        ),
+                        "{opcode:#04X} ({instruction})"
+                    );
+                }
+            }
+
+            let address = 0x2000 + opcode as u16 * 4;
+            let instruction = decode_instruction(&console, address)
+                .unwrap_or_else(|e| panic!("0xCB{opcode:02X} : {e:?}"));
+            assert_eq!(
+                (
+                    instruction.size,
+                    instruction.cycles,
+                    instruction.branch_cycles
+                ),
+                (2, cb_cycles(opcode), None),
+                "0xCB{opcode:02X} ({instruction})"
+            );
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::{collections::BTreeMap, io::Write, path::Path};
+
+use crate::{decoding::decode_instruction, gameboy::Gameboy};
+
+// offline listing of a whole rom, bank after bank
+// it is linear : everything is decoded as code from the start of each bank, data included,
+// except for what doesn't decode and the padding between routines
+
+// runs of the same filler byte at least this long are skipped
+const PADDING_MIN_LEN: u16 = 16;
+
+// labels from a rgbds style .sym file, by (bank, address)
+pub type Symbols = BTreeMap<(u8, u16), String>;
+
+// https://rgbds.gbdev.io/sym/
+// "01:4000 Label" lines, ';' starts a comment
+pub fn load_symbols<P: AsRef<Path>>(path: P) -> std::io::Result<Symbols> {
+    let text = std::fs::read_to_string(path)?;
+    let mut symbols = Symbols::new();
+    for line in text.lines() {
+        let line = line.split(';').next().unwrap_or("").trim();
+        let Some((location, label)) = line.split_once(char::is_whitespace) else {
+            continue;
+        };
+        let Some((bank, address)) = location.split_once(':') else {
+            continue;
+        };
+        if let (Ok(bank), Ok(address)) = (
+            u8::from_str_radix(bank, 16),
+            u16::from_str_radix(address, 16),
+        ) {
+            symbols.insert((bank, address), label.trim().to_string());
+        }
+    }
+
+    return Ok(symbols);
+}
+
+// writes the listing of every rom bank to `out`
+// the console's rom bank & boot rom mapping are changed along the way
+pub fn write_disassembly<W: Write>(
+    console: &mut Gameboy,
+    symbols: &Symbols,
+    out: &mut W,
+) -> std::io::Result<()> {
+    // the cartridge is hidden behind the boot rom until it is unmapped
+    let _ = console.memory_mut().write_byte(0xFF50, 0x01);
+
+    let banks = console.memory().rom_bank_count();
+    for bank in 0..banks {
+        let (start, end) = if bank == 0 {
+            (0x0000u32, 0x4000u32)
+        } else {
+            console.memory_mut().force_rom_bank(bank);
+            (0x4000, 0x8000)
+        };
+        writeln!(out, "; bank {bank:02X}")?;
+
+        let mut address = start;
+        while address < end {
+            let pc = address as u16;
+            if let Some(label) = symbols.get(&(bank as u8, pc)) {
+                writeln!(out, "{label}:")?;
+            }
+
+            let padding = padding_len(console, pc, end);
+            if padding >= PADDING_MIN_LEN as u32 {
+                writeln!(
+                    out,
+                    "; {padding} bytes of {:#04X} skipped",
+                    console.memory().read_byte(pc)
+                )?;
+                address += padding;
+                continue;
+            }
+
+            match decode_instruction(console, pc) {
+                // an instruction can't run past the end of its bank
+                Ok(instr) if address + instr.size as u32 <= end => {
+                    writeln!(out, "{bank:02X}:{pc:04X}  {instr}")?;
+                    address += instr.size as u32;
+                }
+                _ => {
+                    writeln!(
+                        out,
+                        "{bank:02X}:{pc:04X}  db {:#04X}",
+                        console.memory().read_byte(pc)
+                    )?;
+                    address += 1;
+                }
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+// length of the run of 0x00 or 0xFF starting at `address`, 0 for other bytes
+fn padding_len(console: &Gameboy, address: u16, end: u32) -> u32 {
+    let byte = console.memory().read_byte(address);
+    if byte != 0x00 && byte != 0xFF {
+        return 0;
+    }
+
+    return (address as u32..end)
+        .take_while(|&a| console.memory().read_byte(a as u16) == byte)
+        .count() as u32;
+}
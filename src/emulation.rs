@@ -0,0 +1,377 @@
+use std::{
+    io::BufRead,
+    path::PathBuf,
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError},
+        Arc, Mutex, MutexGuard,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use log::warn;
+
+use crate::{
+    debugger::Debugger,
+    decoding::{decode_next_instruction, Operation},
+    error::EmulationError,
+    gameboy::Gameboy,
+    input::{GBInputState, TurboState},
+    pacer::{Pacer, GAMEBOY_FPS},
+    perf::Phase,
+    snapshot::Snapshot,
+};
+
+const DOTS_IN_FRAME: u64 = 70224;
+
+// what a frontend can ask the emulation thread
+pub enum EmulationCommand {
+    Pause,
+    Continue,
+    // executes a single instruction while paused
+    Step,
+    // same as step, but runs called procedures until they return
+    StepOver,
+    // power cycles the console with the same rom
+    Reset,
+    // after an error : skips the failing instruction and resumes
+    SkipInstruction,
+    // writes a save state to the file, or restores the console from it
+    SaveState(PathBuf),
+    LoadState(PathBuf),
+    AddBreakpoint(u16),
+    SetInput(GBInputState),
+    SetTurbo(TurboState),
+    // runs faster than the hardware, see SetFastForwardSpeed
+    SetFastForward(bool),
+    // in multiples of the normal speed, 0 disables the frame limiter
+    SetFastForwardSpeed(u32),
+    // frames per second at normal speed, the real console's by default
+    SetTargetFps(f64),
+    // time the frontend spent presenting the last frame, for the timing statistics
+    RenderTime(Duration),
+    // a line typed in the terminal debugger
+    Debugger(String),
+    Quit,
+}
+
+// what the emulation thread tells the frontends
+pub enum EmulationEvent {
+    // a new frame can be read with `EmulationThread::frame`
+    FrameReady,
+    // the emulation is paused, or stepped while paused
+    Paused { pc: u16, breakpoint: bool },
+    Resumed,
+    // the emulation stopped on an error and is paused until the instruction is skipped
+    // or the console is reset, with a report of what happened
+    Error(String),
+}
+
+// owns the console and runs it at the speed of the real hardware
+// frontends only talk to it through commands and events, and read the last frame
+pub struct EmulationThread {
+    commands: Sender<EmulationCommand>,
+    events: Receiver<EmulationEvent>,
+    // last completed frame : the console draws into its own framebuffer
+    // and copies it here once the frame is done
+    frame: Arc<Mutex<Vec<u8>>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl EmulationThread {
+    // `rom` is kept to reset the console
+    pub fn spawn(console: Gameboy, debugger: Debugger, rom: Vec<u8>) -> EmulationThread {
+        let (commands_sender, commands) = mpsc::channel();
+        let (events_sender, events) = mpsc::channel();
+        let frame = Arc::new(Mutex::new(console.get_framebuffer().to_vec()));
+
+        // the terminal debugger is just another client
+        let stdin_commands = commands_sender.clone();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if stdin_commands
+                    .send(EmulationCommand::Debugger(line))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        let mut core = Core {
+            console,
+            debugger,
+            rom,
+            commands,
+            events: events_sender,
+            frame: frame.clone(),
+            dots: 0,
+            fast_forward: false,
+            fast_forward_speed: 0,
+            pacer: Pacer::new(GAMEBOY_FPS),
+            failed: None,
+        };
+        let handle = std::thread::spawn(move || core.run());
+
+        return EmulationThread {
+            commands: commands_sender,
+            events,
+            frame,
+            handle: Some(handle),
+        };
+    }
+
+    pub fn send(&self, command: EmulationCommand) {
+        // the thread only stops after an error, which was reported as an event
+        let _ = self.commands.send(command);
+    }
+
+    // waits up to `timeout` for the next event
+    // returns none on timeout or once the thread is done
+    pub fn next_event(&self, timeout: Duration) -> Option<EmulationEvent> {
+        return match self.events.recv_timeout(timeout) {
+            Ok(event) => Some(event),
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => None,
+        };
+    }
+
+    pub fn try_next_event(&self) -> Option<EmulationEvent> {
+        return self.events.try_recv().ok();
+    }
+
+    pub fn frame(&self) -> MutexGuard<'_, Vec<u8>> {
+        return self.frame.lock().unwrap();
+    }
+}
+
+impl Drop for EmulationThread {
+    fn drop(&mut self) {
+        self.send(EmulationCommand::Quit);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+// the state living on the emulation thread
+struct Core {
+    console: Gameboy,
+    debugger: Debugger,
+    rom: Vec<u8>,
+    commands: Receiver<EmulationCommand>,
+    events: Sender<EmulationEvent>,
+    frame: Arc<Mutex<Vec<u8>>>,
+    // progress into the current frame
+    dots: u64,
+    fast_forward: bool,
+    fast_forward_speed: u32,
+    pacer: Pacer,
+    // the error the emulation is stopped on, if any
+    failed: Option<EmulationError>,
+}
+
+impl Core {
+    fn run(&mut self) {
+        if self.debugger.is_paused() {
+            self.debugger.print_prompt(&self.console);
+        }
+
+        loop {
+            match self.handle_commands() {
+                Ok(true) => {}
+                Ok(false) => return,
+                Err(e) => {
+                    self.report_error(e);
+                    continue;
+                }
+            }
+
+            if let Err(e) = self.run_frame() {
+                self.report_error(e);
+                continue;
+            }
+
+            if self.debugger.is_paused() {
+                // stopped mid-frame, the rest of it runs once resumed
+                self.send_paused();
+                self.debugger.print_prompt(&self.console);
+                continue;
+            }
+
+            self.dots -= DOTS_IN_FRAME;
+            self.frame
+                .lock()
+                .unwrap()
+                .copy_from_slice(self.console.get_framebuffer());
+            let _ = self.events.send(EmulationEvent::FrameReady);
+
+            // a fast forward speed of 0 is unlimited
+            let speed = if self.fast_forward {
+                self.fast_forward_speed
+            } else {
+                1
+            };
+            self.pacer.wait(speed);
+            self.console.perf_stats_mut().end_frame();
+        }
+    }
+
+    // runs until the end of the frame, or until the debugger pauses
+    fn run_frame(&mut self) -> Result<(), EmulationError> {
+        while self.dots < DOTS_IN_FRAME && !self.debugger.is_paused() {
+            self.dots += self.debugger.step(&mut self.console)?;
+        }
+
+        return Ok(());
+    }
+
+    // applies the pending commands, waiting for them while paused
+    // returns false when the thread should stop
+    fn handle_commands(&mut self) -> Result<bool, EmulationError> {
+        loop {
+            let command = if self.debugger.is_paused() {
+                match self.commands.recv() {
+                    Ok(command) => command,
+                    Err(_) => return Ok(false),
+                }
+            } else {
+                match self.commands.try_recv() {
+                    Ok(command) => command,
+                    Err(TryRecvError::Empty) => return Ok(true),
+                    Err(TryRecvError::Disconnected) => return Ok(false),
+                }
+            };
+
+            // the terminal debugger waits for its next command after these
+            let prompts = matches!(
+                command,
+                EmulationCommand::Pause
+                    | EmulationCommand::Step
+                    | EmulationCommand::StepOver
+                    | EmulationCommand::Debugger(_)
+            );
+            let was_paused = self.debugger.is_paused();
+            let pc_before = self.console.cpu().read_program_counter();
+            match command {
+                EmulationCommand::Pause => self.debugger.pause(),
+                EmulationCommand::Continue => self.debugger.resume(),
+                EmulationCommand::Step => {
+                    if self.debugger.is_paused() {
+                        self.dots += self.debugger.run_command(&mut self.console, "step")?;
+                    }
+                }
+                EmulationCommand::StepOver => {
+                    if self.debugger.is_paused() {
+                        self.step_over()?;
+                    }
+                }
+                EmulationCommand::Reset => {
+                    if let Err(e) = self.console.reset(self.rom.clone()) {
+                        warn!("COULD NOT RESET THE CONSOLE ({e})");
+                    }
+                    self.dots = 0;
+                    if self.failed.take().is_some() {
+                        self.debugger.resume();
+                    }
+                }
+                EmulationCommand::SkipInstruction => {
+                    if let Some(error) = self.failed.take() {
+                        self.dots += self.console.recover_from_error(&error);
+                        self.debugger.resume();
+                    }
+                }
+                EmulationCommand::SaveState(path) => {
+                    match Snapshot::from(&self.console).save(&path) {
+                        Ok(()) => println!("State saved to {}", path.display()),
+                        Err(e) => println!("Error : {e}"),
+                    }
+                }
+                EmulationCommand::LoadState(path) => match Snapshot::load(&path)
+                    .and_then(|snapshot| self.console.load_snapshot(snapshot))
+                {
+                    Ok(()) => {
+                        self.dots = 0;
+                        // shown right away, even while paused
+                        self.frame
+                            .lock()
+                            .unwrap()
+                            .copy_from_slice(self.console.get_framebuffer());
+                        let _ = self.events.send(EmulationEvent::FrameReady);
+                        println!("State loaded from {}", path.display());
+                    }
+                    Err(e) => println!("Error : {e}"),
+                },
+                EmulationCommand::AddBreakpoint(address) => {
+                    self.debugger.add_breakpoint(address);
+                }
+                EmulationCommand::SetInput(state) => self.console.set_input_state(state),
+                EmulationCommand::SetTurbo(state) => self.console.set_turbo_state(state),
+                EmulationCommand::SetFastForward(enabled) => self.fast_forward = enabled,
+                EmulationCommand::SetFastForwardSpeed(speed) => self.fast_forward_speed = speed,
+                EmulationCommand::SetTargetFps(fps) => self.pacer.set_target_fps(fps),
+                EmulationCommand::RenderTime(duration) => {
+                    self.console.perf_stats_mut().add(Phase::Render, duration);
+                }
+                EmulationCommand::Debugger(line) => {
+                    self.dots += self.debugger.run_command(&mut self.console, &line)?;
+                }
+                EmulationCommand::Quit => return Ok(false),
+            }
+
+            let pc = self.console.cpu().read_program_counter();
+            match (was_paused, self.debugger.is_paused()) {
+                (false, true) => self.send_paused(),
+                (true, true) if pc != pc_before => self.send_paused(),
+                (true, false) => {
+                    let _ = self.events.send(EmulationEvent::Resumed);
+                }
+                _ => {}
+            }
+
+            if prompts && self.debugger.is_paused() {
+                self.debugger.print_prompt(&self.console);
+            }
+        }
+    }
+
+    fn step_over(&mut self) -> Result<(), EmulationError> {
+        let pc = self.console.cpu().read_program_counter();
+        let instr = decode_next_instruction(&self.console)?;
+        match instr.op {
+            Operation::CALL { .. } | Operation::CALL_CC { .. } | Operation::RST { .. } => {
+                // the emulation loop takes it from here
+                self.debugger.run_to(pc.wrapping_add(instr.size));
+            }
+            _ => {
+                self.dots += self.debugger.run_command(&mut self.console, "step")?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    // pauses on the error and tells the frontends, which decide what to do next
+    fn report_error(&mut self, error: EmulationError) {
+        let error = error.with_context(&self.console);
+        let mut report = error.to_string();
+        report.push_str("\nLast executed instructions :");
+        for (pc, instruction) in self.debugger.last_instructions(10) {
+            report.push_str(&format!("\n  {pc:#06X} | {instruction}"));
+        }
+
+        self.debugger.pause();
+        self.failed = Some(error);
+        let _ = self.events.send(EmulationEvent::Error(report));
+        self.debugger.print_prompt(&self.console);
+    }
+
+    fn send_paused(&self) {
+        let _ = self.events.send(EmulationEvent::Paused {
+            pc: self.console.cpu().read_program_counter(),
+            breakpoint: self.debugger.is_at_breakpoint(&self.console),
+        });
+    }
+}
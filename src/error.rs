@@ -1,20 +1,76 @@
 use std::fmt::Display;
 
-use crate::decoding::Instruction;
+use crate::{
+    decoding::{self, Instruction},
+    gameboy::Gameboy,
+};
 
 #[derive(Debug)]
 pub struct EmulationError {
     pub ty: EmulationErrorType,
     pub pc: Option<u16>,
+    // the state of the console when it happened, see `with_context`
+    pub context: Option<ErrorContext>,
+}
+
+#[derive(Debug)]
+pub struct ErrorContext {
+    // the instruction at pc, if it could be decoded
+    pub instruction: Option<String>,
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+    pub sp: u16,
+    pub pc: u16,
 }
 
 #[derive(Debug)]
 pub enum EmulationErrorType {
     UnhandledInstructionDecode(u16),
+    // an opcode the cpu doesn't have, as opposed to one the emulator doesn't know yet
+    IllegalOpcode(u8),
     UnhandledInstructionExec(Instruction),
+    // an instruction with an operand it can't take, e.g. "LD : illegal pointer (SP)"
+    IllegalOperand(String),
     UnauthorizedWrite(u16),
 }
 
+impl EmulationError {
+    pub fn new(ty: EmulationErrorType, pc: Option<u16>) -> EmulationError {
+        return EmulationError {
+            ty,
+            pc,
+            context: None,
+        };
+    }
+
+    // the errors are raised deep in the core, which doesn't know much about the console
+    // whoever reports them can add the instruction & registers, so the message is actionable
+    pub fn with_context(mut self, console: &Gameboy) -> EmulationError {
+        let cpu = console.cpu();
+        let instruction = match &self.ty {
+            // already in the message
+            EmulationErrorType::UnhandledInstructionExec(_) => None,
+            _ => self
+                .pc
+                .and_then(|pc| decoding::decode_instruction(console, pc).ok())
+                .map(|instr| decoding::instruction_to_string(&instr)),
+        };
+        self.context = Some(ErrorContext {
+            instruction,
+            af: u16::from_be_bytes([cpu.read_a_register(), cpu.read_f_register()]),
+            bc: cpu.read_bc_register(),
+            de: cpu.read_de_register(),
+            hl: cpu.read_hl_register(),
+            sp: cpu.read_stack_pointer(),
+            pc: cpu.read_program_counter(),
+        });
+
+        return self;
+    }
+}
+
 impl Display for EmulationError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -29,8 +85,12 @@ impl Display for EmulationError {
                         format!("{opcode:#04X}")
                     }
                 ),
+                EmulationErrorType::IllegalOpcode(opcode) =>
+                    format!("Illegal opcode {opcode:#04X} (the cpu would lock up)"),
                 EmulationErrorType::UnhandledInstructionExec(instr) =>
                     format!("Unhandled instruction during execution : {}", instr),
+                EmulationErrorType::IllegalOperand(message) =>
+                    format!("Illegal operand during execution : {message}"),
                 EmulationErrorType::UnauthorizedWrite(address) =>
                     format!("Unauthorized write (Address : {:#06X})", address),
             },
@@ -38,6 +98,130 @@ impl Display for EmulationError {
                 Some(pc) => format!("at PC {:#06X}", pc),
                 None => "".to_string(),
             }
-        )
+        )?;
+
+        if let Some(context) = &self.context {
+            if let Some(instruction) = &context.instruction {
+                write!(f, "\n  Instruction : {instruction}")?;
+            }
+            write!(
+                f,
+                "\n  Registers : AF {:#06X} BC {:#06X} DE {:#06X} HL {:#06X} SP {:#06X} PC {:#06X}",
+                context.af, context.bc, context.de, context.hl, context.sp, context.pc
+            )?;
+        }
+
+        return Ok(());
+    }
+}
+
+impl std::error::Error for EmulationError {}
+
+// problems with the rom file, before any emulation happens
+#[derive(Debug)]
+pub enum RomError {
+    NotFound(String),
+    Unreadable(String, std::io::Error),
+    TooSmall(usize),
+    // size of the rom, memory bank controller, biggest rom it can address
+    TooLarge(usize, &'static str, usize),
+    // the cartridge type byte of the header
+    UnsupportedMbc(u8),
+}
+
+impl Display for RomError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RomError::NotFound(path) => write!(f, "ROM file not found : {path}"),
+            RomError::Unreadable(path, e) => write!(f, "Could not read ROM file {path} ({e})"),
+            RomError::TooSmall(size) => write!(f, "ROM too small to be valid ({size} bytes)"),
+            RomError::TooLarge(size, mbc, max) => write!(
+                f,
+                "ROM too large for its {mbc} ({}KiB, at most {}KiB)",
+                size / 0x400,
+                max / 0x400
+            ),
+            RomError::UnsupportedMbc(byte) => write!(
+                f,
+                "Cartridge type not supported yet : {} ({byte:#04X})",
+                crate::memory::cartridge_type_name(*byte)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+// problems reading or writing a save state file
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    Format(serde_json::Error),
+    // the file was written by a version of the emulator with a different layout
+    Version(u64),
+    // a memory region doesn't have the size of the one it is restored to
+    Size(&'static str, usize),
+    // the checksums of the rom the snapshot was taken with and of the loaded one
+    RomMismatch(u16, u16),
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "Could not access save state file ({e})"),
+            SnapshotError::Format(e) => write!(f, "Invalid save state file ({e})"),
+            SnapshotError::Version(version) => write!(
+                f,
+                "Unsupported save state version : {version} (expected {})",
+                crate::snapshot::SNAPSHOT_VERSION
+            ),
+            SnapshotError::Size(region, size) => {
+                write!(f, "Invalid save state : wrong {region} size ({size} bytes)")
+            }
+            SnapshotError::RomMismatch(expected, found) => write!(
+                f,
+                "Save state was made with another ROM (checksum {expected:#06X}, loaded ROM has {found:#06X})"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(e: std::io::Error) -> Self {
+        return SnapshotError::Io(e);
+    }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(e: serde_json::Error) -> Self {
+        return SnapshotError::Format(e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_message_has_the_instruction() {
+        let mut rom = vec![0; 0x8000];
+        // LD A, 0x42
+        rom[0x0100..0x0102].copy_from_slice(&[0x3E, 0x42]);
+        let mut console = Gameboy::new(rom).unwrap();
+        console.cpu_mut().write_program_counter(0x0100);
+        console.cpu_mut().write_hl_register(0xBEEF);
+
+        let error =
+            EmulationError::new(EmulationErrorType::UnauthorizedWrite(0x1234), Some(0x0100))
+                .with_context(&console);
+        let message = error.to_string();
+        assert!(
+            message.contains("Unauthorized write (Address : 0x1234) at PC 0x0100"),
+            "{message}"
+        );
+        assert!(message.contains("Instruction : ld a, 0x42"), "{message}");
+        assert!(message.contains("HL 0xBEEF"), "{message}");
     }
 }
@@ -0,0 +1,267 @@
+use crate::{decoding::Operand, gameboy::Gameboy};
+
+// small expressions for the debugger, e.g. "a", "[C0A0]" or "[hl+2]"
+// numbers are hexadecimal like everywhere else in the debugger, and register names
+// come first : "a" is the register, "0A" the number
+#[derive(Debug)]
+pub enum Expression {
+    Constant(u16),
+    Register(Operand),
+    ProgramCounter,
+    // the byte at the address
+    Memory(Box<Expression>),
+    Add(Box<Expression>, Box<Expression>),
+    Sub(Box<Expression>, Box<Expression>),
+}
+
+// how a value is shown
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Hex,
+    Decimal,
+    Binary,
+}
+
+pub fn parse_register(name: &str) -> Option<Operand> {
+    return match name {
+        "a" => Some(Operand::R8_A),
+        "b" => Some(Operand::R8_B),
+        "c" => Some(Operand::R8_C),
+        "d" => Some(Operand::R8_D),
+        "e" => Some(Operand::R8_E),
+        "h" => Some(Operand::R8_H),
+        "l" => Some(Operand::R8_L),
+        "af" => Some(Operand::R16_AF),
+        "bc" => Some(Operand::R16_BC),
+        "de" => Some(Operand::R16_DE),
+        "hl" => Some(Operand::R16_HL),
+        "sp" => Some(Operand::R16_SP),
+        _ => None,
+    };
+}
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Expression, String> {
+        let tokens = tokenize(&text.to_lowercase())?;
+        let mut position = 0;
+        let expression = parse_sum(&tokens, &mut position)?;
+        if let Some(token) = tokens.get(position) {
+            return Err(format!("unexpected \"{token}\" in \"{text}\""));
+        }
+
+        return Ok(expression);
+    }
+
+    // additions & subtractions wrap around, like addresses do
+    pub fn evaluate(&self, console: &Gameboy) -> u16 {
+        return match self {
+            Expression::Constant(value) => *value,
+            Expression::Register(register) if self.is_byte() => {
+                console.cpu().read_r8(register) as u16
+            }
+            Expression::Register(register) => console.cpu().read_r16(register),
+            Expression::ProgramCounter => console.cpu().read_program_counter(),
+            Expression::Memory(address) => {
+                console.memory().read_byte(address.evaluate(console)) as u16
+            }
+            Expression::Add(left, right) => {
+                left.evaluate(console).wrapping_add(right.evaluate(console))
+            }
+            Expression::Sub(left, right) => {
+                left.evaluate(console).wrapping_sub(right.evaluate(console))
+            }
+        };
+    }
+
+    // 8 bit registers & memory reads, for the formatting
+    pub fn is_byte(&self) -> bool {
+        return match self {
+            Expression::Register(register) => matches!(
+                register,
+                Operand::R8_A
+                    | Operand::R8_B
+                    | Operand::R8_C
+                    | Operand::R8_D
+                    | Operand::R8_E
+                    | Operand::R8_H
+                    | Operand::R8_L
+            ),
+            Expression::Memory(_) => true,
+            _ => false,
+        };
+    }
+}
+
+impl Format {
+    // "/d" & "/b" select decimal & binary, hexadecimal is the default
+    pub fn parse(text: &str) -> Option<Format> {
+        return match text {
+            "x" => Some(Format::Hex),
+            "d" => Some(Format::Decimal),
+            "b" => Some(Format::Binary),
+            _ => None,
+        };
+    }
+
+    pub fn format(&self, value: u16, is_byte: bool) -> String {
+        return match (self, is_byte) {
+            (Format::Hex, true) => format!("{value:#04X}"),
+            (Format::Hex, false) => format!("{value:#06X}"),
+            (Format::Decimal, _) => format!("{value}"),
+            (Format::Binary, true) => format!("{value:#010b}"),
+            (Format::Binary, false) => format!("{value:#018b}"),
+        };
+    }
+}
+
+impl std::fmt::Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expression::Constant(value) => write!(f, "{value:X}"),
+            Expression::Register(register) => {
+                write!(f, "{}", format!("{register}").to_lowercase())
+            }
+            Expression::ProgramCounter => write!(f, "pc"),
+            Expression::Memory(address) => write!(f, "[{address}]"),
+            Expression::Add(left, right) => write!(f, "{left}+{right}"),
+            Expression::Sub(left, right) => write!(f, "{left}-{right}"),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut word = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            word.push(c);
+            continue;
+        }
+
+        if !word.is_empty() {
+            tokens.push(std::mem::take(&mut word));
+        }
+        match c {
+            '[' | ']' | '+' | '-' => tokens.push(c.to_string()),
+            c if c.is_whitespace() => {}
+            c => return Err(format!("unexpected character '{c}'")),
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+
+    return Ok(tokens);
+}
+
+// sum := operand (('+' | '-') operand)*
+fn parse_sum(tokens: &[String], position: &mut usize) -> Result<Expression, String> {
+    let mut expression = parse_operand(tokens, position)?;
+    while let Some(operator) = tokens.get(*position).filter(|t| *t == "+" || *t == "-") {
+        let is_add = operator == "+";
+        *position += 1;
+        let right = parse_operand(tokens, position)?;
+        expression = if is_add {
+            Expression::Add(Box::new(expression), Box::new(right))
+        } else {
+            Expression::Sub(Box::new(expression), Box::new(right))
+        };
+    }
+
+    return Ok(expression);
+}
+
+// operand := '[' sum ']' | register | number
+fn parse_operand(tokens: &[String], position: &mut usize) -> Result<Expression, String> {
+    let Some(token) = tokens.get(*position) else {
+        return Err("unexpected end of expression".to_string());
+    };
+    *position += 1;
+
+    if token == "[" {
+        let address = parse_sum(tokens, position)?;
+        if tokens.get(*position).map(String::as_str) != Some("]") {
+            return Err("missing \"]\"".to_string());
+        }
+        *position += 1;
+        return Ok(Expression::Memory(Box::new(address)));
+    }
+
+    if token == "pc" {
+        return Ok(Expression::ProgramCounter);
+    }
+    if let Some(register) = parse_register(token) {
+        return Ok(Expression::Register(register));
+    }
+
+    return match u16::from_str_radix(token, 16) {
+        Ok(value) => Ok(Expression::Constant(value)),
+        Err(_) => Err(format!("unknown register or number \"{token}\"")),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_display() {
+        for (text, shown) in [
+            ("a", "a"),
+            ("HL", "hl"),
+            ("pc", "pc"),
+            ("0a", "A"),
+            ("[C0A0]", "[C0A0]"),
+            ("[hl + 2]", "[hl+2]"),
+            ("sp-2+[de]", "sp-2+[de]"),
+            ("[[ff80]]", "[[FF80]]"),
+        ] {
+            let expression = Expression::parse(text).unwrap();
+            assert_eq!(expression.to_string(), shown, "{text}");
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        for text in ["", "[hl", "hl]", "a+", "xyz", "10000", "a*2", "a b"] {
+            assert!(Expression::parse(text).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn evaluate() {
+        let mut console = Gameboy::new(vec![0; 0x8000]).unwrap();
+        console.memory_mut().skip_boot_rom();
+        console.cpu_mut().write_r8(&Operand::R8_A, 0x12);
+        console.cpu_mut().write_r16(&Operand::R16_HL, 0xC0A0);
+        console.memory_mut().write_byte(0xC0A2, 0x34).unwrap();
+        console.memory_mut().write_byte(0xC034, 0x56).unwrap();
+
+        let value = |text: &str| Expression::parse(text).unwrap().evaluate(&console);
+        assert_eq!(value("a"), 0x12);
+        assert_eq!(value("hl"), 0xC0A0);
+        assert_eq!(value("[hl+2]"), 0x34);
+        assert_eq!(value("[C000+[C0A2]]"), 0x56);
+        assert_eq!(value("a-13"), 0xFFFF);
+        assert_eq!(value("hl+FFFF"), 0xC09F);
+
+        assert!(Expression::parse("a").unwrap().is_byte());
+        assert!(Expression::parse("[hl]").unwrap().is_byte());
+        assert!(!Expression::parse("hl").unwrap().is_byte());
+        assert!(!Expression::parse("a+1").unwrap().is_byte());
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(Format::parse("x"), Some(Format::Hex));
+        assert_eq!(Format::parse("d"), Some(Format::Decimal));
+        assert_eq!(Format::parse("b"), Some(Format::Binary));
+        assert_eq!(Format::parse("o"), None);
+
+        assert_eq!(Format::Hex.format(0x0A, true), "0x0A");
+        assert_eq!(Format::Hex.format(0x0A, false), "0x000A");
+        assert_eq!(Format::Decimal.format(0x0A, true), "10");
+        assert_eq!(Format::Binary.format(0x0A, true), "0b00001010");
+        assert_eq!(Format::Binary.format(0x0A, false), "0b0000000000001010");
+    }
+}
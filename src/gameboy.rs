@@ -1,27 +1,133 @@
-use core::panic;
+use std::cell::Cell;
+
 use log::{debug, info, warn};
 
 use crate::{
+    clock::{Clock, SystemClock},
     cpu::CPU,
     decoding::{self, Instruction, Operand, Operation},
-    error::{EmulationError, EmulationErrorType},
-    input::GBInputState,
+    error::{EmulationError, EmulationErrorType, RomError, SnapshotError},
+    input::{Button, GBInputState, TurboState},
     memory::Memory,
+    oam::{self, OamEntry, OAM_ENTRIES},
+    perf::{PerfStats, Phase},
+    serial::SerialDevice,
+    snapshot::{MemorySnapshot, Snapshot, SNAPSHOT_VERSION},
 };
 
 const SCREEN_W: usize = 160;
 const SCREEN_H: usize = 144;
 const BYTES_PER_PIXELS: usize = 4; // rgba_u8
 const TEXTURES_W: usize = 256;
+// number of cycles LY reads 153 for before reading 0
+const LY_153_CYCLES: u64 = 4;
+// how long a transfer driven by the internal clock waits for the other side's answer,
+// about a quarter of a second : past that the line reads as high
+const SERIAL_REPLY_TIMEOUT: u64 = 1 << 20;
+// by priority, i.e. by bit in IE & IF
+const INTERRUPT_NAMES: [&str; 5] = ["VBLANK", "LCD", "TIMER", "SERIAL", "JOYPAD"];
+// the four shades of the screen, from the lightest to the darkest
+pub const SHADES: [[u8; BYTES_PER_PIXELS]; 4] = [
+    [250, 251, 246, /* alpha */ 255],
+    [198, 183, 190, /* alpha */ 255],
+    [86, 90, 117, /* alpha */ 255],
+    [15, 15, 27, /* alpha */ 255],
+];
+
+// the registers the background is drawn with, as they were when a line was drawn
+// games change them mid-frame for split screens, parallax or wobble effects
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LineRegisters {
+    pub scx: u8,
+    pub scy: u8,
+    pub lcdc: u8,
+    pub bgp: u8,
+    pub wx: u8,
+}
+
+// where a pixel of the screen was drawn from, to go from the screen to the vram
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PixelSource {
+    // drawn from the window rather than the background
+    pub window: bool,
+    // the entry of the tile map holding the tile's index
+    pub map_address: u16,
+    pub tile_index: u8,
+    // the first of the tile's 16 bytes
+    pub tile_address: u16,
+    // color (0-3) of the pixel in the tile, before the palette
+    pub color: u8,
+    // the oam entry drawn over the background, if any
+    pub object: Option<usize>,
+}
+
+// https://gbdev.io/pandocs/OAM_Corruption_Bug.html
+// the three ways an access to FE00-FEFF during the OAM scan mixes up the rows of OAM
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum OamCorruption {
+    Write,
+    Read,
+    ReadDuringIncrease,
+}
 
+// determinism : given the same rom and the same inputs applied at the same steps,
+// the emulation must always produce the same state, framebuffers and serial output.
+// this is what input movies, rewind and netplay will rely on.
+// nothing in here may depend on wall-clock time, randomness or iteration order of hashed
+// collections : the time of day (e.g. for the MBC3 real time clock) comes from the
+// injected `Clock`, which can be frozen. the only exceptions are external by nature and opt-in :
+// - a serial device can answer whatever & whenever it wants (e.g. the tcp link)
+// - the perf stats read the wall clock, but never feed back into the emulation
 pub struct Gameboy {
     cpu: CPU,
     memory: Memory,
     // keeps track of cycles elapsed to update various registers
-    div_cycles: u64,  // DIV TIMER
-    ly_cycles: u64,   // LINE Y
-    tima_cycles: u64, // MAIN TIMER
+    ly_cycles: u64,     // LINE Y
+    tima_cycles: u64,   // MAIN TIMER
+    serial_cycles: u64, // SERIAL TRANSFER
+    // cycles spent waiting for the serial device to answer the byte sent, if it is late
+    serial_reply_wait: Option<u64>,
+    // cycles ticked since the start of the current step
+    step_cycles: u64,
+    // the same in dots, which are only half as many as cycles in double speed mode
+    step_dots: u64,
+    // the STAT interrupt sources are or-ed into a single line
+    stat_line: bool,
+    // to blank the screen once when the lcd is turned off
+    lcd_was_enabled: bool,
+    // https://gbdev.io/pandocs/Tile_Maps.html#window
+    // the window has its own line counter, which only moves on lines where it was drawn :
+    // hiding it for a few lines and showing it again resumes where it left off
+    window_line: u8,
+    // https://gbdev.io/pandocs/Scrolling.html#ff4aff4b--wy-wx-window-y-position-x-position-plus-7
+    // set once LY has matched WY during the frame : from then on the window shows on every
+    // line it is enabled on, whatever WY becomes
+    window_y_triggered: bool,
     halted: bool,
+    // when set, errors skip the offending instruction instead of stopping the emulation
+    error_recovery: bool,
+    // when set, 16-bit inc/dec, push & pop on FE00-FEFF during the OAM scan corrupt OAM
+    // off by default : only a few demos & accuracy tests rely on it
+    oam_corruption: bool,
+    // address the cpu accessed outside of HRAM during an OAM DMA on the last step, if any
+    // only HRAM is reachable during a DMA on hardware
+    dma_conflict: Cell<Option<u16>>,
+    // buttons currently held, applied to the joypad register every step
+    input_state: GBInputState,
+    // held buttons that get released every other frame
+    turbo_state: TurboState,
+    // number of v-blanks since power on
+    frame_count: u64,
+    // whatever is plugged into the link port, if anything
+    serial_device: Option<Box<dyn SerialDevice>>,
+    // the time of day, for the cartridge hardware that keeps track of it
+    clock: Box<dyn Clock>,
+    // time spent in each part of the emulation, when enabled
+    perf: PerfStats,
+    // the registers of each line, lines past the current one are from the previous frame
+    line_registers: Box<[LineRegisters; SCREEN_H]>,
+    // the line of the window drawn on each line, if it was drawn
+    window_lines: Box<[Option<u8>; SCREEN_H]>,
     // rendering
     tile_atlas: Box<[u8; TEXTURES_W * TEXTURES_W * BYTES_PER_PIXELS]>, // used for objects to sample
     tilemap: Box<[u8; TEXTURES_W * TEXTURES_W * BYTES_PER_PIXELS]>, // a particular arrangement of tiles used as background
@@ -30,20 +136,90 @@ pub struct Gameboy {
 
 impl Gameboy {
     // constructor
-    pub fn new(rom: Vec<u8>) -> Gameboy {
+    pub fn new(rom: Vec<u8>) -> Result<Gameboy, RomError> {
         let mut mem = Memory::new();
-        mem.load_rom(rom);
-        return Gameboy {
-            cpu: CPU::blank(),
+        mem.load_rom(rom)?;
+        // the dmg boot rom would hand over to a cgb game as if it ran on a dmg
+        let cpu = if mem.is_cgb_mode() {
+            mem.skip_boot_rom();
+            CPU::blank_cgb()
+        } else {
+            CPU::blank()
+        };
+        return Ok(Gameboy {
+            cpu,
             memory: mem,
-            div_cycles: 0,
             ly_cycles: 0,
             tima_cycles: 0,
+            serial_cycles: 0,
+            serial_reply_wait: None,
+            step_cycles: 0,
+            step_dots: 0,
+            stat_line: false,
+            // the screen starts blank
+            lcd_was_enabled: true,
+            window_line: 0,
+            window_y_triggered: false,
             halted: false,
+            error_recovery: false,
+            oam_corruption: false,
+            dma_conflict: Cell::new(None),
+            input_state: GBInputState::default(),
+            turbo_state: TurboState::default(),
+            frame_count: 0,
+            serial_device: None,
+            clock: Box::new(SystemClock),
+            perf: PerfStats::new(),
+            line_registers: Box::new([LineRegisters::default(); SCREEN_H]),
+            window_lines: Box::new([None; SCREEN_H]),
             tile_atlas: Box::new([0; TEXTURES_W * TEXTURES_W * BYTES_PER_PIXELS]),
             tilemap: Box::new([0; TEXTURES_W * TEXTURES_W * BYTES_PER_PIXELS]),
             framebuffer: Box::new([0; SCREEN_W * SCREEN_H * BYTES_PER_PIXELS]),
-        };
+        });
+    }
+
+    pub fn from_file(path: &str) -> Result<Gameboy, RomError> {
+        return Gameboy::new(read_rom_file(path)?);
+    }
+
+    // power cycles the console with `rom` inserted
+    // whatever is plugged into the link port, the clock and the emulator settings are kept
+    pub fn reset(&mut self, rom: Vec<u8>) -> Result<(), RomError> {
+        let mut fresh = Gameboy::new(rom)?;
+        self.abandon_serial_transfer();
+        fresh.serial_device = self.serial_device.take();
+        fresh.clock = std::mem::replace(&mut self.clock, Box::new(SystemClock));
+        fresh.error_recovery = self.error_recovery;
+        fresh.oam_corruption = self.oam_corruption;
+        fresh.turbo_state = self.turbo_state;
+        fresh.perf = std::mem::replace(&mut self.perf, PerfStats::new());
+        for channel in 0..4 {
+            fresh.set_channel_enabled(channel, self.is_channel_enabled(channel));
+        }
+
+        *self = fresh;
+        return Ok(());
+    }
+
+    // restores a save state, keeping the link port device, the clock & the emulator settings like reset
+    // the state has to come from the rom that is loaded, which it doesn't include
+    pub fn load_snapshot(&mut self, snapshot: Snapshot) -> Result<(), SnapshotError> {
+        snapshot.check_rom(self.memory.rom_checksum())?;
+        let mut restored = Gameboy::from(snapshot);
+        restored.memory.restore_rom(&mut self.memory);
+        self.abandon_serial_transfer();
+        restored.serial_device = self.serial_device.take();
+        restored.clock = std::mem::replace(&mut self.clock, Box::new(SystemClock));
+        restored.error_recovery = self.error_recovery;
+        restored.oam_corruption = self.oam_corruption;
+        restored.turbo_state = self.turbo_state;
+        restored.perf = std::mem::replace(&mut self.perf, PerfStats::new());
+        for channel in 0..4 {
+            restored.set_channel_enabled(channel, self.is_channel_enabled(channel));
+        }
+
+        *self = restored;
+        return Ok(());
     }
 
     // accessors to watch values
@@ -51,14 +227,98 @@ impl Gameboy {
         return &self.cpu;
     }
 
+    // for the debugger, to patch registers
+    pub fn cpu_mut(&mut self) -> &mut CPU {
+        return &mut self.cpu;
+    }
+
     pub fn memory(&self) -> &Memory {
         return &self.memory;
     }
 
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        return &mut self.memory;
+    }
+
+    // see Memory::peek_vram
+    pub fn peek_vram(&self, address: u16) -> u8 {
+        return self.memory.peek_vram(address);
+    }
+
+    pub fn dma_conflict(&self) -> Option<u16> {
+        return self.dma_conflict.get();
+    }
+
+    pub fn perf_stats(&self) -> &PerfStats {
+        return &self.perf;
+    }
+
+    pub fn perf_stats_mut(&mut self) -> &mut PerfStats {
+        return &mut self.perf;
+    }
+
+    pub fn is_halted(&self) -> bool {
+        return self.halted;
+    }
+
+    // IME, shown next to the registers : an EI waiting for the next instruction doesn't count yet
+    pub fn interrupts_enabled(&self) -> bool {
+        return self.cpu.interrupts_enabled();
+    }
+
+    // halted with every interrupt disabled : nothing can ever wake the cpu up
+    pub fn is_locked_up(&self) -> bool {
+        return self.halted && !self.memory.any_interrupt_enabled();
+    }
+
+    pub fn connect_serial_device(&mut self, device: Box<dyn SerialDevice>) {
+        self.abandon_serial_transfer();
+        self.serial_device = Some(device);
+    }
+
+    // the host's clock by default, a `ManualClock` makes runs reproducible
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        return self.clock.as_ref();
+    }
+
+    pub fn set_error_recovery(&mut self, enabled: bool) {
+        self.error_recovery = enabled;
+    }
+
+    pub fn set_oam_corruption(&mut self, enabled: bool) {
+        self.oam_corruption = enabled;
+    }
+
+    // mutes or unmutes an audio channel, from 0 (pulse 1) to 3 (noise)
+    // this is independent from the enables the game sets through NR52 & co.
+    pub fn set_channel_enabled(&mut self, channel: usize, enabled: bool) {
+        self.memory.apu_mut().set_channel_enabled(channel, enabled);
+    }
+
+    pub fn is_channel_enabled(&self, channel: usize) -> bool {
+        return self.memory.apu().is_channel_enabled(channel);
+    }
+
     // functions
 
+    // the hardware keeps running while an instruction executes : its memory accesses
+    // tick the timers, ppu, serial port & dma as they happen, so they see the state of
+    // the console at that point of the instruction
+    // the remaining cycles (internal delays) are ticked once the instruction is done
+    // returns the time elapsed in dots, the ppu clock : t-cycles at normal speed
     pub fn step(&mut self) -> Result<u64, EmulationError> {
+        self.step_cycles = 0;
+        self.step_dots = 0;
         let cycles_elapsed;
+        // an EI executed during this step must wait for the next instruction
+        let ime_was_scheduled = self.cpu.is_ime_scheduled();
+        // the rtc of the cartridge follows the time of day, read from the clock before
+        // the instruction so that it can latch it
+        self.memory.update_rtc(self.clock.as_ref());
 
         if self.halted {
             // FIXME : handle this better
@@ -69,32 +329,176 @@ impl Gameboy {
 
             cycles_elapsed = 4;
         } else {
-            let instr = decoding::decode_next_instruction(&self)?;
-            let pc_before = self.cpu.read_program_counter();
-
-            cycles_elapsed = self.execute_instruction(instr).map_err(|mut e| {
-                // some errors that happen for example in memory access
-                // can't know the instruction that called them
-                // so we attach that info here
-                if e.pc.is_none() {
-                    e.pc = Some(pc_before);
-                }
-                e
-            })?;
+            cycles_elapsed = match self.run_next_instruction() {
+                Err(e) if self.error_recovery => self.recover_from_error(&e),
+                result => result?,
+            };
+        }
+
+        self.tick(cycles_elapsed.saturating_sub(self.step_cycles));
+
+        if ime_was_scheduled {
+            self.cpu.apply_scheduled_ime();
         }
 
+        self.apply_input();
         self.handle_interrupts()?;
+
+        return Ok(self.step_dots);
+    }
+
+    // t-cycles of the cpu the last step took, twice its dots in double speed mode
+    pub fn last_step_cycles(&self) -> u64 {
+        return self.step_cycles;
+    }
+
+    // executes whole instructions until at least `budget` cycles have elapsed,
+    // for embedders driving the console from their own loop
+    // returns the cycles actually elapsed, which can go past the budget by one instruction
+    pub fn step_cycles(&mut self, budget: u64) -> Result<u64, EmulationError> {
+        let mut cycles = 0;
+        while cycles < budget {
+            self.step()?;
+            cycles += self.step_cycles;
+        }
+
+        return Ok(cycles);
+    }
+
+    // lets the rest of the hardware catch up with the cpu
+    // in double speed mode the timers, divider & serial port follow the cpu,
+    // but the ppu keeps its pace
+    fn tick(&mut self, cycles: u64) {
+        let dots = if self.memory.is_double_speed() {
+            cycles / 2
+        } else {
+            cycles
+        };
+        self.step_cycles += cycles;
+        self.step_dots += dots;
+        self.memory.advance_div(cycles);
+        self.tima_cycles += cycles;
+        self.serial_cycles += cycles;
+        self.memory.advance_dma(cycles);
+
         self.update_misc();
+        self.tick_ppu(dots);
+        self.tick_vram_dma();
+    }
 
-        // update timing infos
-        self.div_cycles += cycles_elapsed;
-        self.ly_cycles += cycles_elapsed;
-        self.tima_cycles += cycles_elapsed;
+    // the cpu is halted while a vram dma copies its data
+    fn tick_vram_dma(&mut self) {
+        let stall = self.memory.take_vram_dma_cycles();
+        if stall > 0 {
+            self.tick(stall);
+        }
+    }
+
+    // decodes and executes the instruction at pc
+    fn run_next_instruction(&mut self) -> Result<u64, EmulationError> {
+        let sample = self.perf.start();
+        let instr = decoding::decode_next_instruction(&self)?;
+        self.perf.record(Phase::Decode, sample);
+
+        // fetching the opcode & its operands takes one memory access per byte
+        self.tick(instr.size as u64 * 4);
+
+        let pc_before = self.cpu.read_program_counter();
+        self.dma_conflict.set(None);
+        self.memory.record_execute(pc_before);
+
+        let sample = self.perf.start();
+        let cycles_elapsed = self.execute_instruction(instr).map_err(|mut e| {
+            // some errors that happen for example in memory access
+            // can't know the instruction that called them
+            // so we attach that info here
+            if e.pc.is_none() {
+                e.pc = Some(pc_before);
+            }
+            e
+        })?;
+        self.perf.record(Phase::Execute, sample);
+
+        if let Some(address) = self.dma_conflict.get() {
+            warn!(
+                "ACCESS TO {:#06X} DURING OAM DMA (PC {:#06X})",
+                address, pc_before
+            );
+        }
 
         return Ok(cycles_elapsed);
     }
 
-    pub fn update_input(&mut self, input_state: &GBInputState) {
+    // runs until pc reaches `target` or at least `max_cycles` cycles have elapsed,
+    // without going through the debugger : meant for tests & scripts
+    // returns true if the target was reached
+    pub fn run_until_pc(&mut self, target: u16, max_cycles: u64) -> Result<bool, EmulationError> {
+        let mut cycles = 0;
+        while self.cpu.read_program_counter() != target {
+            if cycles >= max_cycles {
+                return Ok(false);
+            }
+            self.step()?;
+            cycles += self.step_cycles;
+        }
+
+        return Ok(true);
+    }
+
+    // logs the error and moves on to the next instruction
+    // returns the cycles elapsed
+    pub fn recover_from_error(&mut self, error: &EmulationError) -> u64 {
+        warn!("SKIPPING INSTRUCTION AFTER ERROR : {error}");
+
+        // execution errors happen after pc has been moved past the instruction,
+        // but decoding errors leave it on the opcode
+        match &error.ty {
+            EmulationErrorType::UnhandledInstructionDecode(opcode) => {
+                let size = if *opcode > 0xFF { 2 } else { 1 };
+                self.cpu.increment_program_counter(size);
+            }
+            EmulationErrorType::IllegalOpcode(_) => self.cpu.increment_program_counter(1),
+            _ => {}
+        }
+
+        return 4;
+    }
+
+    // number of v-blanks since power on
+    pub fn frame_count(&self) -> u64 {
+        return self.frame_count;
+    }
+
+    pub fn set_input_state(&mut self, state: GBInputState) {
+        self.input_state = state;
+    }
+
+    pub fn set_turbo_state(&mut self, state: TurboState) {
+        self.turbo_state = state;
+    }
+
+    pub fn press_button(&mut self, button: Button) {
+        self.input_state.set_button(button, true);
+    }
+
+    pub fn release_button(&mut self, button: Button) {
+        self.input_state.set_button(button, false);
+    }
+
+    // translates the held buttons into the joypad register bits,
+    // depending on which group the game currently selects
+    fn apply_input(&mut self) {
+        let mut input_state = self.input_state;
+        // turbo buttons are held for one frame, released for the next
+        if self.frame_count % 2 == 1 {
+            if self.turbo_state.a_turbo {
+                input_state.a = false;
+            }
+            if self.turbo_state.b_turbo {
+                input_state.b = false;
+            }
+        }
+
         let buttons = self.memory.input_buttons_selected();
         let dpad = self.memory.input_dpad_selected();
 
@@ -129,49 +533,40 @@ impl Gameboy {
             }
         }
 
+        // https://gbdev.io/pandocs/Interrupt_Sources.html#int-60--joypad-interrupt
+        // requested when a selected line goes from high to low, not for as long as it stays low
+        let previous = self.memory.read_input_lower();
         self.memory.update_input_lower(bits);
-        if bits < 0x0F {
+        if previous & !bits != 0 {
             self.memory.request_interrupt(4);
         }
     }
 
+    // https://gbdev.io/pandocs/Interrupts.html
+    // a single interrupt is serviced at a time : the pending one with the highest priority
+    // (the lowest bit), with IME cleared on the way. the others stay requested in IF
+    // and wait for the handler to enable interrupts again (RETI or EI)
     fn handle_interrupts(&mut self) -> Result<(), EmulationError> {
         if !self.cpu().interrupts_enabled() {
             return Ok(());
         }
 
-        // interrupts are priority-based, so we need to check in order
-        // VBLANK
-        if self.memory.is_interrupt_enabled(0) && self.memory.is_interrupt_requested(0) {
-            debug!("VBLANK INTERRUPT");
-            self.cpu.disable_interrupts();
-            self.memory.clear_interrupt(0);
-            self.push_word(self.cpu.read_program_counter())?;
-            self.cpu.write_program_counter(0x40);
-        }
-        // LCD
-        else if self.memory.is_interrupt_enabled(1) && self.memory.is_interrupt_requested(1) {
-            panic!("LCD INTERRUPT REQUESTED AND ENABLED");
-        }
-        // TIMER
-        else if self.memory.is_interrupt_enabled(2) && self.memory.is_interrupt_requested(2) {
-            info!("TIMER INTERRUPT");
-            self.cpu.disable_interrupts();
-            self.memory.clear_interrupt(2);
-            self.push_word(self.cpu.read_program_counter())?;
-            self.cpu.write_program_counter(0x50);
+        let Some(interrupt) = (0..5u8).find(|&interrupt| {
+            self.memory.is_interrupt_enabled(interrupt)
+                && self.memory.is_interrupt_requested(interrupt)
+        }) else {
+            return Ok(());
+        };
 
-            // FIXME : Interrupts should take a lot more time to execute
-            // https://gbdev.io/pandocs/Interrupts.html
-        }
-        // SERIAL
-        else if self.memory.is_interrupt_enabled(3) && self.memory.is_interrupt_requested(3) {
-            panic!("SERIAL INTERRUPT REQUESTED AND ENABLED");
-        }
-        // JOYPAD
-        else if self.memory.is_interrupt_enabled(4) && self.memory.is_interrupt_requested(4) {
-            panic!("JOYPAD INTERRUPT REQUESTED AND ENABLED");
-        }
+        debug!("{} INTERRUPT", INTERRUPT_NAMES[interrupt as usize]);
+        self.cpu.disable_interrupts();
+        self.memory.clear_interrupt(interrupt);
+        self.push_word(self.cpu.read_program_counter())?;
+        // VBLANK 0x40, LCD 0x48, TIMER 0x50, SERIAL 0x58, JOYPAD 0x60
+        self.cpu.write_program_counter(0x40 + 8 * interrupt as u16);
+
+        // FIXME : Interrupts should take a lot more time to execute
+        // https://gbdev.io/pandocs/Interrupts.html
 
         return Ok(());
     }
@@ -198,51 +593,397 @@ impl Gameboy {
             }
         }
 
-        // DIV register
-        if self.div_cycles >= 256 {
-            self.div_cycles -= 256;
-            self.memory.increment_div();
+        // serial transfer
+        if !self.memory.is_serial_transfer_requested() || !self.memory.is_serial_clock_internal() {
+            // the transfer was cancelled by the game
+            self.abandon_serial_transfer();
         }
+        if self.memory.is_serial_transfer_requested() {
+            if let Some(waited) = self.serial_reply_wait {
+                // the byte went out, the answer is late : no need to check more than once per bit
+                if self.serial_cycles >= 512 {
+                    let waited = waited + self.serial_cycles;
+                    self.serial_cycles = 0;
+                    let reply = self.serial_device.as_mut().and_then(|d| d.poll_transfer());
+                    if let Some(incoming) = reply {
+                        self.serial_reply_wait = None;
+                        self.memory.complete_serial_transfer(incoming);
+                    } else if waited >= SERIAL_REPLY_TIMEOUT {
+                        warn!("SERIAL : NO ANSWER FROM THE OTHER SIDE, TRANSFER TIMED OUT");
+                        self.abandon_serial_transfer();
+                        self.memory.complete_serial_transfer(0xFF);
+                    } else {
+                        self.serial_reply_wait = Some(waited);
+                    }
+                }
+            } else if self.memory.is_serial_clock_internal() {
+                // the internal clock shifts 8 bits at 8192 Hz
+                if self.serial_cycles >= 512 * 8 {
+                    self.serial_cycles = 0;
+
+                    let outgoing = self.memory.read_serial_data();
+                    let incoming = match &mut self.serial_device {
+                        Some(device) => device.transfer(outgoing),
+                        // nothing plugged in : the line reads as high
+                        None => Some(0xFF),
+                    };
+                    match incoming {
+                        Some(incoming) => self.memory.complete_serial_transfer(incoming),
+                        // SC bit 7 stays set until the answer to this very byte arrives
+                        None => self.serial_reply_wait = Some(0),
+                    }
+                }
+            } else if self.serial_cycles >= 512 {
+                // the other side drives the clock, so we can only wait for it
+                // no need to check more often than once per bit
+                self.serial_cycles = 0;
+
+                let outgoing = self.memory.read_serial_data();
+                if let Some(incoming) = self
+                    .serial_device
+                    .as_mut()
+                    .and_then(|device| device.poll_external(outgoing))
+                {
+                    self.memory.complete_serial_transfer(incoming);
+                }
+            }
+        } else {
+            self.serial_cycles = 0;
+        }
+    }
 
-        // LY register
+    // the serial device must not take a late answer for the next byte
+    fn abandon_serial_transfer(&mut self) {
+        if self.serial_reply_wait.take().is_some() {
+            if let Some(device) = &mut self.serial_device {
+                device.abandon_transfer();
+            }
+        }
+    }
+
+    // instructions take up to 24 dots, and a vram dma stalls for much longer : the dots are
+    // handed to the ppu one mode at a time, so that no line, mode change or stat interrupt
+    // is skipped when a single call goes past several of them
+    fn tick_ppu(&mut self, dots: u64) {
+        let mut dots = dots;
+        loop {
+            let step = dots.min(self.dots_to_next_ppu_event());
+            self.ly_cycles += step;
+            dots -= step;
+            self.update_ppu();
+            if dots == 0 {
+                return;
+            }
+        }
+    }
+
+    // dots until the next mode change, line or early LY wrap
+    fn dots_to_next_ppu_event(&self) -> u64 {
         if !self.memory.is_lcd_enabled() {
-            self.ly_cycles = 0
+            return u64::MAX;
+        }
+
+        // 0 is never ahead, it stands for no early wrap on the other lines
+        let ly_wrap = if self.memory.ppu_line() == 153 {
+            LY_153_CYCLES
+        } else {
+            0
         };
+        return [ly_wrap, 80, 80 + self.mode_3_length(), 80 + 172 + 204]
+            .into_iter()
+            .filter(|&event| event > self.ly_cycles)
+            .min()
+            .map_or(1, |event| event - self.ly_cycles);
+    }
+
+    fn update_ppu(&mut self) {
+        // LY register
+        // https://gbdev.io/pandocs/LCDC.html#lcdc7--lcd-enable
+        // while the lcd is off LY stays at 0 and the screen is blank,
+        // turning it back on starts a new frame from line 0
+        let lcd_enabled = self.memory.is_lcd_enabled();
+        if !lcd_enabled {
+            self.ly_cycles = 0;
+            self.memory.reset_ly();
+            if self.lcd_was_enabled {
+                self.framebuffer
+                    .chunks_exact_mut(BYTES_PER_PIXELS)
+                    .for_each(|pixel| pixel.copy_from_slice(&SHADES[0]));
+            }
+        }
+        self.lcd_was_enabled = lcd_enabled;
         if self.ly_cycles >= (80 + 172 + 204) {
             self.ly_cycles -= 80 + 172 + 204;
 
             self.memory.increment_ly();
+
+            let sample = self.perf.start();
             self.draw_current_line();
+            self.perf.record(Phase::Ppu, sample);
 
-            if self.memory.read_byte(0xFF44) == 144 {
+            if self.memory.ppu_line() == 144 {
                 // V-BLANK INTERRUPT
                 self.memory.request_interrupt(0);
+                self.frame_count += 1;
             }
         }
 
+        // https://gbdev.io/pandocs/STAT.html#ff44--ly-lcd-y-coordinate-read-only
+        // LY only reads 153 for the first cycles of line 153
+        if self.memory.ppu_line() == 153 && self.ly_cycles >= LY_153_CYCLES {
+            self.memory.wrap_ly_early();
+        }
+
         // LY - LYC compare : https://gbdev.io/pandocs/STAT.html#ff45--lyc-ly-compare
         let ly = self.memory.read_byte(0xFF44);
         let lyc = self.memory.read_byte(0xFF45);
         self.memory.update_lcd_stat_lcy_eq_ly(ly == lyc);
-        // FIXME : update the PPU mode too : https://gbdev.io/pandocs/STAT.html#ff41--stat-lcd-status
+
+        // PPU mode : https://gbdev.io/pandocs/STAT.html#ff41--stat-lcd-status
+        let mode = if !lcd_enabled {
+            0
+        } else if self.memory.ppu_line() >= 144 {
+            1 // V-BLANK
+        } else if self.ly_cycles < 80 {
+            2 // OAM SCAN
+        } else if self.ly_cycles < 80 + self.mode_3_length() {
+            3 // DRAWING
+        } else {
+            0 // H-BLANK
+        };
+        let previous_mode = self.memory.read_byte(0xFF41) & 0b11;
+        self.memory.update_lcd_stat_ppu_mode(mode);
+        if previous_mode == 3 && mode == 0 {
+            self.memory.hblank_dma_step();
+        }
+
+        // STAT interrupt : https://gbdev.io/pandocs/Interrupt_Sources.html#int-48--stat-interrupt
+        // the interrupt is only requested when the shared line goes from low to high,
+        // so while one source holds it up the others can't trigger it ("STAT blocking")
+        let stat = self.memory.read_byte(0xFF41);
+        let stat_line = lcd_enabled
+            && ((stat & 0b0100_0000 != 0 && ly == lyc)
+                || (stat & 0b0010_0000 != 0 && mode == 2)
+                || (stat & 0b0001_0000 != 0 && mode == 1)
+                || (stat & 0b0000_1000 != 0 && mode == 0));
+        if stat_line && !self.stat_line {
+            self.memory.request_interrupt(1);
+        }
+        self.stat_line = stat_line;
+    }
+
+    // https://gbdev.io/pandocs/Rendering.html#mode-3-length
+    // the ppu throws away the first SCX % 8 pixels it fetches, which takes as many dots
+    // the line keeps its length : h-blank is shorter by as much
+    fn mode_3_length(&self) -> u64 {
+        let (scroll_x, _) = self.memory.read_scrolling_registers();
+        return 172 + (scroll_x % 8) as u64;
     }
 
     pub fn get_framebuffer(&self) -> &[u8] {
         return &(*self.framebuffer);
     }
 
+    // the pixels of a single line of the screen, in the same rgba layout as the framebuffer
+    pub fn get_scanline_buffer(&self, line: u8) -> &[u8; SCREEN_W * BYTES_PER_PIXELS] {
+        assert!(
+            (line as usize) < SCREEN_H,
+            "Line {line} is outside the screen !"
+        );
+        let start = line as usize * SCREEN_W * BYTES_PER_PIXELS;
+        return self.framebuffer[start..(start + SCREEN_W * BYTES_PER_PIXELS)]
+            .try_into()
+            .unwrap();
+    }
+
+    // what the registers were when each line of the screen was last drawn
+    pub fn line_registers(&self) -> &[LineRegisters; SCREEN_H] {
+        return &self.line_registers;
+    }
+
+    // what is under the pixel (x, y) of the screen, none outside of it
+    // the background is looked up with the registers of that line, but the window position
+    // & the oam are the current ones : they may have moved since the line was drawn
+    pub fn inspect_pixel(&self, x: usize, y: usize) -> Option<PixelSource> {
+        if x >= SCREEN_W || y >= SCREEN_H {
+            return None;
+        }
+
+        // the same selection & priority as when drawing the line
+        // https://gbdev.io/pandocs/LCDC.html#lcdc2--obj-size
+        let height = if self.line_registers[y].lcdc & 0b0000_0100 != 0 {
+            16
+        } else {
+            8
+        };
+        let object = self
+            .visible_object(&self.line_objects(y, height), x, y, height)
+            .map(|(index, _)| index);
+
+        return Some(PixelSource {
+            object,
+            ..self.background_source(x, y)
+        });
+    }
+
+    // where the background or window pixel at (x, y) of the screen comes from,
+    // with the registers recorded when its line was drawn
+    fn background_source(&self, x: usize, y: usize) -> PixelSource {
+        // https://gbdev.io/pandocs/LCDC.html
+        let registers = self.line_registers[y];
+        // the window line counter, rather than y - WY : the window may have been hidden
+        // on some lines, or WY changed during the frame
+        let window_line = self.window_lines[y].filter(|_| x + 7 >= registers.wx as usize);
+        let window = window_line.is_some();
+        let (map_address, tile_x, tile_y) = if let Some(window_line) = window_line {
+            let map = if registers.lcdc & 0b0100_0000 != 0 {
+                0x9C00
+            } else {
+                0x9800
+            };
+            background_map_address(
+                map,
+                (x + 7 - registers.wx as usize) as u8,
+                window_line,
+                0,
+                0,
+            )
+        } else {
+            let map = if registers.lcdc & 0b0000_1000 != 0 {
+                0x9C00
+            } else {
+                0x9800
+            };
+            background_map_address(map, x as u8, y as u8, registers.scx, registers.scy)
+        };
+
+        // https://gbdev.io/pandocs/Tile_Data.html
+        let tile_index = self.memory.peek_vram(map_address);
+        let tile_address = if registers.lcdc & 0b0001_0000 != 0 {
+            0x8000 + tile_index as u16 * 16
+        } else {
+            (0x9000 + tile_index as i8 as i32 * 16) as u16
+        };
+        let row = tile_address + tile_y as u16 * 2;
+        let color = decode_tile_line(self.memory.peek_vram(row), self.memory.peek_vram(row + 1))
+            [tile_x as usize];
+
+        return PixelSource {
+            window,
+            map_address,
+            tile_index,
+            tile_address,
+            color,
+            object: None,
+        };
+    }
+
+    // https://gbdev.io/pandocs/OAM.html#selection-priority
+    // only the first 10 objects intersecting the line are drawn
+    // y_pos is between -16 and SCREEN_H : sprites can be outside the screen
+    // placing a sprite outside the screen (leaving the x & y pos bytes to 0) is actually
+    // how you're meant to "disable" it being dsrawn
+    // they are returned from the highest drawing priority to the lowest
+    fn line_objects(&self, line: usize, height: isize) -> Vec<(usize, OamEntry)> {
+        let mut objects: Vec<(usize, OamEntry)> = self
+            .oam_entries()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, obj)| {
+                (obj.screen_y()..(obj.screen_y() + height)).contains(&(line as isize))
+            })
+            .take(10)
+            .collect();
+
+        // https://gbdev.io/pandocs/OAM.html#drawing-priority
+        // otherwise the first in oam wins, which is already the order of the entries
+        if self.memory.is_object_priority_by_x() {
+            // the smallest x wins, then the first in oam
+            objects.sort_by_key(|(index, obj)| (obj.x, *index));
+        }
+
+        return objects;
+    }
+
+    // the object drawn at (x, y) : the first one by priority whose pixel isn't transparent,
+    // unless the background is drawn over it, in which case the objects behind don't show either
+    fn visible_object(
+        &self,
+        objects: &[(usize, OamEntry)],
+        x: usize,
+        y: usize,
+        height: isize,
+    ) -> Option<(usize, OamEntry)> {
+        let (index, obj) = objects.iter().copied().find(|(_, obj)| {
+            if !(obj.screen_x()..(obj.screen_x() + 8)).contains(&(x as isize)) {
+                return false;
+            }
+            let (tile, row, column) = object_tile_pixel(obj, x, y, height);
+            let address = 0x8000 + tile as u16 * 16 + row as u16 * 2;
+            // color 0 is transparent for objects
+            return decode_tile_line(
+                self.memory.peek_vram(address),
+                self.memory.peek_vram(address + 1),
+            )[column]
+                != 0;
+        })?;
+
+        // https://gbdev.io/pandocs/Tile_Maps.html#bg-to-obj-priority-in-cgb-mode
+        // only the background & window colors 1-3 can be drawn over an object
+        let background = self.background_source(x, y);
+        if background.color == 0 {
+            return Some((index, obj));
+        }
+        if self.memory.is_cgb_mode() {
+            // LCDC bit 0 clear puts the objects over everything
+            if self.line_registers[y].lcdc & 0b0000_0001 == 0 {
+                return Some((index, obj));
+            }
+            // the tile's attributes can take the priority whatever the object says
+            if self.memory.peek_tile_attributes(background.map_address) & 0b1000_0000 != 0 {
+                return None;
+            }
+        }
+        if obj.priority() {
+            return None;
+        }
+
+        return Some((index, obj));
+    }
+
     fn draw_current_line(&mut self) {
-        let line: usize = self.memory.read_byte(0xFF44) as usize;
+        let line: usize = self.memory.ppu_line() as usize;
         if line >= SCREEN_H {
             //v-blank period
             return;
         }
 
+        self.line_registers[line] = LineRegisters {
+            scx: self.memory.read_byte(0xFF43),
+            scy: self.memory.read_byte(0xFF42),
+            lcdc: self.memory.read_byte(0xFF40),
+            bgp: self.memory.read_byte(0xFF47),
+            wx: self.memory.read_byte(0xFF4B),
+        };
+        self.window_lines[line] = None;
+
+        if line == 0 {
+            // a new frame, the window starts over from its first line
+            self.window_line = 0;
+            self.window_y_triggered = false;
+        }
+        // the comparison happens on every line, even with the window disabled
+        let (window_x, window_y) = self.memory.read_window_position_registers();
+        if line == window_y {
+            self.window_y_triggered = true;
+        }
+
         if !self.memory.is_lcd_enabled() {
             // screen turned off
             self.framebuffer[(line * SCREEN_W * BYTES_PER_PIXELS)
                 ..(line * SCREEN_W * BYTES_PER_PIXELS + SCREEN_W * BYTES_PER_PIXELS)]
-                .copy_from_slice(&[30; SCREEN_W * BYTES_PER_PIXELS]);
+                .chunks_exact_mut(BYTES_PER_PIXELS)
+                .for_each(|pixel| pixel.copy_from_slice(&SHADES[0]));
             return;
         }
 
@@ -261,6 +1002,7 @@ impl Gameboy {
         let (scroll_x, scroll_y) = self.memory.read_scrolling_registers();
 
         let tilemap_y = (line + scroll_y) % 256;
+        // scrolling is per pixel : with SCX % 8 != 0, the first tile of the line is cut
         for screen_x in 0..160 {
             let tilemap_x: usize = (screen_x + scroll_x) % 256;
 
@@ -274,45 +1016,74 @@ impl Gameboy {
                 self.tilemap[(tilemap_y * 256 + tilemap_x) * BYTES_PER_PIXELS + 3];
         }
 
+        // then the window over it
+        // https://gbdev.io/pandocs/Window.html
+        if self.memory.is_window_enabled() && self.window_y_triggered && window_x < SCREEN_W + 7 {
+            let tile_map: u16 = if self.memory.is_window_tile_map_high() {
+                0x9C00
+            } else {
+                0x9800
+            };
+            let window_line = self.window_line as usize;
+            self.window_lines[line] = Some(self.window_line);
+
+            for screen_x in window_x.saturating_sub(7)..SCREEN_W {
+                let x = screen_x + 7 - window_x;
+                let index = self
+                    .memory
+                    .peek_vram(tile_map + ((window_line / 8) * 32 + x / 8) as u16);
+                let tile = self.atlas_tile(index);
+
+                let pixel_start_framebuffer = (line * SCREEN_W + screen_x) * BYTES_PER_PIXELS;
+                let pixel_start_tile_atlas = ((tile / 32) * (TEXTURES_W * 8)
+                    + (tile % 32) * 8
+                    + (window_line % 8) * TEXTURES_W
+                    + x % 8)
+                    * BYTES_PER_PIXELS;
+                self.framebuffer
+                    [pixel_start_framebuffer..(pixel_start_framebuffer + BYTES_PER_PIXELS)]
+                    .copy_from_slice(
+                        &self.tile_atlas
+                            [pixel_start_tile_atlas..(pixel_start_tile_atlas + BYTES_PER_PIXELS)],
+                    );
+            }
+
+            self.window_line += 1;
+        }
+
         // then we can draw the objects
-        for obj in 0..40 {
-            // the stored value is actually the screen y position + 16
-            // y_pos is between -16 and SCREEN_H : sprites can be outside the screen
-            // placing a sprite outside the screen (leaving the x & y pos bytes to 0) is actually
-            // how you're meant to "disable" it being dsrawn
-            let y_pos = self.memory.read_byte(0xFE00 + obj * 4) as isize - 16;
-
-            // if the current line doesn't intersect the sprite, don't bother trying to draw it
-            if !(y_pos..(y_pos + 8)).contains(&(line as isize)) {
-                continue;
-            }
-
-            // same thing for x_pos: it is between -8 and SCREEN_W
-            let x_pos = self.memory.read_byte(0xFE00 + obj * 4 + 1) as isize - 8;
-            let sprite_id = self.memory.read_byte(0xFE00 + obj * 4 + 2);
-
-            for x_pxl in 0..8 {
-                // sprites can be half-outside and half-inside the screen
-                if x_pos + (x_pxl as isize) >= 0 {
-                    let pixel_start_framebuffer =
-                        (line * SCREEN_W + (x_pos as usize) + x_pxl) * BYTES_PER_PIXELS;
-                    let pixel_start_tile_atlas = ((sprite_id as usize / 32) * (TEXTURES_W * 8)
-                        + (sprite_id as usize % 32) * 8
-                        + x_pxl
-                        + ((line - y_pos as usize) * TEXTURES_W))
-                        * BYTES_PER_PIXELS;
-
-                    self.framebuffer
-                        [pixel_start_framebuffer..(pixel_start_framebuffer + BYTES_PER_PIXELS)]
-                        .copy_from_slice(
-                            &self.tile_atlas[pixel_start_tile_atlas
-                                ..(pixel_start_tile_atlas + BYTES_PER_PIXELS)],
-                        );
-                }
+        let height = self.memory.object_height();
+        let objects = self.line_objects(line, height);
+        if objects.is_empty() {
+            return;
+        }
+
+        for screen_x in 0..SCREEN_W {
+            if let Some((_, obj)) = self.visible_object(&objects, screen_x, line, height) {
+                let (sprite_id, row, x_pxl) = object_tile_pixel(&obj, screen_x, line, height);
+                let pixel_start_framebuffer = (line * SCREEN_W + screen_x) * BYTES_PER_PIXELS;
+                let pixel_start_tile_atlas = ((sprite_id as usize / 32) * (TEXTURES_W * 8)
+                    + (sprite_id as usize % 32) * 8
+                    + x_pxl
+                    + row * TEXTURES_W)
+                    * BYTES_PER_PIXELS;
+
+                self.framebuffer
+                    [pixel_start_framebuffer..(pixel_start_framebuffer + BYTES_PER_PIXELS)]
+                    .copy_from_slice(
+                        &self.tile_atlas
+                            [pixel_start_tile_atlas..(pixel_start_tile_atlas + BYTES_PER_PIXELS)],
+                    );
             }
         }
     }
 
+    // the same atlas as get_tile_atlas_2bpp, with the background palette applied
+    // it is only refreshed by update_tile_atlas, at the start of each frame
+    pub fn get_tile_atlas_rgba(&self) -> &[u8; TEXTURES_W * TEXTURES_W * BYTES_PER_PIXELS] {
+        return &self.tile_atlas;
+    }
+
     // returns a 256 * 256 atlas (32 * 32 tiles)
     // with each pixel being an u8 encoding its value (0-3)
     // the gameboy holds only 384 tiles, i.e. 32 * 12
@@ -323,34 +1094,21 @@ impl Gameboy {
         // https://gbdev.io/pandocs/Tile_Data.html
         // each tile is 16 bytes in memory
         // each couple of bytes encodes a line of the tile
-
-        // this whole thing is pretty convoluted but i don't think there's a better way,
-        // as the memory layout of the gameboy tiles is very different from that of "normal" images
+        let tile_data = self.memory.tile_data();
 
         // for each tile
         for id in 0..384usize {
-            let address = (0x8000 + id * 16) as u16;
-
             // for each line of the tile
             for y in 0..8 {
-                let byte_1 = self.memory.read_byte(address + y * 2);
-                let byte_2 = self.memory.read_byte(address + y * 2 + 1);
+                let byte_1 = tile_data[id * 16 + y * 2];
+                let byte_2 = tile_data[id * 16 + y * 2 + 1];
 
-                for x in 0..8 {
-                    let mut value: u8 = 0;
+                let line_start =
+                    // tile start                              | line start
+                    8 * (id % 32) + (8 * 8 * 32) * (id / 32) + (y * 8 * 32);
 
-                    let bit_0 = byte_1 >> (7 - x) & 1;
-                    let bit_1 = byte_2 >> (7 - x) & 1;
-
-                    value |= bit_0;
-                    value |= bit_1 << 1;
-
-                    let pixel =
-                        // tile start                              | pixel start
-                        8 * (id % 32) + (8 * 8 * 32) * (id / 32) + ((y as usize) * 8 * 32) + (x as usize);
-
-                    img[pixel] = value;
-                }
+                img[line_start..(line_start + 8)]
+                    .copy_from_slice(&decode_tile_line(byte_1, byte_2));
             }
         }
 
@@ -359,133 +1117,90 @@ impl Gameboy {
 
     pub fn update_tile_atlas(&mut self) {
         let palette = self.get_palette();
+        let atlas = self.get_tile_atlas_2bpp();
 
-        // https://gbdev.io/pandocs/Tile_Data.html
-        // each tile is 16 bytes in memory
-        // each couple of bytes encodes a line of the tile
-
-        // this whole thing is pretty convoluted but i don't think there's a better way,
-        // as the memory layout of the gameboy tiles is very different from that of "normal" images
-
-        // for each tile
-        for id in 0..384usize {
-            let address = (0x8000 + id * 16) as u16;
-
-            // for each line of the tile
-            for y in 0..8 {
-                let byte_1 = self.memory.read_byte(address + y * 2);
-                let byte_2 = self.memory.read_byte(address + y * 2 + 1);
-
-                for x in 0..8 {
-                    let mut value: u8 = 0;
-
-                    let bit_0 = byte_1 >> (7 - x) & 1;
-                    let bit_1 = byte_2 >> (7 - x) & 1;
-
-                    value |= bit_0;
-                    value |= bit_1 << 1;
-
-                    let pixel =
-                        // tile start                              | pixel start
-                        8 * (id % 32) + (8 * 8 * 32) * (id / 32) + ((y as usize) * 8 * 32) + (x as usize);
-
-                    self.tile_atlas[(pixel * 4)..(pixel * 4 + 4)]
-                        .copy_from_slice(&palette[value as usize]);
-                }
-            }
+        // only the first 12 rows of tiles hold anything
+        for pixel in 0..(384 * 8 * 8) {
+            self.tile_atlas[(pixel * 4)..(pixel * 4 + 4)]
+                .copy_from_slice(&palette[atlas[pixel] as usize]);
         }
     }
 
     pub fn update_tile_map(&mut self) {
         //https://gbdev.io/pandocs/Tile_Maps.html
         let mut indexes = [0; 32 * 32];
-        let palette = self.get_palette();
-        let addressing_mode_bit = self.memory.is_bg_tile_addressing_mode_normal();
-
         for i in 0..(32 * 32) {
-            let index = self.memory.read_byte(0x9800 + i);
-            indexes[i as usize] = if addressing_mode_bit {
-                // index is just the index, starting at tile 0
-                index as u32
-            } else {
-                // index is signed, and the base tile is 256 (first tile of group 3)
-                let signed_index: i32 = i8::from_le_bytes(index.to_le_bytes()) as i32;
-                (256 + signed_index) as u32
-            };
+            indexes[i as usize] = self.atlas_tile(self.memory.peek_vram(0x9800 + i));
         }
 
-        let atlas = self.get_tile_atlas_2bpp();
-
+        // the tiles are copied from the colored atlas, so it needs to be up to date
         // for each tile
         for tile in 0..(32 * 32) {
-            let index = indexes[tile] as usize;
+            let index = indexes[tile];
 
             // for each line of the tile
             for y in 0..8 {
-                for x in 0..8 {
-                    let dst_pixel =
-                    // tile start                              | pixel start
-                    (8 * (tile % 32) + (8 * 8 * 32) * (tile / 32) + ((y as usize) * 8 * 32) + (x as usize)) * 4;
-
-                    let atlas_pos = 8 * (index % 32)
-                        + (8 * 8 * 32) * (index / 32)
-                        + ((y as usize) * 8 * 32)
-                        + (x as usize);
-
-                    // https://lospec.com/palette-list/2bit-demichrome
-                    self.tilemap[dst_pixel..(dst_pixel + 4)]
-                        .copy_from_slice(&palette[atlas[atlas_pos] as usize]);
-                }
+                let dst_line =
+                    // tile start                              | line start
+                    (8 * (tile % 32) + (8 * 8 * 32) * (tile / 32) + (y * 8 * 32)) * 4;
+
+                let atlas_line =
+                    (8 * (index % 32) + (8 * 8 * 32) * (index / 32) + (y * 8 * 32)) * 4;
+
+                self.tilemap[dst_line..(dst_line + 8 * 4)]
+                    .copy_from_slice(&self.tile_atlas[atlas_line..(atlas_line + 8 * 4)]);
             }
         }
     }
 
+    // position in the tile atlas of a tile from the background & window tile maps
+    // https://gbdev.io/pandocs/Tile_Data.html
+    fn atlas_tile(&self, index: u8) -> usize {
+        return if self.memory.is_bg_tile_addressing_mode_normal() {
+            // index is just the index, starting at tile 0
+            index as usize
+        } else {
+            // index is signed, and the base tile is 256 (first tile of group 3)
+            (256 + index as i8 as isize) as usize
+        };
+    }
+
+    // https://gbdev.io/pandocs/OAM.html
+    pub fn oam_entries(&self) -> [OamEntry; OAM_ENTRIES] {
+        return oam::decode_oam(self.memory.oam());
+    }
+
+    // entry `index` of the oam, none past the 40th
+    pub fn get_oam_entry(&self, index: usize) -> Option<OamEntry> {
+        if index >= OAM_ENTRIES {
+            return None;
+        }
+        let bytes = &self.memory.oam()[(index * 4)..(index * 4 + 4)];
+        return Some(OamEntry::from_bytes([
+            bytes[0], bytes[1], bytes[2], bytes[3],
+        ]));
+    }
+
     // FIXME : LCD is always turned on for now, in reality it depends on
     // a certain byte in memory : 	LD ($FF00+$40),A	; $005d  Turn on LCD, showing Background
     pub fn get_obj_y_pos_buffer(&self) -> [u32; 40] {
-        let mut buffer = [0; 40];
-        for obj in 0..40 {
-            buffer[obj] = self.memory.read_byte(0xFE00 + (obj * 4) as u16) as u32;
-        }
-
-        return buffer;
+        return self.oam_entries().map(|obj| obj.y as u32);
     }
 
     pub fn get_obj_x_pos_buffer(&self) -> [u32; 40] {
-        let mut buffer = [0; 40];
-        for obj in 0..40 {
-            buffer[obj] = self.memory.read_byte(0xFE00 + 1 + (obj * 4) as u16) as u32;
-        }
-
-        return buffer;
+        return self.oam_entries().map(|obj| obj.x as u32);
     }
 
     pub fn get_obj_sprite_ids_buffer(&self) -> [u32; 40] {
-        let mut buffer = [0; 40];
-        for obj in 0..40 {
-            buffer[obj] = self.memory.read_byte(0xFE00 + 2 + (obj * 4) as u16) as u32;
-        }
+        return self.oam_entries().map(|obj| obj.tile as u32);
+    }
 
-        return buffer;
+    pub fn get_obj_flags_buffer(&self) -> [u32; 40] {
+        return self.oam_entries().map(|obj| obj.flags.bits() as u32);
     }
 
     fn get_palette(&self) -> [[u8; 4]; 4] {
-        // https://gbdev.io/pandocs/Palettes.html
-        let palette_reg = self.memory.read_byte(0xFF47);
-
-        let mut palette = [[0; 4]; 4];
-        for id in 0..4 {
-            let color = (palette_reg >> (id * 2)) & 3;
-            palette[id] = match color {
-                0 => [250, 251, 246, /* alpha */ 255],
-                1 => [198, 183, 190, /* alpha */ 255],
-                2 => [86, 90, 117, /* alpha */ 255],
-                3 => [15, 15, 27, /* alpha */ 255],
-                _ => unreachable!(),
-            };
-        }
-
-        return palette;
+        return decode_palette(self.memory.read_byte(0xFF47), &SHADES);
     }
 
     fn execute_instruction(&mut self, instr: Instruction) -> Result<u64, EmulationError> {
@@ -537,19 +1252,26 @@ impl Gameboy {
                                     }
 
                                     let address = self.cpu.read_r16(&ptr);
-                                    self.memory.read_byte(address)
+                                    self.read_byte(address)
                                 }
                                 R8_C => {
                                     let address = self.cpu.read_r8(&ptr) as u16 + 0xFF00;
-                                    self.memory.read_byte(address)
+                                    self.read_byte(address)
                                 }
                                 // address from imm8 : IO memory
-                                IMM8(imm8) => self.memory.read_byte(0xFF00 + imm8 as u16),
+                                IMM8(imm8) => self.read_byte(0xFF00 + imm8 as u16),
                                 // adress from imm16
-                                IMM16(imm16) => self.memory.read_byte(imm16),
-                                _ => panic!("(CRITICAL) LD : ILLEGAL POINTER {ptr} at {pc:#06X}"),
+                                IMM16(imm16) => self.read_byte(imm16),
+                                _ => {
+                                    return Err(illegal_operand(
+                                        format!("LD : illegal pointer {ptr}"),
+                                        pc,
+                                    ))
+                                }
                             },
-                            _ => panic!("(CRITICAL) LD : ILLEGAL SRC {src} at {pc:#06X}"),
+                            _ => {
+                                return Err(illegal_operand(format!("LD : illegal src {src}"), pc))
+                            }
                         };
 
                         self.cpu.write_r8(&dst, byte);
@@ -571,18 +1293,19 @@ impl Gameboy {
                                     let sp = self.cpu.read_r16(&R16_SP);
                                     sp.wrapping_add(offset as u16)
                                 }
-                                _ => panic!("(CRITICAL) LD : ILLEGAL STACK POINTER OFFSET {ptr} at {pc:#06X}"),
+                                _ => {
+                                    return Err(illegal_operand(
+                                        format!("LD : illegal stack pointer offset {ptr}"),
+                                        pc,
+                                    ))
+                                }
                             },
                             // special case of 0xF8
-                            SP_PLUS_SIGNED_IMM8(imm8) => {
-                                let sp = self.cpu.read_stack_pointer();
-                                if imm8 >= 0 {
-                                    sp.wrapping_add(imm8.abs() as u16)
-                                } else {
-                                    sp.wrapping_sub(imm8.abs() as u16)
-                               }
+                            // flags : 0 0 h c
+                            SP_PLUS_SIGNED_IMM8(imm8) => self.add_signed_to_sp(imm8),
+                            _ => {
+                                return Err(illegal_operand(format!("LD : illegal src {src}"), pc))
                             }
-                            _ => panic!("(CRITICAL) LD : ILLEGAL SRC {src} at {pc:#06X}"),
                         };
 
                         self.cpu.write_r16(&dst, word);
@@ -607,28 +1330,37 @@ impl Gameboy {
                             // address from imm8 : IO memory
                             IMM8(imm8) => 0xFF00 + imm8 as u16,
                             _ => {
-                                panic!("(CRITICAL) LD : ILLEGAL DST POINTER {ptr} at {pc:#06X}")
+                                return Err(illegal_operand(
+                                    format!("LD : illegal dst pointer {ptr}"),
+                                    pc,
+                                ))
                             }
                         };
 
                         match src {
                             // load byte from r8
                             R8_A | R8_B | R8_C | R8_D | R8_E | R8_H | R8_L => {
-                                self.memory.write_byte(address, self.cpu.read_r8(&src))?
+                                self.write_byte(address, self.cpu.read_r8(&src))?
                             }
                             // load word from sp register
                             R16_SP => {
-                                self.memory
-                                    .write_word(address, self.cpu.read_r16(&R16_SP))?;
+                                self.write_word(address, self.cpu.read_r16(&R16_SP))?;
                             }
                             // load immediate byte
                             IMM8(imm8) => {
-                                self.memory.write_byte(address, imm8)?;
+                                self.write_byte(address, imm8)?;
+                            }
+                            _ => {
+                                return Err(illegal_operand(format!("LD : illegal src {src}"), pc))
                             }
-                            _ => panic!("(CRITICAL) LD : ILLEGAL SRC {src} at {pc:#06X}"),
                         }
                     }
-                    _ => panic!("LD : UNHANDLED DESTINATION {dst} at {pc:#06X}"),
+                    _ => {
+                        return Err(illegal_operand(
+                            format!("LD : unhandled destination {dst}"),
+                            pc,
+                        ))
+                    }
                 }
 
                 if increment_hl {
@@ -657,6 +1389,7 @@ impl Gameboy {
                     let reg = self.cpu.read_r16(&x);
                     let result = reg.wrapping_add(1);
                     self.cpu.write_r16(&x, result);
+                    self.trigger_oam_corruption(reg, OamCorruption::Write);
 
                     // no flags for 16-bit increment
                 }
@@ -664,19 +1397,19 @@ impl Gameboy {
                 PTR(ptr) => match *ptr {
                     R16_HL => {
                         let address = self.cpu.read_r16(&R16_HL);
-                        let byte = self.memory.read_byte(address);
+                        let byte = self.read_byte(address);
                         let result = byte.wrapping_add(1);
-                        self.memory.write_byte(address, result)?;
+                        self.write_byte(address, result)?;
 
                         // inc flags : Z 0 H -
                         self.cpu.write_z_flag(result == 0);
                         self.cpu.write_n_flag(false);
                         self.cpu.write_h_flag((byte & 0xF) == 0xF);
                     }
-                    _ => panic!("(CRITICAL) INC : ILLEGAL POINTER {ptr} at {pc:#06X}"),
+                    _ => return Err(illegal_operand(format!("INC : illegal pointer {ptr}"), pc)),
                 },
 
-                _ => panic!("(CRITICAL) INC : ILLEGAL OPERAND {x} at {pc:#06X}"),
+                _ => return Err(illegal_operand(format!("INC : illegal operand {x}"), pc)),
             },
             Operation::DEC { x } => match x {
                 // decrement 8-bit register
@@ -695,24 +1428,25 @@ impl Gameboy {
                     let reg = self.cpu.read_r16(&x);
                     let result = reg.wrapping_sub(1);
                     self.cpu.write_r16(&x, result);
+                    self.trigger_oam_corruption(reg, OamCorruption::Write);
                 }
                 // memory at address in hl
                 PTR(ptr) => match *ptr {
                     R16_HL => {
                         let address = self.cpu.read_r16(&R16_HL);
-                        let byte = self.memory.read_byte(address);
+                        let byte = self.read_byte(address);
                         let result = byte.wrapping_sub(1);
-                        self.memory.write_byte(address, result)?;
+                        self.write_byte(address, result)?;
 
                         // dec flags : Z 1 H -
                         self.cpu.write_z_flag(result == 0);
                         self.cpu.write_n_flag(true);
                         self.cpu.write_h_flag((byte & 0xF) == 0);
                     }
-                    _ => panic!("(CRITICAL) DEC : ILLEGAL POINTER {ptr} at {pc:#06X}"),
+                    _ => return Err(illegal_operand(format!("DEC : illegal pointer {ptr}"), pc)),
                 },
 
-                _ => panic!("(CRITICAL) DEC : ILLEGAL OPERAND {x} at {pc:#06X}"),
+                _ => return Err(illegal_operand(format!("DEC : illegal operand {x}"), pc)),
             },
             Operation::ADD { x, y } => {
                 // add either does a + y and stores the result in a (8 bits)
@@ -728,11 +1462,21 @@ impl Gameboy {
                             PTR(ptr) => match *ptr {
                                 R16_HL => {
                                     let hl = self.cpu.read_r16(&ptr);
-                                    self.memory.read_byte(hl)
+                                    self.read_byte(hl)
+                                }
+                                _ => {
+                                    return Err(illegal_operand(
+                                        format!("ADD : illegal pointer {ptr}"),
+                                        pc,
+                                    ))
                                 }
-                                _ => panic!("(CRITICAL) ADD : ILLEGAL POINTER {ptr} at {pc:#06X}"),
                             },
-                            _ => panic!("(CRITICAL) ADD : ILLEGAL SECOND OPERAND {y} at {pc:#06X}"),
+                            _ => {
+                                return Err(illegal_operand(
+                                    format!("ADD : illegal second operand {y}"),
+                                    pc,
+                                ))
+                            }
                         };
 
                         let a = self.cpu.read_a_register();
@@ -749,7 +1493,12 @@ impl Gameboy {
                         let value = match y {
                             // add 16-bit register
                             R16_BC | R16_DE | R16_HL | R16_SP => self.cpu.read_r16(&y),
-                            _ => panic!("(CRITICAL) ADD : ILLEGAL SECOND OPERAND {y} at {pc:#06X}"),
+                            _ => {
+                                return Err(illegal_operand(
+                                    format!("ADD : illegal second operand {y}"),
+                                    pc,
+                                ))
+                            }
                         };
 
                         let hl = self.cpu.read_hl_register();
@@ -760,25 +1509,22 @@ impl Gameboy {
                         self.cpu.write_n_flag(false);
                         self.cpu
                             .write_h_flag((hl & 0xFFF) + (value & 0xFFF) > 0xFFF);
-                        self.cpu.write_c_flag(hl < value);
+                        // carry out of bit 15
+                        self.cpu.write_c_flag(hl as u32 + value as u32 > 0xFFFF);
+                    }
+                    _ => {
+                        return Err(illegal_operand(
+                            format!("ADD : illegal first operand {x}"),
+                            pc,
+                        ))
                     }
-                    // signed SP add is also a thing apparently
-                    /* Operand::R16_SP => {
-                        let sp = self.cpu.read_stack_pointer();
-                        let value = match y {
-                            IMM8_SIGNED(imm8) => imm8,
-                            _ => panic!("(CRITICAL) ADD : ILLEGAL SECOND OPERAND {y} at {pc:#06X}"),
-                        };
-
-                        self.cpu.offset_stack_pointer(value);
-
-                        // flags : 0 0 h c
-                        self.cpu.write_z_flag(false);
-                        self.cpu.write_n_flag(false);
-                    } */
-                    _ => panic!("(CRITICAL) ADD : ILLEGAL FIRST OPERAND {x} at {pc:#06X}"),
                 }
             }
+            Operation::LD_SP_PLUS_IMM8 { offset } => {
+                // flags : 0 0 h c
+                let result = self.add_signed_to_sp(offset);
+                self.cpu.write_r16(&R16_SP, result);
+            }
             Operation::ADC { y } => {
                 // like add, but also adds the carry flag (hence the "c")
 
@@ -791,11 +1537,18 @@ impl Gameboy {
                     PTR(ptr) => match *ptr {
                         R16_HL => {
                             let hl = self.cpu.read_r16(&ptr);
-                            self.memory.read_byte(hl)
+                            self.read_byte(hl)
+                        }
+                        _ => {
+                            return Err(illegal_operand(format!("ADC : illegal pointer {ptr}"), pc))
                         }
-                        _ => panic!("(CRITICAL) ADC : ILLEGAL POINTER {ptr} at {pc:#06X}"),
                     },
-                    _ => panic!("(CRITICAL) ADC : ILLEGAL SECOND OPERAND {y} at {pc:#06X}"),
+                    _ => {
+                        return Err(illegal_operand(
+                            format!("ADC : illegal second operand {y}"),
+                            pc,
+                        ))
+                    }
                 };
 
                 let a = self.cpu.read_a_register();
@@ -827,11 +1580,18 @@ impl Gameboy {
                     PTR(ptr) => match *ptr {
                         R16_HL => {
                             let hl = self.cpu.read_r16(&ptr);
-                            self.memory.read_byte(hl)
+                            self.read_byte(hl)
+                        }
+                        _ => {
+                            return Err(illegal_operand(format!("SUB : illegal pointer {ptr}"), pc))
                         }
-                        _ => panic!("(CRITICAL) SUB : ILLEGAL POINTER {ptr} at {pc:#06X}"),
                     },
-                    _ => panic!("(CRITICAL) SUB : ILLEGAL SECOND OPERAND {y} at {pc:#06X}"),
+                    _ => {
+                        return Err(illegal_operand(
+                            format!("SUB : illegal second operand {y}"),
+                            pc,
+                        ))
+                    }
                 };
 
                 let result = a.wrapping_sub(value);
@@ -855,11 +1615,18 @@ impl Gameboy {
                     PTR(ptr) => match *ptr {
                         R16_HL => {
                             let hl = self.cpu.read_r16(&ptr);
-                            self.memory.read_byte(hl)
+                            self.read_byte(hl)
+                        }
+                        _ => {
+                            return Err(illegal_operand(format!("SBC : illegal pointer {ptr}"), pc))
                         }
-                        _ => panic!("(CRITICAL) SBC : ILLEGAL POINTER {ptr} at {pc:#06X}"),
                     },
-                    _ => panic!("(CRITICAL) SBC : ILLEGAL SECOND OPERAND {y} at {pc:#06X}"),
+                    _ => {
+                        return Err(illegal_operand(
+                            format!("SBC : illegal second operand {y}"),
+                            pc,
+                        ))
+                    }
                 };
 
                 let a = self.cpu.read_a_register();
@@ -883,11 +1650,18 @@ impl Gameboy {
                     // second operand can only be another 8-bit register or pointer in hl
                     R8_A | R8_B | R8_C | R8_D | R8_E | R8_H | R8_L => self.cpu.read_r8(&y),
                     PTR(ptr) => match *ptr {
-                        R16_HL => self.memory.read_byte(self.cpu.read_r16(&R16_HL)),
-                        _ => panic!("(CRITICAL) XOR : ILLEGAL POINTER {ptr} at {pc:#06X}"),
+                        R16_HL => self.read_byte(self.cpu.read_r16(&R16_HL)),
+                        _ => {
+                            return Err(illegal_operand(format!("XOR : illegal pointer {ptr}"), pc))
+                        }
                     },
                     IMM8(imm8) => imm8,
-                    _ => panic!("(CRITICAL) XOR : ILLEGAL SECOND OPERAND {y:?} at {pc:#06X}"),
+                    _ => {
+                        return Err(illegal_operand(
+                            format!("XOR : illegal second operand {y:?}"),
+                            pc,
+                        ))
+                    }
                 };
 
                 self.cpu.write_r8(&R8_A, a ^ other);
@@ -905,11 +1679,18 @@ impl Gameboy {
                     // second operand can only be another 8-bit register or pointer in hl
                     R8_A | R8_B | R8_C | R8_D | R8_E | R8_H | R8_L => self.cpu.read_r8(&y),
                     PTR(ptr) => match *ptr {
-                        R16_HL => self.memory.read_byte(self.cpu.read_r16(&R16_HL)),
-                        _ => panic!("(CRITICAL) OR : ILLEGAL POINTER {ptr} at {pc:#06X}"),
+                        R16_HL => self.read_byte(self.cpu.read_r16(&R16_HL)),
+                        _ => {
+                            return Err(illegal_operand(format!("OR : illegal pointer {ptr}"), pc))
+                        }
                     },
                     IMM8(imm8) => imm8,
-                    _ => panic!("(CRITICAL) OR : ILLEGAL SECOND OPERAND {y:?} at {pc:#06X}"),
+                    _ => {
+                        return Err(illegal_operand(
+                            format!("OR : illegal second operand {y:?}"),
+                            pc,
+                        ))
+                    }
                 };
 
                 self.cpu.write_r8(&R8_A, a | other);
@@ -927,11 +1708,18 @@ impl Gameboy {
                     // second operand can only be another 8-bit register or pointer in hl
                     R8_A | R8_B | R8_C | R8_D | R8_E | R8_H | R8_L => self.cpu.read_r8(&y),
                     PTR(ptr) => match *ptr {
-                        R16_HL => self.memory.read_byte(self.cpu.read_r16(&R16_HL)),
-                        _ => panic!("(CRITICAL) AND : ILLEGAL POINTER {ptr} at {pc:#06X}"),
+                        R16_HL => self.read_byte(self.cpu.read_r16(&R16_HL)),
+                        _ => {
+                            return Err(illegal_operand(format!("AND : illegal pointer {ptr}"), pc))
+                        }
                     },
                     IMM8(imm8) => imm8,
-                    _ => panic!("(CRITICAL) AND : ILLEGAL SECOND OPERAND {y:?} at {pc:#06X}"),
+                    _ => {
+                        return Err(illegal_operand(
+                            format!("AND : illegal second operand {y:?}"),
+                            pc,
+                        ))
+                    }
                 };
 
                 self.cpu.write_r8(&R8_A, a & other);
@@ -950,10 +1738,15 @@ impl Gameboy {
                     R8_A | R8_B | R8_C | R8_D | R8_E | R8_H | R8_L => self.cpu.read_r8(&src),
                     // test bit in memory
                     PTR(ptr) => match *ptr {
-                        R16_HL => self.memory.read_byte(self.cpu.read_r16(&R16_HL)),
-                        _ => panic!("(CRITICAL) BIT : ILLEGAL POINTER {ptr:?} at {pc:#06X}"),
+                        R16_HL => self.read_byte(self.cpu.read_r16(&R16_HL)),
+                        _ => {
+                            return Err(illegal_operand(
+                                format!("BIT : illegal pointer {ptr:?}"),
+                                pc,
+                            ))
+                        }
                     },
-                    _ => panic!("(CRITICAL) BIT : ILLEGAL SRC {src:?} at {pc:#06X}"),
+                    _ => return Err(illegal_operand(format!("BIT : illegal src {src:?}"), pc)),
                 };
 
                 // bit instruction flags : Z 0 1 -
@@ -971,12 +1764,17 @@ impl Gameboy {
                     PTR(ptr) => match *ptr {
                         R16_HL => {
                             let address = self.cpu.read_hl_register();
-                            let byte = self.memory.read_byte(address);
-                            self.memory.write_byte(address, byte & !(1 << bit))?;
+                            let byte = self.read_byte(address);
+                            self.write_byte(address, byte & !(1 << bit))?;
+                        }
+                        _ => {
+                            return Err(illegal_operand(
+                                format!("RES : illegal pointer {ptr:?}"),
+                                pc,
+                            ))
                         }
-                        _ => panic!("(CRITICAL) RES : ILLEGAL POINTER {ptr:?} at {pc:#06X}"),
                     },
-                    _ => panic!("(CRITICAL) RES : ILLEGAL OPERAND {x:?} at {pc:#06X}"),
+                    _ => return Err(illegal_operand(format!("RES : illegal operand {x:?}"), pc)),
                 }
 
                 // no flags
@@ -991,12 +1789,17 @@ impl Gameboy {
                     PTR(ptr) => match *ptr {
                         R16_HL => {
                             let address = self.cpu.read_hl_register();
-                            let byte = self.memory.read_byte(address);
-                            self.memory.write_byte(address, byte | (1 << bit))?;
+                            let byte = self.read_byte(address);
+                            self.write_byte(address, byte | (1 << bit))?;
+                        }
+                        _ => {
+                            return Err(illegal_operand(
+                                format!("RES : illegal pointer {ptr:?}"),
+                                pc,
+                            ))
                         }
-                        _ => panic!("(CRITICAL) RES : ILLEGAL POINTER {ptr:?} at {pc:#06X}"),
                     },
-                    _ => panic!("(CRITICAL) RES : ILLEGAL OPERAND {x:?} at {pc:#06X}"),
+                    _ => return Err(illegal_operand(format!("RES : illegal operand {x:?}"), pc)),
                 }
                 // no flags
             }
@@ -1016,18 +1819,22 @@ impl Gameboy {
                     PTR(ptr) => match *ptr {
                         R16_HL => {
                             let hl = self.cpu.read_hl_register();
-                            let value = self.memory.read_byte(hl);
+                            let value = self.read_byte(hl);
                             let lower_to_upper = value << 4;
                             let upper_to_lower = value >> 4;
-                            self.memory
-                                .write_byte(hl, lower_to_upper | upper_to_lower)?;
+                            self.write_byte(hl, lower_to_upper | upper_to_lower)?;
 
                             self.cpu
                                 .write_z_flag((lower_to_upper | upper_to_lower) == 0);
                         }
-                        _ => panic!("(CRITICAL) SWAP : ILLEGAL POINTER {ptr} at {pc:#06X}"),
+                        _ => {
+                            return Err(illegal_operand(
+                                format!("SWAP : illegal pointer {ptr}"),
+                                pc,
+                            ))
+                        }
                     },
-                    _ => panic!("(CRITICAL) SWAP : ILLEGAL OPERAND {x} at {pc:#06X}"),
+                    _ => return Err(illegal_operand(format!("SWAP : illegal operand {x}"), pc)),
                 }
 
                 // flags : z 0 0 0
@@ -1036,14 +1843,56 @@ impl Gameboy {
                 self.cpu.write_h_flag(false);
                 self.cpu.write_c_flag(false);
             }
-            Operation::SRL { x } => {
-                // shift right logical
-                // for flags, see https://rgbds.gbdev.io/docs/v0.8.0/gbz80.7#SRL_r8
+            Operation::SRL { x } => {
+                // shift right logical
+                // for flags, see https://rgbds.gbdev.io/docs/v0.8.0/gbz80.7#SRL_r8
+                match x {
+                    R8_B | R8_C | R8_D | R8_E | R8_H | R8_L | R8_A => {
+                        let register = self.cpu.read_r8(&x);
+                        let carry = register & 1 == 1;
+                        let result = register >> 1;
+                        self.cpu.write_r8(&x, result);
+
+                        // flags : z 0 0 c
+                        self.cpu.write_z_flag(result == 0);
+                        self.cpu.write_n_flag(false);
+                        self.cpu.write_h_flag(false);
+                        self.cpu.write_c_flag(carry);
+                    }
+                    PTR(ptr) => {
+                        match *ptr {
+                            R16_HL => {
+                                let address = self.cpu.read_r16(&R16_HL);
+                                let value = self.read_byte(address);
+                                let carry = value & 1 == 1;
+                                let result = value >> 1;
+                                self.write_byte(address, result)?;
+
+                                // flags : z 0 0 c
+                                self.cpu.write_z_flag(result == 0);
+                                self.cpu.write_n_flag(false);
+                                self.cpu.write_h_flag(false);
+                                self.cpu.write_c_flag(carry);
+                            }
+                            _ => {
+                                return Err(illegal_operand(
+                                    format!("SRL : illegal pointer {ptr}"),
+                                    pc,
+                                ))
+                            }
+                        }
+                    }
+                    _ => return Err(illegal_operand(format!("SRL : illegal operand {x}"), pc)),
+                }
+            }
+            Operation::SRA { x } => {
+                // shift right arithmetic, b7 is kept
+                // for flags, see https://rgbds.gbdev.io/docs/v0.8.0/gbz80.7#SRA_r8
                 match x {
                     R8_B | R8_C | R8_D | R8_E | R8_H | R8_L | R8_A => {
                         let register = self.cpu.read_r8(&x);
                         let carry = register & 1 == 1;
-                        let result = register >> 1;
+                        let result = (register >> 1) | (register & 0x80);
                         self.cpu.write_r8(&x, result);
 
                         // flags : z 0 0 c
@@ -1056,10 +1905,10 @@ impl Gameboy {
                         match *ptr {
                             R16_HL => {
                                 let address = self.cpu.read_r16(&R16_HL);
-                                let value = self.memory.read_byte(address);
+                                let value = self.read_byte(address);
                                 let carry = value & 1 == 1;
-                                let result = value >> 1;
-                                self.memory.write_byte(address, result)?;
+                                let result = (value >> 1) | (value & 0x80);
+                                self.write_byte(address, result)?;
 
                                 // flags : z 0 0 c
                                 self.cpu.write_z_flag(result == 0);
@@ -1067,10 +1916,15 @@ impl Gameboy {
                                 self.cpu.write_h_flag(false);
                                 self.cpu.write_c_flag(carry);
                             }
-                            _ => panic!("(CRITICAL) SRL : ILLEGAL POINTER {ptr} at {pc:#06X}"),
+                            _ => {
+                                return Err(illegal_operand(
+                                    format!("SRA : illegal pointer {ptr}"),
+                                    pc,
+                                ))
+                            }
                         }
                     }
-                    _ => panic!("(CRITICAL) SRL : ILLEGAL OPERAND {x} at {pc:#06X}"),
+                    _ => return Err(illegal_operand(format!("SRA : illegal operand {x}"), pc)),
                 }
             }
             Operation::SLA { x } => {
@@ -1093,10 +1947,10 @@ impl Gameboy {
                         match *ptr {
                             R16_HL => {
                                 let address = self.cpu.read_r16(&R16_HL);
-                                let value = self.memory.read_byte(address);
+                                let value = self.read_byte(address);
                                 let carry = value >> 7 == 1;
                                 let result = value << 1;
-                                self.memory.write_byte(address, result)?;
+                                self.write_byte(address, result)?;
 
                                 // flags : z 0 0 c
                                 self.cpu.write_z_flag(result == 0);
@@ -1104,10 +1958,15 @@ impl Gameboy {
                                 self.cpu.write_h_flag(false);
                                 self.cpu.write_c_flag(carry);
                             }
-                            _ => panic!("(CRITICAL) SRL : ILLEGAL POINTER {ptr} at {pc:#06X}"),
+                            _ => {
+                                return Err(illegal_operand(
+                                    format!("SRL : illegal pointer {ptr}"),
+                                    pc,
+                                ))
+                            }
                         }
                     }
-                    _ => panic!("(CRITICAL) SRL : ILLEGAL OPERAND {x} at {pc:#06X}"),
+                    _ => return Err(illegal_operand(format!("SRL : illegal operand {x}"), pc)),
                 }
             }
             Operation::RL { x } => {
@@ -1136,7 +1995,7 @@ impl Gameboy {
                     PTR(ptr) => match *ptr {
                         R16_HL => {
                             let address = self.cpu.read_r16(&ptr);
-                            let mut to_rotate = self.memory.read_byte(address);
+                            let mut to_rotate = self.read_byte(address);
 
                             // b7 to carry
                             let previous_carry: u8 = if self.cpu.read_c_flag() { 1 } else { 0 };
@@ -1147,16 +2006,18 @@ impl Gameboy {
                             to_rotate |= previous_carry;
 
                             // write back the number
-                            self.memory.write_byte(address, to_rotate)?;
+                            self.write_byte(address, to_rotate)?;
 
                             // flags : z 0 0 c
                             self.cpu.write_z_flag(to_rotate == 0);
                             self.cpu.write_n_flag(false);
                             self.cpu.write_h_flag(false);
                         }
-                        _ => panic!("(CRITICAL) RL : ILLEGAL POINTER {ptr} at {pc:#06X}"),
+                        _ => {
+                            return Err(illegal_operand(format!("RL : illegal pointer {ptr}"), pc))
+                        }
                     },
-                    _ => panic!("(CRITICAL) RL : ILLEGAL OPERAND {x} at {pc:#06X}"),
+                    _ => return Err(illegal_operand(format!("RL : illegal operand {x}"), pc)),
                 }
             }
             Operation::RR { x } => {
@@ -1185,7 +2046,7 @@ impl Gameboy {
                     PTR(ptr) => match *ptr {
                         R16_HL => {
                             let address = self.cpu.read_r16(&ptr);
-                            let mut to_rotate = self.memory.read_byte(address);
+                            let mut to_rotate = self.read_byte(address);
 
                             // b0 to carry
                             let previous_carry: u8 = if self.cpu.read_c_flag() { 1 } else { 0 };
@@ -1196,16 +2057,18 @@ impl Gameboy {
                             to_rotate |= previous_carry << 7;
 
                             // write back the number
-                            self.memory.write_byte(address, to_rotate)?;
+                            self.write_byte(address, to_rotate)?;
 
                             // flags : z 0 0 c
                             self.cpu.write_z_flag(to_rotate == 0);
                             self.cpu.write_n_flag(false);
                             self.cpu.write_h_flag(false);
                         }
-                        _ => panic!("(CRITICAL) RR : ILLEGAL POINTER {ptr} at {pc:#06X}"),
+                        _ => {
+                            return Err(illegal_operand(format!("RR : illegal pointer {ptr}"), pc))
+                        }
                     },
-                    _ => panic!("(CRITICAL) RR : ILLEGAL OPERAND {x} at {pc:#06X}"),
+                    _ => return Err(illegal_operand(format!("RR : illegal operand {x}"), pc)),
                 }
             }
             Operation::RLC { x } => {
@@ -1234,7 +2097,7 @@ impl Gameboy {
                     PTR(ptr) => match *ptr {
                         R16_HL => {
                             let address = self.cpu.read_r16(&ptr);
-                            let mut to_rotate = self.memory.read_byte(address);
+                            let mut to_rotate = self.read_byte(address);
 
                             // b7 to carry
                             let previous_b7: u8 = (to_rotate >> 7) & 1;
@@ -1245,16 +2108,18 @@ impl Gameboy {
                             to_rotate |= previous_b7;
 
                             // write back the number
-                            self.memory.write_byte(address, to_rotate)?;
+                            self.write_byte(address, to_rotate)?;
 
                             // flags : z 0 0 c
                             self.cpu.write_z_flag(to_rotate == 0);
                             self.cpu.write_n_flag(false);
                             self.cpu.write_h_flag(false);
                         }
-                        _ => panic!("(CRITICAL) RLC : ILLEGAL POINTER {ptr} at {pc:#06X}"),
+                        _ => {
+                            return Err(illegal_operand(format!("RLC : illegal pointer {ptr}"), pc))
+                        }
                     },
-                    _ => panic!("(CRITICAL) RLC : ILLEGAL OPERAND {x} at {pc:#06X}"),
+                    _ => return Err(illegal_operand(format!("RLC : illegal operand {x}"), pc)),
                 }
             }
             Operation::RRC { x } => {
@@ -1283,7 +2148,7 @@ impl Gameboy {
                     PTR(ptr) => match *ptr {
                         R16_HL => {
                             let address = self.cpu.read_r16(&ptr);
-                            let mut to_rotate = self.memory.read_byte(address);
+                            let mut to_rotate = self.read_byte(address);
 
                             // b0 to carry
                             let previous_b0: u8 = (to_rotate) & 1;
@@ -1294,16 +2159,18 @@ impl Gameboy {
                             to_rotate |= previous_b0 << 7;
 
                             // write back the number
-                            self.memory.write_byte(address, to_rotate)?;
+                            self.write_byte(address, to_rotate)?;
 
                             // flags : z 0 0 c
                             self.cpu.write_z_flag(to_rotate == 0);
                             self.cpu.write_n_flag(false);
                             self.cpu.write_h_flag(false);
                         }
-                        _ => panic!("(CRITICAL) RRC : ILLEGAL POINTER {ptr} at {pc:#06X}"),
+                        _ => {
+                            return Err(illegal_operand(format!("RRC : illegal pointer {ptr}"), pc))
+                        }
                     },
-                    _ => panic!("(CRITICAL) RRC : ILLEGAL OPERAND {x} at {pc:#06X}"),
+                    _ => return Err(illegal_operand(format!("RRC : illegal operand {x}"), pc)),
                 }
             }
             Operation::RLA => {
@@ -1417,7 +2284,12 @@ impl Gameboy {
             Operation::JR { offset_oprd } => {
                 let offset = match offset_oprd {
                     IMM8_SIGNED(offset) => offset,
-                    _ => panic!("(CRITICAL) JR : ILLEGAL OFFSET {offset_oprd} at {pc:#06X}"),
+                    _ => {
+                        return Err(illegal_operand(
+                            format!("JR : illegal offset {offset_oprd}"),
+                            pc,
+                        ))
+                    }
                 };
 
                 self.cpu.offset_program_counter(offset);
@@ -1427,7 +2299,12 @@ impl Gameboy {
                 if should_jump {
                     let offset = match offset_oprd {
                         IMM8_SIGNED(offset) => offset,
-                        _ => panic!("(CRITICAL) JR_CC : ILLEGAL OFFSET {offset_oprd} at {pc:#06X}"),
+                        _ => {
+                            return Err(illegal_operand(
+                                format!("JR_CC : illegal offset {offset_oprd}"),
+                                pc,
+                            ))
+                        }
                     };
 
                     cycles_elapsed = instr.branch_cycles.unwrap();
@@ -1440,7 +2317,7 @@ impl Gameboy {
                 let address = match addr {
                     IMM16(imm16) => imm16,
                     R16_HL => self.cpu.read_hl_register(),
-                    _ => panic!("(CRITICAL) JP : ILLEGAL ADDRESS {addr} at {pc:#06X}"),
+                    _ => return Err(illegal_operand(format!("JP : illegal address {addr}"), pc)),
                 };
 
                 self.cpu.write_program_counter(address);
@@ -1451,7 +2328,12 @@ impl Gameboy {
                     let address = match addr {
                         IMM16(imm16) => imm16,
                         R16_HL => self.cpu.read_hl_register(),
-                        _ => panic!("(CRITICAL) JP CC : ILLEGAL ADDRESS {addr} at {pc:#06X}"),
+                        _ => {
+                            return Err(illegal_operand(
+                                format!("JP CC : illegal address {addr}"),
+                                pc,
+                            ))
+                        }
                     };
 
                     self.cpu.write_program_counter(address);
@@ -1463,7 +2345,10 @@ impl Gameboy {
                 let address = match proc {
                     IMM16(imm16) => imm16,
                     _ => {
-                        panic!("(CRITICAL) CALL : ILLEGAL PROCEDURE ADDRESS {proc} at {pc:#06X}")
+                        return Err(illegal_operand(
+                            format!("CALL : illegal procedure address {proc}"),
+                            pc,
+                        ))
                     }
                 };
 
@@ -1478,7 +2363,10 @@ impl Gameboy {
                 let address = match proc {
                     IMM16(imm16) => imm16,
                     _ => {
-                        panic!("(CRITICAL) CALL CC : ILLEGAL PROCEDURE ADDRESS {proc} at {pc:#06X}")
+                        return Err(illegal_operand(
+                            format!("CALL CC : illegal procedure address {proc}"),
+                            pc,
+                        ))
                     }
                 };
 
@@ -1498,9 +2386,7 @@ impl Gameboy {
                 // rst is like call, but only for a few fixed addresses
                 let address = match addr {
                     IMM16(imm16) => imm16,
-                    _ => {
-                        panic!("(CRITICAL) RST : ILLEGAL ADDRESS {addr} at {pc:#06X}")
-                    }
+                    _ => return Err(illegal_operand(format!("RST : illegal address {addr}"), pc)),
                 };
 
                 // push the return address to the stack
@@ -1524,6 +2410,9 @@ impl Gameboy {
 
                     // jump to where the procedure was called
                     self.cpu.write_program_counter(return_address);
+
+                    // update the cycles elapsed since we branched
+                    cycles_elapsed = instr.branch_cycles.unwrap();
                 }
             }
             Operation::RETI => {
@@ -1532,25 +2421,37 @@ impl Gameboy {
                 // jump to where the procedure was called
                 self.cpu.write_program_counter(return_address);
 
-                // re-enable interrupts
+                // re-enable interrupts : unlike EI there is no delay,
+                // a pending interrupt is serviced right after the return
                 self.cpu.enable_interrupts();
             }
             Operation::PUSH { reg } => {
                 let to_push = match reg {
                     R16_BC | R16_DE | R16_HL | R16_AF => self.cpu.read_r16(&reg),
-                    _ => panic!("(CRITICAL) PUSH : ILLEGAL OPERAND {reg} at {pc:#06X}"),
+                    _ => return Err(illegal_operand(format!("PUSH : illegal operand {reg}"), pc)),
                 };
 
+                // the decrement of sp, then each write
+                let sp = self.cpu.read_stack_pointer();
+                self.trigger_oam_corruption(sp, OamCorruption::Write);
+                self.trigger_oam_corruption(sp.wrapping_sub(1), OamCorruption::Write);
+                self.trigger_oam_corruption(sp.wrapping_sub(2), OamCorruption::Write);
+
                 self.push_word(to_push)?;
             }
 
             Operation::POP { reg } => {
                 match reg {
                     R16_BC | R16_DE | R16_HL | R16_AF => {
+                        // the first read comes with an increment of sp, the second doesn't
+                        let sp = self.cpu.read_stack_pointer();
+                        self.trigger_oam_corruption(sp, OamCorruption::ReadDuringIncrease);
+                        self.trigger_oam_corruption(sp.wrapping_add(1), OamCorruption::Read);
+
                         let word = self.pop_word();
                         self.cpu.write_r16(&reg, word);
                     }
-                    _ => panic!("(CRITICAL) POP : ILLEGAL OPERAND {reg} at {pc:#06X}"),
+                    _ => return Err(illegal_operand(format!("POP : illegal operand {reg}"), pc)),
                 };
             }
 
@@ -1562,10 +2463,17 @@ impl Gameboy {
                     R8_A | R8_B | R8_C | R8_D | R8_E | R8_H | R8_L => self.cpu.read_r8(&y),
                     IMM8(imm8) => imm8,
                     PTR(ptr) => match *ptr {
-                        R16_HL => self.memory.read_byte(self.cpu.read_r16(&R16_HL)),
-                        _ => panic!("(CRITICAL) CP : ILLEGAL POINTER {ptr} at {pc:#06X}"),
+                        R16_HL => self.read_byte(self.cpu.read_r16(&R16_HL)),
+                        _ => {
+                            return Err(illegal_operand(format!("CP : illegal pointer {ptr}"), pc))
+                        }
                     },
-                    _ => panic!("(CRITICAL) CP : ILLEGAL SECOND OPERAND {y} at {pc:#06X}"),
+                    _ => {
+                        return Err(illegal_operand(
+                            format!("CP : illegal second operand {y}"),
+                            pc,
+                        ))
+                    }
                 };
 
                 // cp flags : Z 1 H C
@@ -1579,7 +2487,9 @@ impl Gameboy {
                 warn!("DI : INTERRUPTS DISABLED");
             }
             Operation::EI => {
-                self.cpu.enable_interrupts();
+                // https://gbdev.io/pandocs/Interrupts.html#ei
+                // ime is only set once the following instruction is done
+                self.cpu.schedule_enable_interrupts();
                 warn!("EI : INTERRUPTS ENABLED")
             }
             Operation::CPL => {
@@ -1589,6 +2499,20 @@ impl Gameboy {
                 self.cpu.write_n_flag(true);
                 self.cpu.write_h_flag(true);
             }
+            Operation::SCF => {
+                // https://rgbds.gbdev.io/docs/v0.8.0/gbz80.7#SCF
+                // flags : - 0 0 1
+                self.cpu.write_n_flag(false);
+                self.cpu.write_h_flag(false);
+                self.cpu.write_c_flag(true);
+            }
+            Operation::CCF => {
+                // https://rgbds.gbdev.io/docs/v0.8.0/gbz80.7#CCF
+                // flags : - 0 0 c
+                self.cpu.write_n_flag(false);
+                self.cpu.write_h_flag(false);
+                self.cpu.write_c_flag(!self.cpu.read_c_flag());
+            }
 
             Operation::HALT => {
                 // https://rgbds.gbdev.io/docs/v0.8.0/gbz80.7#HALT
@@ -1606,11 +2530,30 @@ impl Gameboy {
                     }
                 }
             }
+            Operation::STOP => {
+                // https://gbdev.io/pandocs/CGB_Registers.html#ff4d--key1-cgb-mode-only-prepare-speed-switch
+                if self.memory.switch_speed() {
+                    info!(
+                        "SWITCHED TO {} SPEED",
+                        if self.memory.is_double_speed() {
+                            "DOUBLE"
+                        } else {
+                            "NORMAL"
+                        }
+                    );
+                } else {
+                    // FIXME : stop should wait for a button press with the lcd off
+                    warn!("STOP : LOW POWER MODE NOT IMPLEMENTED");
+                }
+
+                // stop resets the divider
+                self.memory.write_byte(0xFF04, 0)?;
+            }
             _ => {
-                return Err(EmulationError {
-                    ty: EmulationErrorType::UnhandledInstructionExec(instr),
-                    pc: Some(pc),
-                });
+                return Err(EmulationError::new(
+                    EmulationErrorType::UnhandledInstructionExec(instr),
+                    Some(pc),
+                ));
             }
         }
 
@@ -1618,12 +2561,121 @@ impl Gameboy {
     }
     // utilities common to multiple opcodes
 
+    // sp + signed offset, shared by `add sp, imm8` and `ld hl, sp + imm8`
+    // half carry & carry come from the unsigned addition of the low byte of sp
+    // and the offset, whatever its sign
+    fn add_signed_to_sp(&mut self, offset: i8) -> u16 {
+        let sp = self.cpu.read_stack_pointer();
+        let offset = offset as i16 as u16;
+
+        self.cpu.write_z_flag(false);
+        self.cpu.write_n_flag(false);
+        self.cpu.write_h_flag((sp & 0xF) + (offset & 0xF) > 0xF);
+        self.cpu.write_c_flag((sp & 0xFF) + (offset & 0xFF) > 0xFF);
+
+        return sp.wrapping_add(offset);
+    }
+
+    // memory accesses made by the cpu go through these,
+    // as opposed to the ones made by the ppu & co
+    // every memory access takes 4 cycles, the access itself happening at the end of them
+    fn read_byte(&mut self, address: u16) -> u8 {
+        self.tick(4);
+        self.check_dma_conflict(address);
+        self.memory.record_read(address);
+        return self.memory.read_byte(address);
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) -> Result<(), EmulationError> {
+        self.tick(4);
+        self.check_dma_conflict(address);
+        self.memory.record_write(address);
+        self.memory.write_byte(address, value)?;
+
+        // a general purpose dma starts right away
+        self.tick_vram_dma();
+
+        return Ok(());
+    }
+
+    fn read_word(&mut self, address: u16) -> u16 {
+        let low = self.read_byte(address);
+        let high = self.read_byte(address.wrapping_add(1));
+        return u16::from_le_bytes([low, high]);
+    }
+
+    fn write_word(&mut self, address: u16, value: u16) -> Result<(), EmulationError> {
+        let [low, high] = value.to_le_bytes();
+        self.write_byte(address, low)?;
+        return self.write_byte(address.wrapping_add(1), high);
+    }
+
+    fn check_dma_conflict(&self, address: u16) {
+        if self.memory.is_dma_active() && !(0xFF80..=0xFFFE).contains(&address) {
+            self.dma_conflict.set(Some(address));
+        }
+    }
+
+    // https://gbdev.io/pandocs/OAM_Corruption_Bug.html
+    // oam is seen as 20 rows of 4 words, the ppu reads one row per cycle during the OAM scan
+    // `address` is the value of the 16-bit register being used
+    fn trigger_oam_corruption(&mut self, address: u16, corruption: OamCorruption) {
+        if !self.oam_corruption
+            || !(0xFE00..=0xFEFF).contains(&address)
+            || !self.memory.is_lcd_enabled()
+            || self.memory.ppu_line() >= 144
+            || self.ly_cycles >= 80
+        {
+            return;
+        }
+
+        // all the accesses of an instruction are seen as happening on the same row,
+        // the first one is never corrupted
+        let row = (self.ly_cycles / 4) as usize;
+        if row == 0 {
+            return;
+        }
+
+        let oam = self.memory.oam_mut();
+        let word = |oam: &[u8; 0xA0], row: usize, i: usize| -> u16 {
+            return u16::from_le_bytes([oam[row * 8 + i * 2], oam[row * 8 + i * 2 + 1]]);
+        };
+        let write_word = |oam: &mut [u8; 0xA0], row: usize, i: usize, value: u16| {
+            oam[(row * 8 + i * 2)..(row * 8 + i * 2 + 2)].copy_from_slice(&value.to_le_bytes());
+        };
+        let copy_row = |oam: &mut [u8; 0xA0], from: usize, to: usize| {
+            oam.copy_within((from * 8)..(from * 8 + 8), to * 8);
+        };
+
+        if corruption == OamCorruption::ReadDuringIncrease && (4..19).contains(&row) {
+            let a = word(oam, row - 2, 0);
+            let b = word(oam, row - 1, 0);
+            let c = word(oam, row, 0);
+            let d = word(oam, row - 1, 2);
+            write_word(oam, row - 1, 0, (b & (a | c | d)) | (a & c & d));
+            copy_row(oam, row - 1, row);
+            copy_row(oam, row - 1, row - 2);
+        }
+
+        // a is the first word of the row, b & c the first and third of the previous one
+        let a = word(oam, row, 0);
+        let b = word(oam, row - 1, 0);
+        let c = word(oam, row - 1, 2);
+        let first = match corruption {
+            OamCorruption::Write => ((a ^ c) & (b ^ c)) ^ c,
+            OamCorruption::Read | OamCorruption::ReadDuringIncrease => b | (a & c),
+        };
+        // the last three words come from the previous row
+        oam.copy_within((row * 8 - 6)..(row * 8), row * 8 + 2);
+        write_word(oam, row, 0, first);
+    }
+
     /* fn push_byte(&mut self, byte: u8) {
         // decrement stack pointer
         self.cpu.offset_stack_pointer(-1);
 
         // write byte
-        self.memory.write_byte(self.cpu.read_stack_pointer(), byte);
+        self.write_byte(self.cpu.read_stack_pointer(), byte);
     } */
 
     fn push_word(&mut self, word: u16) -> Result<(), EmulationError> {
@@ -1631,15 +2683,14 @@ impl Gameboy {
         self.cpu.offset_stack_pointer(-2);
 
         // write word
-        self.memory
-            .write_word(self.cpu.read_stack_pointer(), word)?;
+        self.write_word(self.cpu.read_stack_pointer(), word)?;
 
         Ok(())
     }
 
     /* fn pop_byte(&mut self) -> u8 {
         // read byte
-        let byte = self.memory.read_byte(self.cpu.read_stack_pointer());
+        let byte = self.read_byte(self.cpu.read_stack_pointer());
 
         // decrement stack pointer
         self.cpu.offset_stack_pointer(1);
@@ -1649,7 +2700,7 @@ impl Gameboy {
 
     fn pop_word(&mut self) -> u16 {
         // read word
-        let word = self.memory.read_word(self.cpu.read_stack_pointer());
+        let word = self.read_word(self.cpu.read_stack_pointer());
 
         // decrement stack pointer
         self.cpu.offset_stack_pointer(2);
@@ -1657,3 +2708,1323 @@ impl Gameboy {
         return word;
     }
 }
+
+impl From<&Gameboy> for Snapshot {
+    fn from(console: &Gameboy) -> Self {
+        return Snapshot {
+            version: SNAPSHOT_VERSION,
+            rom_checksum: console.memory.rom_checksum(),
+            cpu: console.cpu.clone(),
+            memory: MemorySnapshot::from(&console.memory),
+            div_cycles: 0,
+            ly_cycles: console.ly_cycles,
+            tima_cycles: console.tima_cycles,
+            serial_cycles: console.serial_cycles,
+            step_cycles: console.step_cycles,
+            step_dots: console.step_dots,
+            stat_line: console.stat_line,
+            lcd_was_enabled: console.lcd_was_enabled,
+            window_line: console.window_line,
+            window_y_triggered: console.window_y_triggered,
+            halted: console.halted,
+            input_state: console.input_state,
+            turbo_state: console.turbo_state,
+            frame_count: console.frame_count,
+            framebuffer: console.framebuffer.to_vec(),
+        };
+    }
+}
+
+// the sizes are expected to have been checked with `Snapshot::check_sizes`
+// nothing is plugged into the link port of the restored console, and it reads the host's clock
+// there is no rom either, see `Gameboy::load_snapshot`
+impl From<Snapshot> for Gameboy {
+    fn from(snapshot: Snapshot) -> Self {
+        let whole_div = snapshot.memory.div.is_some();
+        let mut console = Gameboy {
+            cpu: snapshot.cpu,
+            memory: snapshot.memory.into(),
+            ly_cycles: snapshot.ly_cycles,
+            tima_cycles: snapshot.tima_cycles,
+            serial_cycles: snapshot.serial_cycles,
+            serial_reply_wait: None,
+            step_cycles: snapshot.step_cycles,
+            step_dots: snapshot.step_dots,
+            stat_line: snapshot.stat_line,
+            lcd_was_enabled: snapshot.lcd_was_enabled,
+            window_line: snapshot.window_line,
+            // older snapshots only know whether the window was drawn this frame
+            window_y_triggered: snapshot.window_y_triggered || snapshot.window_line > 0,
+            halted: snapshot.halted,
+            error_recovery: false,
+            oam_corruption: false,
+            dma_conflict: Cell::new(None),
+            input_state: snapshot.input_state,
+            turbo_state: snapshot.turbo_state,
+            frame_count: snapshot.frame_count,
+            serial_device: None,
+            clock: Box::new(SystemClock),
+            perf: PerfStats::new(),
+            line_registers: Box::new([LineRegisters::default(); SCREEN_H]),
+            window_lines: Box::new([None; SCREEN_H]),
+            tile_atlas: Box::new([0; TEXTURES_W * TEXTURES_W * BYTES_PER_PIXELS]),
+            tilemap: Box::new([0; TEXTURES_W * TEXTURES_W * BYTES_PER_PIXELS]),
+            framebuffer: Box::new([0; SCREEN_W * SCREEN_H * BYTES_PER_PIXELS]),
+        };
+        if !whole_div {
+            // older files save the upper half of the divider as DIV, with the other registers
+            let div = console.memory.read_byte(0xFF04);
+            console
+                .memory
+                .set_internal_div(u16::from_be_bytes([div, snapshot.div_cycles as u8]));
+        }
+        console.framebuffer.copy_from_slice(&snapshot.framebuffer);
+        // the rest of the frame is drawn with these
+        console.update_tile_atlas();
+        console.update_tile_map();
+
+        return console;
+    }
+}
+
+pub fn read_rom_file(path: &str) -> Result<Vec<u8>, RomError> {
+    return std::fs::read(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => RomError::NotFound(path.to_string()),
+        _ => RomError::Unreadable(path.to_string(), e),
+    });
+}
+
+// an instruction was decoded with an operand it can't take, which is a decoding bug :
+// execution moved past it already, so it can be skipped like any other error
+fn illegal_operand(message: String, pc: u16) -> EmulationError {
+    return EmulationError::new(EmulationErrorType::IllegalOperand(message), Some(pc));
+}
+
+// SPREAD_BITS[byte] has bit n of byte moved to the lowest bit of byte 7 - n
+// so that the leftmost pixel of a tile line ends up first in memory
+const SPREAD_BITS: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut bit = 0;
+        while bit < 8 {
+            table[byte] |= (((byte >> bit) & 1) as u64) << (8 * (7 - bit));
+            bit += 1;
+        }
+        byte += 1;
+    }
+    table
+};
+
+// https://gbdev.io/pandocs/Scrolling.html
+// the entry of the 32 * 32 tile map at `map` under a pixel of the screen, and the position
+// of the pixel in its tile. the map wraps around past its right & bottom edges
+pub fn background_map_address(
+    map: u16,
+    screen_x: u8,
+    screen_y: u8,
+    scroll_x: u8,
+    scroll_y: u8,
+) -> (u16, u8, u8) {
+    let x = screen_x.wrapping_add(scroll_x);
+    let y = screen_y.wrapping_add(scroll_y);
+    return (map + (y as u16 / 8) * 32 + x as u16 / 8, x % 8, y % 8);
+}
+
+// the tile, row & column of the pixel at (x, line) of an object covering it
+// 8x16 objects are two tiles, the first one on top : bit 0 of the index is ignored
+fn object_tile_pixel(obj: &OamEntry, x: usize, line: usize, height: isize) -> (u8, usize, usize) {
+    let row = (line as isize - obj.screen_y()) as usize;
+    let tile = if height == 16 {
+        (obj.tile & 0xFE) + (row / 8) as u8
+    } else {
+        obj.tile
+    };
+    return (tile, row % 8, (x as isize - obj.screen_x()) as usize);
+}
+
+// the 8 pixel values (0-3) of a tile line, from its two bytes
+// the first byte holds the low bit of each pixel, the second byte the high bit
+fn decode_tile_line(byte_1: u8, byte_2: u8) -> [u8; 8] {
+    let line = SPREAD_BITS[byte_1 as usize] | (SPREAD_BITS[byte_2 as usize] << 1);
+    return line.to_le_bytes();
+}
+
+// https://gbdev.io/pandocs/Palettes.html
+// BGP, OBP0 & OBP1 give a shade (0-3) to each of the 4 colors, two bits each
+pub fn palette_shades(register: u8) -> [u8; 4] {
+    return [0, 1, 2, 3].map(|color| (register >> (color * 2)) & 0b11);
+}
+
+// the rgba color of each of the 4 colors of a palette register, picked from `shades`
+pub fn decode_palette(
+    register: u8,
+    shades: &[[u8; BYTES_PER_PIXELS]; 4],
+) -> [[u8; BYTES_PER_PIXELS]; 4] {
+    return palette_shades(register).map(|shade| shades[shade as usize]);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{
+        clock::ManualClock,
+        snapshot::{diff_states, StateDiff},
+    };
+
+    // a console past the boot rom, at the entry point of `rom`
+    fn console_at_entry(rom: Vec<u8>) -> Gameboy {
+        let mut console = Gameboy::new(rom).unwrap();
+        console.memory_mut().skip_boot_rom();
+        console.cpu_mut().write_program_counter(0x0100);
+        console.cpu_mut().write_stack_pointer(0xFFFE);
+        return console;
+    }
+
+    #[test]
+    fn snapshots_take_the_rom_from_the_cartridge() {
+        let rom = |global_checksum: u16, byte: u8| {
+            let mut rom = vec![0; 0x8000];
+            rom[0x014E..0x0150].copy_from_slice(&global_checksum.to_be_bytes());
+            rom[0x4000] = byte;
+            return rom;
+        };
+        let mut console = console_at_entry(rom(0x1234, 0xAA));
+        console.memory_mut().write_byte(0xC000, 0x42).unwrap();
+
+        let snapshot = Snapshot::from(&console);
+        assert_eq!(snapshot.rom_checksum, 0x1234);
+        let json = serde_json::to_value(&snapshot).unwrap();
+        assert!(json["memory"].get("fixed_rom_bank").is_none());
+        assert!(json["memory"].get("switch_rom_bank").is_none());
+
+        console.memory_mut().write_byte(0xC000, 0x00).unwrap();
+        console.load_snapshot(snapshot).unwrap();
+        assert_eq!(console.memory().read_byte(0xC000), 0x42);
+        assert_eq!(console.memory().read_byte(0x4000), 0xAA);
+        assert_eq!(console.memory().cartridge_info().global_checksum, 0x1234);
+
+        // another game : refused, and the console is left as it was
+        let mut other = console_at_entry(rom(0x5678, 0xBB));
+        let error = other.load_snapshot(Snapshot::from(&console)).unwrap_err();
+        assert!(matches!(error, SnapshotError::RomMismatch(0x1234, 0x5678)));
+        assert_eq!(other.memory().read_byte(0x4000), 0xBB);
+        assert_eq!(other.memory().read_byte(0xC000), 0x00);
+    }
+
+    #[test]
+    fn diff_between_two_states() {
+        let mut console = console_at_entry(vec![0; 0x8000]);
+        console.cpu_mut().write_r8(&Operand::R8_A, 0x01);
+        let before = Snapshot::from(&console);
+        assert_eq!(diff_states(&before, &Snapshot::from(&console)), []);
+
+        console.cpu_mut().write_r8(&Operand::R8_A, 0x42);
+        console.memory_mut().write_byte(0xC010, 0x99).unwrap();
+        let after = Snapshot::from(&console);
+        assert_eq!(
+            diff_states(&before, &after),
+            [
+                StateDiff::Value("cpu.a".to_string(), "1".to_string(), "66".to_string()),
+                StateDiff::Region("memory.wram".to_string(), 1, 0x10),
+            ]
+        );
+    }
+
+    #[test]
+    fn rom_file_errors() {
+        let path = std::env::temp_dir().join(format!("koholint-rom-{}.gb", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let _ = std::fs::remove_file(path);
+        match Gameboy::from_file(path) {
+            Err(RomError::NotFound(missing)) => assert_eq!(missing, path),
+            other => panic!("expected a missing file, got {:?}", other.err()),
+        }
+
+        // too small to even hold the entry point
+        std::fs::write(path, []).unwrap();
+        let result = Gameboy::from_file(path);
+        std::fs::remove_file(path).unwrap();
+        assert!(matches!(result, Err(RomError::TooSmall(0))));
+    }
+
+    #[test]
+    fn clock_kept_across_reset_and_load() {
+        let clock = ManualClock::new(Duration::from_secs(100));
+        let mut console = console_at_entry(vec![0; 0x8000]);
+        console.set_clock(Box::new(clock.clone()));
+
+        console.reset(vec![0; 0x8000]).unwrap();
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(console.clock().now(), Duration::from_secs(101));
+
+        let snapshot = Snapshot::from(&console);
+        console.load_snapshot(snapshot).unwrap();
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(console.clock().now(), Duration::from_secs(102));
+    }
+
+    #[test]
+    fn mbc3_rtc_follows_the_clock() {
+        let mut rom = vec![0; 0x8000];
+        // MBC3+TIMER+BATTERY
+        rom[0x0147] = 0x0F;
+        let clock = ManualClock::new(Duration::from_secs(1000));
+        let mut console = console_at_entry(rom);
+        console.set_clock(Box::new(clock.clone()));
+        console.step().unwrap();
+
+        // seconds register, latched with 00 then 01
+        let latched_seconds = |console: &mut Gameboy| {
+            console.step().unwrap();
+            let memory = console.memory_mut();
+            memory.write_byte(0x4000, 0x08).unwrap();
+            memory.write_byte(0x6000, 0x00).unwrap();
+            memory.write_byte(0x6000, 0x01).unwrap();
+            return memory.read_byte(0xA000);
+        };
+        assert_eq!(latched_seconds(&mut console), 0);
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(latched_seconds(&mut console), 5);
+        // the registers keep the latched time until the next latch
+        clock.advance(Duration::from_secs(3));
+        console.step().unwrap();
+        assert_eq!(console.memory().read_byte(0xA000), 5);
+        assert_eq!(latched_seconds(&mut console), 8);
+
+        // the ram is back once a ram bank is selected
+        console.memory_mut().write_byte(0x4000, 0x00).unwrap();
+        console.memory_mut().write_byte(0xA000, 0x42).unwrap();
+        assert_eq!(console.memory().read_byte(0xA000), 0x42);
+    }
+
+    #[test]
+    fn background_map_addresses() {
+        // (screen x, screen y, scx, scy) -> (entry, x in tile, y in tile)
+        for (position, expected) in [
+            ((0, 0, 0, 0), (0x9800, 0, 0)),
+            ((9, 17, 0, 0), (0x9841, 1, 1)),
+            ((0, 0, 12, 20), (0x9841, 4, 4)),
+            ((159, 143, 0, 0), (0x9A33, 7, 7)),
+            // past the right & bottom edges, the map starts over
+            ((10, 0, 0xF8, 0), (0x9800, 2, 0)),
+            ((0, 10, 0, 0xF8), (0x9800, 0, 2)),
+            ((255, 255, 0, 0), (0x9BFF, 7, 7)),
+        ] {
+            let (x, y, scroll_x, scroll_y) = position;
+            assert_eq!(
+                background_map_address(0x9800, x, y, scroll_x, scroll_y),
+                expected,
+                "{position:?}"
+            );
+        }
+        assert_eq!(background_map_address(0x9C00, 9, 17, 0, 0).0, 0x9C41);
+    }
+
+    // runs until the ppu starts drawing `line`, or the next frame for line 0
+    fn run_to_line(console: &mut Gameboy, line: u8) {
+        let frame = console.frame_count();
+        while console.memory().ppu_line() != line || (line == 0 && console.frame_count() == frame) {
+            console.step().unwrap();
+        }
+    }
+
+    #[test]
+    fn inspect_window_pixels() {
+        let mut console = console_at_entry(vec![0; 0x8000]);
+        // lcd, window & background on, window at (20, 10)
+        console.memory_mut().write_byte(0xFF4A, 10).unwrap();
+        console.memory_mut().write_byte(0xFF4B, 27).unwrap();
+        run_to_line(&mut console, 0);
+        console.memory_mut().write_byte(0xFF40, 0xB1).unwrap();
+        // hidden on lines 20 to 29
+        run_to_line(&mut console, 20);
+        console.memory_mut().write_byte(0xFF40, 0x91).unwrap();
+        run_to_line(&mut console, 30);
+        console.memory_mut().write_byte(0xFF40, 0xB1).unwrap();
+        run_to_line(&mut console, 0);
+
+        let pixel = console.inspect_pixel(30, 15).unwrap();
+        assert!(pixel.window);
+        assert_eq!(pixel.map_address, 0x9800 + 1);
+        assert!(!console.inspect_pixel(30, 25).unwrap().window);
+        // the window resumes on its 21st line, not on line 40 - WY
+        let pixel = console.inspect_pixel(30, 40).unwrap();
+        assert!(pixel.window);
+        assert_eq!(pixel.map_address, 0x9800 + 2 * 32 + 1);
+        // left of the window
+        assert!(!console.inspect_pixel(19, 40).unwrap().window);
+        assert!(console.inspect_pixel(20, 40).unwrap().window);
+        // above it
+        assert!(!console.inspect_pixel(30, 9).unwrap().window);
+
+        // off the right edge, the window isn't drawn at all
+        console.memory_mut().write_byte(0xFF4B, 167).unwrap();
+        run_to_line(&mut console, 0);
+        assert!(!console.inspect_pixel(159, 40).unwrap().window);
+    }
+
+    // https://gbdev.io/pandocs/Tile_Maps.html#window
+    // the window line counter only moves on lines where the window was drawn, and
+    // WY is only compared to LY on each line : changing it afterwards doesn't stop the window
+    #[test]
+    fn window_line_counter_across_toggles() {
+        let mut console = console_at_entry(vec![0; 0x8000]);
+        console.memory_mut().write_byte(0xFF4A, 10).unwrap();
+        console.memory_mut().write_byte(0xFF4B, 7).unwrap();
+        run_to_line(&mut console, 0);
+        console.memory_mut().write_byte(0xFF40, 0xB1).unwrap();
+        // the lines are drawn as LY reaches them : the writes apply from the next one
+        run_to_line(&mut console, 20);
+        console.memory_mut().write_byte(0xFF40, 0x91).unwrap();
+        run_to_line(&mut console, 30);
+        console.memory_mut().write_byte(0xFF40, 0xB1).unwrap();
+        run_to_line(&mut console, 143);
+
+        let mut expected = [None; SCREEN_H];
+        for line in 10..=20 {
+            expected[line] = Some(line as u8 - 10);
+        }
+        for line in 31..SCREEN_H {
+            expected[line] = Some(line as u8 - 20);
+        }
+        assert_eq!(*console.window_lines, expected);
+    }
+
+    #[test]
+    fn window_resumes_where_it_stopped() {
+        let mut console = console_at_entry(vec![0; 0x8000]);
+        let memory = console.memory_mut();
+        // the rows of the window map (9C00) are tiles 1, 2 & 3, in colors 1, 2 & 3
+        for tile in 1..=3u16 {
+            for row in 0..8 {
+                let address = 0x8000 + tile * 16 + row * 2;
+                memory
+                    .write_byte(address, if tile & 1 != 0 { 0xFF } else { 0 })
+                    .unwrap();
+                memory
+                    .write_byte(address + 1, if tile & 2 != 0 { 0xFF } else { 0 })
+                    .unwrap();
+            }
+            for column in 0..32 {
+                memory
+                    .write_byte(0x9C00 + (tile - 1) * 32 + column, tile as u8)
+                    .unwrap();
+            }
+        }
+        memory.write_byte(0xFF47, 0xE4).unwrap();
+        memory.write_byte(0xFF4A, 10).unwrap();
+        memory.write_byte(0xFF4B, 7).unwrap();
+
+        // shown on lines 10-15, hidden on 16-30, shown again from 31
+        run_to_line(&mut console, 0);
+        console.memory_mut().write_byte(0xFF40, 0xF1).unwrap();
+        run_to_line(&mut console, 15);
+        console.memory_mut().write_byte(0xFF40, 0xD1).unwrap();
+        run_to_line(&mut console, 30);
+        console.memory_mut().write_byte(0xFF40, 0xF1).unwrap();
+        run_to_line(&mut console, 143);
+
+        let shade = |line: usize| {
+            let start = line * SCREEN_W * BYTES_PER_PIXELS;
+            return <[u8; 4]>::try_from(&console.get_framebuffer()[start..start + 4]).unwrap();
+        };
+        assert_eq!(shade(9), SHADES[0]);
+        assert_eq!(shade(12), SHADES[1]);
+        assert_eq!(shade(20), SHADES[0]);
+        // line 31 is the 7th line of the window, line 33 the 9th, from the second row of tiles
+        assert_eq!(shade(31), SHADES[1]);
+        assert_eq!(shade(32), SHADES[1]);
+        assert_eq!(shade(33), SHADES[2]);
+        assert_eq!(shade(41), SHADES[3]);
+    }
+
+    #[test]
+    fn window_line_counter_across_wy_changes() {
+        let mut console = console_at_entry(vec![0; 0x8000]);
+        console.memory_mut().write_byte(0xFF4A, 10).unwrap();
+        console.memory_mut().write_byte(0xFF4B, 7).unwrap();
+        run_to_line(&mut console, 0);
+        console.memory_mut().write_byte(0xFF40, 0xB1).unwrap();
+
+        // moved below the current line once the window started : it goes on
+        run_to_line(&mut console, 40);
+        console.memory_mut().write_byte(0xFF4A, 100).unwrap();
+        run_to_line(&mut console, 143);
+        let mut expected = [None; SCREEN_H];
+        for line in 10..SCREEN_H {
+            expected[line] = Some(line as u8 - 10);
+        }
+        assert_eq!(*console.window_lines, expected);
+
+        // moved above the current line before the window started : LY never matches it
+        run_to_line(&mut console, 20);
+        console.memory_mut().write_byte(0xFF4A, 10).unwrap();
+        run_to_line(&mut console, 143);
+        assert_eq!(*console.window_lines, [None; SCREEN_H]);
+
+        // moved further down before the window started : it starts there instead
+        run_to_line(&mut console, 5);
+        console.memory_mut().write_byte(0xFF4A, 60).unwrap();
+        run_to_line(&mut console, 143);
+        let mut expected = [None; SCREEN_H];
+        for line in 60..SCREEN_H {
+            expected[line] = Some(line as u8 - 60);
+        }
+        assert_eq!(*console.window_lines, expected);
+    }
+
+    #[test]
+    fn inspect_tall_objects() {
+        let mut console = console_at_entry(vec![0; 0x8000]);
+        // an object at (30, 50), with no transparent pixel in tiles 0 & 1
+        console.memory_mut().write_byte(0xFE00, 16 + 50).unwrap();
+        console.memory_mut().write_byte(0xFE01, 8 + 30).unwrap();
+        for address in 0x8000..0x8020 {
+            console.memory_mut().write_byte(address, 0xFF).unwrap();
+        }
+
+        console.memory_mut().write_byte(0xFF40, 0x93).unwrap();
+        run_to_line(&mut console, 0);
+        run_to_line(&mut console, 0);
+        assert_eq!(console.inspect_pixel(31, 55).unwrap().object, Some(0));
+        assert_eq!(console.inspect_pixel(31, 60).unwrap().object, None);
+
+        // 8x16
+        console.memory_mut().write_byte(0xFF40, 0x97).unwrap();
+        run_to_line(&mut console, 0);
+        run_to_line(&mut console, 0);
+        assert_eq!(console.inspect_pixel(31, 60).unwrap().object, Some(0));
+        assert_eq!(console.inspect_pixel(31, 66).unwrap().object, None);
+    }
+
+    #[test]
+    fn overlapping_objects_priority() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0143] = 0xC0;
+        let mut console = console_at_entry(rom);
+        let memory = console.memory_mut();
+        // the background is tile 0 in color 1, object tiles 1 & 2 are in colors 3 & 2
+        for row in 0..8 {
+            memory.write_byte(0x8000 + row * 2, 0xFF).unwrap();
+            memory.write_byte(0x8010 + row * 2, 0xFF).unwrap();
+            memory.write_byte(0x8011 + row * 2, 0xFF).unwrap();
+            memory.write_byte(0x8021 + row * 2, 0xFF).unwrap();
+        }
+        memory.write_byte(0xFF47, 0xE4).unwrap();
+        // object 0 at (20, 10) is right of object 1 at (14, 10), they overlap on 20-21
+        memory.write_byte(0xFE00, 16 + 10).unwrap();
+        memory.write_byte(0xFE01, 8 + 20).unwrap();
+        memory.write_byte(0xFE02, 1).unwrap();
+        memory.write_byte(0xFE04, 16 + 10).unwrap();
+        memory.write_byte(0xFE05, 8 + 14).unwrap();
+        memory.write_byte(0xFE06, 2).unwrap();
+        memory.write_byte(0xFF40, 0x93).unwrap();
+
+        let drawn = |console: &Gameboy| {
+            let start = (10 * SCREEN_W + 21) * BYTES_PER_PIXELS;
+            return <[u8; 4]>::try_from(&console.get_framebuffer()[start..start + 4]).unwrap();
+        };
+
+        // https://gbdev.io/pandocs/OAM.html#drawing-priority
+        // OPRI = 0 : the first in oam wins
+        console.memory_mut().write_byte(0xFF6C, 0).unwrap();
+        run_to_line(&mut console, 0);
+        run_to_line(&mut console, 0);
+        assert_eq!(console.inspect_pixel(21, 10).unwrap().object, Some(0));
+        assert_eq!(drawn(&console), SHADES[3]);
+
+        // OPRI = 1 : the smallest x wins
+        console.memory_mut().write_byte(0xFF6C, 1).unwrap();
+        run_to_line(&mut console, 0);
+        assert_eq!(console.inspect_pixel(21, 10).unwrap().object, Some(1));
+        assert_eq!(drawn(&console), SHADES[2]);
+
+        // https://gbdev.io/pandocs/Tile_Maps.html#bg-to-obj-priority-in-cgb-mode
+        // the attributes of the background tile under (21, 10), in vram bank 1
+        console.memory_mut().write_byte(0xFF4F, 1).unwrap();
+        console
+            .memory_mut()
+            .write_byte(0x9822, 0b1000_0000)
+            .unwrap();
+        console.memory_mut().write_byte(0xFF4F, 0).unwrap();
+        assert_eq!(console.memory().read_byte(0x9822), 0);
+        run_to_line(&mut console, 0);
+        assert_eq!(console.inspect_pixel(21, 10).unwrap().object, None);
+        assert_eq!(drawn(&console), SHADES[1]);
+        // still on top where the background tile has no priority
+        assert_eq!(console.inspect_pixel(15, 10).unwrap().object, Some(1));
+
+        // LCDC bit 0 clear : the objects win whatever the attributes say
+        console.memory_mut().write_byte(0xFF40, 0x92).unwrap();
+        run_to_line(&mut console, 0);
+        assert_eq!(console.inspect_pixel(21, 10).unwrap().object, Some(1));
+        assert_eq!(drawn(&console), SHADES[2]);
+    }
+
+    // https://gbdev.io/pandocs/Rendering.html#mode-3-length
+    #[test]
+    fn scx_lengthens_mode_3() {
+        let mut console = console_at_entry(vec![0; 0x8000]);
+        // tile 0 has color 1 in its first column only
+        for row in 0..8 {
+            console
+                .memory_mut()
+                .write_byte(0x8000 + row * 2, 0x80)
+                .unwrap();
+        }
+        console.memory_mut().write_byte(0xFF47, 0xE4).unwrap();
+
+        for scx in [0, 3] {
+            console.memory_mut().write_byte(0xFF43, scx).unwrap();
+            run_to_line(&mut console, 0);
+            run_to_line(&mut console, 20);
+            assert_eq!(console.mode_3_length(), 172 + scx as u64);
+
+            // the last dot of a mode 3 lengthened by 3
+            console.tick_ppu(80 + 174 - console.ly_cycles);
+            let mode = console.memory().read_byte(0xFF41) & 0b11;
+            assert_eq!(mode, if scx == 3 { 3 } else { 0 }, "scx {scx}");
+            console.tick_ppu(1);
+            assert_eq!(console.memory().read_byte(0xFF41) & 0b11, 0, "scx {scx}");
+            // the line still ends after 456 dots
+            console.tick_ppu(456 - 80 - 175);
+            assert_eq!(console.memory().read_byte(0xFF44), 21, "scx {scx}");
+
+            // the first tile column shows up 3 pixels to the left
+            let line = console.get_scanline_buffer(19);
+            let shade = |x: usize| &line[(x * BYTES_PER_PIXELS)..((x + 1) * BYTES_PER_PIXELS)];
+            let shifted = 8 - scx as usize % 8;
+            assert_eq!(shade(shifted % 8), SHADES[1], "scx {scx}");
+            assert_eq!(shade(shifted % 8 + 1), SHADES[0], "scx {scx}");
+            assert_eq!(shade((shifted + 8) % 160), SHADES[1], "scx {scx}");
+        }
+    }
+
+    #[test]
+    fn long_ppu_ticks_keep_stat_interrupts() {
+        let mut console = console_at_entry(vec![0; 0x8000]);
+        // the mode 2 source only
+        console
+            .memory_mut()
+            .write_byte(0xFF41, 0b0010_0000)
+            .unwrap();
+
+        // (dots into line 10, dots ticked at once, mode at the end)
+        for (start, dots, mode) in [(90, 456, 3), (260, 456 + 80, 0)] {
+            run_to_line(&mut console, 10);
+            console.tick_ppu(start - console.ly_cycles);
+            console.memory_mut().write_byte(0xFF0F, 0).unwrap();
+
+            console.tick_ppu(dots);
+            assert_eq!(console.memory().read_byte(0xFF44), 11, "{dots} dots");
+            assert_eq!(
+                console.memory().read_byte(0xFF41) & 0b11,
+                mode,
+                "{dots} dots"
+            );
+            // the oam scan of line 11 went by in the middle of the chunk
+            assert_eq!(
+                console.memory().read_byte(0xFF0F) & 0b10,
+                0b10,
+                "{dots} dots"
+            );
+        }
+    }
+
+    // https://gbdev.io/pandocs/STAT.html#ff44--ly-lcd-y-coordinate-read-only
+    #[test]
+    fn ly_wraps_early_on_line_153() {
+        let mut console = console_at_entry(vec![0; 0x8000]);
+        console.memory_mut().write_byte(0xFF45, 0).unwrap();
+        run_to_line(&mut console, 152);
+        console.tick_ppu(456 - console.ly_cycles);
+        assert_eq!(console.memory().read_byte(0xFF44), 153);
+
+        console.tick_ppu(LY_153_CYCLES - 1);
+        assert_eq!(console.memory().read_byte(0xFF44), 153);
+        assert_eq!(console.memory().read_byte(0xFF41) & 0b100, 0);
+        console.tick_ppu(1);
+        assert_eq!(console.memory().read_byte(0xFF44), 0);
+        // LYC = 0 already matches, on what is still line 153 for the ppu
+        assert_eq!(console.memory().read_byte(0xFF41) & 0b100, 0b100);
+        assert_eq!(console.memory().ppu_line(), 153);
+        assert_eq!(console.memory().read_byte(0xFF41) & 0b11, 1);
+
+        // and the next line is line 0
+        console.tick_ppu(456 - LY_153_CYCLES);
+        assert_eq!(console.memory().ppu_line(), 0);
+        assert_eq!(console.memory().read_byte(0xFF44), 0);
+        assert_eq!(console.memory().read_byte(0xFF41) & 0b11, 2);
+    }
+
+    // https://gbdev.io/pandocs/STAT.html#stat-blocking
+    #[test]
+    fn stat_blocking() {
+        let mut console = console_at_entry(vec![0; 0x8000]);
+        // ticks to `dots` into the current line (or the next one if past it)
+        // and returns whether a stat interrupt was requested on the way
+        let stat_interrupt = |console: &mut Gameboy, dots: u64| {
+            console.memory_mut().write_byte(0xFF0F, 0).unwrap();
+            console.tick_ppu((dots + 456 - console.ly_cycles) % 456);
+            return console.memory().read_byte(0xFF0F) & 0b10 != 0;
+        };
+
+        // h-blank & LY = LYC sources, LYC = 10
+        console
+            .memory_mut()
+            .write_byte(0xFF41, 0b0100_1000)
+            .unwrap();
+        console.memory_mut().write_byte(0xFF45, 10).unwrap();
+        run_to_line(&mut console, 9);
+        assert!(stat_interrupt(&mut console, 300));
+        // line 10 : the line is still up from the h-blank when LY = LYC,
+        // and it stays up through the next h-blank
+        assert!(!stat_interrupt(&mut console, 0));
+        assert!(!stat_interrupt(&mut console, 300));
+        // line 11 : the line went down during the oam scan
+        assert!(!stat_interrupt(&mut console, 100));
+        assert!(stat_interrupt(&mut console, 300));
+
+        // LY = LYC alone
+        console
+            .memory_mut()
+            .write_byte(0xFF41, 0b0100_0000)
+            .unwrap();
+        console.memory_mut().write_byte(0xFF45, 12).unwrap();
+        // the line goes down with the h-blank source
+        assert!(!stat_interrupt(&mut console, 301));
+        assert!(stat_interrupt(&mut console, 0));
+        assert_eq!(console.memory().read_byte(0xFF44), 12);
+
+        // h-blank & oam scan sources : the line stays up from one to the other
+        console
+            .memory_mut()
+            .write_byte(0xFF41, 0b0010_1000)
+            .unwrap();
+        console.memory_mut().write_byte(0xFF45, 200).unwrap();
+        run_to_line(&mut console, 20);
+        assert!(stat_interrupt(&mut console, 300));
+        assert!(!stat_interrupt(&mut console, 40));
+        assert!(!stat_interrupt(&mut console, 100));
+        assert!(stat_interrupt(&mut console, 300));
+    }
+
+    // https://gbdev.io/pandocs/OAM_Corruption_Bug.html
+    #[test]
+    fn inc_rr_corrupts_oam_during_the_scan() {
+        let mut rom = vec![0; 0x8000];
+        // INC HL
+        rom[0x0200] = 0x23;
+        let pattern: [u8; 0xA0] = std::array::from_fn(|i| i as u8);
+
+        // (corruption enabled, dots into the line when INC HL runs, corrupted)
+        for (enabled, dots, corrupted) in [(true, 40, true), (false, 40, false), (true, 100, false)]
+        {
+            let mut console = console_at_entry(rom.clone());
+            console.set_oam_corruption(enabled);
+            console.memory_mut().oam_mut().copy_from_slice(&pattern);
+            run_to_line(&mut console, 10);
+            console.tick_ppu(dots - console.ly_cycles);
+            console.cpu_mut().write_program_counter(0x0200);
+            console.cpu_mut().write_hl_register(0xFE00);
+            console.step().unwrap();
+            assert_eq!(console.cpu().read_hl_register(), 0xFE01);
+
+            let oam = console.memory().oam();
+            let case = format!("enabled {enabled}, {dots} dots");
+            if corrupted {
+                // after the opcode fetch, row 11 is being read : ((a ^ c) & (b ^ c)) ^ c of
+                // the words of rows 10 & 11 gives back the first word of row 10,
+                // and the others are copied from it
+                assert_eq!(oam[88..96], pattern[80..88], "{case}");
+                assert_eq!(oam[..88], pattern[..88], "{case}");
+                assert_eq!(oam[96..], pattern[96..], "{case}");
+            } else {
+                assert_eq!(*oam, pattern, "{case}");
+            }
+        }
+    }
+
+    #[test]
+    fn scx_logged_per_line() {
+        let mut console = console_at_entry(vec![0; 0x8000]);
+        console.memory_mut().write_byte(0xFF42, 0x42).unwrap();
+        // a raster effect : SCX = LY, written as each line starts, for the next one
+        run_to_line(&mut console, 0);
+        console.memory_mut().write_byte(0xFF43, 1).unwrap();
+        for line in 1..SCREEN_H as u8 - 1 {
+            run_to_line(&mut console, line);
+            console.memory_mut().write_byte(0xFF43, line + 1).unwrap();
+        }
+        run_to_line(&mut console, 0);
+
+        for (line, registers) in console.line_registers().iter().enumerate() {
+            // line 0 is drawn with the last value of the frame before
+            let scx = if line == 0 { SCREEN_H - 1 } else { line };
+            assert_eq!(registers.scx as usize, scx, "line {line}");
+            assert_eq!(registers.scy, 0x42, "line {line}");
+        }
+    }
+
+    #[test]
+    fn lcd_off_mid_frame() {
+        let mut console = console_at_entry(vec![0; 0x8000]);
+        // a background in color 3 over the whole screen
+        for address in 0x8000..0x8010 {
+            console.memory_mut().write_byte(address, 0xFF).unwrap();
+        }
+        console.memory_mut().write_byte(0xFF47, 0xE4).unwrap();
+        run_to_line(&mut console, 0);
+        run_to_line(&mut console, 70);
+        assert!(console
+            .get_framebuffer()
+            .chunks_exact(BYTES_PER_PIXELS)
+            .take(SCREEN_W * 70)
+            .all(|pixel| pixel == SHADES[3]));
+
+        console.memory_mut().write_byte(0xFF40, 0x11).unwrap();
+        console.step().unwrap();
+        assert_eq!(console.memory().read_byte(0xFF44), 0);
+        assert_eq!(console.memory().read_byte(0xFF41) & 0b11, 0);
+        assert!(console
+            .get_framebuffer()
+            .chunks_exact(BYTES_PER_PIXELS)
+            .all(|pixel| pixel == SHADES[0]));
+        // and it stays that way
+        console.step_cycles(456 * 10).unwrap();
+        assert_eq!(console.memory().read_byte(0xFF44), 0);
+        assert_eq!(console.memory().read_byte(0xFF41) & 0b11, 0);
+
+        // turned back on, the frame starts over from the oam scan of line 0
+        console.memory_mut().write_byte(0xFF40, 0x91).unwrap();
+        console.step().unwrap();
+        assert_eq!(console.memory().read_byte(0xFF44), 0);
+        assert_eq!(console.memory().read_byte(0xFF41) & 0b11, 2);
+        console.step_cycles(456).unwrap();
+        assert_eq!(console.memory().read_byte(0xFF44), 1);
+    }
+
+    #[test]
+    fn oam_entry_bounds() {
+        let mut console = console_at_entry(vec![0; 0x8000]);
+        console.memory_mut().write_byte(0xFE9E, 0x42).unwrap();
+        assert_eq!(console.get_oam_entry(39).unwrap().tile, 0x42);
+        assert!(console.get_oam_entry(40).is_none());
+        assert!(console.get_oam_entry(usize::MAX).is_none());
+    }
+
+    #[test]
+    fn tile_line_decoding() {
+        // https://gbdev.io/pandocs/Tile_Data.html, one bit at a time
+        for byte_1 in 0..=255u8 {
+            for byte_2 in 0..=255u8 {
+                let expected: [u8; 8] = std::array::from_fn(|x| {
+                    let bit = 7 - x;
+                    ((byte_2 >> bit) & 1) << 1 | ((byte_1 >> bit) & 1)
+                });
+                assert_eq!(
+                    decode_tile_line(byte_1, byte_2),
+                    expected,
+                    "{byte_1:#04X} {byte_2:#04X}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn cycle_budget_in_double_speed() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0143] = 0xC0;
+        // STOP, which switches the speed after KEY1 is armed, then JP 0150 over the header
+        rom[0x0100] = 0x10;
+        rom[0x0102..0x0105].copy_from_slice(&[0xC3, 0x50, 0x01]);
+        let mut console = Gameboy::new(rom).unwrap();
+        console.memory_mut().write_byte(0xFF4D, 0x01).unwrap();
+        console.step().unwrap();
+        console.step().unwrap();
+        assert!(console.memory().is_double_speed());
+
+        // 4 cycles per NOP, whatever the speed
+        let start = console.cpu().read_program_counter();
+        let cycles = console.step_cycles(1000).unwrap();
+        assert_eq!(cycles, 1000);
+        assert_eq!(console.cpu().read_program_counter(), start + 250);
+        assert_eq!(console.last_step_cycles(), 4);
+
+        assert!(!console.run_until_pc(0x7000, 400).unwrap());
+        assert_eq!(console.cpu().read_program_counter(), start + 350);
+    }
+
+    #[test]
+    fn div_speed_against_ly() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0143] = 0xC0;
+        // STOP, which switches the speed after KEY1 is armed, then JP 0150 over the header
+        rom[0x0100] = 0x10;
+        rom[0x0102..0x0105].copy_from_slice(&[0xC3, 0x50, 0x01]);
+
+        // the divider counts cpu cycles, the ppu dots : 64 lines are 64 * 456 dots
+        for double_speed in [false, true] {
+            let mut console = console_at_entry(rom.clone());
+            if double_speed {
+                console.memory_mut().write_byte(0xFF4D, 0x01).unwrap();
+            }
+            console.step().unwrap();
+            console.step().unwrap();
+            assert_eq!(console.memory().is_double_speed(), double_speed);
+
+            run_to_line(&mut console, 10);
+            let start = console.memory().internal_div();
+            run_to_line(&mut console, 74);
+            let counted = console.memory().internal_div().wrapping_sub(start) as i64;
+            let expected = 64 * 456 * if double_speed { 2 } else { 1 };
+            // the lines are only seen once the instruction crossing them is over
+            assert!(
+                (counted - expected).abs() <= 16,
+                "{counted} cycles for {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn conditional_branch_timings() {
+        // https://gbdev.io/pandocs/CPU_Instruction_Set.html
+        // (name, first opcode, size, cycles when not taken, cycles when taken)
+        let branches = [
+            ("jr", 0x20, 2, 8, 12),
+            ("ret", 0xC0, 1, 8, 20),
+            ("jp", 0xC2, 3, 12, 16),
+            ("call", 0xC4, 3, 12, 24),
+        ];
+        // nz, z, nc, c : the flag each condition tests, and the value it needs to branch
+        let conditions = [
+            ("nz", false, false),
+            ("z", false, true),
+            ("nc", true, false),
+            ("c", true, true),
+        ];
+
+        for (name, first_opcode, size, not_taken, taken) in branches {
+            for (i, (cc, carry, expected)) in conditions.into_iter().enumerate() {
+                for branch in [false, true] {
+                    let mut rom = vec![0; 0x8000];
+                    rom[0x0100] = first_opcode + i as u8 * 8;
+                    // jr +0x10, or jp / call 0x1234
+                    rom[0x0101..0x0103].copy_from_slice(&[0x10, 0x12]);
+                    let mut console = console_at_entry(rom);
+                    // ret pops 0x4000
+                    console.cpu_mut().write_stack_pointer(0xFFFC);
+                    console.memory_mut().write_byte(0xFFFC, 0x00).unwrap();
+                    console.memory_mut().write_byte(0xFFFD, 0x40).unwrap();
+
+                    let value = if branch { expected } else { !expected };
+                    if carry {
+                        console.cpu_mut().write_c_flag(value);
+                    } else {
+                        console.cpu_mut().write_z_flag(value);
+                    }
+                    console.step().unwrap();
+
+                    let pc = console.cpu().read_program_counter();
+                    assert_eq!(pc != 0x0100 + size, branch, "{name} {cc} : pc {pc:#06X}");
+                    assert_eq!(
+                        console.last_step_cycles(),
+                        if branch { taken } else { not_taken },
+                        "{name} {cc}, {}",
+                        if branch { "taken" } else { "not taken" }
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn one_interrupt_per_reti() {
+        let mut rom = vec![0; 0x8000];
+        // RETI in the vblank & timer handlers, NOPs everywhere else
+        rom[0x40] = 0xD9;
+        rom[0x50] = 0xD9;
+        let mut console = console_at_entry(rom);
+        console.memory_mut().write_byte(0xFFFF, 0b101).unwrap();
+        console.memory_mut().write_byte(0xFF0F, 0b101).unwrap();
+        console.cpu_mut().enable_interrupts();
+
+        // vblank first, the timer stays requested
+        console.step().unwrap();
+        assert_eq!(console.cpu().read_program_counter(), 0x40);
+        assert!(!console.interrupts_enabled());
+        assert!(console.memory().is_interrupt_requested(2));
+        assert!(!console.memory().is_interrupt_requested(0));
+
+        // its RETI lets the timer in
+        console.step().unwrap();
+        assert_eq!(console.cpu().read_program_counter(), 0x50);
+        assert!(!console.memory().is_interrupt_requested(2));
+
+        // and that one returns to the main code
+        console.step().unwrap();
+        assert_eq!(console.cpu().read_program_counter(), 0x0101);
+        assert!(console.interrupts_enabled());
+    }
+
+    #[test]
+    fn joypad_interrupt_on_press_only() {
+        let mut console = console_at_entry(vec![0; 0x8000]);
+        // both button groups selected
+        console.memory_mut().write_byte(0xFF00, 0x00).unwrap();
+        console.step().unwrap();
+        assert!(!console.memory().is_interrupt_requested(4));
+
+        console.press_button(Button::A);
+        console.step().unwrap();
+        assert!(console.memory().is_interrupt_requested(4));
+
+        // held down : not requested again
+        console.memory_mut().write_byte(0xFF0F, 0x00).unwrap();
+        for _ in 0..10 {
+            console.step().unwrap();
+        }
+        assert!(!console.memory().is_interrupt_requested(4));
+    }
+
+    // answers every byte with its complement, after a number of polls
+    struct LateDevice {
+        polls: usize,
+        late_by: Option<usize>,
+        sent: Option<u8>,
+        abandoned: Arc<Mutex<usize>>,
+    }
+
+    impl SerialDevice for LateDevice {
+        fn transfer(&mut self, byte: u8) -> Option<u8> {
+            self.sent = Some(byte);
+            self.polls = 0;
+            return None;
+        }
+
+        fn poll_transfer(&mut self) -> Option<u8> {
+            self.polls += 1;
+            if Some(self.polls) == self.late_by {
+                return self.sent.take().map(|byte| !byte);
+            }
+            return None;
+        }
+
+        fn abandon_transfer(&mut self) {
+            self.sent = None;
+            *self.abandoned.lock().unwrap() += 1;
+        }
+
+        fn poll_external(&mut self, _reply: u8) -> Option<u8> {
+            return None;
+        }
+    }
+
+    #[test]
+    fn serial_transfers_wait_for_their_answer() {
+        for late_by in [Some(3), None] {
+            let mut console = console_at_entry(vec![0; 0x8000]);
+            let abandoned = Arc::new(Mutex::new(0));
+            console.connect_serial_device(Box::new(LateDevice {
+                polls: 0,
+                late_by,
+                sent: None,
+                abandoned: abandoned.clone(),
+            }));
+            // send 0x42 on the internal clock
+            console.memory_mut().write_byte(0xFF01, 0x42).unwrap();
+            console.memory_mut().write_byte(0xFF02, 0x81).unwrap();
+
+            // shifted out, but still in progress : the answer is polled once per bit
+            console.step_cycles(512 * 8 + 512 * 2).unwrap();
+            assert_eq!(console.memory().read_byte(0xFF02) & 0x80, 0x80);
+
+            match late_by {
+                Some(_) => {
+                    console.step_cycles(512).unwrap();
+                    assert_eq!(console.memory().read_byte(0xFF02) & 0x80, 0);
+                    assert_eq!(console.memory().read_byte(0xFF01), !0x42);
+                    assert_eq!(*abandoned.lock().unwrap(), 0);
+                }
+                None => {
+                    // 2 bits were waited for already
+                    console.step_cycles(SERIAL_REPLY_TIMEOUT - 512 * 4).unwrap();
+                    assert_eq!(console.memory().read_byte(0xFF02) & 0x80, 0x80);
+                    console.step_cycles(512 * 3).unwrap();
+                    assert_eq!(console.memory().read_byte(0xFF02) & 0x80, 0);
+                    assert_eq!(console.memory().read_byte(0xFF01), 0xFF);
+                    assert_eq!(*abandoned.lock().unwrap(), 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn recovery_skips_illegal_instructions() {
+        // an illegal opcode, then INC A
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100] = 0xD3;
+        rom[0x0101] = 0x3C;
+        let mut console = console_at_entry(rom.clone());
+        assert!(matches!(
+            console.step().unwrap_err().ty,
+            EmulationErrorType::IllegalOpcode(0xD3)
+        ));
+
+        let mut console = console_at_entry(rom);
+        console.set_error_recovery(true);
+        let a = console.cpu().read_a_register();
+        console.step().unwrap();
+        console.step().unwrap();
+        assert_eq!(console.cpu().read_program_counter(), 0x0102);
+        assert_eq!(console.cpu().read_a_register(), a.wrapping_add(1));
+
+        // operands the decoding never produces : CALL A, followed by INC A
+        let mut rom = vec![0; 0x8000];
+        rom[0x0103] = 0x3C;
+        let mut console = console_at_entry(rom);
+        console.set_error_recovery(true);
+        let error = console
+            .execute_instruction(Instruction {
+                op: Operation::CALL {
+                    proc: Operand::R8_A,
+                },
+                size: 3,
+                cycles: 24,
+                branch_cycles: None,
+            })
+            .unwrap_err();
+        assert!(matches!(error.ty, EmulationErrorType::IllegalOperand(_)));
+        assert_eq!(error.pc, Some(0x0100));
+        assert!(error
+            .to_string()
+            .contains("CALL : illegal procedure address a"));
+
+        console.recover_from_error(&error);
+        let a = console.cpu().read_a_register();
+        console.step().unwrap();
+        assert_eq!(console.cpu().read_program_counter(), 0x0104);
+        assert_eq!(console.cpu().read_a_register(), a.wrapping_add(1));
+    }
+
+    #[test]
+    fn accesses_outside_hram_during_oam_dma() {
+        // LD A, (C000) then LDH A, (80)
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0105].copy_from_slice(&[0xFA, 0x00, 0xC0, 0xF0, 0x80]);
+        let mut console = console_at_entry(rom);
+        console.memory_mut().write_byte(0xFF46, 0xC0).unwrap();
+
+        console.step().unwrap();
+        assert_eq!(console.dma_conflict(), Some(0xC000));
+        // HRAM stays reachable
+        console.step().unwrap();
+        assert_eq!(console.dma_conflict(), None);
+
+        // the dma is over after 160 m-cycles
+        console.step_cycles(160 * 4).unwrap();
+        console.cpu_mut().write_program_counter(0x0100);
+        console.step().unwrap();
+        assert_eq!(console.dma_conflict(), None);
+    }
+
+    #[test]
+    fn signed_offsets_to_sp() {
+        // (sp, offset, result, half carry, carry) : the flags come from the low byte of sp
+        let cases = [
+            (0x0000, 0x01, 0x0001, false, false),
+            (0x000F, 0x01, 0x0010, true, false),
+            (0x00F0, 0x10, 0x0100, false, true),
+            (0x00FF, 0x01, 0x0100, true, true),
+            // -1 : the offset is added as an unsigned byte for the flags, and z stays clear
+            (0x0001, 0xFF, 0x0000, true, true),
+            (0x1000, 0xFF, 0x0FFF, false, false),
+        ];
+
+        for (opcode, cycles) in [(0xE8, 16), (0xF8, 12)] {
+            for (sp, offset, result, half_carry, carry) in cases {
+                let mut rom = vec![0; 0x8000];
+                rom[0x0100..0x0102].copy_from_slice(&[opcode, offset]);
+                let mut console = console_at_entry(rom);
+                console.cpu_mut().write_stack_pointer(sp);
+                console.cpu_mut().write_hl_register(0x1234);
+                console.cpu_mut().write_z_flag(true);
+                console.cpu_mut().write_n_flag(true);
+                console.step().unwrap();
+
+                let cpu = console.cpu();
+                let case = format!("{opcode:#04X} : sp {sp:#06X}, offset {offset:#04X}");
+                if opcode == 0xE8 {
+                    assert_eq!(cpu.read_stack_pointer(), result, "{case}");
+                    assert_eq!(cpu.read_hl_register(), 0x1234, "{case}");
+                } else {
+                    assert_eq!(cpu.read_hl_register(), result, "{case}");
+                    assert_eq!(cpu.read_stack_pointer(), sp, "{case}");
+                }
+                assert!(!cpu.read_z_flag(), "{case}");
+                assert!(!cpu.read_n_flag(), "{case}");
+                assert_eq!(cpu.read_h_flag(), half_carry, "{case}");
+                assert_eq!(cpu.read_c_flag(), carry, "{case}");
+                assert_eq!(console.last_step_cycles(), cycles, "{case}");
+            }
+        }
+    }
+
+    #[test]
+    fn add_hl_flags() {
+        // (hl, operand, result, half carry, carry) : carries out of bits 11 & 15
+        let cases = [
+            (0x0000, 0x0001, 0x0001, false, false),
+            (0x0FFF, 0x0001, 0x1000, true, false),
+            (0x0800, 0x0800, 0x1000, true, false),
+            (0x00FF, 0x0001, 0x0100, false, false),
+            (0xF000, 0x1000, 0x0000, false, true),
+            (0xFFFF, 0x0001, 0x0000, true, true),
+            (0x8FFF, 0x8001, 0x1000, true, true),
+        ];
+
+        for opcode in [0x09, 0x19, 0x39] {
+            for (hl, operand, result, half_carry, carry) in cases {
+                for z in [false, true] {
+                    let mut rom = vec![0; 0x8000];
+                    rom[0x0100] = opcode;
+                    let mut console = console_at_entry(rom);
+                    let cpu = console.cpu_mut();
+                    cpu.write_hl_register(hl);
+                    let operand_register = match opcode {
+                        0x09 => Operand::R16_BC,
+                        0x19 => Operand::R16_DE,
+                        _ => Operand::R16_SP,
+                    };
+                    cpu.write_r16(&operand_register, operand);
+                    cpu.write_z_flag(z);
+                    cpu.write_n_flag(true);
+                    console.step().unwrap();
+
+                    let cpu = console.cpu();
+                    let case = format!("{opcode:#04X} : hl {hl:#06X}, operand {operand:#06X}");
+                    assert_eq!(cpu.read_hl_register(), result, "{case}");
+                    // z is left alone
+                    assert_eq!(cpu.read_z_flag(), z, "{case}");
+                    assert!(!cpu.read_n_flag(), "{case}");
+                    assert_eq!(cpu.read_h_flag(), half_carry, "{case}");
+                    assert_eq!(cpu.read_c_flag(), carry, "{case}");
+                    assert_eq!(console.last_step_cycles(), 8, "{case}");
+                }
+            }
+        }
+
+        // ADD HL, HL
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100] = 0x29;
+        let mut console = console_at_entry(rom);
+        console.cpu_mut().write_hl_register(0x8800);
+        console.step().unwrap();
+        assert_eq!(console.cpu().read_hl_register(), 0x1000);
+        assert!(console.cpu().read_h_flag());
+        assert!(console.cpu().read_c_flag());
+    }
+
+    #[test]
+    fn sp_and_pc_wrap_around() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0000] = 0x12;
+        // PUSH BC ; POP DE
+        rom[0x0100..0x0102].copy_from_slice(&[0xC5, 0xD1]);
+        let mut console = console_at_entry(rom);
+        console.memory_mut().write_byte(0xFFFF, 0x00).unwrap();
+
+        // the word goes to FFFF (IE) & 0000 (rom, the write is lost)
+        console.cpu_mut().write_stack_pointer(0x0001);
+        console.cpu_mut().write_r16(&Operand::R16_BC, 0xAB00);
+        console.step().unwrap();
+        assert_eq!(console.cpu().read_stack_pointer(), 0xFFFF);
+        assert_eq!(console.memory().read_byte(0xFFFF), 0x00);
+        console.step().unwrap();
+        assert_eq!(console.cpu().read_stack_pointer(), 0x0001);
+        assert_eq!(console.cpu().read_r16(&Operand::R16_DE), 0x1200);
+
+        // NOP at FFFF (IE is 0)
+        console.cpu_mut().write_program_counter(0xFFFF);
+        console.step().unwrap();
+        assert_eq!(console.cpu().read_program_counter(), 0x0000);
+        console.cpu_mut().increment_program_counter(0xFFFF);
+        assert_eq!(console.cpu().read_program_counter(), 0xFFFF);
+        console.cpu_mut().offset_program_counter(2);
+        assert_eq!(console.cpu().read_program_counter(), 0x0001);
+        console.cpu_mut().offset_program_counter(-2);
+        assert_eq!(console.cpu().read_program_counter(), 0xFFFF);
+        console.cpu_mut().write_stack_pointer(0xFFFF);
+        console.cpu_mut().offset_stack_pointer(1);
+        assert_eq!(console.cpu().read_stack_pointer(), 0x0000);
+        console.cpu_mut().offset_stack_pointer(-1);
+        assert_eq!(console.cpu().read_stack_pointer(), 0xFFFF);
+    }
+
+    #[test]
+    fn interrupt_right_after_reti() {
+        // RETI to 0200 with the timer interrupt waiting
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100] = 0xD9;
+        let mut console = console_at_entry(rom);
+        console.cpu_mut().write_stack_pointer(0xFFFC);
+        console.memory_mut().write_byte(0xFFFC, 0x00).unwrap();
+        console.memory_mut().write_byte(0xFFFD, 0x02).unwrap();
+        console.memory_mut().write_byte(0xFFFF, 0b100).unwrap();
+        console.memory_mut().write_byte(0xFF0F, 0b100).unwrap();
+        assert!(!console.interrupts_enabled());
+
+        // no delay like EI's : the interrupt is serviced before anything runs at 0200
+        console.step().unwrap();
+        assert_eq!(console.cpu().read_program_counter(), 0x50);
+        assert!(!console.memory().is_interrupt_requested(2));
+        // and returns to 0200
+        assert_eq!(console.cpu().read_stack_pointer(), 0xFFFC);
+        assert_eq!(console.memory().read_byte(0xFFFC), 0x00);
+        assert_eq!(console.memory().read_byte(0xFFFD), 0x02);
+
+        // EI on the other hand lets one more instruction run
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100] = 0xFB;
+        let mut console = console_at_entry(rom);
+        console.memory_mut().write_byte(0xFFFF, 0b100).unwrap();
+        console.memory_mut().write_byte(0xFF0F, 0b100).unwrap();
+        console.step().unwrap();
+        assert_eq!(console.cpu().read_program_counter(), 0x0101);
+        console.step().unwrap();
+        assert_eq!(console.cpu().read_program_counter(), 0x50);
+    }
+
+    #[test]
+    fn ret_leaves_ime_alone() {
+        // RET, RET NZ taken & RET Z not taken
+        for (opcode, z_flag) in [(0xC9, false), (0xC0, false), (0xC8, false)] {
+            for ime in [false, true] {
+                let mut rom = vec![0; 0x8000];
+                rom[0x0100] = opcode;
+                let mut console = console_at_entry(rom);
+                console.cpu_mut().write_stack_pointer(0xFFFC);
+                console.memory_mut().write_byte(0xFFFC, 0x00).unwrap();
+                console.memory_mut().write_byte(0xFFFD, 0x02).unwrap();
+                console.cpu_mut().write_z_flag(z_flag);
+                if ime {
+                    console.cpu_mut().enable_interrupts();
+                }
+
+                console.step().unwrap();
+                assert_eq!(console.interrupts_enabled(), ime, "{opcode:#04X}");
+                assert!(!console.cpu().is_ime_scheduled(), "{opcode:#04X}");
+                let pc = if opcode == 0xC8 { 0x0101 } else { 0x0200 };
+                assert_eq!(console.cpu().read_program_counter(), pc, "{opcode:#04X}");
+            }
+        }
+    }
+}
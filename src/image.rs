@@ -0,0 +1,23 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+// writes raw pixels to a png file
+// `data` is expected to be `width * height` pixels in the layout given by `color`
+pub fn write_png<P: AsRef<Path>>(
+    path: P,
+    width: u32,
+    height: u32,
+    color: png::ColorType,
+    data: &[u8],
+) -> std::io::Result<()> {
+    let file = File::create(path)?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(color);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+    writer.finish()?;
+
+    return Ok(());
+}
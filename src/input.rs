@@ -1,7 +1,13 @@
 use glfw::Glfw;
+use log::info;
+use serde::{Deserialize, Serialize};
 
-use crate::{debugger::Debugger, renderer::Renderer};
+use crate::{
+    config::{Command, EmulatorConfig},
+    renderer::Renderer,
+};
 
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct GBInputState {
     pub up: bool,
     pub right: bool,
@@ -28,59 +34,99 @@ impl Default for GBInputState {
     }
 }
 
+// buttons in turbo mode get pressed & released repeatedly while held
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TurboState {
+    pub a_turbo: bool,
+    pub b_turbo: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Button {
+    Up,
+    Right,
+    Left,
+    Down,
+    A,
+    B,
+    Start,
+    Select,
+}
+
+impl GBInputState {
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        match button {
+            Button::Up => self.up = pressed,
+            Button::Right => self.right = pressed,
+            Button::Left => self.left = pressed,
+            Button::Down => self.down = pressed,
+            Button::A => self.a = pressed,
+            Button::B => self.b = pressed,
+            Button::Start => self.start = pressed,
+            Button::Select => self.select = pressed,
+        }
+    }
+}
+
+// returns the emulator commands triggered since the last call
 pub fn handle_input(
     glfw: &mut Glfw,
     renderer: &mut Renderer,
     events: &glfw::GlfwReceiver<(f64, glfw::WindowEvent)>,
-    debugger: &mut Debugger,
+    config: &EmulatorConfig,
     input_state: &mut GBInputState,
-) {
+    turbo_state: &mut TurboState,
+) -> Vec<Command> {
+    let mut commands = Vec::new();
     glfw.poll_events();
     for (_, event) in glfw::flush_messages(&events) {
         match event {
             glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
                 renderer.window.set_should_close(true)
             }
-            glfw::WindowEvent::Key(glfw::Key::P, _, glfw::Action::Press, _) => {
-                debugger.pause();
-            }
-            glfw::WindowEvent::Key(glfw::Key::Up, _, glfw::Action::Press, _) => {
-                input_state.up = true;
+            glfw::WindowEvent::FramebufferSize(width, height) => {
+                renderer.resize((width, height));
             }
-            glfw::WindowEvent::Key(glfw::Key::Up, _, glfw::Action::Release, _) => {
-                input_state.up = false;
+            glfw::WindowEvent::Key(key, _, glfw::Action::Press, _)
+                if config.command_for_key(key).is_some() =>
+            {
+                commands.extend(config.command_for_key(key));
             }
-            glfw::WindowEvent::Key(glfw::Key::Right, _, glfw::Action::Press, _) => {
-                input_state.right = true;
+            // shift + a button key toggles turbo on that button
+            glfw::WindowEvent::Key(key, _, glfw::Action::Press, modifiers)
+                if modifiers.contains(glfw::Modifiers::Shift) =>
+            {
+                match config.button_for_key(key) {
+                    Some(Button::A) => {
+                        turbo_state.a_turbo = !turbo_state.a_turbo;
+                        info!(
+                            "TURBO A : {}",
+                            if turbo_state.a_turbo { "ON" } else { "OFF" }
+                        );
+                    }
+                    Some(Button::B) => {
+                        turbo_state.b_turbo = !turbo_state.b_turbo;
+                        info!(
+                            "TURBO B : {}",
+                            if turbo_state.b_turbo { "ON" } else { "OFF" }
+                        );
+                    }
+                    _ => {}
+                }
             }
-            glfw::WindowEvent::Key(glfw::Key::Right, _, glfw::Action::Release, _) => {
-                input_state.right = false;
+            glfw::WindowEvent::Key(key, _, glfw::Action::Press, _) => {
+                if let Some(button) = config.button_for_key(key) {
+                    input_state.set_button(button, true);
+                }
             }
-            glfw::WindowEvent::Key(glfw::Key::Left, _, glfw::Action::Press, _) => {
-                input_state.left = true;
-            }
-            glfw::WindowEvent::Key(glfw::Key::Left, _, glfw::Action::Release, _) => {
-                input_state.left = false;
-            }
-            glfw::WindowEvent::Key(glfw::Key::Down, _, glfw::Action::Press, _) => {
-                input_state.down = true;
-            }
-            glfw::WindowEvent::Key(glfw::Key::Down, _, glfw::Action::Release, _) => {
-                input_state.down = false;
-            }
-            glfw::WindowEvent::Key(glfw::Key::Z, _, glfw::Action::Press, _) => {
-                input_state.b = true;
-            }
-            glfw::WindowEvent::Key(glfw::Key::Z, _, glfw::Action::Release, _) => {
-                input_state.b = false;
-            }
-            glfw::WindowEvent::Key(glfw::Key::X, _, glfw::Action::Press, _) => {
-                input_state.a = true;
-            }
-            glfw::WindowEvent::Key(glfw::Key::X, _, glfw::Action::Release, _) => {
-                input_state.a = false;
+            glfw::WindowEvent::Key(key, _, glfw::Action::Release, _) => {
+                if let Some(button) = config.button_for_key(key) {
+                    input_state.set_button(button, false);
+                }
             }
             _ => {}
         }
     }
+
+    return commands;
 }
@@ -0,0 +1,31 @@
+// the emulator core, shared by the frontend binary and the benchmarks
+pub mod apu;
+pub mod clock;
+pub mod compatibility;
+pub mod config;
+#[allow(dead_code)]
+pub mod cpu;
+pub mod debugger;
+#[allow(dead_code)]
+pub mod decoding;
+pub mod disassembly;
+pub mod emulation;
+pub mod error;
+pub mod expression;
+pub mod gameboy;
+pub mod image;
+pub mod input;
+#[allow(non_contiguous_range_endpoints)]
+pub mod memory;
+pub mod oam;
+pub mod pacer;
+pub mod perf;
+pub mod printer;
+pub mod quirks;
+pub mod ram_search;
+#[allow(dead_code)]
+pub mod renderer;
+pub mod rtc;
+pub mod serial;
+pub mod snapshot;
+pub mod test_rom;
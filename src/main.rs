@@ -1,85 +1,675 @@
-use debugger::Debugger;
-use error::EmulationError;
-use gameboy::Gameboy;
-use input::{handle_input, GBInputState};
+use koholint_emulator::{
+    compatibility,
+    config::{Command, EmulatorConfig},
+    debugger::Debugger,
+    disassembly,
+    emulation::{EmulationCommand, EmulationEvent, EmulationThread},
+    error::EmulationError,
+    gameboy::{read_rom_file, Gameboy},
+    image,
+    input::{handle_input, GBInputState, TurboState},
+    pacer,
+    printer::PrinterDevice,
+    quirks, renderer,
+    serial::{SerialCapture, TcpLink},
+    snapshot::{self, Snapshot},
+    test_rom::{self, CYCLES_PER_SECOND},
+};
 use pollster::FutureExt;
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+};
 
-#[allow(dead_code)]
-mod cpu;
-mod debugger;
-#[allow(dead_code)]
-mod decoding;
-mod error;
-mod gameboy;
-mod input;
-#[allow(non_contiguous_range_endpoints)]
-mod memory;
-#[allow(dead_code)]
-mod renderer;
+const WINDOW_TITLE: &str = "Koholint Gameboy Emulator";
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
     if args.len() < 2 {
-        println!("Usage : gbemulator <rom file>");
+        println!("Usage : gbemulator disasm <rom file> <output file> [sym file]");
+        println!("        gbemulator diffstates <state file> <state file>");
+        println!("        gbemulator --check <rom file>");
+        println!(
+            "        gbemulator <rom file | --recent [n]> [-p] [--recover] [--oam-bug] [--no-sound] [--link-listen <addr>] [--link-connect <addr>] [--printer <file prefix>] [--scale <1-8>] [--fps <n>] [--resume] [--headless [--frames <n>] [--screenshot-on-exit <file>]] [--test-mode [--test-timeout <seconds>] [--test-frames <n> [--expect-hash <hash>] [--test-screenshot <file>]]]"
+        );
         return;
     }
     env_logger::init();
 
+    if args[1] == "disasm" {
+        std::process::exit(run_disassembler(&args));
+    }
+    if args[1] == "diffstates" {
+        std::process::exit(run_state_diff(&args));
+    }
+    if args[1] == "--check" {
+        std::process::exit(run_compatibility_check(&args));
+    }
+
     if let Err(e) = run(args) {
         println!("Error : {e}");
     }
 }
 
 fn run(args: Vec<String>) -> Result<(), EmulationError> {
-    let rom = std::fs::read(&args[1]).unwrap();
-    let mut console = Gameboy::new(rom);
+    let mut config = EmulatorConfig::load_or_create();
+
+    // the rom is either given directly, or picked from the recent list
+    let rom_path = if args[1] == "--recent" {
+        match args.get(2).map(|n| n.parse::<usize>()) {
+            Some(Ok(n)) if (1..=config.recent_roms.len()).contains(&n) => config.recent_roms[n - 1]
+                .path
+                .to_string_lossy()
+                .into_owned(),
+            Some(_) => {
+                println!(
+                    "Error : invalid recent rom (should be between 1 and {})",
+                    config.recent_roms.len()
+                );
+                std::process::exit(2);
+            }
+            None => {
+                for (i, recent) in config.recent_roms.iter().enumerate() {
+                    println!("{} : {} ({})", i + 1, recent.title, recent.path.display());
+                }
+                return Ok(());
+            }
+        }
+    } else {
+        args[1].clone()
+    };
+
+    let (rom, mut console) = match read_rom_file(&rom_path).and_then(|rom| {
+        let console = Gameboy::new(rom.clone())?;
+        Ok((rom, console))
+    }) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            println!("Error : {e}");
+            std::process::exit(1);
+        }
+    };
+    console.set_error_recovery(args.iter().any(|a| a.eq("--recover")));
+    console.set_oam_corruption(args.iter().any(|a| a.eq("--oam-bug")));
+    // the registers are still emulated, games read them back
+    if args.iter().any(|a| a.eq("--no-sound")) {
+        for channel in 0..4 {
+            console.set_channel_enabled(channel, false);
+        }
+    }
+
+    if args.iter().any(|a| a.eq("--test-mode")) {
+        if let Some(frames) = get_flag_value(&args, "--test-frames") {
+            let frames = match frames.parse() {
+                Ok(frames) => frames,
+                Err(e) => {
+                    println!("Error : invalid number of test frames ({e})");
+                    std::process::exit(2);
+                }
+            };
+            let expected = match get_flag_value(&args, "--expect-hash")
+                .map(|hash| u64::from_str_radix(hash, 16))
+            {
+                None => None,
+                Some(Ok(hash)) => Some(hash),
+                Some(Err(e)) => {
+                    println!("Error : invalid expected hash ({e})");
+                    std::process::exit(2);
+                }
+            };
+            let screenshot = get_flag_value(&args, "--test-screenshot");
+            std::process::exit(run_screen_test(console, frames, expected, screenshot)?);
+        }
+
+        let timeout = match get_flag_value(&args, "--test-timeout").map(str::parse) {
+            None => 60,
+            Some(Ok(seconds)) => seconds,
+            Some(Err(e)) => {
+                println!("Error : invalid test timeout ({e})");
+                std::process::exit(2);
+            }
+        };
+        std::process::exit(run_test_rom(console, timeout)?);
+    }
+
+    // the flag only applies to this run
+    let scale = match get_flag_value(&args, "--scale").map(str::parse::<u32>) {
+        None => config.scale,
+        Some(Ok(scale)) if (1..=8).contains(&scale) => scale,
+        Some(Ok(scale)) => {
+            println!("Error : invalid scale ({scale}, should be between 1 and 8)");
+            std::process::exit(2);
+        }
+        Some(Err(e)) => {
+            println!("Error : invalid scale ({e})");
+            std::process::exit(2);
+        }
+    };
+
+    if let Ok(path) = std::fs::canonicalize(&rom_path) {
+        config.last_rom_directory = path.parent().map(|dir| dir.to_path_buf());
+        config.add_recent_rom(path, quirks::cartridge_title(&rom).unwrap_or_default());
+    }
+    // left by the last session when save_state_on_exit is set
+    let resume_path = std::path::Path::new(&rom_path).with_extension("resume.json");
+    if args.iter().any(|a| a.eq("--resume")) {
+        match Snapshot::load(&resume_path).and_then(|snapshot| console.load_snapshot(snapshot)) {
+            Ok(()) => println!("Resumed from {}", resume_path.display()),
+            Err(e) => println!("Error : could not resume ({e}), starting over"),
+        }
+    } else if resume_path.exists() {
+        println!("A previous session was saved, launch with --resume to continue it");
+    }
 
     let flag_paused = args.iter().any(|a| a.eq("-p"));
     let mut debugger = Debugger::new(flag_paused);
+    // breakpoints kept from previous sessions with save-breaks
+    let breaks_path = std::path::Path::new(&rom_path).with_extension("breaks");
+    if breaks_path.exists() {
+        match debugger.load_breakpoints(&breaks_path.to_string_lossy()) {
+            Ok(added) => println!("Loaded {added} breakpoints from {}", breaks_path.display()),
+            Err(e) => println!("Error : could not load breakpoints ({e})"),
+        }
+    }
+
+    // link cable over tcp : one emulator listens, the other connects
+    // unless a printer is plugged in the port instead
+    let link = if let Some(prefix) = get_flag_value(&args, "--printer") {
+        console.connect_serial_device(Box::new(PrinterDevice::new(prefix)));
+        None
+    } else if let Some(address) = get_flag_value(&args, "--link-listen") {
+        Some(TcpLink::listen(address))
+    } else if let Some(address) = get_flag_value(&args, "--link-connect") {
+        Some(TcpLink::connect(address))
+    } else {
+        None
+    };
+    // whatever the game sends is printed when running headless, unless something is plugged in
+    let port_free = !matches!(link, Some(Ok(_))) && get_flag_value(&args, "--printer").is_none();
+    match link {
+        Some(Ok(link)) => console.connect_serial_device(Box::new(link)),
+        Some(Err(e)) => println!("Error : could not establish link ({e}), running unplugged"),
+        None => {}
+    }
+
+    if args.iter().any(|a| a.eq("--headless")) {
+        let frames = match get_flag_value(&args, "--frames").map(str::parse) {
+            None => None,
+            Some(Ok(frames)) => Some(frames),
+            Some(Err(e)) => {
+                println!("Error : invalid number of frames ({e})");
+                std::process::exit(2);
+            }
+        };
+        let output = if port_free {
+            let (capture, output) = SerialCapture::new();
+            console.connect_serial_device(Box::new(capture));
+            Some(output)
+        } else {
+            None
+        };
+        let screenshot = get_flag_value(&args, "--screenshot-on-exit");
+        run_headless(console, frames, output, screenshot)?;
+        config.store();
+        return Ok(());
+    }
 
     let mut glfw = glfw::init(glfw::fail_on_errors).unwrap();
     glfw.window_hint(glfw::WindowHint::ClientApi(glfw::ClientApiHint::NoApi));
 
     let (mut window, events) = glfw
         .create_window(
-            640, // this is x4 the gameboy's resolution
-            576,
-            "Koholint Gameboy Emulator",
+            scale * 160, // the gameboy's resolution
+            scale * 144,
+            WINDOW_TITLE,
             glfw::WindowMode::Windowed,
         )
         .expect("Failed to create GLFW window.");
 
     window.set_key_polling(true);
+    window.set_framebuffer_size_polling(true);
 
     let mut renderer = renderer::Renderer::new(&mut window).block_on();
+    renderer.set_screen_filter(config.screen_filter, config.crt);
+
+    // from here on, the console lives on its own thread
+    let emulation = EmulationThread::spawn(console, debugger, rom);
+    emulation.send(EmulationCommand::SetFastForwardSpeed(
+        config.fast_forward_speed,
+    ));
+    if let Some(fps) = get_flag_value(&args, "--fps") {
+        match fps.parse::<f64>() {
+            Ok(fps) if (pacer::MIN_FPS..=pacer::MAX_FPS).contains(&fps) => {
+                emulation.send(EmulationCommand::SetTargetFps(fps))
+            }
+            Ok(fps) => println!(
+                "Error : invalid frame rate ({fps}, should be between {} and {})",
+                pacer::MIN_FPS,
+                pacer::MAX_FPS
+            ),
+            Err(e) => println!("Error : invalid frame rate ({e})"),
+        }
+    }
 
-    //
-    let mut dots = 0;
-    const DOTS_IN_FRAME: u64 = 70224;
-    let mut frame_start = std::time::Instant::now();
     let mut input = GBInputState::default();
+    let mut turbo = TurboState::default();
+    let mut fast_forward = false;
+    let mut screenshots = 0;
+    // a single slot, next to the rom
+    let state_path = std::path::Path::new(&rom_path).with_extension("state.json");
+    // stopped on an emulation error
+    let mut failed = false;
+    // position & size to go back to when leaving fullscreen
+    let mut windowed_geometry = None;
     while !renderer.window().should_close() {
-        handle_input(&mut glfw, &mut renderer, &events, &mut debugger, &mut input);
-        while dots < DOTS_IN_FRAME {
-            dots += debugger.step(&mut console)?;
-            console.update_input(&input);
+        let previous_input = input;
+        let previous_turbo = turbo;
+        let commands = handle_input(
+            &mut glfw,
+            &mut renderer,
+            &events,
+            &config,
+            &mut input,
+            &mut turbo,
+        );
+        for command in commands {
+            match command {
+                Command::Screenshot => {
+                    let path = format!("screenshot_{screenshots}.png");
+                    match image::write_png(
+                        &path,
+                        160,
+                        144,
+                        png::ColorType::Rgba,
+                        &emulation.frame(),
+                    ) {
+                        Ok(()) => println!("Screenshot saved to {path}"),
+                        Err(e) => println!("Error : could not save screenshot ({e})"),
+                    }
+                    screenshots += 1;
+                }
+                Command::FastForward => {
+                    fast_forward = !fast_forward;
+                    println!("Fast forward : {}", if fast_forward { "ON" } else { "OFF" });
+                    emulation.send(EmulationCommand::SetFastForward(fast_forward));
+                }
+                Command::SaveState => {
+                    emulation.send(EmulationCommand::SaveState(state_path.clone()));
+                }
+                Command::LoadState => {
+                    emulation.send(EmulationCommand::LoadState(state_path.clone()));
+                }
+                Command::Fullscreen => {
+                    toggle_fullscreen(&mut glfw, renderer.window, &mut windowed_geometry);
+                }
+                Command::Reset => emulation.send(EmulationCommand::Reset),
+                Command::Pause => emulation.send(EmulationCommand::Pause),
+                // continuing after an error means skipping the failing instruction
+                Command::Continue if failed => emulation.send(EmulationCommand::SkipInstruction),
+                Command::Continue => emulation.send(EmulationCommand::Continue),
+                Command::StepOver => emulation.send(EmulationCommand::StepOver),
+                Command::StepInto => emulation.send(EmulationCommand::Step),
+            }
+        }
+        if input != previous_input {
+            emulation.send(EmulationCommand::SetInput(input));
+        }
+        if turbo != previous_turbo {
+            emulation.send(EmulationCommand::SetTurbo(turbo));
+        }
+
+        // the timeout keeps the window responsive while the emulation is paused
+        let mut new_frame = false;
+        let mut event = emulation.next_event(std::time::Duration::from_millis(16));
+        while let Some(current) = event {
+            match current {
+                EmulationEvent::FrameReady => new_frame = true,
+                // the title bar doubles as the debugger banner
+                EmulationEvent::Paused { pc, breakpoint } => {
+                    let state = if breakpoint {
+                        format!("Breakpoint {pc:#06X} hit")
+                    } else {
+                        format!("Paused at {pc:#06X}")
+                    };
+                    let keys = &config.commands;
+                    renderer.window.set_title(&format!(
+                        "{WINDOW_TITLE} - {state} - press {} to step, {} to continue",
+                        keys.step_over, keys.continue_
+                    ));
+                }
+                EmulationEvent::Resumed => {
+                    failed = false;
+                    renderer.window.set_title(WINDOW_TITLE);
+                }
+                EmulationEvent::Error(report) => {
+                    println!("Error : {report}");
+                    failed = true;
+                    renderer.window.set_clipboard_string(&report);
+                    let keys = &config.commands;
+                    renderer.window.set_title(&format!(
+                        "{WINDOW_TITLE} - Emulation error (report copied to the clipboard) - press {} to skip the instruction, {} to reset",
+                        keys.continue_, keys.reset
+                    ));
+                    renderer.render(&error_overlay(&emulation.frame())).unwrap();
+                }
+            }
+            event = emulation.try_next_event();
         }
-        dots = 0;
 
         // FIXME : rendering one big frame at 60hz is not accurate enough :
         // many games modify stuff mid-frame to create effects
         // for good accuracy, the frame needs to be drawn line-by-line
+        if new_frame && !failed {
+            let render_start = std::time::Instant::now();
+            renderer.render(&emulation.frame()).unwrap();
+            emulation.send(EmulationCommand::RenderTime(render_start.elapsed()));
+        }
+    }
 
-        renderer.render(&console).unwrap();
+    if config.save_state_on_exit {
+        // handled before the thread quits
+        emulation.send(EmulationCommand::SaveState(resume_path));
+    }
+    config.store();
+    Ok(())
+}
 
-        while frame_start.elapsed().as_millis() < 16 {}
-        frame_start = std::time::Instant::now();
+// the last frame tinted red, shown while the emulation is stopped on an error
+fn error_overlay(frame: &[u8]) -> Vec<u8> {
+    return frame
+        .chunks_exact(4)
+        .flat_map(|pixel| [pixel[0] / 2 + 100, pixel[1] / 3, pixel[2] / 3, pixel[3]])
+        .collect();
+}
 
-        /* if fps_start.elapsed().as_millis() >= 1000 {
-            println!("FPS : {frames}");
-            frames = 0;
-            fps_start = std::time::Instant::now();
-        } */
+// switches between windowed mode and fullscreen on the primary monitor
+fn toggle_fullscreen(
+    glfw: &mut glfw::Glfw,
+    window: &mut glfw::Window,
+    windowed_geometry: &mut Option<(i32, i32, i32, i32)>,
+) {
+    if let Some((x, y, width, height)) = windowed_geometry.take() {
+        window.set_monitor(
+            glfw::WindowMode::Windowed,
+            x,
+            y,
+            width as u32,
+            height as u32,
+            None,
+        );
+        return;
     }
-    Ok(())
+
+    glfw.with_primary_monitor(|_, monitor| {
+        let Some(monitor) = monitor else {
+            println!("Error : no monitor to go fullscreen on");
+            return;
+        };
+        let Some(mode) = monitor.get_video_mode() else {
+            println!("Error : could not read the monitor's video mode");
+            return;
+        };
+
+        let (x, y) = window.get_pos();
+        let (width, height) = window.get_size();
+        *windowed_geometry = Some((x, y, width, height));
+        window.set_monitor(
+            glfw::WindowMode::FullScreen(monitor),
+            0,
+            0,
+            mode.width,
+            mode.height,
+            Some(mode.refresh_rate),
+        );
+    });
+}
+
+// runs a test rom without any window until it reports a result,
+// locks up or runs for `timeout` emulated seconds
+// two conventions are understood :
+// - blargg's roms print "Passed" or "Failed" to the serial port
+// - mooneye's roms execute `ld b, b` with fibonacci numbers in the registers when passing
+// returns the process exit code : 0 if passed, 1 if failed, 2 if no result was reported
+fn run_test_rom(mut console: Gameboy, timeout: u64) -> Result<i32, EmulationError> {
+    const DOTS_IN_FRAME: u64 = 70224;
+
+    let (capture, output) = SerialCapture::new();
+    console.connect_serial_device(Box::new(capture));
+
+    let mut cycles = 0;
+    let mut verdict = None;
+    while verdict.is_none() && cycles < timeout * CYCLES_PER_SECOND && !console.is_locked_up() {
+        // checking the output once per frame is plenty
+        let frame_end = cycles + DOTS_IN_FRAME;
+        while cycles < frame_end && verdict.is_none() {
+            cycles += console.step().map_err(|e| e.with_context(&console))?;
+            verdict = test_rom::mooneye_verdict(&console).map(|passed| !passed as i32);
+        }
+        if verdict.is_some() {
+            println!("Mooneye test finished after {cycles} cycles");
+            break;
+        }
+
+        let output = output.lock().unwrap();
+        let text = String::from_utf8_lossy(&output);
+        verdict = match text.lines().rev().find(|line| !line.trim().is_empty()) {
+            Some(line) if line.starts_with("Passed") => Some(0),
+            Some(line) if line.starts_with("Failed") => Some(1),
+            _ => None,
+        };
+    }
+
+    println!("{}", String::from_utf8_lossy(&output.lock().unwrap()));
+    return Ok(match verdict {
+        Some(code) => code,
+        None => {
+            println!(
+                "No test result after {} emulated seconds",
+                cycles / CYCLES_PER_SECOND
+            );
+            2
+        }
+    });
+}
+
+// "disasm <rom> <out> [sym]" : writes the listing of the whole rom, with the labels
+// from the sym file if one is given
+fn run_disassembler(args: &[String]) -> i32 {
+    let (Some(rom_path), Some(out_path)) = (args.get(2), args.get(3)) else {
+        println!("Error : Usage : gbemulator disasm <rom file> <output file> [sym file]");
+        return 2;
+    };
+
+    let symbols = match args.get(4).map(disassembly::load_symbols) {
+        None => Default::default(),
+        Some(Ok(symbols)) => symbols,
+        Some(Err(e)) => {
+            println!("Error : could not read symbols ({e})");
+            return 1;
+        }
+    };
+    let mut console = match read_rom_file(rom_path).and_then(Gameboy::new) {
+        Ok(console) => console,
+        Err(e) => {
+            println!("Error : {e}");
+            return 1;
+        }
+    };
+
+    let result = std::fs::File::create(out_path).and_then(|file| {
+        let mut out = std::io::BufWriter::new(file);
+        disassembly::write_disassembly(&mut console, &symbols, &mut out)?;
+        out.flush()
+    });
+    return match result {
+        Ok(()) => {
+            println!("Disassembly written to {out_path}");
+            0
+        }
+        Err(e) => {
+            println!("Error : could not write disassembly ({e})");
+            1
+        }
+    };
+}
+
+// prints what the rom needs that the emulator lacks, from its header
+// exits with 0 if it should run, 1 if something it needs is unsupported
+fn run_compatibility_check(args: &[String]) -> i32 {
+    let Some(rom_path) = args.get(2) else {
+        println!("Error : Usage : gbemulator --check <rom file>");
+        return 2;
+    };
+    let rom = match read_rom_file(rom_path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            println!("Error : {e}");
+            return 2;
+        }
+    };
+
+    println!(
+        "{} ({})",
+        quirks::cartridge_title(&rom).unwrap_or_else(|| rom_path.to_string()),
+        rom_path
+    );
+    let findings = compatibility::check(&rom);
+    if findings.is_empty() {
+        println!("Nothing unsupported found in the header");
+    }
+    for finding in &findings {
+        println!("{finding}");
+    }
+
+    return if findings
+        .iter()
+        .any(|finding| matches!(finding, compatibility::Finding::Unsupported(_)))
+    {
+        1
+    } else {
+        0
+    };
+}
+
+// prints what differs between two save states
+// exits with 0 if they are the same, 1 otherwise
+fn run_state_diff(args: &[String]) -> i32 {
+    let (Some(path_a), Some(path_b)) = (args.get(2), args.get(3)) else {
+        println!("Error : Usage : gbemulator diffstates <state file> <state file>");
+        return 2;
+    };
+
+    let (a, b) = match (Snapshot::load(path_a), Snapshot::load(path_b)) {
+        (Ok(a), Ok(b)) => (a, b),
+        (Err(e), _) | (_, Err(e)) => {
+            println!("Error : {e}");
+            return 2;
+        }
+    };
+
+    let diffs = snapshot::diff_states(&a, &b);
+    if diffs.is_empty() {
+        println!("The states are the same");
+        return 0;
+    }
+    for diff in &diffs {
+        println!("{diff}");
+    }
+    println!("{} differences", diffs.len());
+
+    return 1;
+}
+
+// runs without a window, as fast as possible, e.g. for ci or on a server
+// stops after `frames` frames if given, or once the cpu is locked up
+// the serial output is printed as it comes
+fn run_headless(
+    mut console: Gameboy,
+    frames: Option<u64>,
+    output: Option<Arc<Mutex<Vec<u8>>>>,
+    screenshot: Option<&str>,
+) -> Result<(), EmulationError> {
+    const DOTS_IN_FRAME: u64 = 70224;
+
+    // frames are counted in cycles, so they go on while the lcd is off
+    let mut cycles = 0;
+    let mut printed = 0;
+    let mut result = Ok(());
+    while frames.map_or(true, |frames| cycles < frames * DOTS_IN_FRAME) && !console.is_locked_up() {
+        let frame_end = cycles + DOTS_IN_FRAME;
+        while cycles < frame_end && result.is_ok() {
+            match console.step() {
+                Ok(elapsed) => cycles += elapsed,
+                Err(e) => result = Err(e.with_context(&console)),
+            }
+        }
+
+        if let Some(output) = &output {
+            let output = output.lock().unwrap();
+            print!("{}", String::from_utf8_lossy(&output[printed..]));
+            std::io::stdout().flush().unwrap();
+            printed = output.len();
+        }
+        if result.is_err() {
+            break;
+        }
+    }
+
+    // the screen as it was at the end, even after an error
+    if let Some(path) = screenshot {
+        match image::write_png(
+            path,
+            160,
+            144,
+            png::ColorType::Rgba,
+            console.get_framebuffer(),
+        ) {
+            Ok(()) => println!("Screenshot saved to {path}"),
+            Err(e) => println!("Error : could not save screenshot ({e})"),
+        }
+    }
+
+    return result;
+}
+
+// for the tests that are checked by looking at the screen, like dmg-acid2 & cgb-acid2 :
+// runs `frames` frames and compares a hash of the framebuffer with the one of a known-good run
+// returns 0 if they match, 1 if they don't, and 0 with no expected hash so it can be recorded
+fn run_screen_test(
+    mut console: Gameboy,
+    frames: u64,
+    expected: Option<u64>,
+    screenshot: Option<&str>,
+) -> Result<i32, EmulationError> {
+    test_rom::run_frames(&mut console, frames).map_err(|e| e.with_context(&console))?;
+
+    let framebuffer = console.get_framebuffer();
+    if let Some(path) = screenshot {
+        if let Err(e) = image::write_png(path, 160, 144, png::ColorType::Rgba, framebuffer) {
+            println!("Error : could not save screenshot ({e})");
+        }
+    }
+
+    let hash = test_rom::framebuffer_hash(framebuffer);
+    println!(
+        "Framebuffer hash after {} frames : {hash:016X}",
+        console.frame_count()
+    );
+    return Ok(match expected {
+        Some(expected) if expected != hash => {
+            println!("Failed : expected {expected:016X}");
+            1
+        }
+        Some(_) => {
+            println!("Passed");
+            0
+        }
+        None => 0,
+    });
+}
+
+// returns the argument following a flag, e.g. the address in "--link-listen 0.0.0.0:5000"
+fn get_flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    let position = args.iter().position(|a| a.eq(flag))?;
+    return args.get(position + 1).map(|value| value.as_str());
 }
@@ -1,28 +1,173 @@
+use std::cell::Cell;
+
 use log::{debug, info, trace, warn};
+use serde::{Deserialize, Serialize};
 
-use crate::error::{EmulationError, EmulationErrorType};
+use crate::{
+    apu::Apu,
+    clock::Clock,
+    error::{EmulationError, EmulationErrorType, RomError},
+    quirks::{self, GameQuirks},
+    rtc::Rtc,
+    snapshot::{self, MemorySnapshot},
+};
 
 // https://gbdev.io/pandocs/Memory_Map.html
 // TODO : add support for MBC and switchable ROM banks
-// TODO : add support for switchable WRAM in gameboy color mode
 // TODO : add support for switchable VRAM in gameboy color mode
 // TODO : add support for switchable external RAM
 
+// https://gbdev.io/pandocs/Power_Up_Sequence.html#hardware-registers
+// the divider as the dmg boot rom leaves it
+// the cgb one depends on how long its boot rom ran, which varies with the cartridge,
+// so the same value is used when the boot rom is skipped
+const POST_BOOT_DIV: u16 = 0xABCC;
+// 128 banks of 16 KiB
+const MBC1_MAX_ROM_SIZE: usize = 0x200000;
+// 128 banks too, the MBC30 with its 8th bit isn't supported
+const MBC3_MAX_ROM_SIZE: usize = 0x200000;
+
 pub struct Memory {
     boot_rom: [u8; 0x100],        // 0000-00FF | Boot ROM (mapped only during boot)
     fixed_rom_bank: [u8; 0x4000], // 0000-3FFF | 16 KiB fixed ROM bank
     switch_rom_bank: Vec<[u8; 0x4000]>, // 4000-7FFF | 16 KiB switchable ROM bank
     vram: [u8; 0x2000],           // 8000-9FFF | 8KiB Video Ram
+    vram_1: [u8; 0x2000],         // 8000-9FFF | 8KiB Video Ram (bank 1 in CGB mode)
     ext_ram: [u8; 0x2000],        // A000-BFFF | 8 KiB External RAM (cartridge)
-    wram: [u8; 0x4000],           // C000-CFFF | 4 KiB Work RAM
-    switchable_wram: [u8; 0x4000], // D000-DFFF | 4 KiB Work RAM
+    wram: [u8; 0x1000],           // C000-CFFF | 4 KiB Work RAM
+    switchable_wram: Vec<[u8; 0x1000]>, // D000-DFFF | 4 KiB Work RAM (banks 1-7 in CGB mode)
     oam: [u8; 160],               // FE00-FE9F | Object Attribute Memory
     io_hw: [u8; 0x80],            // FF00-FF7F | Memory-Mapped I/O
     hram: [u8; 0x7F],             // FF80-FFFE | High Ram
     ie: u8,                       // FFFF      | Interrupt Enable Register (IE)
     // ---------------
     mbc: MBC,
+    // https://gbdev.io/pandocs/MBC1.html
+    // BANK1 (2000-3FFF), never 0 : the low bits of the rom bank
     selected_rom_bank: u8,
+    // BANK2 (4000-5FFF) : the upper bits of the rom bank
+    rom_bank_high: u8,
+    // MODE (6000-7FFF) : when set, BANK2 also applies to the 0000-3FFF area
+    banking_mode: bool,
+    // multicarts (MBC1M) only wire 4 bits of BANK1, so BANK2 selects which game's 256KiB
+    mbc1_multicart: bool,
+    // https://gbdev.io/pandocs/MBC3.html#4000-5fff---ram-bank-number---or---rtc-register-select-write-only
+    // MBC3 (4000-5FFF) : the ram bank, or the rtc register mapped to A000-BFFF from 08 to 0C
+    ram_bank: u8,
+    // the real time clock of MBC3+TIMER cartridges
+    rtc: Option<Rtc>,
+    // line the ppu is on, which is what LY shows except at the start of line 153
+    ppu_line: u8,
+    // https://gbdev.io/pandocs/Timer_and_Divider_Registers.html#ff04--div-divider-register
+    // the divider counts t-cycles, DIV shows its upper 8 bits
+    div: u16,
+    // t-cycles left before the current OAM DMA transfer is over, 0 if none
+    // the copy itself is instantaneous, this is only kept to catch
+    // accesses the cpu couldn't make on hardware
+    dma_cycles: u64,
+    // t-cycles the cpu has to wait for the vram dma blocks just copied
+    vram_dma_cycles: u64,
+    // next addresses a vram dma copies from & to
+    vram_dma_source: u16,
+    vram_dma_destination: u16,
+    // blocks left to copy by the hblank dma, one per hblank, none if inactive
+    hblank_dma_blocks: Option<u8>,
+    // FF10-FF3F are handled by the apu
+    apu: Apu,
+    // the cartridge supports the gameboy color features
+    cgb_mode: bool,
+    // the cpu runs twice as fast, switched through KEY1 & STOP
+    double_speed: bool,
+    // special handling needed by the loaded game, if it is a known one
+    quirks: GameQuirks,
+    // the header of the loaded cartridge
+    cartridge_info: CartridgeInfo,
+    // allocated when access tracking starts, and kept once it stops so they can still be dumped
+    access_counters: Option<AccessCounters>,
+    // accesses are only counted while this is set
+    access_tracking: bool,
+}
+
+// https://gbdev.io/pandocs/The_Cartridge_Header.html
+// the fields of the header are kept as they are in the rom, except for the title
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CartridgeInfo {
+    // 0134-0143 (0134-0142 on cgb cartridges), up to the first zero
+    // newer cartridges also use the end of the area for the manufacturer code
+    pub title: String,
+    // 013F-0142
+    pub manufacturer_code: [u8; 4],
+    // 0143
+    pub cgb_flag: u8,
+    // 0144-0145, two ascii characters, only used when the old licensee code is 0x33
+    pub new_licensee_code: [u8; 2],
+    // 0146, 0x03 for super gameboy features
+    pub sgb_flag: u8,
+    // 0147
+    pub cartridge_type: u8,
+    // 0148
+    pub rom_size: u8,
+    // 0149
+    pub ram_size: u8,
+    // 014A, 0x00 for japan, 0x01 for overseas
+    pub destination: u8,
+    // 014B
+    pub old_licensee_code: u8,
+    // 014C
+    pub version: u8,
+    // 014D
+    pub header_checksum: u8,
+    // 014E-014F, big endian, not checked by the console
+    pub global_checksum: u16,
+}
+
+impl CartridgeInfo {
+    pub fn cartridge_type_name(&self) -> &'static str {
+        return cartridge_type_name(self.cartridge_type);
+    }
+
+    // https://gbdev.io/pandocs/The_Cartridge_Header.html#0148--rom-size
+    // none for the values no cartridge uses
+    pub fn rom_size_bytes(&self) -> Option<usize> {
+        return (self.rom_size <= 0x08).then(|| 0x8000 << self.rom_size);
+    }
+
+    // https://gbdev.io/pandocs/The_Cartridge_Header.html#0149--ram-size
+    // 0x01 is unused, but was listed as 2KiB in some unofficial documentation
+    pub fn ram_size_bytes(&self) -> Option<usize> {
+        return match self.ram_size {
+            0x00 => Some(0),
+            0x01 => Some(0x800),
+            0x02 => Some(0x2000),
+            0x03 => Some(0x8000),
+            0x04 => Some(0x20000),
+            0x05 => Some(0x10000),
+            _ => None,
+        };
+    }
+}
+
+// number of cpu reads, writes & executed instructions per address
+pub struct AccessCounters {
+    // reads go through &self, hence the cells
+    reads: Box<[Cell<u32>; 0x10000]>,
+    writes: Box<[u32; 0x10000]>,
+    executes: Box<[u32; 0x10000]>,
+}
+
+impl AccessCounters {
+    pub fn reads(&self, address: u16) -> u32 {
+        return self.reads[address as usize].get();
+    }
+
+    pub fn writes(&self, address: u16) -> u32 {
+        return self.writes[address as usize];
+    }
+
+    // instructions starting at this address
+    pub fn executes(&self, address: u16) -> u32 {
+        return self.executes[address as usize];
+    }
 }
 
 impl Memory {
@@ -55,112 +200,260 @@ impl Memory {
             fixed_rom_bank: [0; 0x4000],
             switch_rom_bank: vec![[0; 0x4000]],
             vram: [0; 0x2000],
+            vram_1: [0; 0x2000],
             ext_ram: [0; 0x2000],
-            wram: [0; 0x4000],
-            switchable_wram: [0; 0x4000],
+            wram: [0; 0x1000],
+            switchable_wram: vec![[0; 0x1000]; 7],
             oam: [0; 0x00A0],
             io_hw: [0; 0x80],
             hram: [0; 0x7F],
             ie: 0x00,
             mbc: MBC::NONE,
             selected_rom_bank: 1,
+            rom_bank_high: 0,
+            banking_mode: false,
+            mbc1_multicart: false,
+            ram_bank: 0,
+            rtc: None,
+            ppu_line: 0,
+            div: 0,
+            dma_cycles: 0,
+            vram_dma_cycles: 0,
+            vram_dma_source: 0,
+            vram_dma_destination: 0x8000,
+            hblank_dma_blocks: None,
+            apu: Apu::new(),
+            cgb_mode: false,
+            double_speed: false,
+            quirks: GameQuirks::default(),
+            cartridge_info: CartridgeInfo::default(),
+            access_counters: None,
+            access_tracking: false,
         };
 
         mem.io_hw[0] = 0b00110000;
+        // no vram dma running
+        mem.io_hw[0x55] = 0xFF;
 
         return mem;
     }
 
-    pub fn load_rom(&mut self, rom: Vec<u8>) {
-        assert!(rom.len() >= 0x4000, "ROM is too small !");
+    pub fn load_rom(&mut self, mut rom: Vec<u8>) -> Result<(), RomError> {
+        if rom.is_empty() {
+            return Err(RomError::TooSmall(rom.len()));
+        }
 
-        info!(
-            "CARTRIDGE TITLE : {}",
-            std::str::from_utf8(&rom[0x0134..0x0143])
-                .expect("TITLE field in cartdrige header is not ASCII : your rom may be corrupted")
-        );
+        // tiny homebrew & test roms may not even have a header
+        let has_header = rom.len() >= 0x0150;
+        if !has_header {
+            warn!("ROM HAS NO CARTRIDGE HEADER, LOADING IT AS ROM ONLY");
+        }
+
+        // https://gbdev.io/pandocs/The_Cartridge_Header.html#0148--rom-size
+        if has_header {
+            let size_byte = rom[0x0148];
+            if size_byte <= 0x08 {
+                let declared_size = 0x8000 << size_byte;
+                if rom.len() != declared_size {
+                    warn!(
+                        "ROM IS {}KiB BUT ITS HEADER DECLARES {}KiB",
+                        rom.len() / 0x400,
+                        declared_size / 0x400
+                    );
+                    // the banks are mapped from the declared size : trailing data is dropped
+                    // and missing banks read as open bus
+                    // rom only cartridges keep everything, see the mbc check below
+                    if rom[0x0147] != 0x00 {
+                        rom.resize(declared_size, 0xFF);
+                    }
+                }
+            } else {
+                warn!("UNKNOWN ROM SIZE IN HEADER ({size_byte:#04X})");
+            }
+        }
+
+        // roms that don't fill the two banks read as open bus past their end
+        if rom.len() < 0x8000 {
+            rom.resize(0x8000, 0xFF);
+        }
+
+        if has_header {
+            self.cartridge_info = Memory::map_cartridge_info(&rom);
+            let title = &self.cartridge_info.title;
+            if title.is_ascii() {
+                info!("CARTRIDGE TITLE : {title}");
+            } else {
+                warn!("CARTRIDGE TITLE IS NOT ASCII, SKIPPING IT");
+            }
+
+            if let Some(game) = quirks::lookup(&rom) {
+                info!("KNOWN GAME : {} ({:?})", game.name, game.quirks);
+                if let Some(note) = game.note {
+                    warn!("{} : {}", game.name.to_uppercase(), note.to_uppercase());
+                }
+                self.quirks = game.quirks;
+            }
+        }
+        self.mbc1_multicart = is_mbc1_multicart(&rom);
+        if self.mbc1_multicart {
+            info!("MBC1 MULTICART (MBC1M) WIRING");
+        }
+
+        // https://gbdev.io/pandocs/The_Cartridge_Header.html#0143--cgb-flag
+        // until cgb rendering exists, games that also run on the dmg (0x80) are better off
+        // in dmg mode : only the cgb-only ones (0xC0) get cgb mode
+        self.cgb_mode = has_header && self.cartridge_info.cgb_flag == 0xC0;
+        if self.cgb_mode {
+            info!("CARTRIDGE ONLY RUNS IN CGB MODE");
+        } else if has_header && self.cartridge_info.cgb_flag & 0x80 != 0 {
+            info!("CARTRIDGE SUPPORTS CGB MODE, RUNNING IT IN DMG MODE");
+        }
+        // the cgb boot rom picks the object priority mode from the compatibility mode,
+        // the oam position for cgb games and the x coordinate for the others
+        self.io_hw[0x6C] = !self.cgb_mode as u8;
 
         // check the cartridge memory bank controller byte
-        let mbc_byte = rom[0x0147];
+        let mbc_byte = if has_header {
+            self.cartridge_info.cartridge_type
+        } else {
+            0x00
+        };
         match mbc_byte {
-            0x00 => {
-                info!("CARTRIDGE TYPE : ROM ONLY");
-                assert!(
-                    rom.len() <= 0x8000,
-                    "ROM size too big for its MBC type : the ROM file may be corrupted."
+            0x00 if rom.len() > 0x8000 => {
+                // some homebrew are padded past 32KiB without updating the header
+                warn!(
+                    "ROM ONLY CARTRIDGE IS {}KiB, MORE THAN 32KiB : LOADING IT AS MBC1",
+                    rom.len() / 0x400
                 );
+                self.map_mbc1(&rom)?;
+            }
+            0x00 => {
+                info!("CARTRIDGE TYPE : {}", cartridge_type_name(mbc_byte));
 
                 // simply map the rom to the two banks
                 self.fixed_rom_bank.copy_from_slice(&rom[0..0x4000]);
-                if rom.len() > 0x4000 {
-                    self.switch_rom_bank[0][..(rom.len() - 0x4000)].copy_from_slice(&rom[0x4000..]);
-                }
+                self.switch_rom_bank[0].copy_from_slice(&rom[0x4000..0x8000]);
             }
             0x01 => {
-                info!("CARTRIDGE TYPE : MBC1");
-                self.mbc = MBC::MBC1;
-
-                // map the fixed rom bank,
-                // then the switchable banks until all the rom has been mapped
-                self.fixed_rom_bank[..].copy_from_slice(&rom[0..0x4000]);
-                let mut switchable_banks: Vec<[u8; 0x4000]> = Vec::new();
-                let mut mapped = 0x4000;
-                while mapped < rom.len() {
-                    let mut bank = [0; 0x4000];
-                    let to_copy: usize = 0x4000.min(rom.len() - mapped);
-                    bank[0..to_copy].copy_from_slice(&rom[mapped..(mapped + to_copy)]);
-                    switchable_banks.push(bank);
-
-                    mapped += 0x4000;
+                info!("CARTRIDGE TYPE : {}", cartridge_type_name(mbc_byte));
+                self.map_mbc1(&rom)?;
+            }
+            0x0F..=0x13 => {
+                info!("CARTRIDGE TYPE : {}", cartridge_type_name(mbc_byte));
+                self.map_mbc3(&rom)?;
+                // MBC3+TIMER+BATTERY & MBC3+TIMER+RAM+BATTERY
+                if mbc_byte <= 0x10 {
+                    self.rtc = Some(Rtc::default());
                 }
-
-                self.switch_rom_bank = switchable_banks;
-
-                info!(
-                    "MBC1 : {} ROM BANKS (TOTAL SIZE : {}KiB)",
-                    1 + self.switch_rom_bank.len(),
-                    rom.len() / 0x400,
-                );
             }
             _ => {
-                panic!(
-                    "ROMS using this MEMORY BANK CONTROLLER are not yet supported : {}",
-                    match mbc_byte {
-                        0x00 => "ROM ONLY",
-                        0x01 => "MBC1",
-                        0x02 => "MBC1+RAM",
-                        0x03 => "MBC1+RAM+BATTERY",
-                        0x05 => "MBC2",
-                        0x06 => "MBC2+BATTERY",
-                        0x08 => "ROM+RAM",
-                        0x09 => "ROM+RAM+BATTERY",
-                        0x0B => "MMM01",
-                        0x0C => "MMM01+RAM",
-                        0x0D => "MMM01+RAM+BATTERY",
-                        0x0F => "MBC3+TIMER+BATTERY",
-                        0x10 => "MBC3+TIMER+RAM+BATTERY",
-                        0x11 => "MBC3",
-                        0x12 => "MBC3+RAM",
-                        0x13 => "MBC3+RAM+BATTERY",
-                        0x19 => "MBC5",
-                        0x1A => "MBC5+RAM",
-                        0x1B => "MBC5+RAM+BATTERY",
-                        0x1C => "MBC5+RUMBLE",
-                        0x1D => "MBC5+RUMBLE+RAM",
-                        0x1E => "MBC5+RUMBLE+RAM+BATTERY",
-                        0xFC => "POCKET CAMERA",
-                        0xFD => "BANDAI TAMA5",
-                        0xFE => "HuC3",
-                        0xFF => "HuC1+RAM+BATTERY",
-                        _ => "UNKNOWN",
-                    }
-                );
+                return Err(RomError::UnsupportedMbc(mbc_byte));
             }
         }
 
-        //FIXME : until display is implemented, pretend we are always in V-Blank
-        // value at 0xFF44 is used to determine vertical-blank period
-        self.io_hw[0x44] = 144;
+        return Ok(());
+    }
+
+    // https://gbdev.io/pandocs/Power_Up_Sequence.html#hardware-registers
+    // unmaps the boot rom, with the lcd registers as it would have left them
+    pub fn skip_boot_rom(&mut self) {
+        self.io_hw[0x50] = 0x01;
+        self.io_hw[0x40] = 0x91; // LCDC
+        self.io_hw[0x47] = 0xFC; // BGP
+        self.set_internal_div(POST_BOOT_DIV);
+    }
+
+    // https://gbdev.io/pandocs/The_Cartridge_Header.html
+    // `header` is the rom from 0x0000 to at least 0x0150
+    pub fn map_cartridge_info(header: &[u8]) -> CartridgeInfo {
+        // cgb cartridges give the last byte of the title up to the cgb flag
+        let title_end = if header[0x0143] & 0x80 != 0 {
+            0x0143
+        } else {
+            0x0144
+        };
+        let title = &header[0x0134..title_end];
+        let title = &title[..title.iter().position(|&b| b == 0).unwrap_or(title.len())];
+
+        return CartridgeInfo {
+            title: String::from_utf8_lossy(title).into_owned(),
+            manufacturer_code: [
+                header[0x013F],
+                header[0x0140],
+                header[0x0141],
+                header[0x0142],
+            ],
+            cgb_flag: header[0x0143],
+            new_licensee_code: [header[0x0144], header[0x0145]],
+            sgb_flag: header[0x0146],
+            cartridge_type: header[0x0147],
+            rom_size: header[0x0148],
+            ram_size: header[0x0149],
+            destination: header[0x014A],
+            old_licensee_code: header[0x014B],
+            version: header[0x014C],
+            header_checksum: header[0x014D],
+            global_checksum: u16::from_be_bytes([header[0x014E], header[0x014F]]),
+        };
+    }
+
+    fn map_mbc1(&mut self, rom: &[u8]) -> Result<(), RomError> {
+        // https://gbdev.io/pandocs/MBC1.html
+        // 7 bits of bank number : 128 banks at most
+        if rom.len() > MBC1_MAX_ROM_SIZE {
+            return Err(RomError::TooLarge(rom.len(), "MBC1", MBC1_MAX_ROM_SIZE));
+        }
+        self.mbc = MBC::MBC1;
+
+        // map the fixed rom bank,
+        // then the switchable banks until all the rom has been mapped
+        self.fixed_rom_bank[..].copy_from_slice(&rom[0..0x4000]);
+        let mut switchable_banks: Vec<[u8; 0x4000]> = Vec::new();
+        let mut mapped = 0x4000;
+        while mapped < rom.len() {
+            let mut bank = [0; 0x4000];
+            let to_copy: usize = 0x4000.min(rom.len() - mapped);
+            bank[0..to_copy].copy_from_slice(&rom[mapped..(mapped + to_copy)]);
+            switchable_banks.push(bank);
+
+            mapped += 0x4000;
+        }
+
+        self.switch_rom_bank = switchable_banks;
+
+        info!(
+            "MBC1 : {} ROM BANKS (TOTAL SIZE : {}KiB)",
+            1 + self.switch_rom_bank.len(),
+            rom.len() / 0x400,
+        );
+        return Ok(());
+    }
+
+    fn map_mbc3(&mut self, rom: &[u8]) -> Result<(), RomError> {
+        // https://gbdev.io/pandocs/MBC3.html
+        if rom.len() > MBC3_MAX_ROM_SIZE {
+            return Err(RomError::TooLarge(rom.len(), "MBC3", MBC3_MAX_ROM_SIZE));
+        }
+        self.mbc = MBC::MBC3;
+        // the multicart wiring only exists for MBC1
+        self.mbc1_multicart = false;
+
+        self.fixed_rom_bank[..].copy_from_slice(&rom[0..0x4000]);
+        self.switch_rom_bank = rom[0x4000..]
+            .chunks(0x4000)
+            .map(|chunk| {
+                let mut bank = [0; 0x4000];
+                bank[0..chunk.len()].copy_from_slice(chunk);
+                return bank;
+            })
+            .collect();
+
+        info!(
+            "MBC3 : {} ROM BANKS (TOTAL SIZE : {}KiB)",
+            1 + self.switch_rom_bank.len(),
+            rom.len() / 0x400,
+        );
+        return Ok(());
     }
 
     // accessors
@@ -170,27 +463,34 @@ impl Memory {
             0x0000..0x4000 => {
                 // the bootrom stays mapped onto 0x00-0xFF until 0x01 is written to 0xFF50
                 // then cartridge data is accessible
-                if (self.read_byte(0xFF50) == 0) && (address <= 0xFF) {
+                if (self.io_hw[0x50] == 0) && (address <= 0xFF) {
                     return self.boot_rom[address as usize];
+                } else if let MBC::MBC1 = self.mbc {
+                    return self.rom_bank_byte(self.mbc1_rom_bank(false), address as usize);
                 } else {
                     return self.fixed_rom_bank[address as usize];
                 }
             }
             // SECOND ROM BANK
             0x4000..0x8000 => match self.mbc {
-                MBC::NONE => self.switch_rom_bank[0][(address - 0x4000) as usize],
                 MBC::MBC1 => {
-                    let selected = self.selected_rom_bank.max(1);
-                    // bank 0 is the fixed bank, hence the -1 here
-                    self.switch_rom_bank[selected as usize - 1][(address - 0x4000) as usize]
-                }
-                _ => {
-                    todo!("other mbc types")
+                    self.rom_bank_byte(self.mbc1_rom_bank(true), (address - 0x4000) as usize)
                 }
+                MBC::MBC3 => self.rom_bank_byte(self.mbc3_rom_bank(), (address - 0x4000) as usize),
+                // load_rom never maps the MBC2, it only comes from hand edited save states :
+                // its rom is read as if it had no controller
+                MBC::NONE | MBC::MBC2 => self.switch_rom_bank[0][(address - 0x4000) as usize],
             },
             // VRAM
             0x8000..0xA000 => {
-                return self.vram[(address - 0x8000) as usize];
+                return self.vram_bank()[(address - 0x8000) as usize];
+            }
+            // EXTERNAL RAM
+            0xA000..0xC000 => {
+                if let Some(register) = self.selected_rtc_register() {
+                    return self.rtc.as_ref().map_or(0xFF, |rtc| rtc.read(register));
+                }
+                return self.ext_ram[(address - 0xA000) as usize];
             }
             // WRAM
             0xC000..0xD000 => {
@@ -198,18 +498,27 @@ impl Memory {
             }
             // SWITCHABLE WRAM
             0xD000..0xE000 => {
-                return self.switchable_wram[(address - 0xD000) as usize];
+                return self.switchable_wram[self.wram_bank() - 1][(address - 0xD000) as usize];
+            }
+            // ECHO RAM
+            0xE000..0xFE00 => {
+                // mirror of C000-DDFF
+                return self.read_byte(address - 0x2000);
             }
             // OAM
             0xFE00..0xFEA0 => {
                 return self.oam[(address - 0xFE00) as usize];
             }
+            0xFEA0..0xFF00 => {
+                // Nintendo says use of this area is prohibited.
+                return 0xFF;
+            }
             // MEMORY IO
             0xFF00..0xFF80 => {
                 // filtering the adress to warn for unimplemented things
                 match address {
                     0xFF00 => {
-                        return self.io_hw[0];
+                        return self.io_hw[0] | io_read_mask(address);
                     }
                     0xFF40 => { /* lcd control byte */ }
                     0xFF42..=0xFF43 => { /* screen scrolling bytes,it's fine to access */ }
@@ -218,14 +527,42 @@ impl Memory {
                         // LYC indicates on which line an interrupt should be triggered
                     }
                     0xFF47 => { /* palette byte */ }
+                    0xFF48..=0xFF49 => { /* object palette bytes */ }
                     0xFF07 => { /* timer info byte, fine too */ }
                     0xFF04 => { /* divider register byte, fine too */ }
                     0xFF50 => { /* disables the boot rom when non-zero */ }
                     0xFF0F => { /* interrupt request register */ }
+                    0xFF01..=0xFF02 => { /* serial data & control */ }
+                    0xFF4D if self.cgb_mode => {
+                        // KEY1 : current speed in bit 7, switch armed in bit 0
+                        return (self.double_speed as u8) << 7
+                            | 0b0111_1110
+                            | (self.io_hw[0x4D] & 0b1);
+                    }
+                    0xFF55 if self.cgb_mode => {
+                        // HDMA5 : blocks left - 1, and bit 7 clear while an hblank dma runs
+                        return self.io_hw[0x55];
+                    }
+                    0xFF56 if self.cgb_mode => {
+                        // RP : the led & read enable bits as written, and bit 1 always set
+                        // since no infrared light is ever received
+                        return self.io_hw[0x56] | 0b0011_1110;
+                    }
+                    0xFF4F if self.cgb_mode => {
+                        // VBK, only bit 0 exists
+                        return self.io_hw[0x4F] | 0b1111_1110;
+                    }
+                    0xFF6C if self.cgb_mode => {
+                        // OPRI, only bit 0 exists
+                        return self.io_hw[0x6C] | 0b1111_1110;
+                    }
+                    0xFF70 if self.cgb_mode => {
+                        // SVBK, the upper bits are unused
+                        return self.io_hw[0x70] | 0b1111_1000;
+                    }
 
-                    0xFF10..=0xFF26 => {
-                        /* audio stuff is less important for now */
-                        info!("CALL TO AUDIO MEMORY READ (ADDRESS {:#06X})", address);
+                    0xFF10..=0xFF3F => {
+                        return self.apu.read(address) | io_read_mask(address);
                     }
 
                     _ => {
@@ -233,7 +570,7 @@ impl Memory {
                     }
                 }
 
-                return self.io_hw[(address - 0xFF00) as usize];
+                return self.io_hw[(address - 0xFF00) as usize] | io_read_mask(address);
             }
             // HRAM
             0xFF80..0xFFFF => {
@@ -244,7 +581,6 @@ impl Memory {
                 warn!("CALL TO INTERRUPT ENABLE READ");
                 return self.ie;
             }
-            _ => panic!("READ_BYTE : INVALID ADDRESS ({:#06X})", address),
         }
     }
 
@@ -260,27 +596,32 @@ impl Memory {
             0x2000..0x4000 => {
                 // writing to this rom address range selects the rom bank
                 // for the MBC
-                let nb_additional_banks = self.switch_rom_bank.len() as u8;
-                let mut corrected_value = value;
-
-                if corrected_value == 0 {
-                    // bank 0 is mapped in a fixed manner to 0000..=4000
-                    // and can't be mapped twice
-                    corrected_value = 1
+                // only 5 bits are wired on the MBC1 and 7 on the MBC3, and a 0 reads as 1 :
+                // bank 0 is mapped in a fixed manner to 0000..=4000 and can't be mapped twice
+                // (banks past the end of the rom wrap around, see mbc1_rom_bank)
+                let mask = match self.mbc {
+                    MBC::MBC3 => 0b111_1111,
+                    _ => 0b1_1111,
                 };
-                if corrected_value > nb_additional_banks {
-                    // on the gameboy, this register is masked
-                    // so that you can't map a bank that doesn't exist
-                    corrected_value = nb_additional_banks;
-                }
-
-                self.selected_rom_bank = corrected_value;
-            }
-            0x4000..0x6000 => {
-                // writing to this range  switches the selected ram bank
-                // for 32 KiB RAM cartridges
-                todo!("ram switching")
+                self.selected_rom_bank = (value & mask).max(1);
             }
+            0x4000..0x6000 => match self.mbc {
+                // the ram bank, or an rtc register
+                // FIXME : only 8 KiB of external ram exist for now, so ram banking is ignored
+                MBC::MBC3 => self.ram_bank = value,
+                // the upper bits of the rom bank, or the ram bank for 32 KiB RAM cartridges
+                _ => self.rom_bank_high = value & 0b11,
+            },
+            0x6000..0x8000 => match self.mbc {
+                // https://gbdev.io/pandocs/MBC3.html#6000-7fff---latch-clock-data-write-only
+                // writing 00 then 01 copies the rtc counter to its registers
+                MBC::MBC3 => {
+                    if let Some(rtc) = self.rtc.as_mut() {
+                        rtc.write_latch(value);
+                    }
+                }
+                _ => self.banking_mode = value & 0b1 != 0,
+            },
             // VRAM
             0x8000..0xA000 => {
                 trace!(
@@ -288,10 +629,16 @@ impl Memory {
                     value,
                     address
                 );
-                self.vram[(address - 0x8000) as usize] = value;
+                self.vram_bank_mut()[(address - 0x8000) as usize] = value;
             }
             // EXTERNAL RAM
             0xA000..0xC000 => {
+                if let Some(register) = self.selected_rtc_register() {
+                    if let Some(rtc) = self.rtc.as_mut() {
+                        rtc.write(register, value);
+                    }
+                    return Ok(());
+                }
                 trace!(
                     "Wrote byte {:#04X} to EXTERNAL RAM at address {:#06X}",
                     value,
@@ -315,8 +662,13 @@ impl Memory {
                     value,
                     address
                 );
-                self.switchable_wram[(address - 0xD000) as usize] = value;
-                //warn!("SWITCHABLE WRAM NOT YET SUPPORTED, BEHAVIOR MAY BE UNEXPECTED !");
+                let bank = self.wram_bank();
+                self.switchable_wram[bank - 1][(address - 0xD000) as usize] = value;
+            }
+            // ECHO RAM
+            0xE000..0xFE00 => {
+                // mirror of C000-DDFF
+                return self.write_byte(address - 0x2000, value);
             }
             // OAM
             0xFE00..0xFEA0 => {
@@ -338,18 +690,21 @@ impl Memory {
                 }
                 0xFF01 => {
                     debug!("WRITE TO SERIAL DATA REGISTER");
+                    self.io_hw[0x01] = value;
                 }
                 0xFF02 => {
                     debug!("WRITE TO SERIAL CONTROL REGISTER");
+                    self.io_hw[0x02] = value;
                 }
                 0xFF04 => {
                     // writing to the DIV register clears it
-                    self.io_hw[0x04] = 0x00;
-                }
-                0xFF10..0xFF40 => {
-                    // audio registers, not important for now
-                    debug!("WRITE TO AUDIO REGISTER ({:#06X})", address);
+                    // which counts as a falling edge for the apu
+                    if self.io_hw[0x04] & (1 << self.div_apu_bit()) != 0 {
+                        self.apu.step();
+                    }
+                    self.set_internal_div(0);
                 }
+                0xFF10..0xFF40 => self.apu.write(address, value),
                 0xFF41 => {
                     // the lower 3 bits of LCD STAT are read-only
                     // and should not be overwritten by a call to write_byte
@@ -361,9 +716,33 @@ impl Memory {
                     for i in 0..160u16 {
                         self.oam[i as usize] = self.read_byte(start_adress + i);
                     }
+                    // one byte per m-cycle
+                    self.dma_cycles = 160 * 4;
+                }
+                // the vram dma only exists in cgb mode, these are unused registers on the dmg
+                0xFF51..=0xFF54 if self.cgb_mode => {
+                    // VRAM DMA source (HDMA1-2) & destination (HDMA3-4)
+                    self.io_hw[(address - 0xFF00) as usize] = value;
+                }
+                0xFF55 if self.cgb_mode => {
+                    // https://gbdev.io/pandocs/CGB_Registers.html#lcd-vram-dma-transfers
+                    self.start_vram_dma(value);
+                }
+                0xFF40 => {
+                    // https://gbdev.io/pandocs/LCDC.html#lcdc7--lcd-enable
+                    // turning the lcd off outside of v-blank could damage the screen on hardware
+                    if self.is_lcd_enabled()
+                        && value & 0b1000_0000 == 0
+                        && self.io_hw[0x41] & 0b11 != 1
+                    {
+                        warn!("LCD TURNED OFF OUTSIDE OF V-BLANK (LY {})", self.ppu_line);
+                    }
+                    self.io_hw[0x40] = value;
+                }
+                0xFF0F => {
+                    // only the 5 interrupt bits exist, the others always read as 1
+                    self.io_hw[0x0F] = value & 0b0001_1111;
                 }
-                0xFF0F |            // IF 
-                0xFF40 |            // LCD CONTROL
                 0xFF42 | 0xFF43 |   // SCX & SCY
                 0xFF45 |            // LCY
                 0xFF47 |            // PALETTE
@@ -376,19 +755,65 @@ impl Memory {
                     self.io_hw[(address - 0xFF00) as usize] = value;
                 }
                 0xFF48 | 0xFF49 => {
-                    info!("WRITE TO OBJ PALETTE REGISTER");
+                    // kept so they read back, but objects are still drawn with the shades of
+                    // the tile atlas
                     // FIXME : this is important for object palette
+                    self.io_hw[(address - 0xFF00) as usize] = value;
+                }
+                0xFF4D => {
+                    // only the prepare bit is writable, the switch happens on STOP
+                    if self.cgb_mode {
+                        self.io_hw[0x4D] = value & 0b1;
+                    } else {
+                        debug!("WRITE TO KEY1 OUTSIDE OF CGB MODE");
+                    }
+                }
+                0xFF56 => {
+                    // https://gbdev.io/pandocs/CGB_Registers.html#ff56--rp-cgb-mode-only-infrared-communications-port
+                    // there is nothing on the other side, the led is only kept for reading back
+                    if self.cgb_mode {
+                        self.io_hw[0x56] = value & 0b1100_0001;
+                    } else {
+                        debug!("WRITE TO RP OUTSIDE OF CGB MODE");
+                    }
+                }
+                0xFF4F => {
+                    // https://gbdev.io/pandocs/CGB_Registers.html#ff4f--vbk-cgb-mode-only-vram-bank
+                    if self.cgb_mode {
+                        self.io_hw[0x4F] = value & 0b1;
+                    } else {
+                        debug!("WRITE TO VBK OUTSIDE OF CGB MODE");
+                    }
+                }
+                0xFF6C => {
+                    // https://gbdev.io/pandocs/CGB_Registers.html#ff6c--opri-cgb-mode-only-object-priority-mode
+                    if self.cgb_mode {
+                        self.io_hw[0x6C] = value & 0b1;
+                    } else {
+                        debug!("WRITE TO OPRI OUTSIDE OF CGB MODE");
+                    }
+                }
+                0xFF70 => {
+                    // https://gbdev.io/pandocs/CGB_Registers.html#ff70--svbk-cgb-mode-only-wram-bank
+                    if self.cgb_mode {
+                        self.io_hw[0x70] = value & 0b0000_0111;
+                    } else {
+                        debug!("WRITE TO SVBK OUTSIDE OF CGB MODE");
+                    }
                 }
                 0xFF7F => {
-                    // this register is supposed to be unused but dr mario somehow writes to it
-                    // maybe a bug in my emulator
-                    warn!("WEIRD DR MARIO WRITE AT 0xFF7F");
+                    // this register is unused, but some games (e.g. dr mario) write to it
+                    if self.quirks.writes_unused_ff7f {
+                        debug!("WRITE TO UNUSED REGISTER 0xFF7F");
+                    } else {
+                        warn!("WRITE TO UNUSED REGISTER 0xFF7F");
+                    }
                 }
                 _ => {
-                    return Err(EmulationError {
-                        ty: EmulationErrorType::UnauthorizedWrite(address),
-                        pc: None,
-                    });
+                    return Err(EmulationError::new(
+                        EmulationErrorType::UnauthorizedWrite(address),
+                        None,
+                    ));
                 }
             },
             // HRAM
@@ -401,38 +826,130 @@ impl Memory {
                 self.ie = value;
             }
             _ => {
-                return Err(EmulationError {
-                    ty: EmulationErrorType::UnauthorizedWrite(address),
-                    pc: None,
-                });
+                return Err(EmulationError::new(
+                    EmulationErrorType::UnauthorizedWrite(address),
+                    None,
+                ));
             }
         }
 
         Ok(())
     }
 
+    // writes straight to the underlying storage : no mbc, no register side effects,
+    // no logging. for setting up the state of the console in tests
+    #[cfg(any(test, debug_assertions))]
+    pub fn direct_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..0x4000 => self.fixed_rom_bank[address as usize] = value,
+            0x4000..0x8000 => {
+                let bank = match self.mbc {
+                    MBC::NONE => 0,
+                    _ => (self.selected_rom_bank() as usize).max(1) - 1,
+                };
+                self.switch_rom_bank[bank][(address - 0x4000) as usize] = value;
+            }
+            0x8000..0xA000 => self.vram_bank_mut()[(address - 0x8000) as usize] = value,
+            0xA000..0xC000 => self.ext_ram[(address - 0xA000) as usize] = value,
+            0xC000..0xD000 => self.wram[(address - 0xC000) as usize] = value,
+            0xD000..0xE000 => {
+                let bank = self.wram_bank();
+                self.switchable_wram[bank - 1][(address - 0xD000) as usize] = value;
+            }
+            0xE000..0xFE00 => self.direct_write(address - 0x2000, value),
+            0xFE00..0xFEA0 => self.oam[(address - 0xFE00) as usize] = value,
+            0xFEA0..0xFF00 => {}
+            0xFF00..0xFF80 => self.io_hw[(address - 0xFF00) as usize] = value,
+            0xFF80..0xFFFF => self.hram[(address - 0xFF80) as usize] = value,
+            0xFFFF => self.ie = value,
+        }
+    }
+
     pub fn read_word(&self, address: u16) -> u16 {
         let mut bytes: [u8; 2] = [0; 2];
         bytes[0] = self.read_byte(address);
-        bytes[1] = self.read_byte(address + 1);
+        bytes[1] = self.read_byte(address.wrapping_add(1));
         return u16::from_le_bytes(bytes);
     }
 
     pub fn write_word(&mut self, address: u16, value: u16) -> Result<(), EmulationError> {
         let value_bytes = value.to_le_bytes();
         self.write_byte(address, value_bytes[0])?;
-        self.write_byte(address + 1, value_bytes[1])?;
+        self.write_byte(address.wrapping_add(1), value_bytes[1])?;
 
         Ok(())
     }
 
+    // access tracking
+
+    // counts from zero again
+    pub fn start_access_tracking(&mut self) {
+        self.access_tracking = true;
+        self.clear_access_counters();
+    }
+
+    // the counters are kept until the next start or clear
+    pub fn stop_access_tracking(&mut self) {
+        self.access_tracking = false;
+    }
+
+    pub fn is_access_tracking(&self) -> bool {
+        return self.access_tracking;
+    }
+
+    pub fn clear_access_counters(&mut self) {
+        self.access_counters = Some(AccessCounters {
+            // built on the heap, an array this size would overflow the stack
+            reads: vec![Cell::new(0); 0x10000]
+                .into_boxed_slice()
+                .try_into()
+                .unwrap(),
+            writes: vec![0; 0x10000].into_boxed_slice().try_into().unwrap(),
+            executes: vec![0; 0x10000].into_boxed_slice().try_into().unwrap(),
+        });
+    }
+
+    pub fn access_counters(&self) -> Option<&AccessCounters> {
+        return self.access_counters.as_ref();
+    }
+
+    pub fn record_read(&self, address: u16) {
+        if !self.access_tracking {
+            return;
+        }
+        if let Some(counters) = &self.access_counters {
+            let counter = &counters.reads[address as usize];
+            counter.set(counter.get().saturating_add(1));
+        }
+    }
+
+    pub fn record_write(&mut self, address: u16) {
+        if !self.access_tracking {
+            return;
+        }
+        if let Some(counters) = &mut self.access_counters {
+            let counter = &mut counters.writes[address as usize];
+            *counter = counter.saturating_add(1);
+        }
+    }
+
+    pub fn record_execute(&mut self, address: u16) {
+        if !self.access_tracking {
+            return;
+        }
+        if let Some(counters) = &mut self.access_counters {
+            let counter = &mut counters.executes[address as usize];
+            *counter = counter.saturating_add(1);
+        }
+    }
+
     // functions to write to the hw registers bypassing the MMU
 
     // the lower 3 bits of LCD STAT are read-only
     // and they should not be overwritten by a call to write_byte
     // however the cpu needs to have a way to update them
     // bits 0 & 1 are the current PPU MODE
-    pub fn _update_lcd_stat_ppu_mode(&mut self, ppu_mode: u8) {
+    pub fn update_lcd_stat_ppu_mode(&mut self, ppu_mode: u8) {
         assert!(ppu_mode < 4, "PPU mode should be a 2-bit value (0-3) !");
         let lcd_stat = self.io_hw[0x41];
 
@@ -442,17 +959,38 @@ impl Memory {
     pub fn update_lcd_stat_lcy_eq_ly(&mut self, lcy_eq_ly: bool) {
         let lcd_stat = self.io_hw[0x41];
         self.io_hw[0x41] = if lcy_eq_ly {
-            lcd_stat | 0b_0000_1000
+            lcd_stat | 0b_0000_0100
         } else {
-            lcd_stat & 0b_1111_0111
+            lcd_stat & 0b_1111_1011
         }
     }
 
     // writing to the DIV register from code should set it to 0x00
     // so this function is used to update it without clearing it
-    pub fn increment_div(&mut self) {
-        let div = self.io_hw[0x04];
-        self.io_hw[0x04] = div.wrapping_add(1);
+    pub fn advance_div(&mut self, cycles: u64) {
+        let old = self.div as u64;
+        let new = old + cycles;
+        // every time the bit goes from 1 to 0, which happens once per period of the next bit
+        let period_bit = self.div_apu_bit() + 8 + 1;
+        for _ in 0..((new >> period_bit) - (old >> period_bit)) {
+            self.apu.step();
+        }
+        self.set_internal_div(new as u16);
+    }
+
+    pub fn internal_div(&self) -> u16 {
+        return self.div;
+    }
+
+    pub fn set_internal_div(&mut self, value: u16) {
+        self.div = value;
+        self.io_hw[0x04] = (value >> 8) as u8;
+    }
+
+    // https://gbdev.io/pandocs/Audio_details.html#div-apu
+    // the frame sequencer steps when this bit of DIV goes from 1 to 0
+    fn div_apu_bit(&self) -> u32 {
+        return if self.double_speed { 5 } else { 4 };
     }
 
     // TIMA overflow should request an interrupt
@@ -474,8 +1012,55 @@ impl Memory {
 
     // LCD Y is read only
     pub fn increment_ly(&mut self) {
-        let ly = self.io_hw[0x44];
-        self.io_hw[0x44] = (ly + 1) % 154;
+        self.ppu_line = (self.ppu_line + 1) % 154;
+        self.io_hw[0x44] = self.ppu_line;
+    }
+
+    // LY is held at 0 while the lcd is off
+    pub fn reset_ly(&mut self) {
+        self.ppu_line = 0;
+        self.io_hw[0x44] = 0;
+    }
+
+    // on line 153, LY already reads 0 after a few cycles
+    // while the ppu itself only gets back to line 0 at the end of the line
+    pub fn wrap_ly_early(&mut self) {
+        self.io_hw[0x44] = 0;
+    }
+
+    pub fn ppu_line(&self) -> u8 {
+        return self.ppu_line;
+    }
+
+    pub fn oam(&self) -> &[u8; 0xA0] {
+        return &self.oam;
+    }
+
+    // for the oam corruption bug, which bypasses the usual access rules
+    pub fn oam_mut(&mut self) -> &mut [u8; 0xA0] {
+        return &mut self.oam;
+    }
+
+    // 8000-97FF : the 384 tiles, 16 bytes each
+    pub fn tile_data(&self) -> &[u8] {
+        return &self.vram[..0x1800];
+    }
+
+    // `address` is in 8000-9FFF
+    // reads vram whatever mode the ppu is in, for the ppu itself and the debugging tools :
+    // the cpu goes through read_byte, which is where vram gets locked during mode 3
+    pub fn peek_vram(&self, address: u16) -> u8 {
+        return self.vram[(address - 0x8000) as usize];
+    }
+
+    // https://gbdev.io/pandocs/Tile_Maps.html#bg-map-attributes-cgb-mode-only
+    // the attributes of a tile map entry (9800-9FFF), kept at the same address in vram bank 1
+    // there are none outside of CGB mode
+    pub fn peek_tile_attributes(&self, map_address: u16) -> u8 {
+        if !self.cgb_mode {
+            return 0;
+        }
+        return self.vram_1[(map_address - 0x8000) as usize];
     }
 
     // LCD control byte flags
@@ -493,6 +1078,30 @@ impl Memory {
         return self.read_lcd_ctrl_flag(4);
     }
 
+    pub fn is_window_enabled(&self) -> bool {
+        return self.read_lcd_ctrl_flag(5);
+    }
+
+    // the window uses the tile map at 9C00 instead of 9800
+    pub fn is_window_tile_map_high(&self) -> bool {
+        return self.read_lcd_ctrl_flag(6);
+    }
+
+    // https://gbdev.io/pandocs/LCDC.html#lcdc2--obj-size
+    // objects are 8x8, or 8x16 when set
+    pub fn object_height(&self) -> isize {
+        return if self.read_lcd_ctrl_flag(2) { 16 } else { 8 };
+    }
+
+    // https://gbdev.io/pandocs/Scrolling.html#ff4aff4b--wy-wx-window-y-position-x-position-plus-7
+    // WX is the x position + 7
+    pub fn read_window_position_registers(&self) -> (usize, usize) {
+        let y = self.read_byte(0xff4a);
+        let x = self.read_byte(0xff4b);
+
+        return (x as usize, y as usize);
+    }
+
     pub fn read_scrolling_registers(&self) -> (usize, usize) {
         let y = self.read_byte(0xff42);
         let x = self.read_byte(0xff43);
@@ -509,26 +1118,284 @@ impl Memory {
     }
 
     pub fn is_interrupt_requested(&self, interrupt: u8) -> bool {
-        let interrupt_request_byte = self.read_byte(0xFF0F);
+        let interrupt_request_byte = self.io_hw[0x0F];
 
         return (interrupt_request_byte >> interrupt) & 1 == 1;
     }
 
     pub fn request_interrupt(&mut self, interrupt: u8) {
-        let interrupt_request_byte = self.read_byte(0xFF0F);
+        let interrupt_request_byte = self.io_hw[0x0F];
         self.io_hw[0x0F] = interrupt_request_byte | (1 << interrupt);
     }
 
     pub fn clear_interrupt(&mut self, interrupt: u8) {
-        let interrupt_request_byte = self.read_byte(0xFF0F);
+        let interrupt_request_byte = self.io_hw[0x0F];
         self.io_hw[0x0F] = interrupt_request_byte & !(1 << interrupt);
     }
 
+    pub fn any_interrupt_enabled(&self) -> bool {
+        return self.ie & 0b0001_1111 != 0;
+    }
+
     pub fn interrupt_pending_and_enabled(&self) -> bool {
         let ie = self.ie;
         let i_request = self.io_hw[0x0F];
 
-        return (ie & i_request) != 0;
+        // the upper bits of IE can be set, but don't match any interrupt
+        return (ie & i_request & 0b0001_1111) != 0;
+    }
+
+    // only cgb-only cartridges run in cgb mode, see `load_rom`
+    pub fn is_cgb_mode(&self) -> bool {
+        return self.cgb_mode;
+    }
+
+    pub fn apu(&self) -> &Apu {
+        return &self.apu;
+    }
+
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        return &mut self.apu;
+    }
+
+    // true when overlapping objects are ordered by x coordinate (dmg style),
+    // false when they are by oam position
+    pub fn is_object_priority_by_x(&self) -> bool {
+        return !self.cgb_mode || self.io_hw[0x6C] & 0b1 != 0;
+    }
+
+    pub fn is_double_speed(&self) -> bool {
+        return self.double_speed;
+    }
+
+    // called on STOP : toggles the cpu speed if the switch was armed through KEY1
+    // returns true if the speed changed
+    pub fn switch_speed(&mut self) -> bool {
+        if !self.cgb_mode || self.io_hw[0x4D] & 0b1 == 0 {
+            return false;
+        }
+
+        self.double_speed = !self.double_speed;
+        self.io_hw[0x4D] = 0;
+        return true;
+    }
+
+    // vram bank mapped to 8000-9FFF, selected by VBK
+    // bank 1 holds the cgb tile attributes, and more tiles
+    fn vram_bank(&self) -> &[u8; 0x2000] {
+        if self.cgb_mode && self.io_hw[0x4F] & 0b1 != 0 {
+            return &self.vram_1;
+        }
+        return &self.vram;
+    }
+
+    fn vram_bank_mut(&mut self) -> &mut [u8; 0x2000] {
+        if self.cgb_mode && self.io_hw[0x4F] & 0b1 != 0 {
+            return &mut self.vram_1;
+        }
+        return &mut self.vram;
+    }
+
+    // wram bank mapped to D000-DFFF, between 1 and 7
+    // selecting bank 0 maps bank 1, and there is only bank 1 outside of CGB mode
+    pub fn wram_bank(&self) -> usize {
+        if !self.cgb_mode {
+            return 1;
+        }
+        return (self.io_hw[0x70] & 0b0000_0111).max(1) as usize;
+    }
+
+    pub fn quirks(&self) -> &GameQuirks {
+        return &self.quirks;
+    }
+
+    // all zeroes for roms without a header
+    pub fn cartridge_info(&self) -> &CartridgeInfo {
+        return &self.cartridge_info;
+    }
+
+    // the fixed bank included
+    pub fn rom_bank_count(&self) -> usize {
+        return 1 + self.switch_rom_bank.len();
+    }
+
+    // the global checksum as mapped, which save states are matched against
+    pub fn rom_checksum(&self) -> u16 {
+        return snapshot::rom_checksum(&self.fixed_rom_bank);
+    }
+
+    // save states don't include the rom : a restored memory takes it from the cartridge
+    // that is inserted, which has been checked against the state's `rom_checksum`
+    pub fn restore_rom(&mut self, cartridge: &mut Memory) {
+        std::mem::swap(&mut self.fixed_rom_bank, &mut cartridge.fixed_rom_bank);
+        std::mem::swap(&mut self.switch_rom_bank, &mut cartridge.switch_rom_bank);
+        self.cartridge_info = cartridge.cartridge_info.clone();
+    }
+
+    // MBC
+    // the rom bank currently mapped to 4000-7FFF
+    pub fn selected_rom_bank(&self) -> u8 {
+        return match self.mbc {
+            MBC::MBC1 => self.mbc1_rom_bank(true) as u8,
+            MBC::MBC3 => self.mbc3_rom_bank() as u8,
+            _ => self.selected_rom_bank,
+        };
+    }
+
+    pub fn is_mbc1_multicart(&self) -> bool {
+        return self.mbc1_multicart;
+    }
+
+    // the cartridge has a real time clock, which has to be kept up with the time of day
+    pub fn has_rtc(&self) -> bool {
+        return self.rtc.is_some();
+    }
+
+    // moves the real time clock, if any, to the time of `clock`
+    pub fn update_rtc(&mut self, clock: &dyn Clock) {
+        if let Some(rtc) = self.rtc.as_mut() {
+            rtc.update(clock.now());
+        }
+    }
+
+    // the MBC3 maps an rtc register to A000-BFFF instead of the ram when 08-0C is selected
+    fn selected_rtc_register(&self) -> Option<u8> {
+        return match (self.mbc, self.ram_bank) {
+            (MBC::MBC3, register @ 0x08..=0x0C) => Some(register),
+            _ => None,
+        };
+    }
+
+    // https://gbdev.io/pandocs/MBC3.html#2000-3fff---rom-bank-number-write-only
+    // the MBC3 has no mode or upper bits, banks past the end of the rom wrap around
+    fn mbc3_rom_bank(&self) -> usize {
+        return self.selected_rom_bank as usize % self.rom_bank_count();
+    }
+
+    // maps any rom bank to 4000-7FFF, for tools going through the whole rom
+    // this sets the bank registers directly, so it can even map the banks the mbc1 can't
+    // (00, 20, 40 & 60) there
+    pub fn force_rom_bank(&mut self, bank: usize) {
+        match self.mbc {
+            MBC::MBC1 => {
+                let shift = if self.mbc1_multicart { 4 } else { 5 };
+                self.selected_rom_bank = (bank & ((1 << shift) - 1)) as u8;
+                self.rom_bank_high = (bank >> shift) as u8 & 0b11;
+            }
+            MBC::MBC3 => self.selected_rom_bank = bank as u8,
+            _ => {}
+        }
+    }
+
+    // https://gbdev.io/pandocs/MBC1.html#00003fff--rom-bank-x0-read-only
+    // the bank seen in 4000-7FFF (`high_area`) or in 0000-3FFF
+    // BANK2 goes above the 5 bits of BANK1, or above 4 of them on multicarts, and only
+    // applies to 0000-3FFF in mode 1. banks past the end of the rom wrap around
+    fn mbc1_rom_bank(&self, high_area: bool) -> usize {
+        let shift = if self.mbc1_multicart { 4 } else { 5 };
+        let high = (self.rom_bank_high as usize) << shift;
+        let bank = if high_area {
+            high | (self.selected_rom_bank as usize & ((1 << shift) - 1))
+        } else if self.banking_mode {
+            high
+        } else {
+            0
+        };
+
+        return bank % self.rom_bank_count();
+    }
+
+    fn rom_bank_byte(&self, bank: usize, offset: usize) -> u8 {
+        return if bank == 0 {
+            self.fixed_rom_bank[offset]
+        } else {
+            // bank 0 is the fixed bank, hence the -1 here
+            self.switch_rom_bank[bank - 1][offset]
+        };
+    }
+
+    // a write to HDMA5 : copies ((value & 0x7F) + 1) blocks of 16 bytes to vram,
+    // all at once (general purpose dma) or one per hblank when bit 7 is set
+    fn start_vram_dma(&mut self, value: u8) {
+        let blocks = (value & 0x7F) + 1;
+
+        if let Some(left) = self.hblank_dma_blocks {
+            if value & 0x80 == 0 {
+                // writing bit 7 clear stops the running hblank dma
+                debug!("HBLANK DMA STOPPED WITH {} BLOCKS LEFT", left);
+                self.hblank_dma_blocks = None;
+                self.io_hw[0x55] = 0x80 | (left - 1);
+                return;
+            }
+        }
+
+        self.vram_dma_source = u16::from_be_bytes([self.io_hw[0x51], self.io_hw[0x52]]) & 0xFFF0;
+        // the destination is always in vram
+        self.vram_dma_destination =
+            (u16::from_be_bytes([self.io_hw[0x53], self.io_hw[0x54]]) & 0x1FF0) | 0x8000;
+
+        if value & 0x80 != 0 {
+            debug!(
+                "HBLANK DMA OF {} BLOCKS FROM {:#06X} TO {:#06X}",
+                blocks, self.vram_dma_source, self.vram_dma_destination
+            );
+            self.hblank_dma_blocks = Some(blocks);
+            self.io_hw[0x55] = blocks - 1;
+        } else {
+            debug!(
+                "GENERAL PURPOSE DMA OF {} BLOCKS FROM {:#06X} TO {:#06X}",
+                blocks, self.vram_dma_source, self.vram_dma_destination
+            );
+            for _ in 0..blocks {
+                self.copy_vram_dma_block();
+            }
+            // the transfer is over
+            self.io_hw[0x55] = 0xFF;
+        }
+    }
+
+    // called when the ppu enters hblank on a visible line
+    pub fn hblank_dma_step(&mut self) {
+        let Some(left) = self.hblank_dma_blocks else {
+            return;
+        };
+
+        self.copy_vram_dma_block();
+        if left > 1 {
+            self.hblank_dma_blocks = Some(left - 1);
+            self.io_hw[0x55] = left - 2;
+        } else {
+            self.hblank_dma_blocks = None;
+            self.io_hw[0x55] = 0xFF;
+        }
+    }
+
+    fn copy_vram_dma_block(&mut self) {
+        for _ in 0..0x10 {
+            let byte = self.read_byte(self.vram_dma_source);
+            // the copy wraps around the end of vram
+            let offset = (self.vram_dma_destination - 0x8000) as usize % self.vram.len();
+            self.vram_bank_mut()[offset] = byte;
+
+            self.vram_dma_source = self.vram_dma_source.wrapping_add(1);
+            self.vram_dma_destination = 0x8000 | (self.vram_dma_destination + 1) & 0x1FFF;
+        }
+
+        // 8 m-cycles per block in single speed mode, twice as many in double speed
+        self.vram_dma_cycles += if self.double_speed { 64 } else { 32 };
+    }
+
+    pub fn take_vram_dma_cycles(&mut self) -> u64 {
+        return std::mem::take(&mut self.vram_dma_cycles);
+    }
+
+    // OAM DMA
+    pub fn is_dma_active(&self) -> bool {
+        return self.dma_cycles > 0;
+    }
+
+    pub fn advance_dma(&mut self, cycles: u64) {
+        self.dma_cycles = self.dma_cycles.saturating_sub(cycles);
     }
 
     // Timer
@@ -539,6 +1406,33 @@ impl Memory {
         return self.read_byte(0xFF07) >> 2 & 1 == 1;
     }
 
+    // Serial
+    // https://gbdev.io/pandocs/Serial_Data_Transfer_(Link_Cable).html
+    // 0xFF02 : 7                 |   0
+    //          Transfer enable   |   Clock select (1 = internal)
+
+    pub fn is_serial_transfer_requested(&self) -> bool {
+        return (self.io_hw[0x02] >> 7) & 1 == 1;
+    }
+
+    pub fn is_serial_clock_internal(&self) -> bool {
+        return self.io_hw[0x02] & 1 == 1;
+    }
+
+    pub fn read_serial_data(&self) -> u8 {
+        return self.io_hw[0x01];
+    }
+
+    // once the 8 bits have been shifted, the transfer flag is cleared
+    // and a serial interrupt is requested
+    pub fn complete_serial_transfer(&mut self, received: u8) {
+        self.io_hw[0x01] = received;
+        self.io_hw[0x02] &= 0b_0111_1111;
+
+        // serial interrupt is bit 3
+        self.request_interrupt(3);
+    }
+
     // Input
 
     pub fn input_buttons_selected(&self) -> bool {
@@ -549,6 +1443,11 @@ impl Memory {
         return (self.io_hw[0] >> 4) & 1 == 0;
     }
 
+    // the state of the 4 lines, low when a selected button is pressed
+    pub fn read_input_lower(&self) -> u8 {
+        return self.io_hw[0] & 0b_0000_1111;
+    }
+
     pub fn update_input_lower(&mut self, inputs: u8) {
         assert!(inputs < 16);
 
@@ -556,9 +1455,566 @@ impl Memory {
     }
 }
 
-enum MBC {
+// https://gbdev.io/pandocs/The_Cartridge_Header.html#0147--cartridge-type
+// the name of the hardware in the cartridge, from the type byte of the header
+pub fn cartridge_type_name(byte: u8) -> &'static str {
+    return match byte {
+        0x00 => "ROM ONLY",
+        0x01 => "MBC1",
+        0x02 => "MBC1+RAM",
+        0x03 => "MBC1+RAM+BATTERY",
+        0x05 => "MBC2",
+        0x06 => "MBC2+BATTERY",
+        0x08 => "ROM+RAM",
+        0x09 => "ROM+RAM+BATTERY",
+        0x0B => "MMM01",
+        0x0C => "MMM01+RAM",
+        0x0D => "MMM01+RAM+BATTERY",
+        0x0F => "MBC3+TIMER+BATTERY",
+        0x10 => "MBC3+TIMER+RAM+BATTERY",
+        0x11 => "MBC3",
+        0x12 => "MBC3+RAM",
+        0x13 => "MBC3+RAM+BATTERY",
+        0x19 => "MBC5",
+        0x1A => "MBC5+RAM",
+        0x1B => "MBC5+RAM+BATTERY",
+        0x1C => "MBC5+RUMBLE",
+        0x1D => "MBC5+RUMBLE+RAM",
+        0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+        0xFC => "POCKET CAMERA",
+        0xFD => "BANDAI TAMA5",
+        0xFE => "HuC3",
+        0xFF => "HuC1+RAM+BATTERY",
+        _ => "UNKNOWN",
+    };
+}
+
+// bits of the io registers that are unused or write-only : they always read back as 1
+// https://gbdev.io/pandocs/Hardware_Reg_List.html
+fn io_read_mask(address: u16) -> u8 {
+    return match address {
+        0xFF00 => 0b1100_0000, // P1
+        0xFF02 => 0b0111_1110, // SC
+        0xFF07 => 0b1111_1000, // TAC
+        0xFF0F => 0b1110_0000, // IF
+        // audio : frequencies and lengths are write-only
+        0xFF10 => 0x80,
+        0xFF11 | 0xFF16 => 0x3F,
+        0xFF14 | 0xFF19 | 0xFF1E | 0xFF23 => 0xBF,
+        0xFF13 | 0xFF18 | 0xFF1B | 0xFF1D | 0xFF20 => 0xFF,
+        0xFF1A => 0x7F,
+        0xFF1C => 0x9F,
+        0xFF26 => 0x70,
+        0xFF41 => 0b1000_0000, // STAT
+        // unused registers
+        0xFF03 | 0xFF08..=0xFF0E | 0xFF15 | 0xFF1F | 0xFF27..=0xFF2F | 0xFF4C..=0xFF7F => 0xFF,
+        _ => 0x00,
+    };
+}
+
+// the memory bank controller of the cartridge
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum MBC {
     NONE,
     MBC1,
     MBC2,
     MBC3,
 }
+
+impl From<&Memory> for MemorySnapshot {
+    fn from(memory: &Memory) -> Self {
+        return MemorySnapshot {
+            vram: memory.vram.to_vec(),
+            vram_1: memory.vram_1.to_vec(),
+            ext_ram: memory.ext_ram.to_vec(),
+            wram: memory.wram.to_vec(),
+            switchable_wram: memory.switchable_wram.iter().map(|b| b.to_vec()).collect(),
+            oam: memory.oam.to_vec(),
+            io_hw: memory.io_hw.to_vec(),
+            hram: memory.hram.to_vec(),
+            ie: memory.ie,
+            mbc: memory.mbc,
+            selected_rom_bank: memory.selected_rom_bank,
+            rom_bank_high: memory.rom_bank_high,
+            banking_mode: memory.banking_mode,
+            mbc1_multicart: memory.mbc1_multicart,
+            ram_bank: memory.ram_bank,
+            rtc: memory.rtc.clone(),
+            ppu_line: memory.ppu_line,
+            div: Some(memory.div),
+            dma_cycles: memory.dma_cycles,
+            vram_dma_cycles: memory.vram_dma_cycles,
+            vram_dma_source: memory.vram_dma_source,
+            vram_dma_destination: memory.vram_dma_destination,
+            hblank_dma_blocks: memory.hblank_dma_blocks,
+            apu: memory.apu.clone(),
+            cgb_mode: memory.cgb_mode,
+            double_speed: memory.double_speed,
+            quirks: memory.quirks,
+        };
+    }
+}
+
+// the sizes are expected to have been checked with `Snapshot::check_sizes`
+impl From<MemorySnapshot> for Memory {
+    fn from(snapshot: MemorySnapshot) -> Self {
+        // the rom & cartridge info are added by `restore_rom`
+        let mut memory = Memory::new();
+        memory.vram.copy_from_slice(&snapshot.vram);
+        // older files don't have the second bank
+        if !snapshot.vram_1.is_empty() {
+            memory.vram_1.copy_from_slice(&snapshot.vram_1);
+        }
+        memory.ext_ram.copy_from_slice(&snapshot.ext_ram);
+        memory.wram.copy_from_slice(&snapshot.wram);
+        memory.switchable_wram = snapshot
+            .switchable_wram
+            .iter()
+            .map(|b| b.as_slice().try_into().unwrap())
+            .collect();
+        memory.oam.copy_from_slice(&snapshot.oam);
+        memory.io_hw.copy_from_slice(&snapshot.io_hw);
+        memory.hram.copy_from_slice(&snapshot.hram);
+        memory.ie = snapshot.ie;
+        memory.mbc = snapshot.mbc;
+        memory.selected_rom_bank = snapshot.selected_rom_bank;
+        memory.rom_bank_high = snapshot.rom_bank_high;
+        memory.banking_mode = snapshot.banking_mode;
+        memory.mbc1_multicart = snapshot.mbc1_multicart;
+        memory.ram_bank = snapshot.ram_bank;
+        memory.rtc = snapshot.rtc;
+        memory.ppu_line = snapshot.ppu_line;
+        // older files only have DIV, see `Snapshot::div_cycles` for the rest
+        let div = snapshot
+            .div
+            .unwrap_or(u16::from_be_bytes([snapshot.io_hw[0x04], 0]));
+        memory.set_internal_div(div);
+        memory.dma_cycles = snapshot.dma_cycles;
+        memory.vram_dma_cycles = snapshot.vram_dma_cycles;
+        memory.vram_dma_source = snapshot.vram_dma_source;
+        memory.vram_dma_destination = snapshot.vram_dma_destination;
+        memory.hblank_dma_blocks = snapshot.hblank_dma_blocks;
+        memory.apu = snapshot.apu;
+        memory.cgb_mode = snapshot.cgb_mode;
+        memory.double_speed = snapshot.double_speed;
+        memory.quirks = snapshot.quirks;
+
+        return memory;
+    }
+}
+
+// https://gbdev.io/pandocs/MBC1.html#mbc1m-1-mib-multi-game-compilation-carts
+// the header doesn't tell multicarts apart : each of their games starts with its own
+// header, so they are recognized by the nintendo logo showing up again at the start of
+// one of the other 256KiB game slots
+fn is_mbc1_multicart(rom: &[u8]) -> bool {
+    const LOGO: std::ops::Range<usize> = 0x0104..0x0134;
+    const GAME_SIZE: usize = 0x10 * 0x4000;
+
+    if rom.len() != 4 * GAME_SIZE || rom[0x0147] > 0x03 {
+        return false;
+    }
+
+    return (1..4).any(|game| {
+        rom[(game * GAME_SIZE + LOGO.start)..(game * GAME_SIZE + LOGO.end)] == rom[LOGO]
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 32KiB rom only cartridge, in cgb mode if asked
+    fn memory_with_rom(cgb: bool) -> Memory {
+        let mut rom = vec![0; 0x8000];
+        if cgb {
+            rom[0x0143] = 0xC0;
+        }
+        let mut memory = Memory::new();
+        memory.load_rom(rom).unwrap();
+        return memory;
+    }
+
+    // a general purpose dma of one block from C000 to 8000
+    fn start_gdma(memory: &mut Memory) -> Result<(), EmulationError> {
+        for address in 0xC000..0xC010 {
+            memory.write_byte(address, 0xAA)?;
+        }
+        for (address, value) in [
+            (0xFF51, 0xC0),
+            (0xFF52, 0x00),
+            (0xFF53, 0x00),
+            (0xFF54, 0x00),
+        ] {
+            memory.write_byte(address, value)?;
+        }
+        return memory.write_byte(0xFF55, 0x00);
+    }
+
+    #[test]
+    fn unsupported_mbc_is_an_error() {
+        let mut rom = vec![0; 0x8000];
+        // MBC5+RAM+BATTERY
+        rom[0x0147] = 0x1B;
+        let result = Memory::new().load_rom(rom);
+        assert!(matches!(result, Err(RomError::UnsupportedMbc(0x1B))));
+    }
+
+    #[test]
+    fn only_cgb_only_carts_get_cgb_mode() {
+        for (flag, cgb_mode) in [(0x00, false), (0x80, false), (0xC0, true)] {
+            let mut rom = vec![0; 0x8000];
+            rom[0x0143] = flag;
+            let mut memory = Memory::new();
+            memory.load_rom(rom).unwrap();
+            assert_eq!(memory.is_cgb_mode(), cgb_mode, "{flag:#04X}");
+        }
+    }
+
+    #[test]
+    fn vram_dma_in_cgb_mode() {
+        let mut memory = memory_with_rom(true);
+        start_gdma(&mut memory).unwrap();
+        assert_eq!(memory.peek_vram(0x8000), 0xAA);
+        assert_eq!(memory.peek_vram(0x800F), 0xAA);
+    }
+
+    #[test]
+    fn stat_bit_7_reads_as_1() {
+        let mut memory = memory_with_rom(false);
+        for value in [0x00, 0x78, 0xFF] {
+            memory.write_byte(0xFF41, value).unwrap();
+            let stat = memory.read_byte(0xFF41);
+            assert_eq!(stat & 0x80, 0x80, "{value:#04X}");
+            // the interrupt sources read back as written
+            assert_eq!(stat & 0x78, value & 0x78, "{value:#04X}");
+        }
+    }
+
+    #[test]
+    fn if_upper_bits_read_as_1() {
+        let mut memory = memory_with_rom(false);
+        for value in [0x00, 0x15, 0xFF] {
+            memory.write_byte(0xFF0F, value).unwrap();
+            assert_eq!(memory.read_byte(0xFF0F), 0xE0 | value, "{value:#04X}");
+        }
+        // requested interrupts don't touch them either
+        memory.write_byte(0xFF0F, 0x00).unwrap();
+        memory.request_interrupt(2);
+        assert_eq!(memory.read_byte(0xFF0F), 0xE4);
+    }
+
+    // https://gbdev.io/pandocs/CGB_Registers.html#ff56--rp-cgb-mode-only-infrared-communications-port
+    #[test]
+    fn rp_register() {
+        let mut memory = memory_with_rom(true);
+        // led off, reading disabled, and nothing received
+        assert_eq!(memory.read_byte(0xFF56), 0x3E);
+        for (value, expected) in [(0x01, 0x3F), (0xC0, 0xFE), (0xFF, 0xFF), (0x00, 0x3E)] {
+            memory.write_byte(0xFF56, value).unwrap();
+            assert_eq!(memory.read_byte(0xFF56), expected, "{value:#04X}");
+        }
+
+        // an unused register on the dmg
+        let mut memory = memory_with_rom(false);
+        memory.write_byte(0xFF56, 0x01).unwrap();
+        assert_eq!(memory.read_byte(0xFF56), 0xFF);
+    }
+
+    // https://gbdev.io/pandocs/CGB_Registers.html#ff70--svbk-cgb-mode-only-wram-bank
+    #[test]
+    fn wram_banks() {
+        let mut memory = memory_with_rom(true);
+        for bank in [2, 3] {
+            memory.write_byte(0xFF70, bank).unwrap();
+            memory.write_byte(0xD000, 0x20 + bank).unwrap();
+            memory.write_byte(0xDFFF, 0x30 + bank).unwrap();
+            // the echo of D000-DDFF follows the bank
+            assert_eq!(memory.read_byte(0xF000), 0x20 + bank);
+        }
+        for bank in [2, 3] {
+            memory.write_byte(0xFF70, bank).unwrap();
+            assert_eq!(memory.read_byte(0xFF70), 0xF8 | bank);
+            assert_eq!(memory.read_byte(0xD000), 0x20 + bank);
+            assert_eq!(memory.read_byte(0xDFFF), 0x30 + bank);
+        }
+        // bank 0 maps bank 1, which wasn't written
+        memory.write_byte(0xFF70, 0).unwrap();
+        assert_eq!(memory.read_byte(0xD000), 0x00);
+        // C000-CFFF is always bank 0
+        memory.write_byte(0xC000, 0x42).unwrap();
+        memory.write_byte(0xFF70, 3).unwrap();
+        assert_eq!(memory.read_byte(0xC000), 0x42);
+    }
+
+    #[test]
+    fn access_counters() {
+        let mut memory = memory_with_rom(false);
+        // nothing is counted until tracking starts
+        memory.record_read(0xC000);
+        assert!(memory.access_counters().is_none());
+
+        memory.start_access_tracking();
+        memory.record_read(0xC000);
+        memory.record_read(0xC000);
+        memory.record_write(0xC001);
+        memory.record_execute(0x0150);
+        let counters = memory.access_counters().unwrap();
+        assert_eq!(counters.reads(0xC000), 2);
+        assert_eq!(counters.writes(0xC000), 0);
+        assert_eq!(counters.writes(0xC001), 1);
+        assert_eq!(counters.executes(0x0150), 1);
+
+        // stopped : the counters stay as they were, to be dumped
+        memory.stop_access_tracking();
+        memory.record_read(0xC000);
+        memory.record_write(0xC001);
+        assert!(!memory.is_access_tracking());
+        assert_eq!(memory.access_counters().unwrap().reads(0xC000), 2);
+        assert_eq!(memory.access_counters().unwrap().writes(0xC001), 1);
+
+        memory.clear_access_counters();
+        assert_eq!(memory.access_counters().unwrap().reads(0xC000), 0);
+        assert!(!memory.is_access_tracking());
+
+        memory.start_access_tracking();
+        memory.record_write(0xC001);
+        assert_eq!(memory.access_counters().unwrap().writes(0xC001), 1);
+    }
+
+    #[test]
+    fn div_is_the_top_of_the_internal_divider() {
+        let mut memory = memory_with_rom(false);
+        memory.set_internal_div(0x12FF);
+        assert_eq!(memory.read_byte(0xFF04), 0x12);
+        memory.advance_div(1);
+        assert_eq!(memory.internal_div(), 0x1300);
+        assert_eq!(memory.read_byte(0xFF04), 0x13);
+
+        // the lower half is saved too
+        memory.set_internal_div(0xABCD);
+        let restored = Memory::from(MemorySnapshot::from(&memory));
+        assert_eq!(restored.internal_div(), 0xABCD);
+        assert_eq!(restored.read_byte(0xFF04), 0xAB);
+
+        // any write clears the whole divider
+        memory.write_byte(0xFF04, 0x55).unwrap();
+        assert_eq!(memory.internal_div(), 0);
+    }
+
+    #[test]
+    fn hblank_dma_copies_a_block_per_line() {
+        let mut memory = memory_with_rom(true);
+        // 8 blocks from C000 to 8000, each filled with its number, from 1
+        for address in 0xC000..0xC080u16 {
+            let block = (address >> 4) as u8 & 0x0F;
+            memory.write_byte(address, block + 1).unwrap();
+        }
+        for (address, value) in [
+            (0xFF51, 0xC0),
+            (0xFF52, 0x00),
+            (0xFF53, 0x00),
+            (0xFF54, 0x00),
+        ] {
+            memory.write_byte(address, value).unwrap();
+        }
+        memory.write_byte(0xFF55, 0x87).unwrap();
+        assert_eq!(memory.read_byte(0xFF55), 0x07);
+        assert_eq!(memory.peek_vram(0x8000), 0x00);
+
+        // one block per hblank, over 8 lines
+        for line in 0..8u8 {
+            memory.hblank_dma_step();
+            let copied = 0x8000 + line as u16 * 0x10;
+            assert_eq!(memory.peek_vram(copied), line + 1, "line {line}");
+            assert_eq!(memory.peek_vram(copied + 0x0F), line + 1, "line {line}");
+            // nothing past this line's block
+            assert_eq!(memory.peek_vram(copied + 0x10), 0x00, "line {line}");
+            assert_eq!(memory.take_vram_dma_cycles(), 32, "line {line}");
+            // blocks left minus one, 0xFF once done
+            assert_eq!(
+                memory.read_byte(0xFF55),
+                (6 - line as i8) as u8,
+                "line {line}"
+            );
+        }
+
+        // over
+        memory.hblank_dma_step();
+        assert_eq!(memory.peek_vram(0x8080), 0x00);
+        assert_eq!(memory.take_vram_dma_cycles(), 0);
+    }
+
+    #[test]
+    fn short_rom_reads_as_open_bus() {
+        // 8KiB, the header says 32KiB
+        let mut rom = vec![0x42; 0x2000];
+        rom[0x0147] = 0x00;
+        rom[0x0148] = 0x00;
+        let mut memory = Memory::new();
+        memory.load_rom(rom).unwrap();
+        assert_eq!(memory.read_byte(0x1FFF), 0x42);
+        assert_eq!(memory.read_byte(0x2000), 0xFF);
+        assert_eq!(memory.read_byte(0x7FFF), 0xFF);
+    }
+
+    #[test]
+    fn oversized_rom_only_loads_as_mbc1() {
+        // 64KiB, labeled as a 32KiB rom only cartridge, with a title that isn't ascii
+        let mut rom = vec![0; 0x10000];
+        for (bank, data) in rom.chunks_exact_mut(0x4000).enumerate() {
+            data[0x0100] = bank as u8;
+        }
+        rom[0x0134..0x0143].fill(0xFF);
+        let mut memory = Memory::new();
+        memory.load_rom(rom).unwrap();
+        assert_eq!(memory.rom_bank_count(), 4);
+
+        for bank in 1..4 {
+            memory.write_byte(0x2000, bank).unwrap();
+            assert_eq!(memory.read_byte(0x4100), bank);
+        }
+        assert_eq!(memory.read_byte(0x0100), 0);
+    }
+
+    // an MBC1 rom of `size` bytes declaring 128KiB, with each bank starting with its number
+    fn mbc1_rom(size: usize) -> Vec<u8> {
+        let mut rom = vec![0; size];
+        for (bank, data) in rom.chunks_mut(0x4000).enumerate() {
+            data[0] = bank as u8;
+        }
+        rom[0x0147] = 0x01;
+        rom[0x0148] = 0x02;
+        return rom;
+    }
+
+    #[test]
+    fn rom_size_from_the_header() {
+        let mut memory = Memory::new();
+        memory.load_rom(mbc1_rom(0x20000)).unwrap();
+        assert_eq!(memory.rom_bank_count(), 8);
+        memory.write_byte(0x2000, 7).unwrap();
+        assert_eq!(memory.read_byte(0x4000), 7);
+
+        // truncated : the missing banks read as open bus
+        let mut memory = Memory::new();
+        memory.load_rom(mbc1_rom(0x18000)).unwrap();
+        assert_eq!(memory.rom_bank_count(), 8);
+        memory.write_byte(0x2000, 5).unwrap();
+        assert_eq!(memory.read_byte(0x4000), 5);
+        memory.write_byte(0x2000, 6).unwrap();
+        assert_eq!(memory.read_byte(0x4000), 0xFF);
+
+        // padded : the trailing data doesn't make another bank
+        let mut memory = Memory::new();
+        memory.load_rom(mbc1_rom(0x24000)).unwrap();
+        assert_eq!(memory.rom_bank_count(), 8);
+    }
+
+    #[test]
+    fn rom_too_large_for_mbc1() {
+        // 4MiB, twice what the 7 bits of MBC1 bank number reach
+        let mut rom = mbc1_rom(0x400000);
+        rom[0x0148] = 0x07;
+        let mut memory = Memory::new();
+        assert!(matches!(
+            memory.load_rom(rom),
+            Err(RomError::TooLarge(0x400000, "MBC1", MBC1_MAX_ROM_SIZE))
+        ));
+    }
+
+    #[test]
+    fn mbc1_multicart_detection() {
+        // 1MiB, with a logo (any will do) in the header of the first game only
+        let mut rom = mbc1_rom(0x100000);
+        rom[0x0148] = 0x05;
+        for (i, byte) in rom[0x0104..0x0134].iter_mut().enumerate() {
+            *byte = i as u8 + 1;
+        }
+        let mut memory = Memory::new();
+        memory.load_rom(rom.clone()).unwrap();
+        assert!(!memory.is_mbc1_multicart());
+        // BANK2 goes above the 5 bits of BANK1
+        memory.write_byte(0x4000, 1).unwrap();
+        memory.write_byte(0x2000, 1).unwrap();
+        assert_eq!(memory.read_byte(0x4000), 0x21);
+
+        // the second game starts at 0x40000 with its own header
+        rom.copy_within(0x0104..0x0134, 0x40104);
+        let mut memory = Memory::new();
+        memory.load_rom(rom).unwrap();
+        assert!(memory.is_mbc1_multicart());
+        // BANK2 goes above 4 bits of BANK1, and the fifth one is ignored
+        memory.write_byte(0x4000, 1).unwrap();
+        memory.write_byte(0x2000, 0x11).unwrap();
+        assert_eq!(memory.read_byte(0x4000), 0x11);
+        // in mode 1, 0000-3FFF shows the first bank of the selected game
+        memory.skip_boot_rom();
+        memory.write_byte(0x6000, 1).unwrap();
+        assert_eq!(memory.read_byte(0x0000), 0x10);
+    }
+
+    #[test]
+    fn cartridge_type_names() {
+        for (byte, name) in [
+            (0x00, "ROM ONLY"),
+            // MBC1M multicarts have no byte of their own, they use the MBC1 ones
+            (0x01, "MBC1"),
+            (0x02, "MBC1+RAM"),
+            (0x03, "MBC1+RAM+BATTERY"),
+            (0x06, "MBC2+BATTERY"),
+            (0x10, "MBC3+TIMER+RAM+BATTERY"),
+            (0x1B, "MBC5+RAM+BATTERY"),
+            (0xFC, "POCKET CAMERA"),
+            (0xFF, "HuC1+RAM+BATTERY"),
+            // unused values
+            (0x04, "UNKNOWN"),
+            (0x14, "UNKNOWN"),
+            (0x20, "UNKNOWN"),
+            (0xFB, "UNKNOWN"),
+        ] {
+            assert_eq!(cartridge_type_name(byte), name, "{byte:#04X}");
+        }
+    }
+
+    #[test]
+    fn mbc3_rom_banks() {
+        // 64 banks : past the 5 bits of the MBC1
+        let mut rom = mbc1_rom(0x100000);
+        // MBC3+RAM+BATTERY, 1MiB
+        rom[0x0147] = 0x13;
+        rom[0x0148] = 0x05;
+        let mut memory = Memory::new();
+        memory.load_rom(rom).unwrap();
+        memory.skip_boot_rom();
+        assert_eq!(memory.rom_bank_count(), 64);
+        assert!(!memory.has_rtc());
+
+        for (bank, expected) in [
+            (0x00, 1),
+            (0x01, 1),
+            (0x21, 0x21),
+            (0x3F, 0x3F),
+            (0x7F, 0x3F),
+        ] {
+            memory.write_byte(0x2000, bank).unwrap();
+            assert_eq!(memory.read_byte(0x4000), expected, "bank {bank:#04X}");
+            assert_eq!(memory.read_byte(0x0000), 0);
+        }
+
+        // the latch & rtc registers do nothing without a timer
+        memory.write_byte(0x4000, 0x08).unwrap();
+        memory.write_byte(0x6000, 0x00).unwrap();
+        memory.write_byte(0x6000, 0x01).unwrap();
+        assert_eq!(memory.read_byte(0xA000), 0xFF);
+        assert_eq!(memory.read_byte(0x4000), 0x3F);
+    }
+
+    #[test]
+    fn no_vram_dma_on_dmg() {
+        let mut memory = memory_with_rom(false);
+        assert!(start_gdma(&mut memory).is_err());
+        assert!(memory.write_byte(0xFF55, 0x00).is_err());
+        assert_eq!(memory.peek_vram(0x8000), 0x00);
+    }
+}
@@ -0,0 +1,145 @@
+// https://gbdev.io/pandocs/OAM.html
+// the object attribute memory holds 40 entries of 4 bytes each
+pub const OAM_ENTRIES: usize = 40;
+
+// byte 3 of an oam entry
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OamFlags(u8);
+
+impl OamFlags {
+    // the background & window colors 1-3 are drawn over the object
+    pub const BG_PRIORITY: OamFlags = OamFlags(1 << 7);
+    pub const Y_FLIP: OamFlags = OamFlags(1 << 6);
+    pub const X_FLIP: OamFlags = OamFlags(1 << 5);
+    // the object uses OBP1 instead of OBP0
+    pub const PALETTE_1: OamFlags = OamFlags(1 << 4);
+
+    pub fn from_bits(bits: u8) -> OamFlags {
+        return OamFlags(bits);
+    }
+
+    pub fn bits(&self) -> u8 {
+        return self.0;
+    }
+
+    pub fn contains(&self, other: OamFlags) -> bool {
+        return self.0 & other.0 == other.0;
+    }
+}
+
+impl std::ops::BitOr for OamFlags {
+    type Output = OamFlags;
+
+    fn bitor(self, rhs: OamFlags) -> OamFlags {
+        return OamFlags(self.0 | rhs.0);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OamEntry {
+    // the stored values : y is the screen y position + 16, x the screen x position + 8
+    pub y: u8,
+    pub x: u8,
+    pub tile: u8,
+    pub flags: OamFlags,
+}
+
+impl OamEntry {
+    pub fn from_bytes(bytes: [u8; 4]) -> OamEntry {
+        return OamEntry {
+            y: bytes[0],
+            x: bytes[1],
+            tile: bytes[2],
+            flags: OamFlags::from_bits(bytes[3]),
+        };
+    }
+
+    // y position on screen, between -16 and 144 : objects can be outside the screen
+    pub fn screen_y(&self) -> isize {
+        return self.y as isize - 16;
+    }
+
+    // x position on screen, between -8 and 160
+    pub fn screen_x(&self) -> isize {
+        return self.x as isize - 8;
+    }
+
+    pub fn y_flip(&self) -> bool {
+        return self.flags.contains(OamFlags::Y_FLIP);
+    }
+
+    pub fn x_flip(&self) -> bool {
+        return self.flags.contains(OamFlags::X_FLIP);
+    }
+
+    // 0 for OBP0, 1 for OBP1
+    pub fn palette(&self) -> u8 {
+        return self.flags.contains(OamFlags::PALETTE_1) as u8;
+    }
+
+    // true when the background & window colors 1-3 are drawn over the object
+    pub fn priority(&self) -> bool {
+        return self.flags.contains(OamFlags::BG_PRIORITY);
+    }
+}
+
+// decodes a whole oam region (FE00-FE9F)
+pub fn decode_oam(oam: &[u8; OAM_ENTRIES * 4]) -> [OamEntry; OAM_ENTRIES] {
+    let mut entries = [OamEntry::default(); OAM_ENTRIES];
+    for (entry, bytes) in entries.iter_mut().zip(oam.chunks_exact(4)) {
+        *entry = OamEntry::from_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+
+    return entries;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attribute_decoding() {
+        let entry = OamEntry::from_bytes([0x10, 0x08, 0x42, 0b1101_0000]);
+        assert_eq!(entry.screen_y(), 0);
+        assert_eq!(entry.screen_x(), 0);
+        assert_eq!(entry.tile, 0x42);
+        assert!(entry.priority());
+        assert!(entry.y_flip());
+        assert!(!entry.x_flip());
+        assert_eq!(entry.palette(), 1);
+
+        let entry = OamEntry::from_bytes([0x00, 0xA8, 0x00, 0b0010_0000]);
+        assert_eq!(entry.screen_y(), -16);
+        assert_eq!(entry.screen_x(), 160);
+        assert!(!entry.priority());
+        assert!(!entry.y_flip());
+        assert!(entry.x_flip());
+        assert_eq!(entry.palette(), 0);
+
+        // the low bits are cgb only, they are kept but mean nothing here
+        let flags = OamFlags::from_bits(0b0000_1111);
+        assert_eq!(flags.bits(), 0b0000_1111);
+        assert!(!flags.contains(OamFlags::BG_PRIORITY));
+        assert_eq!((OamFlags::X_FLIP | OamFlags::PALETTE_1).bits(), 0b0011_0000);
+    }
+
+    #[test]
+    fn oam_decoding() {
+        let mut oam = [0; OAM_ENTRIES * 4];
+        oam[4..8].copy_from_slice(&[0x20, 0x18, 0x01, 0x80]);
+        oam[156..160].copy_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF]);
+        let entries = decode_oam(&oam);
+        assert_eq!(entries[0], OamEntry::default());
+        assert_eq!(
+            entries[1],
+            OamEntry {
+                y: 0x20,
+                x: 0x18,
+                tile: 0x01,
+                flags: OamFlags::BG_PRIORITY,
+            }
+        );
+        assert_eq!(entries[39], OamEntry::from_bytes([0xFF; 4]));
+        assert_eq!(entries[39].palette(), 1);
+    }
+}
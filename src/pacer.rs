@@ -0,0 +1,104 @@
+use std::time::{Duration, Instant};
+
+// https://gbdev.io/pandocs/STAT.html#ppu-modes
+// 4194304 Hz / 70224 dots per frame
+pub const GAMEBOY_FPS: f64 = 59.7275;
+// the target frame rate is kept between these
+pub const MIN_FPS: f64 = 1.0;
+pub const MAX_FPS: f64 = 1000.0;
+
+// sleeping can overshoot by about a scheduler tick, so the end of the wait is spun
+const SPIN_MARGIN: Duration = Duration::from_millis(1);
+// if the emulation falls behind by more than this many frames (the host stalled, the window
+// was dragged...), it starts over from now instead of running fast to catch up
+const MAX_LAG_FRAMES: u32 = 4;
+
+// keeps the emulation at a steady frame rate
+// each frame is due a fixed time after the previous one was due, not after it ended,
+// so the time spent sleeping too long is won back on the next frames
+pub struct Pacer {
+    frame_duration: Duration,
+    // when the current frame should end
+    deadline: Instant,
+}
+
+impl Pacer {
+    pub fn new(target_fps: f64) -> Pacer {
+        return Pacer {
+            frame_duration: frame_duration(target_fps),
+            deadline: Instant::now(),
+        };
+    }
+
+    pub fn set_target_fps(&mut self, target_fps: f64) {
+        self.frame_duration = frame_duration(target_fps);
+    }
+
+    // waits for the end of the frame
+    // `speed` is a multiple of the target frame rate, with 0 not waiting at all
+    pub fn wait(&mut self, speed: u32) {
+        let now = Instant::now();
+        if speed == 0 {
+            self.deadline = now;
+            return;
+        }
+
+        let frame_duration = self.frame_duration / speed;
+        self.deadline += frame_duration;
+        if now > self.deadline + frame_duration * MAX_LAG_FRAMES {
+            self.deadline = now;
+            return;
+        }
+
+        let sleep = remaining_sleep(now, self.deadline);
+        if !sleep.is_zero() {
+            std::thread::sleep(sleep);
+        }
+        while Instant::now() < self.deadline {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+fn frame_duration(target_fps: f64) -> Duration {
+    let fps = if target_fps.is_nan() {
+        GAMEBOY_FPS
+    } else {
+        target_fps.clamp(MIN_FPS, MAX_FPS)
+    };
+    return Duration::from_secs_f64(1.0 / fps);
+}
+
+// how long to sleep before spinning until `deadline`
+pub fn remaining_sleep(now: Instant, deadline: Instant) -> Duration {
+    return deadline
+        .saturating_duration_since(now)
+        .saturating_sub(SPIN_MARGIN);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sleep_until_the_spin_margin() {
+        let now = Instant::now();
+        let frame = frame_duration(GAMEBOY_FPS);
+
+        assert_eq!(remaining_sleep(now, now + frame), frame - SPIN_MARGIN);
+        // the last stretch is spun
+        assert_eq!(remaining_sleep(now, now + SPIN_MARGIN / 2), Duration::ZERO);
+        assert_eq!(remaining_sleep(now, now), Duration::ZERO);
+        // late : no sleep at all
+        assert_eq!(remaining_sleep(now + frame, now), Duration::ZERO);
+    }
+
+    #[test]
+    fn target_fps_is_clamped() {
+        assert_eq!(frame_duration(0.0), frame_duration(MIN_FPS));
+        assert_eq!(frame_duration(-60.0), frame_duration(MIN_FPS));
+        assert_eq!(frame_duration(1e9), frame_duration(MAX_FPS));
+        assert_eq!(frame_duration(f64::NAN), frame_duration(GAMEBOY_FPS));
+        assert_eq!(frame_duration(50.0), Duration::from_millis(20));
+    }
+}
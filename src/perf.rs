@@ -0,0 +1,110 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+// number of frames the averages are computed over
+const HISTORY_LEN: usize = 60;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Phase {
+    Decode,  // decoding the next instruction
+    Execute, // executing it
+    Ppu,     // drawing lines into the framebuffer
+    Render,  // frontend : uploading & presenting the frame
+}
+
+impl Phase {
+    pub const ALL: [Phase; 4] = [Phase::Decode, Phase::Execute, Phase::Ppu, Phase::Render];
+}
+
+// wall-clock time spent in the major parts of the emulator
+// sampling is off by default, so it only costs a branch per phase
+pub struct PerfStats {
+    enabled: bool,
+    // time spent in each phase during the frame being emulated
+    current: [Duration; Phase::ALL.len()],
+    // per-phase times and total frame time of the last frames
+    history: VecDeque<([Duration; Phase::ALL.len()], Duration)>,
+    frame_start: Instant,
+}
+
+impl PerfStats {
+    pub fn new() -> PerfStats {
+        return PerfStats {
+            enabled: false,
+            current: [Duration::ZERO; Phase::ALL.len()],
+            history: VecDeque::with_capacity(HISTORY_LEN),
+            frame_start: Instant::now(),
+        };
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        return self.enabled;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.current = [Duration::ZERO; Phase::ALL.len()];
+        self.history.clear();
+        self.frame_start = Instant::now();
+    }
+
+    // returns the start of a sample to be given back to `record`, none when disabled
+    pub fn start(&self) -> Option<Instant> {
+        return self.enabled.then(Instant::now);
+    }
+
+    pub fn record(&mut self, phase: Phase, start: Option<Instant>) {
+        if let Some(start) = start {
+            self.current[phase as usize] += start.elapsed();
+        }
+    }
+
+    // for phases timed elsewhere, e.g. by the frontend on another thread
+    pub fn add(&mut self, phase: Phase, duration: Duration) {
+        if self.enabled {
+            self.current[phase as usize] += duration;
+        }
+    }
+
+    // to be called once per emulated frame
+    pub fn end_frame(&mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history
+            .push_back((self.current, self.frame_start.elapsed()));
+
+        self.current = [Duration::ZERO; Phase::ALL.len()];
+        self.frame_start = Instant::now();
+    }
+
+    // average time per frame spent in a phase
+    pub fn average(&self, phase: Phase) -> Duration {
+        if self.history.is_empty() {
+            return Duration::ZERO;
+        }
+
+        let total: Duration = self
+            .history
+            .iter()
+            .map(|(phases, _)| phases[phase as usize])
+            .sum();
+        return total / self.history.len() as u32;
+    }
+
+    // average wall-clock time of a whole frame, including the frame limiter
+    pub fn average_frame_time(&self) -> Duration {
+        if self.history.is_empty() {
+            return Duration::ZERO;
+        }
+
+        let total: Duration = self.history.iter().map(|(_, frame)| *frame).sum();
+        return total / self.history.len() as u32;
+    }
+}
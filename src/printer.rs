@@ -0,0 +1,377 @@
+use log::{debug, info, warn};
+
+use crate::{image, serial::SerialDevice};
+
+// https://gbdev.io/pandocs/Gameboy_Printer.html
+// the game drives the clock and sends packets, the printer answers 0x00 to every byte
+// except the last two, where it sends its id then its status
+const MAGIC: [u8; 2] = [0x88, 0x33];
+const PRINTER_ID: u8 = 0x81;
+
+// commands
+const INIT: u8 = 0x01;
+const PRINT: u8 = 0x02;
+const DATA: u8 = 0x04;
+const STATUS: u8 = 0x0F;
+
+// status bits
+const STATUS_CHECKSUM_ERROR: u8 = 1 << 0;
+const STATUS_PRINTING: u8 = 1 << 1;
+const STATUS_IMAGE_FULL: u8 = 1 << 2;
+const STATUS_UNPROCESSED: u8 = 1 << 3;
+
+// the image is sent in bands of 160 * 16 pixels (2 rows of 20 tiles), 9 at most
+const BAND_BYTES: usize = 20 * 2 * 16;
+const MAX_BANDS: usize = 9;
+// a row of 20 tiles of 16 bytes, 160 * 8 pixels
+const ROW_BYTES: usize = 20 * 16;
+// status packets the printer reports itself busy for, after a print
+// games wait for the print to start and end before going on
+const PRINTING_POLLS: u8 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PacketState {
+    Magic(usize),
+    Command,
+    Compression,
+    Length(usize),
+    Data,
+    Checksum(usize),
+    Id,
+    Status,
+}
+
+// writes each printed image to "<prefix>_<n>.png"
+pub struct PrinterDevice {
+    state: PacketState,
+    // the packet being received
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+    // tile data received since the last init or print, decompressed
+    image: Vec<u8>,
+    status: u8,
+    printing_polls: u8,
+    prefix: String,
+    prints: usize,
+}
+
+impl PrinterDevice {
+    pub fn new(prefix: &str) -> PrinterDevice {
+        return PrinterDevice {
+            state: PacketState::Magic(0),
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            checksum: 0,
+            received_checksum: 0,
+            image: Vec::new(),
+            status: 0,
+            printing_polls: 0,
+            prefix: prefix.to_string(),
+            prints: 0,
+        };
+    }
+
+    // everything from the command to the data counts in the checksum
+    fn receive(&mut self, byte: u8) -> u8 {
+        let mut reply = 0x00;
+        self.state = match self.state {
+            PacketState::Magic(i) if byte == MAGIC[i] => {
+                if i == 0 {
+                    PacketState::Magic(1)
+                } else {
+                    self.checksum = 0;
+                    PacketState::Command
+                }
+            }
+            // out of sync, wait for the next packet
+            PacketState::Magic(_) => PacketState::Magic(0),
+            PacketState::Command => {
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                self.command = byte;
+                PacketState::Compression
+            }
+            PacketState::Compression => {
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                self.compressed = byte & 1 != 0;
+                PacketState::Length(0)
+            }
+            PacketState::Length(i) => {
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                if i == 0 {
+                    self.length = byte as u16;
+                    PacketState::Length(1)
+                } else {
+                    self.length |= (byte as u16) << 8;
+                    self.data.clear();
+                    if self.length == 0 {
+                        PacketState::Checksum(0)
+                    } else {
+                        PacketState::Data
+                    }
+                }
+            }
+            PacketState::Data => {
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                self.data.push(byte);
+                if self.data.len() == self.length as usize {
+                    PacketState::Checksum(0)
+                } else {
+                    PacketState::Data
+                }
+            }
+            PacketState::Checksum(i) => {
+                if i == 0 {
+                    self.received_checksum = byte as u16;
+                    PacketState::Checksum(1)
+                } else {
+                    self.received_checksum |= (byte as u16) << 8;
+                    self.process_packet();
+                    PacketState::Id
+                }
+            }
+            PacketState::Id => {
+                reply = PRINTER_ID;
+                PacketState::Status
+            }
+            PacketState::Status => {
+                reply = self.status;
+                PacketState::Magic(0)
+            }
+        };
+
+        return reply;
+    }
+
+    fn process_packet(&mut self) {
+        if self.received_checksum != self.checksum {
+            warn!(
+                "PRINTER : INVALID CHECKSUM ({:#06X}, EXPECTED {:#06X})",
+                self.received_checksum, self.checksum
+            );
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !STATUS_CHECKSUM_ERROR;
+
+        match self.command {
+            INIT => {
+                debug!("PRINTER : INIT");
+                self.image.clear();
+                self.status = 0;
+                self.printing_polls = 0;
+            }
+            DATA => {
+                // an empty data packet ends the image
+                let data = if self.compressed {
+                    decompress(&self.data)
+                } else {
+                    self.data.clone()
+                };
+                if self.image.len() + data.len() > BAND_BYTES * MAX_BANDS {
+                    warn!("PRINTER : TOO MUCH IMAGE DATA, IGNORING PACKET");
+                } else {
+                    self.image.extend_from_slice(&data);
+                }
+                if self.image.len() == BAND_BYTES * MAX_BANDS {
+                    self.status |= STATUS_IMAGE_FULL;
+                }
+                self.status |= STATUS_UNPROCESSED;
+            }
+            PRINT => {
+                // sheets, margins, palette, exposure
+                let palette = match self.data.get(2) {
+                    // some games send 0 for the usual palette
+                    Some(0) | None => 0xE4,
+                    Some(&palette) => palette,
+                };
+                self.print(palette);
+                self.image.clear();
+                self.status = STATUS_PRINTING;
+                self.printing_polls = PRINTING_POLLS;
+            }
+            STATUS => {
+                if self.printing_polls > 0 {
+                    self.printing_polls -= 1;
+                    if self.printing_polls == 0 {
+                        self.status &= !STATUS_PRINTING;
+                    }
+                }
+            }
+            command => {
+                warn!("PRINTER : UNKNOWN COMMAND ({command:#04X})");
+            }
+        }
+    }
+
+    fn print(&mut self, palette: u8) {
+        let rows = self.image.len() / ROW_BYTES;
+        if rows == 0 {
+            warn!("PRINTER : NOTHING TO PRINT");
+            return;
+        }
+        if self.image.len() % ROW_BYTES != 0 {
+            warn!(
+                "PRINTER : {} BYTES OF IMAGE DATA PAST THE LAST FULL ROW, DROPPING THEM",
+                self.image.len() % ROW_BYTES
+            );
+        }
+        let pixels = render(&self.image, palette);
+
+        let path = format!("{}_{}.png", self.prefix, self.prints);
+        match image::write_png(
+            &path,
+            160,
+            (rows * 8) as u32,
+            png::ColorType::Grayscale,
+            &pixels,
+        ) {
+            Ok(()) => {
+                info!("PRINTER : PRINTED {path}");
+                println!("Printed {path}");
+            }
+            Err(e) => println!("Error : could not write print {path} ({e})"),
+        }
+        self.prints += 1;
+    }
+}
+
+impl SerialDevice for PrinterDevice {
+    fn transfer(&mut self, byte: u8) -> Option<u8> {
+        return Some(self.receive(byte));
+    }
+
+    // the printer never drives the clock
+    fn poll_external(&mut self, _reply: u8) -> Option<u8> {
+        return None;
+    }
+}
+
+// the image is 160 pixels wide, made of 2bpp tiles in rows of 20
+// one grayscale byte per pixel, a last row with less than 20 tiles is left out
+fn render(image: &[u8], palette: u8) -> Vec<u8> {
+    let rows = image.len() / ROW_BYTES;
+    let mut pixels = vec![0u8; 160 * rows * 8];
+    for (tile, bytes) in image.chunks_exact(16).take(rows * 20).enumerate() {
+        let tile_x = (tile % 20) * 8;
+        let tile_y = (tile / 20) * 8;
+        for y in 0..8 {
+            let low = bytes[2 * y];
+            let high = bytes[2 * y + 1];
+            for x in 0..8 {
+                let color = ((high >> (7 - x)) & 1) << 1 | ((low >> (7 - x)) & 1);
+                let shade = (palette >> (2 * color)) & 0b11;
+                // from white to black
+                pixels[(tile_y + y) * 160 + tile_x + x] = 255 - shade * 85;
+            }
+        }
+    }
+
+    return pixels;
+}
+
+// run-length encoding : a control byte with bit 7 set repeats the next byte
+// (control & 0x7F) + 2 times, otherwise (control + 1) bytes follow as they are
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let control = data[i];
+        i += 1;
+        if control & 0x80 != 0 {
+            let Some(&byte) = data.get(i) else {
+                break;
+            };
+            let count = (control & 0x7F) as usize + 2;
+            output.extend(std::iter::repeat(byte).take(count));
+            i += 1;
+        } else {
+            let count = (control as usize + 1).min(data.len() - i);
+            output.extend_from_slice(&data[i..(i + count)]);
+            i += count;
+        }
+    }
+
+    return output;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // sends a whole packet, returns the printer's id & status bytes
+    fn send_packet(
+        printer: &mut PrinterDevice,
+        command: u8,
+        compressed: bool,
+        data: &[u8],
+    ) -> (u8, u8) {
+        let mut body = vec![
+            command,
+            compressed as u8,
+            data.len() as u8,
+            (data.len() >> 8) as u8,
+        ];
+        body.extend_from_slice(data);
+        let checksum = body
+            .iter()
+            .fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+
+        for &byte in MAGIC.iter().chain(&body).chain(&checksum.to_le_bytes()) {
+            assert_eq!(printer.receive(byte), 0x00);
+        }
+        return (printer.receive(0x00), printer.receive(0x00));
+    }
+
+    #[test]
+    fn decompress_runs_and_literals() {
+        // 3 literal bytes, then 0xAA repeated 4 times
+        assert_eq!(
+            decompress(&[0x02, 1, 2, 3, 0x82, 0xAA]),
+            vec![1, 2, 3, 0xAA, 0xAA, 0xAA, 0xAA]
+        );
+        // a truncated packet stops at what is there
+        assert_eq!(decompress(&[0x05, 1, 2]), vec![1, 2]);
+        assert_eq!(decompress(&[0x85]), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn packets() {
+        let mut printer = PrinterDevice::new("unused");
+        assert_eq!(send_packet(&mut printer, INIT, false, &[]), (PRINTER_ID, 0));
+
+        // a band of 2 rows of blank tiles, compressed into runs of 129 & 124 bytes
+        let mut band = [0xFF, 0x00].repeat(4);
+        band.extend_from_slice(&[0xFA, 0x00]);
+        let (_, status) = send_packet(&mut printer, DATA, true, &band);
+        assert_eq!(status, STATUS_UNPROCESSED);
+        assert_eq!(printer.image.len(), BAND_BYTES);
+
+        // a wrong checksum is reported, and the packet dropped
+        for &byte in MAGIC.iter().chain(&[DATA, 0, 1, 0, 0xFF, 0x00, 0x00]) {
+            printer.receive(byte);
+        }
+        printer.receive(0x00);
+        assert_eq!(
+            printer.receive(0x00) & STATUS_CHECKSUM_ERROR,
+            STATUS_CHECKSUM_ERROR
+        );
+        assert_eq!(printer.image.len(), BAND_BYTES);
+    }
+
+    #[test]
+    fn render_full_rows_only() {
+        // a row of tiles with color 3 everywhere, then a lone tile
+        let image = vec![0xFF; ROW_BYTES + 16];
+        let pixels = render(&image, 0xE4);
+        assert_eq!(pixels.len(), 160 * 8);
+        assert!(pixels.iter().all(|&pixel| pixel == 0));
+
+        assert!(render(&[0x00; 16], 0xE4).is_empty());
+    }
+}
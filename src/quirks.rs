@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+
+// https://gbdev.io/pandocs/The_Cartridge_Header.html
+// a small database of games needing special handling, looked up when a rom is loaded
+// so the mbc & io code can check a flag instead of the cartridge title
+
+// everything a game can need, all off by default
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameQuirks {
+    // writes to the unused register 0xFF7F, which is harmless
+    pub writes_unused_ff7f: bool,
+}
+
+pub struct KnownGame {
+    // as stored in the header, without the padding
+    pub title: &'static str,
+    // header bytes 014E-014F, one per known revision of the game
+    // a rom has to match both its title & one of these : hacks & translations keep the title
+    pub global_checksums: &'static [u16],
+    pub name: &'static str,
+    pub quirks: GameQuirks,
+    // shown to the user when the game is loaded
+    pub note: Option<&'static str>,
+}
+
+const KNOWN_GAMES: &[KnownGame] = &[
+    KnownGame {
+        title: "DR.MARIO",
+        // FIXME : the checksums of the dr mario revisions still have to be read from dumps
+        global_checksums: &[],
+        name: "Dr. Mario",
+        quirks: GameQuirks {
+            writes_unused_ff7f: true,
+        },
+        note: None,
+    },
+    KnownGame {
+        title: "POKEMON RED",
+        global_checksums: &[0x91E6],
+        name: "Pokemon Red",
+        quirks: GameQuirks {
+            writes_unused_ff7f: false,
+        },
+        note: Some("uses an MBC3, which is not supported yet"),
+    },
+    KnownGame {
+        title: "POKEMON BLUE",
+        global_checksums: &[0x9D0A],
+        name: "Pokemon Blue",
+        quirks: GameQuirks {
+            writes_unused_ff7f: false,
+        },
+        note: Some("uses an MBC3, which is not supported yet"),
+    },
+];
+
+// https://gbdev.io/pandocs/The_Cartridge_Header.html#0134-0143--title
+// none if the rom is too small to have a header
+pub fn cartridge_title(header: &[u8]) -> Option<String> {
+    // the title is padded with zeroes, and shorter on newer cartridges
+    // that use the end of the area for the manufacturer code & cgb flag
+    let title_bytes = header.get(0x0134..0x0144)?;
+    let end = title_bytes
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or(title_bytes.len());
+
+    return Some(String::from_utf8_lossy(&title_bytes[..end]).into_owned());
+}
+
+// `header` is the rom from 0x0000 to at least 0x0150
+pub fn lookup(header: &[u8]) -> Option<&'static KnownGame> {
+    let title = cartridge_title(header)?;
+    let global_checksum = u16::from_be_bytes([*header.get(0x014E)?, *header.get(0x014F)?]);
+
+    return KNOWN_GAMES
+        .iter()
+        .find(|game| title == game.title && game.global_checksums.contains(&global_checksum));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(title: &str, global_checksum: u16) -> Vec<u8> {
+        let mut header = vec![0; 0x0150];
+        header[0x0134..0x0134 + title.len()].copy_from_slice(title.as_bytes());
+        header[0x014E..0x0150].copy_from_slice(&global_checksum.to_be_bytes());
+        return header;
+    }
+
+    #[test]
+    fn lookup_by_title_and_checksum() {
+        let game = lookup(&header("POKEMON RED", 0x91E6)).unwrap();
+        assert_eq!(game.name, "Pokemon Red");
+        let game = lookup(&header("POKEMON BLUE", 0x9D0A)).unwrap();
+        assert_eq!(game.name, "Pokemon Blue");
+
+        // another revision, a hack or a translation
+        assert!(lookup(&header("POKEMON RED", 0x1234)).is_none());
+        assert!(lookup(&header("POKEMON REDX", 0x91E6)).is_none());
+        assert!(lookup(&header("POKEMON BLUE", 0x91E6)).is_none());
+        // no header
+        assert!(lookup(&header("POKEMON RED", 0x91E6)[..0x014E]).is_none());
+    }
+
+    #[test]
+    fn titles() {
+        assert_eq!(cartridge_title(&header("TETRIS", 0)).unwrap(), "TETRIS");
+        assert_eq!(
+            cartridge_title(&header("0123456789ABCDEF", 0)).unwrap(),
+            "0123456789ABCDEF"
+        );
+        assert!(cartridge_title(&[0; 0x0140]).is_none());
+    }
+}
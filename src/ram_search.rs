@@ -0,0 +1,197 @@
+use std::collections::BTreeMap;
+
+use crate::gameboy::Gameboy;
+
+// finding where a game keeps a variable : take a snapshot of the ram, change the value
+// in game, then keep the addresses that changed the expected way. repeating the diffs
+// narrows the candidates down (cheat engine style)
+
+const WRAM: std::ops::RangeInclusive<u16> = 0xC000..=0xDFFF;
+const HRAM: std::ops::RangeInclusive<u16> = 0xFF80..=0xFFFE;
+const EXT_RAM: std::ops::RangeInclusive<u16> = 0xA000..=0xBFFF;
+
+// number of results printed by default
+const DEFAULT_RESULT_LIMIT: usize = 20;
+
+// the value of every address of the ram at some point
+pub struct RamSnapshot {
+    values: BTreeMap<u16, u8>,
+}
+
+impl RamSnapshot {
+    // wram (the banks currently mapped) & hram, and the cartridge ram if asked
+    pub fn capture(console: &Gameboy, with_ext_ram: bool) -> RamSnapshot {
+        let mut addresses: Vec<u16> = WRAM.chain(HRAM).collect();
+        if with_ext_ram {
+            addresses.extend(EXT_RAM);
+        }
+
+        return RamSnapshot {
+            values: addresses
+                .into_iter()
+                .map(|address| (address, console.memory().read_byte(address)))
+                .collect(),
+        };
+    }
+
+    pub fn len(&self) -> usize {
+        return self.values.len();
+    }
+}
+
+// how the current value has to relate to the one in the snapshot
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+}
+
+impl Comparison {
+    pub fn parse(text: &str) -> Option<Comparison> {
+        return match text {
+            "==" => Some(Comparison::Equal),
+            "!=" => Some(Comparison::NotEqual),
+            "<" => Some(Comparison::Less),
+            ">" => Some(Comparison::Greater),
+            _ => None,
+        };
+    }
+
+    pub fn matches(&self, old: u8, new: u8) -> bool {
+        return match self {
+            Comparison::Equal => new == old,
+            Comparison::NotEqual => new != old,
+            Comparison::Less => new < old,
+            Comparison::Greater => new > old,
+        };
+    }
+}
+
+pub struct RamSearch {
+    snapshots: BTreeMap<String, RamSnapshot>,
+    // addresses still matching every diff so far, none before the first diff
+    candidates: Option<Vec<u16>>,
+    // printed results are capped to this
+    pub result_limit: usize,
+}
+
+impl RamSearch {
+    pub fn new() -> RamSearch {
+        return RamSearch {
+            snapshots: BTreeMap::new(),
+            candidates: None,
+            result_limit: DEFAULT_RESULT_LIMIT,
+        };
+    }
+
+    pub fn save(&mut self, name: &str, snapshot: RamSnapshot) {
+        self.snapshots.insert(name.to_string(), snapshot);
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        return self.snapshots.keys();
+    }
+
+    // starts the search over, the snapshots are kept
+    pub fn reset(&mut self) {
+        self.candidates = None;
+    }
+
+    // keeps the candidates whose current value relates to the snapshot's as asked
+    // returns (address, old value, new value) for each of them, none if there is no such snapshot
+    pub fn diff(
+        &mut self,
+        console: &Gameboy,
+        name: &str,
+        comparison: Comparison,
+    ) -> Option<Vec<(u16, u8, u8)>> {
+        let snapshot = self.snapshots.get(name)?;
+        let addresses: Vec<u16> = match &self.candidates {
+            Some(candidates) => candidates.clone(),
+            None => snapshot.values.keys().copied().collect(),
+        };
+
+        let matching: Vec<(u16, u8, u8)> = addresses
+            .into_iter()
+            .filter_map(|address| {
+                // candidates from another snapshot may not be in this one
+                let old = *snapshot.values.get(&address)?;
+                let new = console.memory().read_byte(address);
+                comparison.matches(old, new).then_some((address, old, new))
+            })
+            .collect();
+
+        self.candidates = Some(matching.iter().map(|(address, _, _)| *address).collect());
+        return Some(matching);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn console() -> Gameboy {
+        let mut console = Gameboy::new(vec![0; 0x8000]).unwrap();
+        console.memory_mut().skip_boot_rom();
+        return console;
+    }
+
+    #[test]
+    fn comparisons() {
+        assert_eq!(Comparison::parse("=="), Some(Comparison::Equal));
+        assert_eq!(Comparison::parse("!="), Some(Comparison::NotEqual));
+        assert_eq!(Comparison::parse("<"), Some(Comparison::Less));
+        assert_eq!(Comparison::parse(">"), Some(Comparison::Greater));
+        assert_eq!(Comparison::parse("<="), None);
+
+        assert!(Comparison::Equal.matches(3, 3));
+        assert!(Comparison::NotEqual.matches(3, 4));
+        assert!(Comparison::Less.matches(3, 2));
+        assert!(!Comparison::Less.matches(3, 3));
+        assert!(Comparison::Greater.matches(3, 4));
+    }
+
+    #[test]
+    fn snapshot_ranges() {
+        let console = console();
+        let wram_and_hram = 0x2000 + 0x7F;
+        assert_eq!(RamSnapshot::capture(&console, false).len(), wram_and_hram);
+        assert_eq!(
+            RamSnapshot::capture(&console, true).len(),
+            wram_and_hram + 0x2000
+        );
+    }
+
+    #[test]
+    fn diffs_narrow_the_candidates() {
+        let mut console = console();
+        let mut search = RamSearch::new();
+        assert!(search.diff(&console, "start", Comparison::Equal).is_none());
+
+        console.memory_mut().write_byte(0xC123, 3).unwrap();
+        console.memory_mut().write_byte(0xFF90, 7).unwrap();
+        search.save("start", RamSnapshot::capture(&console, false));
+        // the lives go down, some other variable goes up
+        console.memory_mut().write_byte(0xC123, 2).unwrap();
+        console.memory_mut().write_byte(0xFF90, 8).unwrap();
+
+        let changed = search
+            .diff(&console, "start", Comparison::NotEqual)
+            .unwrap();
+        assert_eq!(changed, [(0xC123, 3, 2), (0xFF90, 7, 8)]);
+
+        // only the candidates left are compared
+        search.save("hit", RamSnapshot::capture(&console, false));
+        console.memory_mut().write_byte(0xC123, 1).unwrap();
+        console.memory_mut().write_byte(0xC000, 0x42).unwrap();
+        let decreased = search.diff(&console, "hit", Comparison::Less).unwrap();
+        assert_eq!(decreased, [(0xC123, 2, 1)]);
+
+        // everything is compared again after a reset
+        search.reset();
+        let changed = search.diff(&console, "hit", Comparison::NotEqual).unwrap();
+        assert_eq!(changed.len(), 2);
+    }
+}
@@ -1,6 +1,28 @@
 use glfw::Window;
 
-use crate::gameboy::Gameboy;
+use crate::config::{CrtSettings, ScreenFilter};
+
+// the uniform buffer of the crt shader, laid out like its `CrtParameters`
+// uniform structs are padded to 16 bytes
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct CrtUniform {
+    scanlines: f32,
+    curvature: f32,
+    glow: f32,
+    _padding: f32,
+}
+
+impl From<CrtSettings> for CrtUniform {
+    fn from(settings: CrtSettings) -> Self {
+        return CrtUniform {
+            scanlines: settings.scanlines,
+            curvature: settings.curvature,
+            glow: settings.glow,
+            _padding: 0.0,
+        };
+    }
+}
 
 pub struct Renderer<'a> {
     surface: wgpu::Surface<'a>,
@@ -16,6 +38,11 @@ pub struct Renderer<'a> {
     framebuffer_render_pipeline: wgpu::RenderPipeline,
     framebuffer: wgpu::Texture,
     framebuffer_bind_group: wgpu::BindGroup,
+    // the same with the crt filter, which has its parameters in a second bind group
+    screen_filter: ScreenFilter,
+    crt_render_pipeline: wgpu::RenderPipeline,
+    crt_parameters: wgpu::Buffer,
+    crt_bind_group: wgpu::BindGroup,
 }
 
 impl<'a> Renderer<'a> {
@@ -152,45 +179,70 @@ impl<'a> Renderer<'a> {
                 push_constant_ranges: &[],
             });
 
-        let framebuffer_render_pipeline =
-            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("framebuffer pipeline"),
-                layout: Some(&tilemap_pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &tilemap_shader,
-                    entry_point: "vs_main",
-                    compilation_options: wgpu::PipelineCompilationOptions::default(),
-                    buffers: &[],
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &tilemap_shader,
-                    entry_point: "fs_main",
-                    compilation_options: wgpu::PipelineCompilationOptions::default(),
-                    targets: &[Some(wgpu::ColorTargetState {
-                        format: config.format,
-                        blend: Some(wgpu::BlendState::REPLACE),
-                        write_mask: wgpu::ColorWrites::all(),
-                    })],
-                }),
-                primitive: wgpu::PrimitiveState {
-                    topology: wgpu::PrimitiveTopology::TriangleList,
-                    strip_index_format: None,
-                    front_face: wgpu::FrontFace::Cw,
-                    cull_mode: Some(wgpu::Face::Back),
-                    unclipped_depth: false,
-                    polygon_mode: wgpu::PolygonMode::Fill,
-                    conservative: false,
-                },
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState {
-                    count: 1,
-                    mask: !0,
-                    alpha_to_coverage_enabled: false,
-                },
-                multiview: None,
-                cache: None,
+        let framebuffer_render_pipeline = create_screen_pipeline(
+            &device,
+            "framebuffer pipeline",
+            &tilemap_shader,
+            &tilemap_pipeline_layout,
+            config.format,
+        );
+
+        // crt filter
+        let crt_parameters = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("crt parameters buffer"),
+            size: std::mem::size_of::<CrtUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(
+            &crt_parameters,
+            0,
+            bytemuck::bytes_of(&CrtUniform::from(CrtSettings::default())),
+        );
+
+        let crt_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("crt bind group layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
             });
 
+        let crt_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("crt bind group"),
+            layout: &crt_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: crt_parameters.as_entire_binding(),
+            }],
+        });
+
+        let crt_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("crt shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/crt.wgsl").into()),
+        });
+
+        let crt_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("crt pipeline layout"),
+            bind_group_layouts: &[&tile_map_bind_group_layout, &crt_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let crt_render_pipeline = create_screen_pipeline(
+            &device,
+            "crt pipeline",
+            &crt_shader,
+            &crt_pipeline_layout,
+            config.format,
+        );
+
         Self {
             window,
             surface,
@@ -201,14 +253,38 @@ impl<'a> Renderer<'a> {
             framebuffer_render_pipeline,
             framebuffer,
             framebuffer_bind_group,
+            screen_filter: ScreenFilter::Crisp,
+            crt_render_pipeline,
+            crt_parameters,
+            crt_bind_group,
         }
     }
 
-    pub fn resize(&mut self, _new_size: (i32, i32)) {
-        todo!()
+    // only changes how the screen looks in the window : the framebuffer is left as it is
+    pub fn set_screen_filter(&mut self, filter: ScreenFilter, crt: CrtSettings) {
+        self.screen_filter = filter;
+        self.queue.write_buffer(
+            &self.crt_parameters,
+            0,
+            bytemuck::bytes_of(&CrtUniform::from(crt)),
+        );
+    }
+
+    // the screen is drawn over the whole surface, so it follows the window size
+    pub fn resize(&mut self, new_size: (i32, i32)) {
+        // a minimized window has no size
+        if new_size.0 <= 0 || new_size.1 <= 0 {
+            return;
+        }
+
+        self.size = new_size;
+        self.config.width = new_size.0 as u32;
+        self.config.height = new_size.1 as u32;
+        self.surface.configure(&self.device, &self.config);
     }
 
-    pub fn render(&mut self, console: &Gameboy) -> Result<(), wgpu::SurfaceError> {
+    // `framebuffer` is the 160 * 144 rgba screen
+    pub fn render(&mut self, framebuffer: &[u8]) -> Result<(), wgpu::SurfaceError> {
         {
             self.queue.write_texture(
                 wgpu::ImageCopyTexture {
@@ -217,7 +293,7 @@ impl<'a> Renderer<'a> {
                     origin: wgpu::Origin3d::ZERO,
                     aspect: wgpu::TextureAspect::All,
                 },
-                &console.get_framebuffer(),
+                framebuffer,
                 wgpu::ImageDataLayout {
                     offset: 0,
                     bytes_per_row: Some(4 * 160),
@@ -262,8 +338,17 @@ impl<'a> Renderer<'a> {
             timestamp_writes: None,
             occlusion_query_set: None,
         });
-        render_pass.set_pipeline(&self.framebuffer_render_pipeline);
-        render_pass.set_bind_group(0, &self.framebuffer_bind_group, &[]);
+        match self.screen_filter {
+            ScreenFilter::Crisp => {
+                render_pass.set_pipeline(&self.framebuffer_render_pipeline);
+                render_pass.set_bind_group(0, &self.framebuffer_bind_group, &[]);
+            }
+            ScreenFilter::Crt => {
+                render_pass.set_pipeline(&self.crt_render_pipeline);
+                render_pass.set_bind_group(0, &self.framebuffer_bind_group, &[]);
+                render_pass.set_bind_group(1, &self.crt_bind_group, &[]);
+            }
+        }
         render_pass.draw(0..6, 0..1);
         drop(render_pass);
 
@@ -285,3 +370,87 @@ impl<'a> Renderer<'a> {
         return self.window;
     }
 }
+
+// a quad covering the whole surface, textured by `shader`
+fn create_screen_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    shader: &wgpu::ShaderModule,
+    layout: &wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    return device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::all(),
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Cw,
+            cull_mode: Some(wgpu::Face::Back),
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // https://www.w3.org/TR/WGSL/#address-space-layout-constraints
+    #[test]
+    fn crt_uniform_layout() {
+        assert_eq!(std::mem::size_of::<CrtUniform>() % 16, 0);
+        assert_eq!(std::mem::offset_of!(CrtUniform, scanlines), 0);
+        assert_eq!(std::mem::offset_of!(CrtUniform, curvature), 4);
+        assert_eq!(std::mem::offset_of!(CrtUniform, glow), 8);
+
+        let settings = CrtSettings {
+            scanlines: 0.25,
+            curvature: 0.5,
+            glow: 0.75,
+        };
+        let expected: Vec<u8> = [0.25f32, 0.5, 0.75, 0.0]
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect();
+        assert_eq!(bytemuck::bytes_of(&CrtUniform::from(settings)), expected);
+
+        // the shader reads the fields in the same order
+        let shader = include_str!("shaders/crt.wgsl");
+        let start = shader.find("struct CrtParameters {").unwrap();
+        let end = start + shader[start..].find('}').unwrap();
+        let fields: Vec<&str> = shader[start..end]
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.trim().split_once(": f32"))
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(fields, ["scanlines", "curvature", "glow", "_padding"]);
+    }
+}
@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+// the day counter is 9 bits wide
+const DAYS: u64 = 512;
+
+// https://gbdev.io/pandocs/MBC3.html#the-clock-counter-registers
+// the real time clock of MBC3+TIMER cartridges, which keeps counting while the console is off
+// it has no notion of time itself : `update` is given the time of the console's `Clock`, and
+// the counter moves by however much has passed since the previous call
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Rtc {
+    // seconds counted from day 0 at 00:00:00
+    seconds: u64,
+    // how much of the current second has elapsed
+    subsecond: Duration,
+    // the time given to the last update, none before the first one
+    last_update: Option<Duration>,
+    // DH bit 6 : the counter is stopped
+    halted: bool,
+    // DH bit 7 : the day counter went past 511, stays set until cleared by the game
+    day_carry: bool,
+    // S, M, H, DL & DH as they were when last latched, which is what the game reads
+    latched: [u8; 5],
+    // the last write to 6000-7FFF was a 0, a 1 now latches the counter
+    latch_armed: bool,
+}
+
+impl Rtc {
+    pub fn update(&mut self, now: Duration) {
+        // a clock going backwards (e.g. a state saved with another clock) doesn't move it
+        let elapsed = match self.last_update {
+            Some(last) => now.saturating_sub(last),
+            None => Duration::ZERO,
+        };
+        self.last_update = Some(now);
+        if self.halted {
+            return;
+        }
+
+        self.subsecond += elapsed;
+        let seconds = self.subsecond.as_secs();
+        self.subsecond -= Duration::from_secs(seconds);
+        self.seconds += seconds;
+        if self.seconds >= DAYS * SECONDS_PER_DAY {
+            self.seconds %= DAYS * SECONDS_PER_DAY;
+            self.day_carry = true;
+        }
+    }
+
+    // a write to 6000-7FFF
+    pub fn write_latch(&mut self, value: u8) {
+        if self.latch_armed && value == 0x01 {
+            self.latched = self.registers();
+        }
+        self.latch_armed = value == 0x00;
+    }
+
+    // `register` is the one selected through 4000-5FFF, 08 to 0C
+    pub fn read(&self, register: u8) -> u8 {
+        return self.latched[(register - 0x08) as usize];
+    }
+
+    // writes go to the counter itself, not to the latched registers
+    pub fn write(&mut self, register: u8, value: u8) {
+        let [mut s, mut m, mut h, mut dl, mut dh] = self.registers();
+        match register {
+            0x08 => {
+                s = value & 0b0011_1111;
+                // writing the seconds also restarts the current second
+                self.subsecond = Duration::ZERO;
+            }
+            0x09 => m = value & 0b0011_1111,
+            0x0A => h = value & 0b0001_1111,
+            0x0B => dl = value,
+            _ => dh = value & 0b1100_0001,
+        }
+        let days = ((dh as u64 & 0b1) << 8) | dl as u64;
+        self.seconds = days * SECONDS_PER_DAY + h as u64 * 3600 + m as u64 * 60 + s as u64;
+        self.halted = dh & 0b0100_0000 != 0;
+        self.day_carry = dh & 0b1000_0000 != 0;
+    }
+
+    // S, M, H, DL & DH from the counter
+    fn registers(&self) -> [u8; 5] {
+        let days = self.seconds / SECONDS_PER_DAY;
+        return [
+            (self.seconds % 60) as u8,
+            (self.seconds / 60 % 60) as u8,
+            (self.seconds / 3600 % 24) as u8,
+            days as u8,
+            (days >> 8) as u8 & 0b1 | (self.halted as u8) << 6 | (self.day_carry as u8) << 7,
+        ];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn latched(rtc: &mut Rtc) -> [u8; 5] {
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+        return [0x08, 0x09, 0x0A, 0x0B, 0x0C].map(|register| rtc.read(register));
+    }
+
+    #[test]
+    fn counts_seconds_into_days() {
+        let mut rtc = Rtc::default();
+        rtc.update(Duration::from_secs(1000));
+        assert_eq!(latched(&mut rtc), [0, 0, 0, 0, 0]);
+
+        // 1 day, 2 hours, 3 minutes & 4.5 seconds
+        rtc.update(Duration::from_millis(1_000_000 + 93_784_500));
+        assert_eq!(latched(&mut rtc), [4, 3, 2, 1, 0]);
+        // the half second isn't lost
+        rtc.update(Duration::from_secs(1000 + 93_785));
+        assert_eq!(latched(&mut rtc), [5, 3, 2, 1, 0]);
+
+        // day 256 is in DH, and past day 511 it starts over with the carry set
+        rtc.write(0x0B, 0xFF);
+        rtc.write(0x0C, 0x01);
+        assert_eq!(latched(&mut rtc), [5, 3, 2, 0xFF, 0x01]);
+        rtc.update(Duration::from_secs(1000 + 93_785 + SECONDS_PER_DAY));
+        assert_eq!(latched(&mut rtc), [5, 3, 2, 0x00, 0x80]);
+    }
+
+    #[test]
+    fn latching_and_halting() {
+        let mut rtc = Rtc::default();
+        rtc.update(Duration::ZERO);
+        rtc.update(Duration::from_secs(10));
+        assert_eq!(latched(&mut rtc)[0], 10);
+
+        // the registers only change on a 0 then 1 write
+        rtc.update(Duration::from_secs(20));
+        rtc.write_latch(0x01);
+        assert_eq!(rtc.read(0x08), 10);
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+        assert_eq!(rtc.read(0x08), 20);
+
+        // halted, the time that passes is lost
+        rtc.write(0x0C, 0b0100_0000);
+        rtc.update(Duration::from_secs(30));
+        assert_eq!(latched(&mut rtc), [20, 0, 0, 0, 0b0100_0000]);
+        rtc.write(0x0C, 0x00);
+        rtc.update(Duration::from_secs(35));
+        assert_eq!(latched(&mut rtc), [25, 0, 0, 0, 0]);
+    }
+}
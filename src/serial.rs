@@ -0,0 +1,423 @@
+use std::{
+    collections::VecDeque,
+    io::{Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use log::{info, warn};
+
+// https://gbdev.io/pandocs/Serial_Data_Transfer_(Link_Cable).html
+// anything plugged into the link port
+pub trait SerialDevice: Send {
+    // called when the gameboy drives the clock and shifts out a byte
+    // returns the byte shifted in from the other side, or none if it hasn't arrived yet :
+    // the transfer then stays in progress, and the answer is polled with `poll_transfer`
+    fn transfer(&mut self, byte: u8) -> Option<u8>;
+
+    // the answer to the byte of the transfer in progress, once it has arrived
+    fn poll_transfer(&mut self) -> Option<u8> {
+        return None;
+    }
+
+    // the answer took too long and the transfer ended without it :
+    // it must not be taken for the answer to a later byte
+    fn abandon_transfer(&mut self) {}
+
+    // polled while the gameboy waits on the external clock
+    // returns the byte sent by the other side, if any, which gets `reply` in exchange
+    fn poll_external(&mut self, reply: u8) -> Option<u8>;
+}
+
+// records every byte the gameboy sends, without answering
+// test roms (e.g. blargg's) print their results this way
+pub struct SerialCapture {
+    output: Arc<Mutex<Vec<u8>>>,
+}
+
+impl SerialCapture {
+    // returns the device and a handle to read what was captured
+    pub fn new() -> (SerialCapture, Arc<Mutex<Vec<u8>>>) {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        return (
+            SerialCapture {
+                output: output.clone(),
+            },
+            output,
+        );
+    }
+}
+
+impl SerialDevice for SerialCapture {
+    fn transfer(&mut self, byte: u8) -> Option<u8> {
+        self.output.lock().unwrap().push(byte);
+        return Some(0xFF);
+    }
+
+    fn poll_external(&mut self, _reply: u8) -> Option<u8> {
+        return None;
+    }
+}
+
+// link cable protocol over tcp
+// every message on the wire is two bytes : a kind and a payload
+const PROTOCOL_VERSION: u8 = 1;
+// how long to wait for the peer's hello when connecting
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, PartialEq)]
+pub enum Frame {
+    Hello(u8), // protocol version, sent once by both sides on connection
+    Data(u8),  // byte shifted out by the side driving the clock (8 clock pulses)
+    Reply(u8), // byte shifted back by the side using the external clock
+}
+
+impl Frame {
+    pub fn encode(&self) -> [u8; 2] {
+        return match self {
+            Frame::Hello(version) => [0x00, *version],
+            Frame::Data(byte) => [0x01, *byte],
+            Frame::Reply(byte) => [0x02, *byte],
+        };
+    }
+
+    pub fn decode(bytes: [u8; 2]) -> Option<Frame> {
+        return match bytes[0] {
+            0x00 => Some(Frame::Hello(bytes[1])),
+            0x01 => Some(Frame::Data(bytes[1])),
+            0x02 => Some(Frame::Reply(bytes[1])),
+            _ => None,
+        };
+    }
+}
+
+pub fn write_frame<W: Write>(writer: &mut W, frame: &Frame) -> std::io::Result<()> {
+    writer.write_all(&frame.encode())?;
+    return writer.flush();
+}
+
+pub fn read_frame<R: Read>(reader: &mut R) -> std::io::Result<Frame> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+
+    return decode_frame(bytes);
+}
+
+fn decode_frame(bytes: [u8; 2]) -> std::io::Result<Frame> {
+    return Frame::decode(bytes).ok_or(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("invalid link frame kind ({:#04X})", bytes[0]),
+    ));
+}
+
+// the emulation never waits on the network : after the handshake the socket is non-blocking,
+// and both directions go through buffers that are moved along on every transfer & poll
+pub struct TcpLink {
+    // none once the peer has disconnected : the cable is then simply unplugged
+    stream: Option<TcpStream>,
+    // bytes the socket didn't take yet
+    outgoing: Vec<u8>,
+    // bytes received, short of a whole frame
+    incoming: Vec<u8>,
+    // frames received and not handled yet
+    frames: VecDeque<Frame>,
+    // a byte was sent and its reply hasn't arrived yet
+    awaiting_reply: bool,
+    // replies still to come for abandoned transfers, to be dropped :
+    // the peer answers every byte once and in order, so the next reply is always for
+    // the oldest byte sent
+    abandoned_replies: usize,
+}
+
+impl TcpLink {
+    // blocks until a peer connects
+    pub fn listen<A: ToSocketAddrs>(address: A) -> std::io::Result<TcpLink> {
+        let listener = TcpListener::bind(address)?;
+        info!("LINK : WAITING FOR PEER ON {}", listener.local_addr()?);
+        let (stream, peer) = listener.accept()?;
+        info!("LINK : PEER CONNECTED ({peer})");
+
+        return TcpLink::handshake(stream);
+    }
+
+    pub fn connect<A: ToSocketAddrs>(address: A) -> std::io::Result<TcpLink> {
+        let stream = TcpStream::connect(address)?;
+        info!("LINK : CONNECTED TO {}", stream.peer_addr()?);
+
+        return TcpLink::handshake(stream);
+    }
+
+    fn handshake(mut stream: TcpStream) -> std::io::Result<TcpLink> {
+        // transfers are a single byte each, so don't let them sit in a buffer
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+
+        write_frame(&mut stream, &Frame::Hello(PROTOCOL_VERSION))?;
+        match read_frame(&mut stream)? {
+            Frame::Hello(PROTOCOL_VERSION) => {}
+            frame => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("unexpected handshake from link peer ({frame:?})"),
+                ))
+            }
+        }
+        stream.set_nonblocking(true)?;
+
+        return Ok(TcpLink {
+            stream: Some(stream),
+            outgoing: Vec::new(),
+            incoming: Vec::new(),
+            frames: VecDeque::new(),
+            awaiting_reply: false,
+            abandoned_replies: 0,
+        });
+    }
+
+    fn disconnect(&mut self, error: std::io::Error) {
+        warn!("LINK : PEER DISCONNECTED ({error})");
+        self.stream = None;
+        self.outgoing.clear();
+        self.incoming.clear();
+        self.frames.clear();
+        self.awaiting_reply = false;
+        self.abandoned_replies = 0;
+    }
+
+    fn send(&mut self, frame: Frame) {
+        self.outgoing.extend_from_slice(&frame.encode());
+    }
+
+    // writes what the socket takes, and reads whatever has arrived
+    fn exchange(&mut self) {
+        let Some(stream) = &mut self.stream else {
+            return;
+        };
+
+        let result = write_available(stream, &mut self.outgoing)
+            .and_then(|_| read_available(stream, &mut self.incoming));
+        if let Err(e) = result {
+            self.disconnect(e);
+            return;
+        }
+
+        // a partial frame waits for the rest
+        while self.incoming.len() >= 2 {
+            let bytes = [self.incoming[0], self.incoming[1]];
+            self.incoming.drain(..2);
+            match decode_frame(bytes) {
+                Ok(frame) => self.frames.push_back(frame),
+                Err(e) => {
+                    self.disconnect(e);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn write_available(stream: &mut TcpStream, outgoing: &mut Vec<u8>) -> std::io::Result<()> {
+    while !outgoing.is_empty() {
+        match stream.write(outgoing) {
+            Ok(0) => return Err(std::io::Error::from(std::io::ErrorKind::WriteZero)),
+            Ok(n) => {
+                outgoing.drain(..n);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    return Ok(());
+}
+
+fn read_available(stream: &mut TcpStream, incoming: &mut Vec<u8>) -> std::io::Result<()> {
+    let mut buffer = [0; 64];
+    loop {
+        match stream.read(&mut buffer) {
+            Ok(0) => return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof)),
+            Ok(n) => incoming.extend_from_slice(&buffer[..n]),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+impl SerialDevice for TcpLink {
+    // the peer's answer can't be waited for : it is polled until it arrives
+    fn transfer(&mut self, byte: u8) -> Option<u8> {
+        if self.stream.is_none() {
+            // nothing plugged in : the line reads as high
+            return Some(0xFF);
+        }
+
+        self.send(Frame::Data(byte));
+        self.awaiting_reply = true;
+        return self.poll_transfer();
+    }
+
+    fn poll_transfer(&mut self) -> Option<u8> {
+        if self.stream.is_none() {
+            // unplugged during the transfer
+            return Some(0xFF);
+        }
+        self.exchange();
+
+        let mut reply = None;
+        for frame in std::mem::take(&mut self.frames) {
+            match frame {
+                Frame::Reply(_) if self.abandoned_replies > 0 => self.abandoned_replies -= 1,
+                Frame::Reply(byte) if self.awaiting_reply => {
+                    self.awaiting_reply = false;
+                    reply = Some(byte);
+                }
+                // nothing was sent that it could answer
+                Frame::Reply(_) => {}
+                // both sides are driving the clock : nobody answers the other,
+                // so the line just reads as high
+                Frame::Data(_) => self.send(Frame::Reply(0xFF)),
+                Frame::Hello(_) => {}
+            }
+        }
+        self.exchange();
+
+        return reply;
+    }
+
+    fn abandon_transfer(&mut self) {
+        if self.awaiting_reply {
+            self.awaiting_reply = false;
+            self.abandoned_replies += 1;
+        }
+    }
+
+    fn poll_external(&mut self, reply: u8) -> Option<u8> {
+        self.stream.as_ref()?;
+        self.exchange();
+
+        while let Some(frame) = self.frames.pop_front() {
+            match frame {
+                Frame::Data(byte) => {
+                    self.send(Frame::Reply(reply));
+                    self.exchange();
+                    return Some(byte);
+                }
+                // late answers to our own abandoned transfers
+                Frame::Reply(_) => {
+                    self.abandoned_replies = self.abandoned_replies.saturating_sub(1)
+                }
+                Frame::Hello(_) => {}
+            }
+        }
+
+        return None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn frame_round_trip() {
+        let frames = [
+            Frame::Hello(PROTOCOL_VERSION),
+            Frame::Data(0x00),
+            Frame::Data(0xFF),
+            Frame::Reply(0x42),
+        ];
+
+        let mut wire = Cursor::new(Vec::new());
+        for frame in &frames {
+            write_frame(&mut wire, frame).unwrap();
+        }
+        assert_eq!(wire.get_ref().len(), frames.len() * 2);
+
+        wire.set_position(0);
+        for frame in frames {
+            assert_eq!(read_frame(&mut wire).unwrap(), frame);
+        }
+        // nothing left
+        let error = read_frame(&mut wire).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+
+        let error = read_frame(&mut Cursor::new([0x03, 0x00])).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    // a link connected to a peer driven by hand
+    fn connected_link() -> (TcpLink, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let connecting = std::thread::spawn(move || TcpLink::connect(address).unwrap());
+
+        let (mut peer, _) = listener.accept().unwrap();
+        peer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        write_frame(&mut peer, &Frame::Hello(PROTOCOL_VERSION)).unwrap();
+        assert_eq!(
+            read_frame(&mut peer).unwrap(),
+            Frame::Hello(PROTOCOL_VERSION)
+        );
+
+        return (connecting.join().unwrap(), peer);
+    }
+
+    // polls the transfer in progress until its reply makes it through the socket
+    fn wait_for_reply(link: &mut TcpLink) -> u8 {
+        for _ in 0..5000 {
+            if let Some(reply) = link.poll_transfer() {
+                return reply;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        panic!("no reply from the peer");
+    }
+
+    #[test]
+    fn replies_answer_their_own_byte() {
+        let (mut link, mut peer) = connected_link();
+
+        for byte in [0x12, 0x34, 0x56, 0x78] {
+            // nothing can have been answered yet
+            assert_eq!(link.transfer(byte), None);
+            assert_eq!(link.poll_transfer(), None);
+            assert_eq!(read_frame(&mut peer).unwrap(), Frame::Data(byte));
+            write_frame(&mut peer, &Frame::Reply(!byte)).unwrap();
+            assert_eq!(wait_for_reply(&mut link), !byte);
+        }
+
+        // the reply to an abandoned transfer comes late, and isn't taken for the next one's
+        assert_eq!(link.transfer(0x9A), None);
+        link.abandon_transfer();
+        assert_eq!(link.transfer(0xBC), None);
+        assert_eq!(read_frame(&mut peer).unwrap(), Frame::Data(0x9A));
+        assert_eq!(read_frame(&mut peer).unwrap(), Frame::Data(0xBC));
+        write_frame(&mut peer, &Frame::Reply(!0x9A)).unwrap();
+        write_frame(&mut peer, &Frame::Reply(!0xBC)).unwrap();
+        assert_eq!(wait_for_reply(&mut link), !0xBC);
+
+        // the peer drives the clock
+        write_frame(&mut peer, &Frame::Data(0xDE)).unwrap();
+        let received = loop {
+            if let Some(byte) = link.poll_external(0xF0) {
+                break byte;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        };
+        assert_eq!(received, 0xDE);
+        assert_eq!(read_frame(&mut peer).unwrap(), Frame::Reply(0xF0));
+
+        // unplugged : the line reads as high
+        drop(peer);
+        let reply = match link.transfer(0x00) {
+            Some(reply) => reply,
+            None => wait_for_reply(&mut link),
+        };
+        assert_eq!(reply, 0xFF);
+    }
+}
@@ -0,0 +1,90 @@
+// the same quad as simple.wgsl, with a crt look :
+// scanlines between the lines of the screen, a curved glass & some glow around bright pixels
+
+var<private> v_positions: array<vec2<f32>, 6> = array<vec2<f32>, 6> (
+    vec2<f32>(-1.0, 1.0),
+    vec2<f32>(1.0, -1.0),
+    vec2<f32>(-1.0, -1.0),
+    vec2<f32>(-1.0, 1.0),
+    vec2<f32>(1.0, 1.0),
+    vec2<f32>(1.0, -1.0),
+);
+
+// texture coordinates are flipped on the y axis
+var<private> v_texcoords: array<vec2<f32>, 6> = array<vec2<f32>, 6> (
+    vec2<f32>(0.0, 0.0),
+    vec2<f32>(1.0, 1.0),
+    vec2<f32>(0.0, 1.0),
+    vec2<f32>(0.0, 0.0),
+    vec2<f32>(1.0, 0.0),
+    vec2<f32>(1.0, 1.0),
+);
+
+const SCREEN_SIZE: vec2<f32> = vec2<f32>(160.0, 144.0);
+const PI: f32 = 3.14159265;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) texcoord: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+
+    out.clip_position = vec4<f32>(v_positions[in_vertex_index], 0.0, 1.0);
+    out.texcoord = v_texcoords[in_vertex_index];
+    return out;
+}
+
+@group(0) @binding(0)
+var framebuffer: texture_2d<f32>;
+@group(0) @binding(1)
+var framebuffer_sampler: sampler;
+
+// same layout as `CrtUniform` in renderer.rs, each effect from 0 (off) to 1
+struct CrtParameters {
+    scanlines: f32,
+    curvature: f32,
+    glow: f32,
+    _padding: f32,
+}
+@group(1) @binding(0)
+var<uniform> crt: CrtParameters;
+
+// bends the screen outwards, more so in the corners
+fn curve(texcoord: vec2<f32>) -> vec2<f32> {
+    let centered = texcoord * 2.0 - 1.0;
+    let bent = centered * (1.0 + centered.yx * centered.yx * crt.curvature * 0.5);
+    return bent * 0.5 + 0.5;
+}
+
+// no derivatives are needed, so this can be used outside of uniform control flow
+fn sample(texcoord: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(framebuffer, framebuffer_sampler, texcoord, 0.0).rgb;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texcoord = curve(in.texcoord);
+    // past the edges of the curved glass
+    if any(texcoord < vec2<f32>(0.0)) || any(texcoord > vec2<f32>(1.0)) {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+
+    let texel = 1.0 / SCREEN_SIZE;
+    let color = sample(texcoord);
+    let neighbours = (sample(texcoord + vec2<f32>(texel.x, 0.0))
+        + sample(texcoord - vec2<f32>(texel.x, 0.0))
+        + sample(texcoord + vec2<f32>(0.0, texel.y))
+        + sample(texcoord - vec2<f32>(0.0, texel.y))) / 4.0;
+    // the phosphors light up a bit around bright pixels
+    let glowing = max(color, mix(color, neighbours, 0.5));
+    let lit = mix(color, glowing, crt.glow);
+
+    // darkest between two lines of the screen, untouched in their middle
+    let line = fract(texcoord.y * SCREEN_SIZE.y);
+    let scanline = 1.0 - crt.scanlines * (1.0 - sin(line * PI));
+
+    return vec4<f32>(lit * scanline, 1.0);
+}
@@ -0,0 +1,271 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    apu::Apu,
+    cpu::CPU,
+    error::SnapshotError,
+    input::{GBInputState, TurboState},
+    memory::MBC,
+    quirks::GameQuirks,
+    rtc::Rtc,
+};
+
+// save states : the whole console as plain data, kept apart from the running `Gameboy`
+// they are written as json so state files can be inspected & diffed by hand
+// the version has to be bumped whenever the layout changes, older files are then refused
+// instead of being restored into the wrong fields
+pub const SNAPSHOT_VERSION: u64 = 1;
+
+// what isn't in here is either a setting of the emulator (error recovery, turbo, perf stats),
+// whatever is plugged into the link port, or derived from the rest (the tile atlas & tilemap)
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u64,
+    // global checksum from the header of the rom the snapshot was taken with
+    #[serde(default)]
+    pub rom_checksum: u16,
+    pub cpu: CPU,
+    pub memory: MemorySnapshot,
+    // files from before `MemorySnapshot::div` only have the lower half of the internal divider
+    // here, DIV being the upper one. it isn't written anymore
+    #[serde(default, skip_serializing)]
+    pub div_cycles: u64,
+    pub ly_cycles: u64,
+    pub tima_cycles: u64,
+    pub serial_cycles: u64,
+    pub step_cycles: u64,
+    pub step_dots: u64,
+    pub stat_line: bool,
+    pub lcd_was_enabled: bool,
+    // added after the first version, files without it start from the first window line
+    #[serde(default)]
+    pub window_line: u8,
+    #[serde(default)]
+    pub window_y_triggered: bool,
+    pub halted: bool,
+    pub input_state: GBInputState,
+    pub turbo_state: TurboState,
+    pub frame_count: u64,
+    pub framebuffer: Vec<u8>,
+}
+
+// serde only handles arrays of up to 32 elements, so the memory regions are stored as vectors
+// the boot rom & the cartridge rom never change and aren't saved : the rom is taken from the
+// cartridge the state is loaded with, and `rom_checksum` makes sure it's the same one
+// (files from before still have the rom banks, they are ignored)
+#[derive(Serialize, Deserialize)]
+pub struct MemorySnapshot {
+    pub vram: Vec<u8>,
+    // the cgb vram bank 1, added after the first version
+    #[serde(default)]
+    pub vram_1: Vec<u8>,
+    pub ext_ram: Vec<u8>,
+    pub wram: Vec<u8>,
+    pub switchable_wram: Vec<Vec<u8>>,
+    pub oam: Vec<u8>,
+    pub io_hw: Vec<u8>,
+    pub hram: Vec<u8>,
+    pub ie: u8,
+    pub mbc: MBC,
+    pub selected_rom_bank: u8,
+    // added after the first version, files without them have BANK2 & the mode cleared
+    #[serde(default)]
+    pub rom_bank_high: u8,
+    #[serde(default)]
+    pub banking_mode: bool,
+    #[serde(default)]
+    pub mbc1_multicart: bool,
+    // the MBC3 ram bank & real time clock, added after the first version
+    #[serde(default)]
+    pub ram_bank: u8,
+    #[serde(default)]
+    pub rtc: Option<Rtc>,
+    pub ppu_line: u8,
+    // the whole internal divider, added after the first version
+    #[serde(default)]
+    pub div: Option<u16>,
+    pub dma_cycles: u64,
+    pub vram_dma_cycles: u64,
+    pub vram_dma_source: u16,
+    pub vram_dma_destination: u16,
+    pub hblank_dma_blocks: Option<u8>,
+    pub apu: Apu,
+    pub cgb_mode: bool,
+    pub double_speed: bool,
+    pub quirks: GameQuirks,
+}
+
+impl Snapshot {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        let text = serde_json::to_string(self)?;
+        std::fs::write(path, text)?;
+
+        return Ok(());
+    }
+
+    // the version is checked before the rest of the file is parsed,
+    // and the sizes before anything gets restored
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Snapshot, SnapshotError> {
+        let text = std::fs::read_to_string(path)?;
+        let value: serde_json::Value = serde_json::from_str(&text)?;
+        let version = value
+            .get("version")
+            .and_then(serde_json::Value::as_u64)
+            .unwrap_or(0);
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::Version(version));
+        }
+
+        let snapshot: Snapshot = serde_json::from_value(value)?;
+        snapshot.check_sizes()?;
+
+        return Ok(snapshot);
+    }
+
+    // refuses a snapshot taken with another game, or another revision of it
+    // `checksum` is the one of the loaded rom, see `Memory::rom_checksum`
+    pub fn check_rom(&self, checksum: u16) -> Result<(), SnapshotError> {
+        if checksum != self.rom_checksum {
+            return Err(SnapshotError::RomMismatch(self.rom_checksum, checksum));
+        }
+
+        return Ok(());
+    }
+
+    // converting a snapshot into a `Gameboy` panics if a region has the wrong size
+    pub fn check_sizes(&self) -> Result<(), SnapshotError> {
+        let memory = &self.memory;
+        check_size("vram", &memory.vram, 0x2000)?;
+        if !memory.vram_1.is_empty() {
+            check_size("vram bank 1", &memory.vram_1, 0x2000)?;
+        }
+        check_size("external ram", &memory.ext_ram, 0x2000)?;
+        check_size("wram", &memory.wram, 0x1000)?;
+        if memory.switchable_wram.len() != 7 {
+            return Err(SnapshotError::Size(
+                "switchable wram",
+                memory.switchable_wram.len() * 0x1000,
+            ));
+        }
+        for bank in &memory.switchable_wram {
+            check_size("switchable wram", bank, 0x1000)?;
+        }
+        check_size("oam", &memory.oam, 0xA0)?;
+        check_size("io registers", &memory.io_hw, 0x80)?;
+        check_size("hram", &memory.hram, 0x7F)?;
+        check_size("framebuffer", &self.framebuffer, 160 * 144 * 4)?;
+
+        return Ok(());
+    }
+}
+
+// one difference between two snapshots
+// paths follow the json layout of the state files, e.g. "cpu.a" or "memory.switchable_wram[2]"
+#[derive(Debug, PartialEq)]
+pub enum StateDiff {
+    // a register, counter or flag, with both values as written in the files
+    Value(String, String, String),
+    // a memory region : how many of its bytes differ, and the offset of the first one
+    Region(String, usize, usize),
+    // a region that doesn't have the same size in both snapshots
+    Size(String, usize, usize),
+}
+
+impl std::fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateDiff::Value(path, a, b) => write!(f, "{path} : {a} -> {b}"),
+            StateDiff::Region(path, count, first) => {
+                write!(f, "{path} : {count} bytes differ, from offset {first:#06X}")
+            }
+            StateDiff::Size(path, a, b) => write!(f, "{path} : size {a} -> {b}"),
+        }
+    }
+}
+
+// what differs between two snapshots of the same game, to track down desyncs : two runs
+// with the same inputs should give the same state at the same frame
+pub fn diff_states(a: &Snapshot, b: &Snapshot) -> Vec<StateDiff> {
+    let mut diffs = Vec::new();
+    // both come from the same struct, so they always serialize
+    let (Ok(a), Ok(b)) = (serde_json::to_value(a), serde_json::to_value(b)) else {
+        return diffs;
+    };
+    diff_values("", &a, &b, &mut diffs);
+
+    return diffs;
+}
+
+fn diff_values(
+    path: &str,
+    a: &serde_json::Value,
+    b: &serde_json::Value,
+    diffs: &mut Vec<StateDiff>,
+) {
+    use serde_json::Value;
+
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            for (key, value) in a {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                diff_values(&path, value, b.get(key).unwrap_or(&Value::Null), diffs);
+            }
+        }
+        // memory regions are arrays of bytes, reported as a whole
+        (Value::Array(a), Value::Array(b)) if is_bytes(a) && is_bytes(b) => {
+            if a.len() != b.len() {
+                diffs.push(StateDiff::Size(path.to_string(), a.len(), b.len()));
+                return;
+            }
+            let differing: Vec<usize> = (0..a.len()).filter(|&i| a[i] != b[i]).collect();
+            if let Some(&first) = differing.first() {
+                diffs.push(StateDiff::Region(path.to_string(), differing.len(), first));
+            }
+        }
+        // banks
+        (Value::Array(a), Value::Array(b)) if a.len() == b.len() => {
+            for (i, (a, b)) in a.iter().zip(b).enumerate() {
+                diff_values(&format!("{path}[{i}]"), a, b, diffs);
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            diffs.push(StateDiff::Size(path.to_string(), a.len(), b.len()));
+        }
+        (a, b) if a != b => {
+            diffs.push(StateDiff::Value(
+                path.to_string(),
+                a.to_string(),
+                b.to_string(),
+            ));
+        }
+        _ => {}
+    }
+}
+
+// registers are single numbers, regions have at least a few bytes
+fn is_bytes(values: &[serde_json::Value]) -> bool {
+    return !values.is_empty() && values.iter().all(serde_json::Value::is_u64);
+}
+
+// https://gbdev.io/pandocs/The_Cartridge_Header.html#014e-014f--global-checksum
+// 0 for roms too small to have a header
+pub fn rom_checksum(rom: &[u8]) -> u16 {
+    return match rom.get(0x014E..0x0150) {
+        Some(bytes) => u16::from_be_bytes([bytes[0], bytes[1]]),
+        None => 0,
+    };
+}
+
+fn check_size(region: &'static str, data: &[u8], expected: usize) -> Result<(), SnapshotError> {
+    if data.len() != expected {
+        return Err(SnapshotError::Size(region, data.len()));
+    }
+
+    return Ok(());
+}
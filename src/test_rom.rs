@@ -0,0 +1,70 @@
+use crate::{error::EmulationError, gameboy::Gameboy};
+
+// the conventions test roms use to report a result, shared by `--test-mode` & the
+// integration tests
+
+// 4194304 Hz : an emulated second in t-cycles at normal speed
+pub const CYCLES_PER_SECOND: u64 = 4194304;
+
+// https://github.com/Gekkio/mooneye-test-suite#passfail-reporting
+// true if passed, false if failed, none unless the next instruction is the `ld b, b`
+// ending a mooneye test
+pub fn mooneye_verdict(console: &Gameboy) -> Option<bool> {
+    let pc = console.cpu().read_program_counter();
+    if console.memory().read_byte(pc) != 0x40 {
+        return None;
+    }
+
+    let cpu = console.cpu();
+    let registers = [
+        cpu.read_b_register(),
+        cpu.read_c_register(),
+        cpu.read_d_register(),
+        cpu.read_e_register(),
+        cpu.read_h_register(),
+        cpu.read_l_register(),
+    ];
+    // other roms may use `ld b, b` too, so only trust the two known signatures
+    return match registers {
+        [3, 5, 8, 13, 21, 34] => Some(true),
+        [0x42, 0x42, 0x42, 0x42, 0x42, 0x42] => Some(false),
+        _ => None,
+    };
+}
+
+// runs a mooneye test until it reports, the cpu locks up or `max_cycles` have elapsed
+// returns the verdict if there was one, and the cycles it took
+pub fn run_mooneye(
+    console: &mut Gameboy,
+    max_cycles: u64,
+) -> Result<(Option<bool>, u64), EmulationError> {
+    let mut cycles = 0;
+    while cycles < max_cycles && !console.is_locked_up() {
+        console.step()?;
+        cycles += console.last_step_cycles();
+        if let Some(passed) = mooneye_verdict(console) {
+            return Ok((Some(passed), cycles));
+        }
+    }
+
+    return Ok((None, cycles));
+}
+
+// for the tests checked by looking at the screen, like dmg-acid2 & cgb-acid2 :
+// runs until `frames` frames have been drawn since power on, or the cpu locks up
+pub fn run_frames(console: &mut Gameboy, frames: u64) -> Result<(), EmulationError> {
+    while console.frame_count() < frames && !console.is_locked_up() {
+        console.step()?;
+    }
+
+    return Ok(());
+}
+
+// FNV-1a, which is stable across rust versions unlike the std hashers
+pub fn framebuffer_hash(framebuffer: &[u8]) -> u64 {
+    return framebuffer
+        .iter()
+        .fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01B3)
+        });
+}
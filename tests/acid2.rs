@@ -0,0 +1,39 @@
+// https://github.com/mattcurrie/dmg-acid2 & https://github.com/mattcurrie/cgb-acid2
+// the ppu conformance tests : after a few frames, the screen should match the reference
+// image published with each test. the expected hash is the one --test-mode prints for a run
+// whose screenshot (--test-screenshot) was checked against that image :
+// KOHOLINT_DMG_ACID2=dmg-acid2.gb KOHOLINT_DMG_ACID2_HASH=<hash> cargo test --release --test acid2 -- --ignored
+
+use koholint_emulator::{gameboy::Gameboy, test_rom};
+
+// the test image is drawn within the first frames, and then stays
+const FRAMES: u64 = 60;
+
+fn check_screen(rom_variable: &str, hash_variable: &str) {
+    let rom = std::env::var(rom_variable)
+        .unwrap_or_else(|_| panic!("{rom_variable} should point to the test rom"));
+    let expected = std::env::var(hash_variable)
+        .ok()
+        .and_then(|hash| u64::from_str_radix(&hash, 16).ok())
+        .unwrap_or_else(|| panic!("{hash_variable} should be the hash of the reference screen"));
+
+    let mut console = Gameboy::from_file(&rom).unwrap();
+    test_rom::run_frames(&mut console, FRAMES).unwrap();
+    let hash = test_rom::framebuffer_hash(console.get_framebuffer());
+    assert_eq!(
+        hash, expected,
+        "{rom} : framebuffer hash {hash:016X} after {FRAMES} frames, expected {expected:016X}"
+    );
+}
+
+#[test]
+#[ignore = "needs the dmg-acid2 rom, see KOHOLINT_DMG_ACID2"]
+fn dmg_acid2() {
+    check_screen("KOHOLINT_DMG_ACID2", "KOHOLINT_DMG_ACID2_HASH");
+}
+
+#[test]
+#[ignore = "needs the cgb-acid2 rom, see KOHOLINT_CGB_ACID2"]
+fn cgb_acid2() {
+    check_screen("KOHOLINT_CGB_ACID2", "KOHOLINT_CGB_ACID2_HASH");
+}
@@ -0,0 +1,98 @@
+// the determinism contract on `Gameboy` : the same rom with the same inputs must give the
+// same frames, serial output & cycle count, run after run
+
+use std::time::Duration;
+
+use koholint_emulator::{
+    clock::ManualClock,
+    gameboy::Gameboy,
+    input::Button,
+    serial::SerialCapture,
+    test_rom::{self, CYCLES_PER_SECOND},
+};
+
+const SECONDS: u64 = 10;
+
+const BUTTONS: [Button; 8] = [
+    Button::Up,
+    Button::Right,
+    Button::Left,
+    Button::Down,
+    Button::A,
+    Button::B,
+    Button::Start,
+    Button::Select,
+];
+
+// reads the d-pad, sends it over serial and writes it into the tile data, forever
+fn rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    // jp 0150, over the header
+    rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    #[rustfmt::skip]
+    let program = [
+        0x21, 0x00, 0x80, // ld hl, 8000
+        0x3E, 0x20,       // loop : ld a, 20
+        0xE0, 0x00,       // ldh (00), a : select the d-pad
+        0xF0, 0x00,       // ldh a, (00)
+        0xE0, 0x01,       // ldh (01), a
+        0x22,             // ld (hl+), a
+        0x7C,             // ld a, h
+        0xE6, 0x8F,       // and 8F : stay within 8000-8FFF
+        0x67,             // ld h, a
+        0x3E, 0x81,       // ld a, 81
+        0xE0, 0x02,       // ldh (02), a : start a transfer on the internal clock
+        0x18, 0xED,       // jr loop
+    ];
+    rom[0x0150..0x0150 + program.len()].copy_from_slice(&program);
+    return rom;
+}
+
+// FNV-1a over the (frame hash, serial output, cycles) of a whole run
+fn run() -> u64 {
+    let mut console = Gameboy::new(rom()).unwrap();
+    console.memory_mut().skip_boot_rom();
+    console.cpu_mut().write_program_counter(0x0100);
+    console.cpu_mut().write_stack_pointer(0xFFFE);
+    console.set_clock(Box::new(ManualClock::new(Duration::ZERO)));
+    let (capture, output) = SerialCapture::new();
+    console.connect_serial_device(Box::new(capture));
+
+    let mut hash = test_rom::framebuffer_hash(&[]);
+    let mut cycles = 0;
+    let mut frame = console.frame_count();
+    while cycles < SECONDS * CYCLES_PER_SECOND {
+        console.step().unwrap();
+        cycles += console.last_step_cycles();
+
+        if console.frame_count() != frame {
+            frame = console.frame_count();
+            let frame_hash = test_rom::framebuffer_hash(console.get_framebuffer());
+            hash = test_rom::framebuffer_hash(
+                &[hash.to_le_bytes(), frame_hash.to_le_bytes()].concat(),
+            );
+
+            // the scripted input : a different button every 7 frames, released in between
+            let button = BUTTONS[(frame / 7 % 8) as usize];
+            if frame % 7 < 4 {
+                console.press_button(button);
+            } else {
+                console.release_button(button);
+            }
+        }
+    }
+
+    let output = output.lock().unwrap();
+    assert!(
+        !output.is_empty(),
+        "the rom should have sent something over serial"
+    );
+    return test_rom::framebuffer_hash(
+        &[&hash.to_le_bytes()[..], &output, &cycles.to_le_bytes()].concat(),
+    );
+}
+
+#[test]
+fn same_inputs_same_run() {
+    assert_eq!(run(), run());
+}
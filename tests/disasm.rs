@@ -0,0 +1,55 @@
+// the disasm subcommand, on a rom small enough to check line by line
+
+use std::process::Command;
+
+#[test]
+fn disassembles_every_bank() {
+    let mut rom = vec![0; 0x8000];
+    // jp 0150, over the header
+    rom[0x0101..0x0104].copy_from_slice(&[0xC3, 0x50, 0x01]);
+    // ld a, 42 ; ld (hl+), a ; jr -5
+    rom[0x0150..0x0155].copy_from_slice(&[0x3E, 0x42, 0x22, 0x18, 0xFB]);
+    // inc a ; ret
+    rom[0x4000..0x4002].copy_from_slice(&[0x3C, 0xC9]);
+
+    let dir = std::env::temp_dir().join(format!("koholint-disasm-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rom_path = dir.join("small.gb");
+    let sym_path = dir.join("small.sym");
+    let out_path = dir.join("small.asm");
+    std::fs::write(&rom_path, rom).unwrap();
+    std::fs::write(&sym_path, "00:0150 Main\n01:4000 Helper ; in bank 1\n").unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_koholint-emulator"))
+        .arg("disasm")
+        .args([&rom_path, &out_path, &sym_path])
+        .env("XDG_CONFIG_HOME", &dir)
+        .status()
+        .unwrap();
+    let listing = std::fs::read_to_string(&out_path);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(status.success());
+    let listing = listing.unwrap();
+
+    // the runs of zeroes are skipped
+    let lines: Vec<&str> = listing
+        .lines()
+        .filter(|line| !line.ends_with("skipped"))
+        .map(|line| line.trim_end())
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            "; bank 00",
+            "00:0101  jp 0x0150",
+            "Main:",
+            "00:0150  ld a, 0x42",
+            "00:0152  ld (hl+), a",
+            "00:0153  jr -5",
+            "; bank 01",
+            "Helper:",
+            "01:4000  inc a",
+            "01:4001  ret",
+        ]
+    );
+}
@@ -0,0 +1,54 @@
+// runs the emulator binary without a window, as ci would
+
+use std::{path::PathBuf, process::Command};
+
+// sends 'F' over serial on every v-blank, forever
+fn rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    // cgb only, so the boot rom is skipped
+    rom[0x0143] = 0xC0;
+    // jp 0150, over the header
+    rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    #[rustfmt::skip]
+    let program = [
+        0x3E, 0x01,       // ld a, 01
+        0xE0, 0xFF,       // ldh (ff), a : only the v-blank interrupt
+        0x76,             // loop : halt, until the next v-blank with ime off
+        0xAF,             // xor a
+        0xE0, 0x0F,       // ldh (0f), a
+        0x3E, b'F',       // ld a, 'F'
+        0xE0, 0x01,       // ldh (01), a
+        0x3E, 0x81,       // ld a, 81
+        0xE0, 0x02,       // ldh (02), a : start a transfer on the internal clock
+        0x18, 0xF2,       // jr loop
+    ];
+    rom[0x0150..0x0150 + program.len()].copy_from_slice(&program);
+    return rom;
+}
+
+// the serial output of a headless run of `frames` frames
+fn run_headless(frames: u64) -> String {
+    let dir =
+        std::env::temp_dir().join(format!("koholint-headless-{}-{frames}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rom_path: PathBuf = dir.join("frames.gb");
+    std::fs::write(&rom_path, rom()).unwrap();
+
+    // the config is written in a directory of its own, not the user's one
+    let output = Command::new(env!("CARGO_BIN_EXE_koholint-emulator"))
+        .arg(&rom_path)
+        .args(["--headless", "--frames", &frames.to_string()])
+        .env("XDG_CONFIG_HOME", &dir)
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(output.status.success(), "{output:?}");
+    return String::from_utf8(output.stdout).unwrap();
+}
+
+#[test]
+fn headless_run_stops_after_its_frames() {
+    assert_eq!(run_headless(1), "F");
+    assert_eq!(run_headless(5), "FFFFF");
+}
@@ -0,0 +1,65 @@
+// https://github.com/Gekkio/mooneye-test-suite
+// runs every rom of a directory of mooneye tests, its subdirectories included :
+// KOHOLINT_MOONEYE_DIR=path/to/mooneye cargo test --release --test mooneye -- --ignored --nocapture
+
+use std::path::{Path, PathBuf};
+
+use koholint_emulator::{
+    gameboy::Gameboy,
+    test_rom::{self, CYCLES_PER_SECOND},
+};
+
+// the tests report within a few emulated seconds, boot included
+const TIMEOUT_SECONDS: u64 = 60;
+
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) {
+    let entries = std::fs::read_dir(dir).unwrap_or_else(|e| panic!("{}: {e}", dir.display()));
+    for entry in entries {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            find_roms(&path, roms);
+        } else if path.extension().is_some_and(|extension| extension == "gb") {
+            roms.push(path);
+        }
+    }
+}
+
+#[test]
+#[ignore = "needs the mooneye test roms, see KOHOLINT_MOONEYE_DIR"]
+fn mooneye() {
+    let dir = std::env::var("KOHOLINT_MOONEYE_DIR")
+        .expect("KOHOLINT_MOONEYE_DIR should point to a directory of mooneye test roms");
+    let mut roms = Vec::new();
+    find_roms(Path::new(&dir), &mut roms);
+    roms.sort();
+    assert!(!roms.is_empty(), "no .gb file in {dir}");
+
+    let mut failed = Vec::new();
+    for path in &roms {
+        let name = path.strip_prefix(&dir).unwrap_or(path).display();
+        let result = Gameboy::from_file(&path.to_string_lossy())
+            .map_err(|e| e.to_string())
+            .and_then(|mut console| {
+                test_rom::run_mooneye(&mut console, TIMEOUT_SECONDS * CYCLES_PER_SECOND)
+                    .map_err(|e| e.to_string())
+            });
+        match result {
+            Ok((Some(true), cycles)) => println!("PASS {name} ({cycles} cycles)"),
+            Ok((Some(false), cycles)) => {
+                println!("FAIL {name} ({cycles} cycles)");
+                failed.push(path);
+            }
+            Ok((None, cycles)) => {
+                println!("FAIL {name} (no result after {cycles} cycles)");
+                failed.push(path);
+            }
+            Err(e) => {
+                println!("FAIL {name} ({e})");
+                failed.push(path);
+            }
+        }
+    }
+
+    println!("{} of {} passed", roms.len() - failed.len(), roms.len());
+    assert!(failed.is_empty(), "{} mooneye tests failed", failed.len());
+}